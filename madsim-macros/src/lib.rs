@@ -14,6 +14,35 @@ pub fn message_derive_rtype(input: TokenStream) -> TokenStream {
     request::expand(&ast).into()
 }
 
+/// Generates RPC handlers for a service.
+///
+/// On an impl block, methods marked with `#[rpc]` handle the request type of their argument,
+/// and `serve` and `serve_on` functions are added to the type.
+///
+/// On a trait of async methods, a request type is generated for each method, along with a
+/// `{Trait}Client` that calls the methods over an `Endpoint` and a `{Trait}Server` that
+/// dispatches requests to an implementation of the trait.
+///
+/// Client methods return `io::Result` of the method's output, unless the output is already a
+/// `Result`, in which case transport errors are converted into its error type by `From`.
+///
+/// # Example
+///
+/// ```ignore
+/// #[madsim::service]
+/// pub trait Kv {
+///     async fn get(&self, key: String) -> Option<String>;
+///     async fn put(&self, key: String, value: String);
+/// }
+///
+/// // server
+/// KvServer::new(MyKv::default()).serve(addr).await?;
+///
+/// // client
+/// let client = KvClient::new(ep, addr);
+/// client.put("k".into(), "v".into()).await?;
+/// assert_eq!(client.get("k".into()).await?, Some("v".into()));
+/// ```
 #[proc_macro_attribute]
 pub fn service(args: TokenStream, input: TokenStream) -> TokenStream {
     service::service(args, input)
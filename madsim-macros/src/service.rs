@@ -1,18 +1,24 @@
 use darling::FromMeta;
 use proc_macro::TokenStream as TokenStream1;
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use std::convert::TryFrom;
 use syn::{spanned::Spanned, *};
 
 pub fn service(_args: TokenStream1, input: TokenStream1) -> TokenStream1 {
-    let mut input = parse_macro_input!(input as ItemImpl);
-    service2(&mut input)
-        .unwrap_or_else(|e| {
+    let input = parse_macro_input!(input as Item);
+    match input {
+        Item::Impl(mut input) => service2(&mut input).unwrap_or_else(|e| {
             let ce = e.into_compile_error();
             quote! { #input #ce }
-        })
-        .into()
+        }),
+        Item::Trait(mut input) => service_trait(&mut input).unwrap_or_else(|e| {
+            let ce = e.into_compile_error();
+            quote! { #input #ce }
+        }),
+        _ => Error::new(input.span(), "expect an impl block or a trait").into_compile_error(),
+    }
+    .into()
 }
 
 fn service2(input: &mut ItemImpl) -> Result<TokenStream> {
@@ -109,6 +115,230 @@ fn gen_add_rpc_handler(input: &mut ItemImpl, calls: &[RpcFn]) {
     input.items.push(syn::parse2(serve_on).unwrap());
 }
 
+fn service_trait(input: &mut ItemTrait) -> Result<TokenStream> {
+    let methods = trait_methods(input)?;
+    let vis = &input.vis;
+    let name = &input.ident;
+    let client = format_ident!("{}Client", name);
+    let server = format_ident!("{}Server", name);
+
+    let requests = methods.iter().map(|m| {
+        let req = &m.request;
+        let doc = format!("The request of [`{}::{}`].", name, m.name);
+        let args = &m.args;
+        let tys = &m.tys;
+        let output = &m.output;
+        let path = format!("{}::{}", name, m.name);
+        quote! {
+            #[doc = #doc]
+            #[derive(::madsim::export::serde::Serialize, ::madsim::export::serde::Deserialize)]
+            #[serde(crate = "::madsim::export::serde")]
+            #vis struct #req {
+                #(pub #args: #tys,)*
+            }
+
+            impl ::madsim::net::rpc::Request for #req {
+                type Response = #output;
+                const ID: u64 = ::madsim::net::rpc::hash_str(concat!(module_path!(), #path));
+            }
+        }
+    });
+
+    let calls = methods.iter().map(|m| {
+        let method = &m.name;
+        let req = &m.request;
+        let args = &m.args;
+        let tys = &m.tys;
+        let output = &m.output;
+        let doc = format!("Calls [`{}::{}`] on the server.", name, m.name);
+        if is_result(output) {
+            // transport errors are converted into the error type of the method
+            return quote! {
+                #[doc = #doc]
+                pub async fn #method(&self, #(#args: #tys),*) -> #output {
+                    match self.ep.call(self.addr, #req { #(#args),* }).await {
+                        Ok(rsp) => rsp,
+                        Err(e) => Err(::std::convert::From::from(e)),
+                    }
+                }
+            };
+        }
+        quote! {
+            #[doc = #doc]
+            pub async fn #method(&self, #(#args: #tys),*) -> std::io::Result<#output> {
+                self.ep.call(self.addr, #req { #(#args),* }).await
+            }
+        }
+    });
+
+    let handlers = methods.iter().map(|m| {
+        let method = &m.name;
+        let req = &m.request;
+        let args = &m.args;
+        quote! {
+            let this = self.inner.clone();
+            ep.add_rpc_handler(move |req: #req| {
+                let this = this.clone();
+                async move { this.#method(#(req.#args),*).await }
+            });
+        }
+    });
+
+    let client_doc = format!("The client of [`{name}`] service.");
+    let server_doc = format!("The server of [`{name}`] service.");
+    let output = quote! {
+        #input
+
+        #(#requests)*
+
+        #[doc = #client_doc]
+        #[derive(Clone)]
+        #vis struct #client {
+            ep: ::madsim::net::Endpoint,
+            addr: std::net::SocketAddr,
+        }
+
+        impl #client {
+            /// Creates a client calling the server at `addr` from the endpoint.
+            pub fn new(ep: ::madsim::net::Endpoint, addr: std::net::SocketAddr) -> Self {
+                Self { ep, addr }
+            }
+
+            /// Returns the address of the server.
+            pub fn addr(&self) -> std::net::SocketAddr {
+                self.addr
+            }
+
+            #(#calls)*
+        }
+
+        #[doc = #server_doc]
+        #vis struct #server<T> {
+            inner: std::sync::Arc<T>,
+        }
+
+        impl<T: #name> #server<T> {
+            /// Creates a server dispatching requests to `inner`.
+            pub fn new(inner: T) -> Self {
+                Self { inner: std::sync::Arc::new(inner) }
+            }
+
+            /// Binds to `addr` and serves requests forever.
+            pub async fn serve(self, addr: std::net::SocketAddr) -> std::io::Result<()> {
+                let ep = ::madsim::net::Endpoint::bind(addr).await?;
+                self.serve_on(ep).await
+            }
+
+            /// Serves requests on the endpoint forever.
+            pub async fn serve_on(self, ep: ::madsim::net::Endpoint) -> std::io::Result<()> {
+                self.add_handlers(&ep);
+                ::madsim::export::futures::future::pending::<()>().await;
+                Ok(())
+            }
+
+            /// Adds RPC handlers of all methods to the endpoint.
+            pub fn add_handlers(&self, ep: &::madsim::net::Endpoint) {
+                #(#handlers)*
+            }
+        }
+    };
+    Ok(output)
+}
+
+/// Useful information of a method in a service trait.
+struct TraitMethod {
+    name: Ident,
+    request: Ident,
+    args: Vec<Ident>,
+    tys: Vec<Type>,
+    output: Type,
+}
+
+/// Check the methods of a service trait and turn `async fn` into `fn -> impl Future + Send`.
+fn trait_methods(input: &mut ItemTrait) -> Result<Vec<TraitMethod>> {
+    input.colon_token.get_or_insert_with(Default::default);
+    input.supertraits.push(parse_quote!(Send));
+    input.supertraits.push(parse_quote!(Sync));
+    input.supertraits.push(parse_quote!('static));
+
+    let mut methods = vec![];
+    for item in &mut input.items {
+        let method = match item {
+            TraitItem::Method(m) => m,
+            _ => continue,
+        };
+        let sig = &mut method.sig;
+        if sig.asyncness.take().is_none() {
+            return Err(Error::new(sig.span(), "service methods must be async"));
+        }
+        if !sig.generics.params.is_empty() {
+            return Err(Error::new(
+                sig.generics.span(),
+                "service methods can not be generic",
+            ));
+        }
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(r)) if r.reference.is_some() && r.mutability.is_none() => {}
+            _ => {
+                return Err(Error::new(
+                    sig.span(),
+                    "expect `&self` as the first argument",
+                ))
+            }
+        }
+        let mut args = vec![];
+        let mut tys = vec![];
+        for arg in inputs {
+            let FnArg::Typed(arg) = arg else {
+                unreachable!()
+            };
+            match &*arg.pat {
+                Pat::Ident(pat) => args.push(pat.ident.clone()),
+                pat => return Err(Error::new(pat.span(), "expect an identifier")),
+            }
+            tys.push((*arg.ty).clone());
+        }
+        let output: Type = match &sig.output {
+            ReturnType::Default => parse_quote!(()),
+            ReturnType::Type(_, ty) => (**ty).clone(),
+        };
+        sig.output = parse_quote! {
+            -> impl std::future::Future<Output = #output> + Send
+        };
+        if let Some(block) = &method.default {
+            method.default = Some(parse_quote!({ async move #block }));
+        }
+        let camel: String = sig
+            .ident
+            .to_string()
+            .split('_')
+            .map(|s| {
+                let mut cs = s.chars();
+                cs.next()
+                    .map(|c| c.to_uppercase().chain(cs).collect::<String>())
+                    .unwrap_or_default()
+            })
+            .collect();
+        methods.push(TraitMethod {
+            name: sig.ident.clone(),
+            request: format_ident!("{}{}Request", input.ident, camel),
+            args,
+            tys,
+            output,
+        });
+    }
+    Ok(methods)
+}
+
+/// Returns whether the type is a `Result`.
+fn is_result(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => (path.path.segments.last()).map_or(false, |s| s.ident == "Result"),
+        _ => false,
+    }
+}
+
 /// Find and remove attribute with specific `path`.
 fn take_attribute(attrs: &mut Vec<Attribute>, path: &str) -> Option<Attribute> {
     attrs
@@ -0,0 +1,18 @@
+//! The `tempfile` simulator on madsim.
+//!
+//! In simulation, temporary files and directories are created in the simulated file system of
+//! the current node by [`madsim::fs`], and their random names are drawn from the random stream
+//! of the simulation. Operations are applied immediately without taking simulated disk time,
+//! as the API is synchronous.
+//!
+//! Files are returned as `madsim::fs::File`, so the API differs from `tempfile` where it
+//! exposes [`std::fs::File`].
+
+#[cfg(not(madsim))]
+pub use tempfile::*;
+
+#[cfg(madsim)]
+pub use self::sim::*;
+
+#[cfg(madsim)]
+mod sim;
@@ -0,0 +1,293 @@
+use madsim::{
+    fs::{self, File, OpenOptions},
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+/// The number of attempts to find an unused name.
+const NUM_RETRIES: u32 = 1 << 31;
+
+/// Runs a file system operation to completion, without the simulated disk time.
+fn run<T>(f: impl Future<Output = Result<T>>) -> Result<T> {
+    fs::run_untimed(f)
+}
+
+/// Creates a new temporary directory in [`env::temp_dir`].
+pub fn tempdir() -> Result<TempDir> {
+    TempDir::new()
+}
+
+/// Creates a new temporary directory in `dir`.
+pub fn tempdir_in(dir: impl AsRef<Path>) -> Result<TempDir> {
+    TempDir::new_in(dir)
+}
+
+/// Creates a new temporary file in [`env::temp_dir`] which is removed immediately.
+///
+/// The file stays accessible through the returned handle.
+pub fn tempfile() -> Result<File> {
+    tempfile_in(env::temp_dir())
+}
+
+/// Creates a new temporary file in `dir` which is removed immediately.
+pub fn tempfile_in(dir: impl AsRef<Path>) -> Result<File> {
+    Builder::new().tempfile_in(dir)?.reopen_and_remove()
+}
+
+/// A directory in the simulated file system that is removed on drop.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    /// Creates a new temporary directory in [`env::temp_dir`].
+    pub fn new() -> Result<TempDir> {
+        Builder::new().tempdir()
+    }
+
+    /// Creates a new temporary directory in `dir`.
+    pub fn new_in(dir: impl AsRef<Path>) -> Result<TempDir> {
+        Builder::new().tempdir_in(dir)
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the directory and returns its path.
+    pub fn into_path(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+
+    /// Removes the directory and its contents, returning the error if any.
+    pub fn close(mut self) -> Result<()> {
+        self.keep = true;
+        run(fs::remove_dir_all(&self.path))
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            _ = run(fs::remove_dir_all(&self.path));
+        }
+    }
+}
+
+/// A file in the simulated file system that is removed on drop.
+#[derive(Debug)]
+pub struct NamedTempFile {
+    path: PathBuf,
+    /// Always `Some` until the file is kept.
+    file: Option<File>,
+}
+
+impl NamedTempFile {
+    /// Creates a new temporary file in [`env::temp_dir`].
+    pub fn new() -> Result<NamedTempFile> {
+        Builder::new().tempfile()
+    }
+
+    /// Creates a new temporary file in `dir`.
+    pub fn new_in(dir: impl AsRef<Path>) -> Result<NamedTempFile> {
+        Builder::new().tempfile_in(dir)
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the file.
+    pub fn as_file(&self) -> &File {
+        self.file.as_ref().unwrap()
+    }
+
+    /// Returns the file mutably.
+    pub fn as_file_mut(&mut self) -> &mut File {
+        self.file.as_mut().unwrap()
+    }
+
+    /// Opens the file again, with read and write access.
+    pub fn reopen(&self) -> Result<File> {
+        run(OpenOptions::new().read(true).write(true).open(&self.path))
+    }
+
+    /// Moves the file to `new_path`, replacing any file there, and returns the file.
+    pub fn persist(self, new_path: impl AsRef<Path>) -> Result<File> {
+        run(fs::rename(&self.path, new_path))?;
+        Ok(self.keep_file())
+    }
+
+    /// Moves the file to `new_path`, failing if a file already exists there.
+    pub fn persist_noclobber(self, new_path: impl AsRef<Path>) -> Result<File> {
+        let new_path = new_path.as_ref();
+        if run(fs::try_exists(new_path))? {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("file already exists: {new_path:?}"),
+            ));
+        }
+        self.persist(new_path)
+    }
+
+    /// Keeps the file and returns it with its path.
+    pub fn keep(self) -> Result<(File, PathBuf)> {
+        let path = self.path.clone();
+        Ok((self.keep_file(), path))
+    }
+
+    /// Removes the file, returning the error if any.
+    pub fn close(mut self) -> Result<()> {
+        self.file = None;
+        run(fs::remove_file(&self.path))
+    }
+
+    /// Returns the file without removing it.
+    fn keep_file(mut self) -> File {
+        self.file.take().unwrap()
+    }
+
+    /// Returns the file after removing its name.
+    fn reopen_and_remove(self) -> Result<File> {
+        let file = self.reopen()?;
+        self.close()?;
+        Ok(file)
+    }
+}
+
+impl AsRef<Path> for NamedTempFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for NamedTempFile {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            _ = run(fs::remove_file(&self.path));
+        }
+    }
+}
+
+/// Creates temporary files and directories with custom names.
+#[derive(Debug, Clone)]
+pub struct Builder<'a, 'b> {
+    prefix: &'a OsStr,
+    suffix: &'b OsStr,
+    rand_bytes: usize,
+}
+
+impl Default for Builder<'_, '_> {
+    fn default() -> Self {
+        Builder {
+            prefix: OsStr::new(".tmp"),
+            suffix: OsStr::new(""),
+            rand_bytes: 6,
+        }
+    }
+}
+
+impl<'a, 'b> Builder<'a, 'b> {
+    /// Creates a new builder with the default names, e.g. `.tmpXXXXXX`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the prefix of names.
+    pub fn prefix<S: AsRef<OsStr> + ?Sized>(&mut self, prefix: &'a S) -> &mut Self {
+        self.prefix = prefix.as_ref();
+        self
+    }
+
+    /// Sets the suffix of names.
+    pub fn suffix<S: AsRef<OsStr> + ?Sized>(&mut self, suffix: &'b S) -> &mut Self {
+        self.suffix = suffix.as_ref();
+        self
+    }
+
+    /// Sets the number of random characters in names.
+    pub fn rand_bytes(&mut self, rand: usize) -> &mut Self {
+        self.rand_bytes = rand;
+        self
+    }
+
+    /// Creates a new temporary file in [`env::temp_dir`].
+    pub fn tempfile(&self) -> Result<NamedTempFile> {
+        self.tempfile_in(env::temp_dir())
+    }
+
+    /// Creates a new temporary file in `dir`.
+    pub fn tempfile_in(&self, dir: impl AsRef<Path>) -> Result<NamedTempFile> {
+        self.create_in(dir.as_ref(), |path| {
+            let file = run(OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path))?;
+            Ok(NamedTempFile {
+                path,
+                file: Some(file),
+            })
+        })
+    }
+
+    /// Creates a new temporary directory in [`env::temp_dir`].
+    pub fn tempdir(&self) -> Result<TempDir> {
+        self.tempdir_in(env::temp_dir())
+    }
+
+    /// Creates a new temporary directory in `dir`.
+    pub fn tempdir_in(&self, dir: impl AsRef<Path>) -> Result<TempDir> {
+        self.create_in(dir.as_ref(), |path| {
+            run(fs::create_dir(&path))?;
+            Ok(TempDir { path, keep: false })
+        })
+    }
+
+    /// Calls `f` with random paths in `dir` until one does not exist.
+    fn create_in<T>(&self, dir: &Path, mut f: impl FnMut(PathBuf) -> Result<T>) -> Result<T> {
+        if dir == env::temp_dir() {
+            run(fs::create_dir_all(dir))?;
+        }
+        for _ in 0..NUM_RETRIES {
+            let path = dir.join(self.name());
+            match f(path) {
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && self.rand_bytes > 0 => continue,
+                res => return res,
+            }
+        }
+        Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "too many temporary files exist",
+        ))
+    }
+
+    /// Returns a random name.
+    fn name(&self) -> OsString {
+        let rand: String = (thread_rng().sample_iter(Alphanumeric))
+            .take(self.rand_bytes)
+            .map(char::from)
+            .collect();
+        let mut name = OsString::from(self.prefix);
+        name.push(rand);
+        name.push(self.suffix);
+        name
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg(madsim)]
+
+use madsim::{fs, runtime::Runtime};
+use madsim_tempfile::{tempdir, Builder, NamedTempFile};
+use std::{path::PathBuf, time::Duration};
+
+fn run(seed: u64) -> PathBuf {
+    let runtime = Runtime::with_seed_and_config(seed, madsim::Config::default());
+    let node = runtime.create_node().build();
+    runtime.block_on(async move {
+        node.spawn(async { tempdir().unwrap().into_path() })
+            .await
+            .unwrap()
+    })
+}
+
+#[test]
+fn deterministic_names() {
+    assert_eq!(run(1), run(1));
+    assert_ne!(run(1), run(2));
+}
+
+#[test]
+fn disk_latency() {
+    let mut config = madsim::Config::default();
+    config.fs.disk.latency = Duration::from_millis(1)..Duration::from_millis(2);
+    let runtime = Runtime::with_seed_and_config(1, config);
+    let node = runtime.create_node().build();
+    runtime.block_on(async move {
+        node.spawn(async {
+            let dir = tempdir().unwrap();
+            let file = NamedTempFile::new_in(&dir).unwrap();
+            let path = file.path().to_owned();
+            drop(file);
+            assert!(!fs::try_exists(&path).await.unwrap());
+        })
+        .await
+        .unwrap()
+    });
+}
+
+#[test]
+fn simulated_fs() {
+    let runtime = Runtime::new();
+    let node = runtime.create_node().build();
+    runtime.block_on(async move {
+        node.spawn(async {
+            let dir = tempdir().unwrap();
+            let dir_path = dir.path().to_owned();
+            assert!(fs::metadata(&dir_path).await.unwrap().is_dir());
+
+            let file = NamedTempFile::new_in(&dir).unwrap();
+            file.as_file().write_all_at(b"data", 0).await.unwrap();
+            let path = file.path().to_owned();
+            assert_eq!(fs::read(&path).await.unwrap(), b"data");
+            drop(file);
+            assert!(!fs::try_exists(&path).await.unwrap());
+
+            let file = (Builder::new().prefix("sst-").suffix(".tmp"))
+                .tempfile_in(&dir)
+                .unwrap();
+            let name = file.path().file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("sst-") && name.ends_with(".tmp"));
+            file.persist(dir.path().join("000001.sst")).unwrap();
+            assert!(fs::try_exists(dir.path().join("000001.sst")).await.unwrap());
+
+            drop(dir);
+            assert!(!fs::try_exists(&dir_path).await.unwrap());
+            // nothing is written to the real disk
+            assert!(!dir_path.exists());
+        })
+        .await
+        .unwrap()
+    });
+}
@@ -24,7 +24,11 @@ mod sim {
 
     pub mod task {
         #[cfg(tokio_unstable)]
-        pub use madsim::task::yield_now as consume_budget;
+        pub use madsim::task::consume_budget;
+        // a private import shadows the glob, as tokio only has it with `tokio_unstable`
+        #[cfg(not(tokio_unstable))]
+        #[allow(unused_imports)]
+        use madsim::task::consume_budget;
 
         pub use madsim::task::*;
         #[cfg(feature = "rt")]
@@ -4,14 +4,17 @@ use std::future::Future;
 use std::time::Duration;
 
 use futures_util::{pin_mut, Stream, StreamExt};
+use madsim::task::JoinHandle;
 use tonic::codegen::http::uri::PathAndQuery;
 use tracing::instrument;
 
 use crate::{
     codegen::{BoxMessage, IdentityInterceptor, RequestExt},
+    flow::{self, StreamReceiver},
+    metadata::MetadataValue,
     service::Interceptor,
-    sim::AppendMetadata,
-    Request, Response, Status, Streaming,
+    sim::{decode_grpc_timeout, encode_grpc_timeout, GRPC_TIMEOUT, RESERVED_HEADERS},
+    Extensions, Request, Response, Status, Streaming,
 };
 
 #[derive(Debug, Clone)]
@@ -33,9 +36,10 @@ impl<T> Grpc<T, IdentityInterceptor> {
 // Message type matrix:
 // |          |                single                  |                   stream                     |
 // |----------|----------------------------------------|----------------------------------------------|
-// | request  | (PathAndQuery, bool, Request<Box<M1>>) | (PathAndQuery, bool, Request<Box<()>>), M1.. |
-// | response | Result<Response<Box<M2>>>              | Result<Response<()>>, Result<Box<M2>>.., ()  |
+// | request  | (PathAndQuery, bool, Request<Box<M1>>) | (PathAndQuery, bool, Request<Box<()>>), M1.., () |
+// | response | Result<Response<Box<M2>>>              | Result<Response<()>>, Result<Box<M2>>.., ()      |
 //
+// Streaming calls are flow-controlled after the first request message. See `flow` module.
 impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
     /// Creates a new gRPC client with the provided `GrpcService` and interceptor.
     pub fn with_interceptor(inner: crate::transport::Channel, interceptor: F) -> Self {
@@ -51,7 +55,7 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
     #[instrument(name = "rpc", skip_all, fields(?path))]
     pub async fn unary<M1, M2, C>(
         &mut self,
-        mut request: Request<M1>,
+        request: Request<M1>,
         path: PathAndQuery,
         _codec: C,
     ) -> Result<Response<M2>, Status>
@@ -59,12 +63,11 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
         M1: Send + Sync + 'static,
         M2: Send + Sync + 'static,
     {
-        let timeout = self.inner.timeout;
+        let timeout = self.timeout_of(&request);
         let future = async move {
-            request.append_metadata();
-            let request = request.intercept(&mut self.interceptor)?.boxed();
-            let addr = self.inner.ep.peer_addr().unwrap();
-            let (tx, mut rx) = self.inner.ep.connect1(addr).await?;
+            let request = request.intercept(&mut self.interceptor)?;
+            let request = self.to_wire(request).boxed();
+            let (tx, mut rx) = self.inner.connect1().await?;
             // send request
             tx.send(Box::new((path, false, request))).await?;
             // receive response
@@ -82,7 +85,7 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
     #[instrument(name = "rpc", skip_all, fields(?path))]
     pub async fn client_streaming<M1, M2, C>(
         &mut self,
-        mut request: Request<impl Stream<Item = M1> + Send + 'static>,
+        request: Request<impl Stream<Item = M1> + Send + 'static>,
         path: PathAndQuery,
         _codec: C,
     ) -> Result<Response<M2>, Status>
@@ -90,16 +93,14 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
         M1: Send + Sync + 'static,
         M2: Send + Sync + 'static,
     {
-        let timeout = self.inner.timeout;
+        let timeout = self.timeout_of(&request);
         let future = async move {
-            request.append_metadata();
             let request = request.intercept(&mut self.interceptor)?;
-            let addr = self.inner.ep.peer_addr().unwrap();
-            let (tx, mut rx) = self.inner.ep.connect1(addr).await?;
-            // send requests
-            Self::send_request_stream(request, tx, path, false).await?;
-            // receive response
+            let request = self.to_wire(request);
+            let (task, mut rx) = self.start_request_stream(request, path, false).await?;
+            // receive response, then stop sending requests
             let rsp = rx.recv().await?;
+            task.abort();
             let rsp = *rsp
                 .downcast::<Result<Response<BoxMessage>, Status>>()
                 .expect("message type mismatch");
@@ -113,7 +114,7 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
     #[instrument(name = "rpc", skip_all, fields(?path))]
     pub async fn server_streaming<M1, M2, C>(
         &mut self,
-        mut request: Request<M1>,
+        request: Request<M1>,
         path: PathAndQuery,
         _codec: C,
     ) -> Result<Response<Streaming<M2>>, Status>
@@ -121,14 +122,14 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
         M1: Send + Sync + 'static,
         M2: Send + Sync + 'static,
     {
-        let timeout = self.inner.timeout;
+        let timeout = self.timeout_of(&request);
         let future = async move {
-            request.append_metadata();
-            let request = request.intercept(&mut self.interceptor)?.boxed();
-            let addr = self.inner.ep.peer_addr().unwrap();
-            let (tx, mut rx) = self.inner.ep.connect1(addr).await?;
+            let request = request.intercept(&mut self.interceptor)?;
+            let request = self.to_wire(request).boxed();
+            let (tx, rx) = self.inner.connect1().await?;
             // send request
             tx.send(Box::new((path, true, request))).await?;
+            let (_, mut rx) = flow::split(tx, rx);
             // receive responses
             let res = *(rx.recv().await?)
                 .downcast::<Result<Response<()>, Status>>()
@@ -143,7 +144,7 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
     #[instrument(name = "rpc", skip_all, fields(?path))]
     pub async fn streaming<M1, M2, C>(
         &mut self,
-        mut request: Request<impl Stream<Item = M1> + Send + 'static>,
+        request: Request<impl Stream<Item = M1> + Send + 'static>,
         path: PathAndQuery,
         _codec: C,
     ) -> Result<Response<Streaming<M2>>, Status>
@@ -151,16 +152,11 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
         M1: Send + Sync + 'static,
         M2: Send + Sync + 'static,
     {
-        let timeout = self.inner.timeout;
+        let timeout = self.timeout_of(&request);
         let future = async move {
-            request.append_metadata();
             let request = request.intercept(&mut self.interceptor)?;
-            let addr = self.inner.ep.peer_addr().unwrap();
-            let (tx, mut rx) = self.inner.ep.connect1(addr).await?;
-            // send requests in a background task
-            let task = madsim::task::spawn(async move {
-                _ = Self::send_request_stream(request, tx, path, true).await;
-            });
+            let request = self.to_wire(request);
+            let (task, mut rx) = self.start_request_stream(request, path, true).await?;
             // receive responses
             let res = *(rx.recv().await?)
                 .downcast::<Result<Response<()>, Status>>()
@@ -171,25 +167,65 @@ impl<F: Interceptor> Grpc<crate::transport::Channel, F> {
         with_timeout(timeout, future).await
     }
 
-    async fn send_request_stream<M1>(
+    /// Converts a request to what the server receives.
+    ///
+    /// Reserved headers are replaced by those of the transport, and extensions are only local.
+    fn to_wire<T>(&self, request: Request<T>) -> Request<T> {
+        let (mut metadata, _, inner) = request.into_parts();
+        for key in RESERVED_HEADERS {
+            metadata.remove(key);
+        }
+        metadata.insert("te", MetadataValue::from_static("trailers"));
+        metadata.insert(
+            "content-type",
+            MetadataValue::from_static("application/grpc"),
+        );
+        metadata.insert("user-agent", self.inner.user_agent.clone());
+        let mut request = Request::from_parts(metadata, Extensions::new(), inner);
+        // propagate the deadline to the server
+        if let Some(timeout) = self.timeout_of(&request) {
+            let value = encode_grpc_timeout(timeout).parse().unwrap();
+            request.metadata_mut().insert(GRPC_TIMEOUT, value);
+        }
+        request
+    }
+
+    /// Returns the timeout of a request, which is the shorter one of the channel and the
+    /// `grpc-timeout` header set by [`Request::set_timeout`].
+    fn timeout_of<T>(&self, request: &Request<T>) -> Option<Duration> {
+        let header = (request.metadata().get(GRPC_TIMEOUT))
+            .and_then(|value| decode_grpc_timeout(value.to_str().ok()?));
+        [self.inner.timeout, header].into_iter().flatten().min()
+    }
+
+    /// Sends the stream start message, then the requests in a background task.
+    async fn start_request_stream<M1>(
+        &self,
         request: Request<impl Stream<Item = M1> + Send + 'static>,
-        tx: madsim::net::Sender,
         path: PathAndQuery,
         server_streaming: bool,
-    ) -> Result<(), Status>
+    ) -> Result<(JoinHandle<()>, StreamReceiver), Status>
     where
         M1: Send + Sync + 'static,
     {
+        let (tx, rx) = self.inner.connect1().await?;
         let (metadata, extensions, stream) = request.into_parts();
         let header = Request::from_parts(metadata, extensions, Box::new(()) as BoxMessage);
         // send stream start message
         tx.send(Box::new((path, server_streaming, header))).await?;
+        let (mut tx, rx) = flow::split(tx, rx);
         // send requests
-        pin_mut!(stream);
-        while let Some(item) = stream.next().await {
-            tx.send(Box::new(item)).await?;
-        }
-        Ok(())
+        let task = madsim::task::spawn(async move {
+            pin_mut!(stream);
+            while let Some(item) = stream.next().await {
+                if tx.send(Box::new(item)).await.is_err() {
+                    return;
+                }
+            }
+            // end of stream
+            _ = tx.send(Box::new(())).await;
+        });
+        Ok((task, rx))
     }
 }
 
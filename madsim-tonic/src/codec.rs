@@ -1,10 +1,11 @@
-use crate::{codegen::BoxMessage, Status};
+use crate::{codegen::BoxMessage, flow::StreamReceiver, metadata::MetadataMap, Status};
 use async_stream::try_stream;
 use futures_util::{Stream, StreamExt};
 use madsim::task::JoinHandle;
 use std::{
     fmt,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
 };
 use tonic::codegen::BoxStream;
@@ -12,18 +13,22 @@ use tonic::codegen::BoxStream;
 /// Streaming requests and responses.
 pub struct Streaming<T> {
     stream: BoxStream<T>,
+    trailers: Arc<Mutex<Option<MetadataMap>>>,
 }
 
 impl<T: Send + 'static> Streaming<T> {
     /// Creates a new streaming.
     ///
-    /// The elements will be received from the endpoint starting with the given tag.
+    /// The elements will be received from the connection.
     /// If this is a bi-directional streaming RPC, `request_sending_task` is required.
     pub(crate) fn new(
-        mut rx: madsim::net::Receiver,
+        mut rx: StreamReceiver,
         request_sending_task: Option<JoinHandle<()>>,
     ) -> Self {
+        let trailers = Arc::new(Mutex::new(None));
+        let trailers1 = trailers.clone();
         Streaming {
+            trailers,
             stream: try_stream! {
                 // For bi-directional streaming, we spawn a task to send requests.
                 // This is used to cancel the task when the stream is dropped.
@@ -31,10 +36,14 @@ impl<T: Send + 'static> Streaming<T> {
                 // receive messages
                 loop {
                     let msg = rx.recv().await.map_err(|_| Status::unknown("error reading a body from connection: broken pipe"))?;
-                    if msg.downcast_ref::<()>().is_some() {
-                        // end of stream
-                        break;
-                    }
+                    let msg = match msg.downcast::<MetadataMap>() {
+                        Ok(trailers) => {
+                            // end of stream
+                            *trailers1.lock().unwrap() = Some(*trailers);
+                            break;
+                        }
+                        Err(msg) => msg,
+                    };
                     let msg = *msg.downcast::<Result<BoxMessage, Status>>().unwrap();
                     yield *msg?.downcast::<T>().unwrap();
                 }
@@ -48,7 +57,10 @@ impl<T: Send + 'static> Streaming<T> {
     /// This method is used by macros only. Not a public API.
     #[doc(hidden)]
     pub fn from_stream(stream: BoxStream<T>) -> Self {
-        Streaming { stream }
+        Streaming {
+            stream,
+            trailers: Default::default(),
+        }
     }
 }
 
@@ -57,6 +69,15 @@ impl<T> Streaming<T> {
     pub async fn message(&mut self) -> Result<Option<T>, Status> {
         self.stream.next().await.transpose()
     }
+
+    /// Fetch the trailing metadata.
+    ///
+    /// This will drain the stream of all its messages to receive the trailing metadata.
+    /// Returns `None` if the stream has no trailers, such as a request stream.
+    pub async fn trailers(&mut self) -> Result<Option<MetadataMap>, Status> {
+        while self.message().await?.is_some() {}
+        Ok(self.trailers.lock().unwrap().take())
+    }
 }
 
 impl<T> fmt::Debug for Streaming<T> {
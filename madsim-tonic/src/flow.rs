@@ -0,0 +1,118 @@
+//! Flow control of streams.
+//!
+//! The connections of the simulator are unbounded. To make a fast sender wait for a slow
+//! receiver as in HTTP/2, the receiver acknowledges the number of messages it has consumed,
+//! and the sender never runs more than [`STREAM_WINDOW`] messages ahead.
+
+use crate::codegen::BoxMessage;
+use futures_util::{
+    channel::{mpsc, oneshot},
+    select_biased, FutureExt, StreamExt,
+};
+use madsim::net::{Receiver, Sender};
+use std::{io, sync::Arc};
+
+/// The maximum number of messages of a stream that are sent but not yet consumed by the receiver.
+pub(crate) const STREAM_WINDOW: u64 = 16;
+
+/// Acknowledges that the receiver has consumed this many messages.
+struct WindowUpdate(u64);
+
+/// Splits a connection into flow-controlled halves.
+///
+/// Messages sent before the split are not counted, so both sides must split at the same point.
+pub(crate) fn split(tx: Sender, mut rx: Receiver) -> (StreamSender, StreamReceiver) {
+    let tx = Arc::new(tx);
+    let (ack_tx, ack_rx) = mpsc::unbounded();
+    let (data_tx, data_rx) = mpsc::unbounded();
+    // the connection is closed once both halves are dropped
+    let (close_tx, mut close_rx) = oneshot::channel::<()>();
+    let close_tx = Arc::new(close_tx);
+    madsim::task::spawn(async move {
+        loop {
+            let msg = select_biased! {
+                _ = &mut close_rx => return,
+                msg = rx.recv().fuse() => msg,
+            };
+            let msg = match msg.map(|msg| msg.downcast::<WindowUpdate>()) {
+                Ok(Ok(update)) => {
+                    _ = ack_tx.unbounded_send(update.0);
+                    continue;
+                }
+                Ok(Err(msg)) => Ok(msg),
+                Err(e) => Err(e),
+            };
+            let end = msg.is_err();
+            _ = data_tx.unbounded_send(msg);
+            if end {
+                return;
+            }
+        }
+    });
+    let sender = StreamSender {
+        tx: tx.clone(),
+        acks: ack_rx,
+        sent: 0,
+        acked: 0,
+        _close: close_tx.clone(),
+    };
+    let receiver = StreamReceiver {
+        tx,
+        data: data_rx,
+        received: 0,
+        _close: close_tx,
+    };
+    (sender, receiver)
+}
+
+/// The sending half of a flow-controlled connection.
+pub(crate) struct StreamSender {
+    tx: Arc<Sender>,
+    acks: mpsc::UnboundedReceiver<u64>,
+    sent: u64,
+    acked: u64,
+    _close: Arc<oneshot::Sender<()>>,
+}
+
+impl StreamSender {
+    /// Sends a message, waiting for the receiver if the window is full.
+    pub async fn send(&mut self, msg: BoxMessage) -> io::Result<()> {
+        while self.sent - self.acked >= STREAM_WINDOW {
+            let acked = self.acks.next().await.ok_or_else(connection_reset)?;
+            self.acked = self.acked.max(acked);
+        }
+        self.tx.send(msg).await?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    /// Waits for the receiver to close the connection.
+    pub async fn closed(&self) {
+        self.tx.closed().await;
+    }
+}
+
+/// The receiving half of a flow-controlled connection.
+pub(crate) struct StreamReceiver {
+    tx: Arc<Sender>,
+    data: mpsc::UnboundedReceiver<io::Result<BoxMessage>>,
+    received: u64,
+    _close: Arc<oneshot::Sender<()>>,
+}
+
+impl StreamReceiver {
+    /// Receives a message and acknowledges it to the sender.
+    pub async fn recv(&mut self) -> io::Result<BoxMessage> {
+        let msg = self.data.next().await.ok_or_else(connection_reset)??;
+        self.received += 1;
+        if self.received % (STREAM_WINDOW / 2) == 0 {
+            let update = Box::new(WindowUpdate(self.received));
+            _ = self.tx.send(update).await;
+        }
+        Ok(msg)
+    }
+}
+
+fn connection_reset() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, "connection reset")
+}
@@ -1,4 +1,5 @@
 pub use self::codec::Streaming;
+use std::time::Duration;
 pub use tonic::{
     async_trait, metadata, service, Code, Extensions, IntoRequest, IntoStreamingRequest, Request,
     Response, Status,
@@ -13,9 +14,63 @@ macro_rules! include_proto {
 
 pub mod client;
 pub mod codec;
+mod flow;
 pub(crate) mod tower;
 pub mod transport;
 
+/// Headers set by the transport, which are removed from the metadata of requests.
+const RESERVED_HEADERS: [&str; 6] = [
+    "te",
+    "user-agent",
+    "content-type",
+    "grpc-message",
+    "grpc-message-type",
+    "grpc-status",
+];
+
+/// The `user-agent` header of requests.
+const USER_AGENT: &str = "tonic/0.8.3";
+
+/// The header of the timeout of a request.
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// Units of `grpc-timeout` in nanoseconds, from the most precise.
+const GRPC_TIMEOUT_UNITS: [(char, u128); 6] = [
+    ('n', 1),
+    ('u', 1_000),
+    ('m', 1_000_000),
+    ('S', 1_000_000_000),
+    ('M', 60_000_000_000),
+    ('H', 3_600_000_000_000),
+];
+
+/// Encodes a timeout in the most precise unit that fits in 8 digits.
+fn encode_grpc_timeout(timeout: Duration) -> String {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    for (unit, scale) in GRPC_TIMEOUT_UNITS {
+        if nanos / scale <= MAX {
+            return format!("{}{unit}", nanos / scale);
+        }
+    }
+    format!("{MAX}H")
+}
+
+/// Decodes a timeout, returns `None` if invalid.
+fn decode_grpc_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at(value.len().checked_sub(1)?);
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    let value: u64 = digits.parse().ok()?;
+    let (_, scale) = (GRPC_TIMEOUT_UNITS.iter()).find(|(u, _)| unit.starts_with(*u))?;
+    let nanos = value as u128 * scale;
+    Some(Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    ))
+}
+
 /// Append header to metadata.
 trait AppendMetadata {
     fn append_metadata(&mut self);
@@ -26,12 +81,6 @@ impl AppendMetadata for metadata::MetadataMap {
         self.append("date", chrono::Utc::now().to_rfc2822().parse().unwrap());
     }
 }
-impl<T> AppendMetadata for Request<T> {
-    fn append_metadata(&mut self) {
-        let metadata = self.metadata_mut();
-        metadata.append("content-type", "application/grpc".parse().unwrap());
-    }
-}
 impl<T> AppendMetadata for Result<Response<T>, Status> {
     fn append_metadata(&mut self) {
         match self {
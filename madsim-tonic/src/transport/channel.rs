@@ -1,18 +1,40 @@
 //! Client implementation and builder.
 
 use super::Error;
-use std::{fmt, net::SocketAddr, sync::Arc, time::Duration};
+use crate::{metadata::AsciiMetadataValue, sim::USER_AGENT, Status};
+use madsim::{
+    net::{Receiver, Sender},
+    rand::{thread_rng, Rng},
+    time::Instant,
+};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 use tonic::{
     codegen::{http::HeaderValue, Bytes, StdError},
     transport::Uri,
 };
+use tracing::debug;
 
 /// Channel builder.
+///
+/// Channels reconnect with exponential backoff as in the [gRPC spec]: after a failed connection,
+/// calls fail with `Unavailable` without connecting until the backoff expires, which starts at
+/// 1 second and grows by 1.6 times up to 120 seconds, with a jitter of 20%.
+///
+/// [gRPC spec]: https://github.com/grpc/grpc/blob/master/doc/connection-backoff.md
 #[derive(Debug, Clone)]
 pub struct Endpoint {
     uri: Uri,
     timeout: Option<Duration>,
     connect_timeout: Option<Duration>,
+    user_agent: Option<String>,
 }
 
 impl Endpoint {
@@ -59,45 +81,36 @@ impl Endpoint {
 
     /// Create a channel from this config.
     pub async fn connect(&self) -> Result<Channel, Error> {
-        if let Some(dur) = self.connect_timeout {
-            madsim::time::timeout(dur, self.connect_inner())
-                .await
-                .map_err(Error::from_source)?
-        } else {
-            self.connect_inner().await
-        }
-    }
-
-    // Connect without timeout.
-    async fn connect_inner(&self) -> Result<Channel, Error> {
-        let host_port = (self.uri.authority())
-            .ok_or_else(Error::new_invalid_uri)?
-            .as_str();
-        let addr: SocketAddr = madsim::net::lookup_host(host_port)
-            .await
-            .map_err(|e| Error::new_invalid_uri().with(e))?
-            .next()
-            .ok_or_else(Error::new_invalid_uri)?;
-        let ep = madsim::net::Endpoint::connect(addr)
-            .await
-            .map_err(Error::from_source)?;
-
+        self.uri.authority().ok_or_else(Error::new_invalid_uri)?;
+        let channel = self.connect_lazy();
         // handshake
-        ep.connect1(addr).await.map_err(Error::from_source)?;
+        let subchannel = &channel.subchannels[0];
+        subchannel.try_connect().await.map_err(Error::from_source)?;
+        *subchannel.state.lock().unwrap() = State::Ready;
+        Ok(channel)
+    }
 
-        Ok(Channel {
-            ep: Arc::new(ep),
-            timeout: self.timeout,
-        })
+    /// Create a channel from this config.
+    ///
+    /// The channel connects on the first call.
+    pub fn connect_lazy(&self) -> Channel {
+        Channel::balance_list(std::iter::once(self.clone()))
     }
 
     /// Set a custom user-agent header.
-    pub fn user_agent<T>(self, _user_agent: T) -> Result<Self, Error>
+    ///
+    /// The header is prepended to the default `user-agent` of tonic.
+    pub fn user_agent<T>(self, user_agent: T) -> Result<Self, Error>
     where
         T: TryInto<HeaderValue>,
     {
-        // ignore this setting
-        Ok(self)
+        let user_agent = (user_agent.try_into().ok())
+            .and_then(|value| value.to_str().ok().map(String::from))
+            .ok_or_else(Error::new_invalid_user_agent)?;
+        Ok(Endpoint {
+            user_agent: Some(user_agent),
+            ..self
+        })
     }
 
     /// Set a custom origin.
@@ -174,6 +187,7 @@ impl From<Uri> for Endpoint {
             uri,
             timeout: None,
             connect_timeout: None,
+            user_agent: None,
         }
     }
 }
@@ -195,10 +209,186 @@ impl TryFrom<String> for Endpoint {
 }
 
 /// A default batteries included `transport` channel.
+///
+/// A channel may balance calls over several endpoints. See [`Channel::balance_list`].
 #[derive(Clone)]
 pub struct Channel {
-    pub(crate) ep: Arc<madsim::net::Endpoint>,
+    subchannels: Arc<[Subchannel]>,
+    policy: LoadBalance,
+    /// The index of the next subchannel for round-robin.
+    next: Arc<AtomicUsize>,
     pub(crate) timeout: Option<Duration>,
+    pub(crate) user_agent: AsciiMetadataValue,
+}
+
+/// The policy to pick an endpoint for each call of a balanced channel.
+///
+/// Endpoints that are backing off from a failed connection are skipped. If connecting to the
+/// picked endpoint fails, the call fails over to the next one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadBalance {
+    /// Use the first available endpoint in the list.
+    PickFirst,
+    /// Rotate through the available endpoints.
+    #[default]
+    RoundRobin,
+}
+
+/// A connection to one of the endpoints of a channel.
+struct Subchannel {
+    endpoint: Endpoint,
+    /// The local endpoint, which is bound on the first connection.
+    ep: Mutex<Option<Arc<madsim::net::Endpoint>>>,
+    state: Mutex<State>,
+}
+
+/// The state of the connection of a subchannel.
+#[derive(Debug)]
+enum State {
+    /// Not connected yet.
+    Idle,
+    /// The last connection succeeded.
+    Ready,
+    /// The last connection failed. No connection is tried until `retry_at`.
+    TransientFailure {
+        retry_at: Instant,
+        backoff: Duration,
+    },
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+const BACKOFF_MULTIPLIER: f64 = 1.6;
+const BACKOFF_JITTER: f64 = 0.2;
+
+impl State {
+    /// Records a failed connection and backs off.
+    fn fail(&mut self) {
+        let backoff = match *self {
+            State::TransientFailure { backoff, .. } => {
+                backoff.mul_f64(BACKOFF_MULTIPLIER).min(MAX_BACKOFF)
+            }
+            _ => INITIAL_BACKOFF,
+        };
+        let jitter = thread_rng().gen_range(1.0 - BACKOFF_JITTER..1.0 + BACKOFF_JITTER);
+        let retry_at = Instant::now() + backoff.mul_f64(jitter);
+        *self = State::TransientFailure { retry_at, backoff };
+    }
+
+    /// Returns whether it is backing off from a failed connection.
+    fn is_backing_off(&self) -> bool {
+        matches!(*self, State::TransientFailure { retry_at, .. } if Instant::now() < retry_at)
+    }
+}
+
+impl Channel {
+    /// Balance calls across the endpoints in round-robin.
+    ///
+    /// The timeout and user agent of calls are those of the first endpoint.
+    pub fn balance_list(list: impl Iterator<Item = Endpoint>) -> Self {
+        Self::balance_list_with(list, LoadBalance::RoundRobin)
+    }
+
+    /// Balance calls across the endpoints with the given policy.
+    ///
+    /// This method is only available in simulation.
+    pub fn balance_list_with(list: impl Iterator<Item = Endpoint>, policy: LoadBalance) -> Self {
+        let subchannels: Vec<_> = list.map(Subchannel::new).collect();
+        let first = subchannels.first().map(|s| &s.endpoint);
+        let user_agent = match first.and_then(|e| e.user_agent.as_ref()) {
+            Some(user_agent) => format!("{user_agent} {USER_AGENT}"),
+            None => USER_AGENT.into(),
+        };
+        Channel {
+            timeout: first.and_then(|e| e.timeout),
+            // checked in `Endpoint::user_agent`
+            user_agent: user_agent.parse().unwrap(),
+            subchannels: subchannels.into(),
+            policy,
+            next: Default::default(),
+        }
+    }
+
+    /// Opens a connection for a call.
+    ///
+    /// Fails fast if all endpoints are backing off from failed connections.
+    pub(crate) async fn connect1(&self) -> Result<(Sender, Receiver), Status> {
+        let n = self.subchannels.len();
+        let start = match self.policy {
+            LoadBalance::PickFirst => 0,
+            LoadBalance::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % n.max(1),
+        };
+        let mut error = None;
+        for i in 0..n {
+            let subchannel = &self.subchannels[(start + i) % n];
+            if subchannel.state.lock().unwrap().is_backing_off() {
+                continue;
+            }
+            match subchannel.connect1().await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(error.unwrap_or_else(|| match n {
+            0 => Status::unavailable("no endpoint"),
+            _ => Status::unavailable("connection failed recently, waiting to reconnect"),
+        }))
+    }
+}
+
+impl Subchannel {
+    fn new(endpoint: Endpoint) -> Self {
+        Subchannel {
+            endpoint,
+            ep: Default::default(),
+            state: Mutex::new(State::Idle),
+        }
+    }
+
+    /// Opens a connection and updates the state.
+    async fn connect1(&self) -> Result<(Sender, Receiver), Status> {
+        match self.try_connect().await {
+            Ok(conn) => {
+                *self.state.lock().unwrap() = State::Ready;
+                Ok(conn)
+            }
+            Err(e) => {
+                let mut state = self.state.lock().unwrap();
+                state.fail();
+                debug!(uri = %self.endpoint.uri, ?state, "failed to connect: {e}");
+                Err(Status::unavailable(format!("error trying to connect: {e}")))
+            }
+        }
+    }
+
+    /// Connects to the server within the connect timeout.
+    async fn try_connect(&self) -> io::Result<(Sender, Receiver)> {
+        let connect = async {
+            let ep = self.bind().await?;
+            ep.connect1(ep.peer_addr()?).await
+        };
+        match self.endpoint.connect_timeout {
+            Some(timeout) => (madsim::time::timeout(timeout, connect).await)
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timeout"))?,
+            None => connect.await,
+        }
+    }
+
+    /// Returns the local endpoint, resolving the server address and binding if not yet.
+    async fn bind(&self) -> io::Result<Arc<madsim::net::Endpoint>> {
+        if let Some(ep) = self.ep.lock().unwrap().clone() {
+            return Ok(ep);
+        }
+        let host_port = (self.endpoint.uri.authority())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid URI"))?
+            .as_str();
+        let addr: SocketAddr = (madsim::net::lookup_host(host_port).await?)
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+        let ep = Arc::new(madsim::net::Endpoint::connect(addr).await?);
+        *self.ep.lock().unwrap() = Some(ep.clone());
+        Ok(ep)
+    }
 }
 
 impl fmt::Debug for Channel {
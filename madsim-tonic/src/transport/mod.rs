@@ -1,6 +1,6 @@
 //! Batteries included server and client.
 
-pub use self::channel::{Channel, Endpoint};
+pub use self::channel::{Channel, Endpoint, LoadBalance};
 pub use self::error::Error;
 pub use self::server::Server;
 pub use tonic::codegen::http::Uri;
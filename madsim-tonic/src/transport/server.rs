@@ -2,9 +2,10 @@
 
 use super::{Error, NamedService};
 use crate::codegen::{BoxMessage, BoxMessageStream, RequestExt, ResponseExt};
-use crate::sim::AppendMetadata;
+use crate::flow;
+use crate::sim::{decode_grpc_timeout, AppendMetadata, GRPC_TIMEOUT};
 use crate::tower::layer::util::{Identity, Stack};
-use crate::{Request, Response, Status};
+use crate::{metadata::MetadataMap, Extensions, Request, Response, Status};
 use async_stream::try_stream;
 use futures_util::{future::poll_fn, select_biased, FutureExt, StreamExt};
 use madsim::net::Endpoint;
@@ -23,13 +24,17 @@ use tracing::*;
 /// A default batteries included `transport` server.
 #[derive(Clone, Debug)]
 pub struct Server<L = Identity> {
+    timeout: Option<Duration>,
     _mark: PhantomData<L>,
 }
 
 #[allow(clippy::derivable_impls)]
 impl Default for Server {
     fn default() -> Self {
-        Self { _mark: PhantomData }
+        Self {
+            timeout: None,
+            _mark: PhantomData,
+        }
     }
 }
 
@@ -64,7 +69,10 @@ impl<L> Server<L> {
     /// Set the Tower Layer all services will be wrapped in.
     pub fn layer<NewLayer>(self, _new_layer: NewLayer) -> Server<Stack<NewLayer, L>> {
         tracing::warn!("layer is unimplemented and ignored");
-        Server { _mark: PhantomData }
+        Server {
+            timeout: self.timeout,
+            _mark: PhantomData,
+        }
     }
 
     /// Configure TLS for this server.
@@ -83,10 +91,15 @@ impl<L> Server<L> {
     }
 
     /// Set a timeout on for all request handlers.
+    ///
+    /// Requests fail with `DeadlineExceeded` if not finished before this timeout, or the
+    /// `grpc-timeout` of the request if shorter.
     #[must_use]
-    pub fn timeout(self, _timeout: Duration) -> Self {
-        // ignore this setting
-        self
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Server {
+            timeout: Some(timeout),
+            ..self
+        }
     }
 
     /// Sets the `SETTINGS_INITIAL_WINDOW_SIZE` option for HTTP2 stream-level flow control.
@@ -222,6 +235,11 @@ impl<L> Router<L> {
                 .expect("invalid type");
             let span = debug_span!("request", ?addr, ?path);
             debug!(parent: &span, "received");
+            let header = (request.metadata().get(GRPC_TIMEOUT))
+                .and_then(|value| decode_grpc_timeout(value.to_str().ok()?));
+            let timeout = [self.server.timeout, header].into_iter().flatten().min();
+            let mut expired = Box::pin(expired(timeout)).fuse();
+            let (mut tx, mut rx) = flow::split(tx, rx);
 
             request.set_remote_addr(addr);
             let request: Request<BoxMessageStream> = request.map(move |msg| {
@@ -232,6 +250,10 @@ impl<L> Router<L> {
                     // request stream
                     try_stream! {
                         while let Ok(msg) = rx.recv().await {
+                            if msg.downcast_ref::<()>().is_some() {
+                                // end of stream
+                                break;
+                            }
                             yield msg;
                         }
                     }
@@ -258,14 +280,17 @@ impl<L> Router<L> {
             poll_fn(|cx| svc.poll_ready(cx)).await.unwrap();
             let rsp_future = svc.call((path, request));
             madsim::task::spawn(async move {
-                let mut result: Result<Response<BoxMessageStream>, Status> =
-                    rsp_future.instrument(span.clone()).await;
+                let mut result: Result<Response<BoxMessageStream>, Status> = select_biased! {
+                    _ = &mut expired => Err(deadline_exceeded()),
+                    result = rsp_future.instrument(span.clone()).fuse() => result,
+                };
                 result.append_metadata();
                 if server_streaming {
                     let (header, stream) = match result {
                         Ok(response) => {
-                            let (metadata, extensions, stream) = response.into_parts();
-                            let header = Response::from_parts(metadata, extensions, ());
+                            // extensions are only local
+                            let (metadata, _, stream) = response.into_parts();
+                            let header = Response::from_parts(metadata, Extensions::new(), ());
                             (Ok(header), Some(stream))
                         }
                         Err(e) => (Err(e), None),
@@ -273,7 +298,9 @@ impl<L> Router<L> {
                     // send the header
                     tx.send(Box::new(header)).await?;
                     // send the stream
-                    let Some(mut stream) = stream else { return Ok(()) };
+                    let Some(mut stream) = stream else {
+                        return Ok(());
+                    };
                     let mut count = 0;
                     loop {
                         let msg = select_biased! {
@@ -281,22 +308,31 @@ impl<L> Router<L> {
                                 debug!(parent: &span, "client closed");
                                 return Ok(());
                             }
+                            _ = &mut expired => Err(deadline_exceeded()),
                             msg = stream.next().fuse() => match msg {
                                 Some(msg) => msg,
                                 None => break,
                             }
                         };
                         // rsp: Result<BoxMessage, Status>
+                        let is_err = msg.is_err();
                         tx.send(Box::new(msg)).await?;
+                        if is_err {
+                            // the error ends the stream
+                            debug!(parent: &span, "failed after {count}");
+                            return Ok(());
+                        }
                         count += 1;
                     }
                     // send the trailer
-                    tx.send(Box::new(())).await?;
+                    let mut trailer = MetadataMap::new();
+                    trailer.insert("grpc-status", "0".parse().unwrap());
+                    tx.send(Box::new(trailer)).await?;
                     debug!(parent: &span, "completed {count}");
                 } else {
                     let rsp: Result<Response<BoxMessage>, Status> = match result {
                         Ok(response) => {
-                            let (metadata, extensions, mut stream) = response.into_parts();
+                            let (metadata, _, mut stream) = response.into_parts();
                             let inner: BoxMessage = select_biased! {
                                 _ = tx.closed().fuse() => {
                                     debug!(parent: &span, "client closed");
@@ -304,7 +340,7 @@ impl<L> Router<L> {
                                 }
                                 msg = stream.next().fuse() => msg.unwrap().unwrap(),
                             };
-                            Ok(Response::from_parts(metadata, extensions, inner))
+                            Ok(Response::from_parts(metadata, Extensions::new(), inner))
                         }
                         Err(e) => Err(e),
                     };
@@ -317,3 +353,15 @@ impl<L> Router<L> {
         }
     }
 }
+
+/// Resolves when the timeout of a request expires.
+async fn expired(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => madsim::time::sleep(timeout).await,
+        None => pending().await,
+    }
+}
+
+fn deadline_exceeded() -> Status {
+    Status::deadline_exceeded("Timeout expired")
+}
@@ -0,0 +1,31 @@
+//! The `uuid` simulator on madsim.
+//!
+//! The API is the same as [`uuid`]. The type [`Uuid`] is re-exported unchanged, so it
+//! interoperates with other crates using `uuid`.
+//!
+//! In simulation, random bytes are drawn from the random stream of the current node through
+//! the `getrandom` interception of madsim, and timestamps are read from the simulated wall
+//! clock of the node. For timestamp-based UUIDs, use a [`Context`] created in the simulation,
+//! or [`context`] which is created for each runtime, instead of the process-wide context.
+//! [`now_v1`] and [`now_v6`] do this in place of `Uuid::now_v1` and `Uuid::now_v6`.
+
+#[cfg(not(madsim))]
+pub use uuid::*;
+
+/// Creates a version 1 UUID from the current time. The same as `Uuid::now_v1`.
+#[cfg(all(not(madsim), feature = "std", feature = "v1"))]
+pub fn now_v1(node_id: &[u8; 6]) -> Uuid {
+    Uuid::now_v1(node_id)
+}
+
+/// Creates a version 6 UUID from the current time. The same as `Uuid::now_v6`.
+#[cfg(all(not(madsim), feature = "std", feature = "v6"))]
+pub fn now_v6(node_id: &[u8; 6]) -> Uuid {
+    Uuid::now_v6(node_id)
+}
+
+#[cfg(madsim)]
+pub use self::sim::*;
+
+#[cfg(madsim)]
+mod sim;
@@ -0,0 +1,56 @@
+pub use uuid::*;
+
+#[cfg(any(feature = "v1", feature = "v6"))]
+pub use self::context::context;
+#[cfg(all(feature = "std", feature = "v1"))]
+pub use self::context::now_v1;
+#[cfg(all(feature = "std", feature = "v6"))]
+pub use self::context::now_v6;
+
+#[cfg(any(feature = "v1", feature = "v6"))]
+mod context {
+    use madsim::{
+        plugin::{simulator, Simulator},
+        rand::{GlobalRng, Rng},
+        time::TimeHandle,
+        Config,
+    };
+    use std::sync::Arc;
+    use uuid::{timestamp::context::Context, Timestamp, Uuid};
+
+    /// Returns the clock sequence context of the current runtime.
+    ///
+    /// Unlike the process-wide context used by `Uuid::now_v1` and `Uuid::now_v6`,
+    /// its counter starts from a value drawn from the seed in each simulation.
+    pub fn context() -> Arc<Context> {
+        simulator::<UuidSim>().context.clone()
+    }
+
+    /// Creates a version 1 UUID from the simulated time and [`context`].
+    ///
+    /// Use it instead of `Uuid::now_v1`, whose process-wide context is shared by simulations.
+    #[cfg(all(feature = "std", feature = "v1"))]
+    pub fn now_v1(node_id: &[u8; 6]) -> Uuid {
+        Uuid::new_v1(Timestamp::now(&*context()), node_id)
+    }
+
+    /// Creates a version 6 UUID from the simulated time and [`context`].
+    ///
+    /// Use it instead of `Uuid::now_v6`, whose process-wide context is shared by simulations.
+    #[cfg(all(feature = "std", feature = "v6"))]
+    pub fn now_v6(node_id: &[u8; 6]) -> Uuid {
+        Uuid::new_v6(Timestamp::now(&*context()), node_id)
+    }
+
+    struct UuidSim {
+        context: Arc<Context>,
+    }
+
+    impl Simulator for UuidSim {
+        fn new(rand: &GlobalRng, _time: &TimeHandle, _config: &Config) -> Self {
+            UuidSim {
+                context: Arc::new(Context::new(rand.clone().gen())),
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#![cfg(madsim)]
+
+use madsim::runtime::Runtime;
+use madsim_uuid::Uuid;
+
+fn run(seed: u64) -> Vec<Uuid> {
+    let runtime = Runtime::with_seed_and_config(seed, madsim::Config::default());
+    let node = runtime.create_node().build();
+    runtime.block_on(async move {
+        node.spawn(async { (0..10).map(|_| Uuid::new_v4()).collect() })
+            .await
+            .unwrap()
+    })
+}
+
+#[test]
+fn deterministic_v4() {
+    assert_eq!(run(1), run(1));
+    assert_ne!(run(1), run(2));
+}
+
+#[cfg(all(feature = "std", feature = "v1"))]
+#[test]
+fn deterministic_v1() {
+    let run = |seed| {
+        let runtime = Runtime::with_seed_and_config(seed, madsim::Config::default());
+        let node = runtime.create_node().build();
+        runtime.block_on(async move {
+            node.spawn(async {
+                (0..10)
+                    .map(|_| madsim_uuid::now_v1(&[1, 2, 3, 4, 5, 6]))
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .unwrap()
+        })
+    };
+    // the clock sequence is not carried over between simulations
+    assert_eq!(run(1), run(1));
+}
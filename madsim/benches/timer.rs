@@ -0,0 +1,68 @@
+//! Benchmarks of the simulated timer.
+//!
+//! Run with `RUSTFLAGS="--cfg madsim" cargo bench --bench timer`.
+//! The cost per timer should stay flat as the number of outstanding timers grows.
+
+#[cfg(madsim)]
+mod sim {
+    use criterion::*;
+    use futures_util::FutureExt;
+    use madsim::{
+        runtime::Runtime,
+        time::{sleep, Duration},
+    };
+
+    const COUNTS: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+    /// Register `n` sleeps with random deadlines, and cancel them.
+    fn insert_cancel(c: &mut Criterion) {
+        let mut group = c.benchmark_group("timer insert and cancel");
+        for n in COUNTS {
+            group.throughput(Throughput::Elements(n as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+                let rt = Runtime::new();
+                b.iter(|| {
+                    rt.block_on(async move {
+                        let mut sleeps = Vec::with_capacity(n);
+                        for i in 0..n {
+                            let mut s =
+                                Box::pin(sleep(Duration::from_micros((1 + i * 7919 % n) as u64)));
+                            assert!((&mut s).now_or_never().is_none());
+                            sleeps.push(s);
+                        }
+                        drop(sleeps);
+                    })
+                });
+            });
+        }
+        group.finish();
+    }
+
+    /// Register `n` sleeps with random deadlines, and fire them all.
+    fn fire(c: &mut Criterion) {
+        let mut group = c.benchmark_group("timer fire");
+        group.sample_size(10);
+        for n in COUNTS {
+            group.throughput(Throughput::Elements(n as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+                let rt = Runtime::new();
+                b.iter(|| {
+                    rt.block_on(async move {
+                        let sleeps =
+                            (0..n).map(|i| sleep(Duration::from_micros((1 + i * 7919 % n) as u64)));
+                        futures_util::future::join_all(sleeps).await;
+                    })
+                });
+            });
+        }
+        group.finish();
+    }
+
+    criterion_group!(benches, insert_cancel, fire);
+}
+
+#[cfg(madsim)]
+criterion::criterion_main!(sim::benches);
+
+#[cfg(not(madsim))]
+fn main() {}
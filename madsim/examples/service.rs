@@ -0,0 +1,40 @@
+use madsim::net::Endpoint;
+use std::{collections::HashMap, sync::Mutex};
+
+#[madsim::service]
+pub trait Kv {
+    async fn get(&self, key: String) -> Option<String>;
+    async fn put(&self, key: String, value: String);
+}
+
+#[derive(Default)]
+struct Server {
+    kv: Mutex<HashMap<String, String>>,
+}
+
+impl Kv for Server {
+    async fn get(&self, key: String) -> Option<String> {
+        self.kv.lock().unwrap().get(&key).cloned()
+    }
+
+    async fn put(&self, key: String, value: String) {
+        self.kv.lock().unwrap().insert(key, value);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Some(addr) = std::env::args().nth(1) {
+        // client
+        let ep = Endpoint::bind("127.0.0.1:0").await.unwrap();
+        let client = KvClient::new(ep, addr.parse().unwrap());
+        client.put("hello".into(), "world".into()).await.unwrap();
+        let value = client.get("hello".into()).await.unwrap();
+        println!("get: {value:?}");
+    } else {
+        // server
+        let ep = Endpoint::bind("127.0.0.1:0").await.unwrap();
+        println!("listening on {}", ep.local_addr().unwrap());
+        KvServer::new(Server::default()).serve_on(ep).await.unwrap();
+    }
+}
@@ -26,4 +26,5 @@ pub use _std::*;
 #[doc(hidden)]
 pub mod export {
     pub use futures_util as futures;
+    pub use serde;
 }
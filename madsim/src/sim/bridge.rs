@@ -0,0 +1,126 @@
+//! A bridge to run futures on a real tokio runtime.
+//!
+//! Some dependencies can not be simulated, e.g. a database running in a container. The bridge
+//! lets a simulated task talk to them through real I/O, while keeping the rest of the
+//! simulation deterministic.
+
+use crate::{
+    rand::Rng,
+    runtime::Handle,
+    task::{allow_blocking, JoinHandle, Spawner},
+    time,
+};
+use futures_util::FutureExt;
+use std::{
+    future::Future,
+    panic::{resume_unwind, AssertUnwindSafe},
+    sync::mpsc,
+};
+
+lazy_static::lazy_static! {
+    /// The real runtime, driven by a dedicated thread.
+    static ref RUNTIME: tokio::runtime::Handle = {
+        let (tx, rx) = mpsc::channel();
+        // system threads can not be spawned from a task
+        crate::context::exit_task(|| {
+            std::thread::Builder::new()
+                .name("madsim-bridge".into())
+                .spawn(move || {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .expect("failed to build the bridge runtime");
+                    _ = tx.send(runtime.handle().clone());
+                    runtime.block_on(std::future::pending::<()>());
+                })
+                .expect("failed to spawn bridge thread")
+        });
+        rx.recv().expect("bridge thread exited")
+    };
+}
+
+/// Runs the future on a real tokio runtime, outside the simulation.
+///
+/// The returned task completes after a simulated duration drawn from
+/// [`Config::blocking_latency`](crate::task::Config::blocking_latency), when it waits for the
+/// future to finish in real time. So the result re-enters the simulation at a deterministic
+/// point no matter how long the future actually takes. The simulation is as deterministic as
+/// the results of the future.
+///
+/// The future runs on another thread. It must not access simulated resources, and it keeps
+/// running if the returned task is aborted or its node is killed.
+///
+/// # Example
+///
+/// ```
+/// # use madsim::runtime::Runtime;
+/// # Runtime::new().block_on(async {
+/// let value = madsim::bridge::spawn_external(async {
+///     // real I/O, e.g. querying a database
+///     1 + 1
+/// })
+/// .await
+/// .unwrap();
+/// assert_eq!(value, 2);
+/// # });
+/// ```
+#[track_caller]
+pub fn spawn_external<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let handle = Handle::current();
+    let latency = handle.config.task.blocking_latency.clone();
+    let delay = handle.rand.with(|rng| rng.gen_range(latency));
+    let (tx, rx) = mpsc::sync_channel(1);
+    RUNTIME.spawn(async move {
+        _ = tx.send(AssertUnwindSafe(future).catch_unwind().await);
+    });
+    Spawner::current().spawn(async move {
+        time::sleep(delay).await;
+        match allow_blocking(|| rx.recv()).expect("bridge thread exited") {
+            Ok(value) => value,
+            Err(payload) => resume_unwind(payload),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::{panic::catch_unwind, time::Duration};
+
+    #[test]
+    fn real_time() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let t0 = time::Instant::now();
+            let value = spawn_external(async {
+                tokio::task::yield_now().await;
+                // takes real time but no simulated time
+                std::thread::sleep(Duration::from_millis(10));
+                42
+            })
+            .await
+            .unwrap();
+            assert_eq!(value, 42);
+            assert!(t0.elapsed() < Duration::from_millis(1));
+        });
+    }
+
+    #[test]
+    fn panic() {
+        let runtime = Runtime::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(async {
+                spawn_external(async { panic!("external panic") })
+                    .await
+                    .unwrap();
+            })
+        }));
+        let err = result.unwrap_err();
+        assert_eq!(panic_message::panic_message(&err), "external panic");
+    }
+}
@@ -1,7 +1,27 @@
 //! Buggify allows you to cooperate with the simulator to inject failures.
 //!
 //! Learn more: <https://transactional.blog/simulation/buggify>
+//!
+//! # Fault points
+//!
+//! The [`buggify!`](crate::buggify!) macro marks a fault point in the code. As in
+//! FoundationDB, each fault point is either activated or not for the whole run, decided by
+//! the seed. An activated fault point fires with the given probability, 25% by default.
+//!
+//! ```
+//! # async fn read(_: &mut [u8]) {}
+//! # async fn f() {
+//! let mut buf = vec![0; 4096];
+//! if madsim::buggify!() {
+//!     // exercise short reads
+//!     buf.truncate(1);
+//! }
+//! read(&mut buf).await;
+//! # }
+//! ```
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use tracing::info;
 
 /// Returns true with a probability of 25% if buggify is enabled.
@@ -14,6 +34,47 @@ pub fn buggify_with_prob(probability: f64) -> bool {
     crate::rand::thread_rng().buggify_with_prob(probability)
 }
 
+/// The implementation of [`buggify!`](crate::buggify!) at the location.
+#[doc(hidden)]
+pub fn buggify_at(file: &str, line: u32, column: u32, probability: f64) -> bool {
+    let Some(rand) = crate::rand::try_thread_rng() else {
+        return false;
+    };
+    if !rand.is_buggify_enabled() {
+        return false;
+    }
+    // `DefaultHasher::new` uses fixed keys, so the activation only depends on the seed
+    let mut hasher = DefaultHasher::new();
+    (rand.seed(), file, line, column).hash(&mut hasher);
+    let activated = hasher.finish() % 4 == 0;
+    activated && rand.buggify_with_prob(probability)
+}
+
+/// Returns true if the fault point activated in this run fires.
+///
+/// Returns false when buggify is disabled or outside the simulation.
+///
+/// `buggify!()` fires with a probability of 25%. `buggify!(p)` fires with probability `p`.
+#[macro_export]
+macro_rules! buggify {
+    () => {
+        $crate::buggify!(0.25)
+    };
+    ($probability:expr) => {
+        $crate::buggify::buggify_at(file!(), line!(), column!(), $probability)
+    };
+}
+
+/// Returns true if the fault point activated in this run fires with the probability.
+///
+/// Same as [`buggify!(p)`](crate::buggify!).
+#[macro_export]
+macro_rules! buggify_with_prob {
+    ($probability:expr) => {
+        $crate::buggify!($probability)
+    };
+}
+
 /// Enable buggify.
 pub fn enable() {
     info!("buggify enabled");
@@ -61,7 +122,24 @@ mod tests {
             for _ in 0..10 {
                 assert!(!crate::buggify::buggify());
                 assert!(!crate::buggify::buggify_with_prob(1.0));
+                assert!(!crate::buggify!(1.0));
             }
         });
     }
+
+    #[test]
+    fn fault_point() {
+        let mut activated = 0;
+        for seed in 0..100 {
+            let runtime = Runtime::with_seed_and_config(seed, crate::Config::default());
+            let fired = runtime.block_on(async move {
+                crate::buggify::enable();
+                (0..10).filter(|_| crate::buggify_with_prob!(1.0)).count()
+            });
+            // an activated fault point always fires with probability 1
+            assert!(fired == 0 || fired == 10);
+            activated += (fired == 10) as u32;
+        }
+        assert!((10..40).contains(&activated)); // 25%
+    }
 }
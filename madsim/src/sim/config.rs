@@ -1,11 +1,19 @@
 //! Simulation configuration.
 
 use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
     hash::{Hash, Hasher},
+    io,
+    net::IpAddr,
+    ops::Range,
+    path::Path,
     str::FromStr,
+    time::{Duration, SystemTime},
 };
 
-use crate::net::{self, tcp};
+use crate::net::{self, tcp, LinkConfig, NetProfile, NodeSelector};
+use crate::{fs, rand, task, time};
 use ahash::AHasher;
 use serde::{Deserialize, Serialize};
 
@@ -20,17 +28,776 @@ pub struct Config {
     /// Tcp Configurations
     #[serde(default)]
     pub tcp: tcp::TcpConfig,
+
+    /// Time configurations.
+    #[serde(default)]
+    pub time: time::Config,
+
+    /// Random configurations.
+    #[serde(default)]
+    pub rand: rand::Config,
+
+    /// Task configurations.
+    #[serde(default)]
+    pub task: task::Config,
+
+    /// File system configurations.
+    #[serde(default)]
+    pub fs: fs::Config,
+
+    /// Nodes created when the runtime starts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nodes: Vec<NodeConfig>,
+
+    /// Configuration overrides for links between nodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkRule>,
+
+    /// Faults injected during the simulation.
+    ///
+    /// The nodes of faults must be declared in [`nodes`](Config::nodes).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faults: Vec<Fault>,
+
+    /// The task scheduler.
+    #[serde(default)]
+    pub scheduler: Scheduler,
+
+    /// The wall-clock watchdog. Disabled if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<Watchdog>,
+}
+
+/// A watchdog thread that dumps the state of all tasks if the simulation makes no progress
+/// in real time, for example when a task blocks the thread or loops without yielding.
+///
+/// # Example
+///
+/// ```toml
+/// [watchdog]
+/// timeout = { secs = 60, nanos = 0 }
+/// abort = true
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Watchdog {
+    /// The real time without any task poll after which the watchdog fires.
+    pub timeout: Duration,
+    /// Whether to abort the process after the dump. Otherwise the dump is only printed.
+    #[serde(default)]
+    pub abort: bool,
+}
+
+/// The algorithm to choose the next task to poll.
+///
+/// # Example
+///
+/// ```toml
+/// [scheduler]
+/// kind = "pct"
+/// depth = 3
+/// steps = 10000
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Scheduler {
+    /// Choose a ready task uniformly at random.
+    #[default]
+    Random,
+    /// Probabilistic concurrency testing.
+    ///
+    /// Each task has a random priority and the ready task with the highest priority is polled.
+    /// At `depth - 1` random steps, the priority of the polled task is lowered below all others.
+    /// A bug that needs `d` ordering constraints is found with a probability of at least
+    /// `1 / (k * n^(d-1))` for `k` tasks and `n` steps, if `depth >= d`.
+    ///
+    /// A step is a poll of a task. Since a task is polled until it yields, a task waiting in a
+    /// busy loop of `yield_now` for a task with lower priority will never make progress.
+    Pct {
+        /// The number of ordering constraints of bugs to find. At least 1.
+        depth: usize,
+        /// The estimated number of polls in a run. Priority change points are chosen from it.
+        steps: u64,
+    },
+}
+
+/// A node created when the runtime starts.
+///
+/// The node has no init function. Get its handle by name with
+/// [`Handle::get_node`](crate::runtime::Handle::get_node) to spawn tasks on it.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct NodeConfig {
+    /// The name of the node.
+    pub name: String,
+    /// The IP address of the node.
+    #[serde(default)]
+    pub ip: Option<IpAddr>,
+    /// The zone of the node. It is added as the label `zone`.
+    #[serde(default)]
+    pub zone: Option<String>,
+    /// Labels of the node.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Environment variables of the node.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Configuration override for links from nodes selected by `src` to nodes selected by `dst`.
+///
+/// See [`NetSim::set_link_config`](crate::net::NetSim::set_link_config).
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Hash, Clone)]
+pub struct LinkRule {
+    /// The source nodes.
+    pub src: NodeSelector,
+    /// The destination nodes.
+    pub dst: NodeSelector,
+    /// The name of a built-in [`NetProfile`] as the base configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// The link configuration. Fields set here override the profile.
+    #[serde(flatten)]
+    pub config: LinkConfig,
+}
+
+impl LinkRule {
+    /// Returns the link configuration with the profile applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the profile is not found.
+    pub fn resolve(&self) -> LinkConfig {
+        match &self.profile {
+            Some(name) => {
+                let profile = NetProfile::from_name(name)
+                    .unwrap_or_else(|| panic!("network profile not found: {name}"));
+                self.config.clone().or(profile.into())
+            }
+            None => self.config.clone(),
+        }
+    }
+}
+
+/// A fault injected at a given time since the simulation starts.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct Fault {
+    /// The time when the fault is injected.
+    pub at: Duration,
+    /// The fault.
+    #[serde(flatten)]
+    pub kind: FaultKind,
+}
+
+/// The kind of fault. Nodes are referred by name.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FaultKind {
+    /// Kill a node.
+    Kill {
+        /// The node name.
+        node: String,
+    },
+    /// Restart a node.
+    Restart {
+        /// The node name.
+        node: String,
+    },
+    /// Pause a node.
+    Pause {
+        /// The node name.
+        node: String,
+    },
+    /// Resume a node.
+    Resume {
+        /// The node name.
+        node: String,
+    },
+    /// Disconnect a node from the network.
+    Clog {
+        /// The node name.
+        node: String,
+    },
+    /// Reconnect a node to the network.
+    Unclog {
+        /// The node name.
+        node: String,
+    },
+    /// Disconnect the link from `src` to `dst`.
+    ClogLink {
+        /// The source node name.
+        src: String,
+        /// The destination node name.
+        dst: String,
+    },
+    /// Reconnect the link from `src` to `dst`.
+    UnclogLink {
+        /// The source node name.
+        src: String,
+        /// The destination node name.
+        dst: String,
+    },
+    /// Step the wall clock of a node forward.
+    ClockForward {
+        /// The node name.
+        node: String,
+        /// The amount of the step.
+        by: Duration,
+    },
+    /// Step the wall clock of a node backward.
+    ClockBackward {
+        /// The node name.
+        node: String,
+        /// The amount of the step.
+        by: Duration,
+    },
+}
+
+impl FaultKind {
+    /// Returns the node names with their field names.
+    fn nodes(&self) -> Vec<(&'static str, &String)> {
+        match self {
+            FaultKind::Kill { node }
+            | FaultKind::Restart { node }
+            | FaultKind::Pause { node }
+            | FaultKind::Resume { node }
+            | FaultKind::Clog { node }
+            | FaultKind::Unclog { node }
+            | FaultKind::ClockForward { node, .. }
+            | FaultKind::ClockBackward { node, .. } => vec![("node", node)],
+            FaultKind::ClogLink { src, dst } | FaultKind::UnclogLink { src, dst } => {
+                vec![("src", src), ("dst", dst)]
+            }
+        }
+    }
 }
 
 impl Config {
+    /// Returns a builder of config.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
     /// Returns the hash value of this config.
     pub fn hash(&self) -> u64 {
         let mut hasher = AHasher::new_with_keys(0, 0);
         Hash::hash(self, &mut hasher);
         hasher.finish()
     }
+
+    /// Load and validate a config from a TOML or YAML file.
+    ///
+    /// The format is determined by the file extension: `.yaml` or `.yml` for YAML,
+    /// and TOML otherwise.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let config: Config = match path.extension().and_then(|s| s.to_str()) {
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            _ => toml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check if the config is valid.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check_loss_rate("net.packet_loss_rate", self.net.packet_loss_rate)?;
+        check_latency("net.send_latency", &self.net.send_latency)?;
+        check_latency("task.blocking_latency", &self.task.blocking_latency)?;
+        if let Some(poll_time) = &self.task.poll_time {
+            check_latency("task.poll_time", poll_time)?;
+        }
+        if !(0.0..1e6).contains(&self.time.clock_drift) {
+            return Err(invalid(
+                "time.clock_drift",
+                format!(
+                    "clock drift {} must be in [0, 1e6) ppm",
+                    self.time.clock_drift
+                ),
+            ));
+        }
+        if let Some(ntp) = &self.time.ntp {
+            if ntp.poll_interval.is_zero() {
+                return Err(invalid(
+                    "time.ntp.poll_interval",
+                    "poll interval must be greater than 0",
+                ));
+            }
+        }
+        if self.time.clock_resolution == Some(Duration::ZERO) {
+            return Err(invalid(
+                "time.clock_resolution",
+                "clock resolution must be greater than 0",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.fs.unsynced_persist_rate) {
+            return Err(invalid(
+                "fs.unsynced_persist_rate",
+                format!(
+                    "unsynced persist rate must be in [0, 1], got {}",
+                    self.fs.unsynced_persist_rate
+                ),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.fs.torn_write_rate) {
+            return Err(invalid(
+                "fs.torn_write_rate",
+                format!(
+                    "torn write rate must be in [0, 1], got {}",
+                    self.fs.torn_write_rate
+                ),
+            ));
+        }
+        if self.fs.sector_size == 0 {
+            return Err(invalid(
+                "fs.sector_size",
+                "sector size must be greater than 0",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.task.spurious_wakeup_rate) {
+            return Err(invalid(
+                "task.spurious_wakeup_rate",
+                format!(
+                    "spurious wakeup rate must be in [0, 1], got {}",
+                    self.task.spurious_wakeup_rate
+                ),
+            ));
+        }
+        let weights = (self.task.node_weights.iter().map(|w| ("node_weights", w)))
+            .chain(self.task.task_weights.iter().map(|w| ("task_weights", w)));
+        for (field, (name, &weight)) in weights {
+            if !(weight.is_finite() && weight >= 0.0) {
+                return Err(invalid(
+                    format!("task.{field}.{name}"),
+                    format!("weight must be a non-negative number, got {weight}"),
+                ));
+            }
+        }
+        if let Some(watchdog) = &self.watchdog {
+            if watchdog.timeout.is_zero() {
+                return Err(invalid(
+                    "watchdog.timeout",
+                    "timeout must be greater than 0",
+                ));
+            }
+        }
+        if let Scheduler::Pct { depth: 0, .. } = self.scheduler {
+            return Err(invalid("scheduler.depth", "depth must be greater than 0"));
+        }
+        let mut names = HashSet::new();
+        let mut ips = HashSet::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !names.insert(&node.name) {
+                return Err(invalid(
+                    format!("nodes[{i}].name"),
+                    format!("duplicate node name {:?}", node.name),
+                ));
+            }
+            if let Some(ip) = node.ip {
+                if !ips.insert(ip) {
+                    return Err(invalid(
+                        format!("nodes[{i}].ip"),
+                        format!("duplicate IP address {ip}"),
+                    ));
+                }
+            }
+        }
+        for (i, fault) in self.faults.iter().enumerate() {
+            for (field, name) in fault.kind.nodes() {
+                if !names.contains(name) {
+                    return Err(invalid(
+                        format!("faults[{i}].{field}"),
+                        format!("node {name:?} is not declared in nodes"),
+                    ));
+                }
+            }
+        }
+        for (i, link) in self.links.iter().enumerate() {
+            if let Some(name) = &link.profile {
+                if NetProfile::from_name(name).is_none() {
+                    let names: Vec<_> = NetProfile::ALL.iter().map(|p| p.name).collect();
+                    return Err(invalid(
+                        format!("links[{i}].profile"),
+                        format!("unknown profile {name:?}, expected one of {names:?}"),
+                    ));
+                }
+            }
+            if let Some(tail) = &link.config.tail_latency {
+                if !(0.0..=1.0).contains(&tail.probability) {
+                    return Err(invalid(
+                        format!("links[{i}].tail_latency.probability"),
+                        format!("probability must be in [0, 1], got {}", tail.probability),
+                    ));
+                }
+                check_latency(&format!("links[{i}].tail_latency.latency"), &tail.latency)?;
+            }
+            if let Some(rate) = link.config.packet_loss_rate {
+                check_loss_rate(&format!("links[{i}].packet_loss_rate"), rate)?;
+            }
+            if let Some(latency) = &link.config.send_latency {
+                check_latency(&format!("links[{i}].send_latency"), latency)?;
+            }
+            if link.config.bandwidth == Some(0) {
+                return Err(invalid(
+                    format!("links[{i}].bandwidth"),
+                    "bandwidth must be positive",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A builder of [`Config`] with validation.
+///
+/// # Example
+///
+/// ```
+/// use madsim::{config::NodeConfig, Config};
+/// use std::time::Duration;
+///
+/// let config = Config::builder()
+///     .packet_loss_rate(0.01)
+///     .send_latency(Duration::from_millis(1)..Duration::from_millis(50))
+///     .node(NodeConfig::new("server").zone("us-east"))
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.net.packet_loss_rate, 0.01);
+///
+/// // invalid configs are rejected
+/// assert!(Config::builder().packet_loss_rate(2.0).build().is_err());
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder with a reliable network: no packet loss and low latency.
+    pub fn reliable() -> Self {
+        Self::new()
+            .packet_loss_rate(0.0)
+            .send_latency(Duration::from_micros(100)..Duration::from_millis(1))
+    }
+
+    /// Creates a builder with an unreliable network: 10% packet loss and high latency variance.
+    pub fn unreliable() -> Self {
+        Self::new()
+            .packet_loss_rate(0.1)
+            .send_latency(Duration::from_millis(1)..Duration::from_millis(200))
+    }
+
+    /// Sets the possibility of packet loss. It must be in `[0, 1]`.
+    pub fn packet_loss_rate(mut self, rate: f64) -> Self {
+        self.config.net.packet_loss_rate = rate;
+        self
+    }
+
+    /// Sets the latency range of sending packets. It must not be empty.
+    pub fn send_latency(mut self, latency: Range<Duration>) -> Self {
+        self.config.net.send_latency = latency;
+        self
+    }
+
+    /// Sets the TCP configurations.
+    pub fn tcp(mut self, tcp: tcp::TcpConfig) -> Self {
+        self.config.tcp = tcp;
+        self
+    }
+
+    /// Sets the maximum skew of node wall clocks.
+    pub fn clock_skew(mut self, skew: Duration) -> Self {
+        self.config.time.clock_skew = skew;
+        self
+    }
+
+    /// Sets the maximum drift rate of node wall clocks in parts per million.
+    pub fn clock_drift(mut self, ppm: f64) -> Self {
+        self.config.time.clock_drift = ppm;
+        self
+    }
+
+    /// Sets the `SystemTime` when the simulation starts.
+    pub fn epoch(mut self, epoch: SystemTime) -> Self {
+        self.config.time.epoch = Some(epoch);
+        self
+    }
+
+    /// Lets calls to `getrandom` from the crate receive real entropy.
+    pub fn real_entropy(mut self, crate_name: impl Into<String>) -> Self {
+        self.config.rand.real_entropy.push(crate_name.into());
+        self
+    }
+
+    /// Sets how to report entropy sources that break determinism.
+    pub fn entropy_audit(mut self, mode: rand::EntropyAudit) -> Self {
+        self.config.rand.entropy_audit = mode;
+        self
+    }
+
+    /// Sets the resolution of `Instant::now` and `SystemTime::now`.
+    pub fn clock_resolution(mut self, resolution: Duration) -> Self {
+        self.config.time.clock_resolution = Some(resolution);
+        self
+    }
+
+    /// Enables the simulated NTP service.
+    pub fn ntp(mut self, ntp: time::NtpConfig) -> Self {
+        self.config.time.ntp = Some(ntp);
+        self
+    }
+
+    /// Sets the probability of waking a task spuriously after it returns `Pending`.
+    pub fn spurious_wakeup_rate(mut self, rate: f64) -> Self {
+        self.config.task.spurious_wakeup_rate = rate;
+        self
+    }
+
+    /// Sets the number of resource operations a task can perform in one poll before it is
+    /// forced to yield. 0 means unlimited.
+    pub fn budget(mut self, budget: u32) -> Self {
+        self.config.task.budget = budget;
+        self
+    }
+
+    /// Sets the probability that each unsynced write is persisted anyway on power failure.
+    /// See [`fs::Config::unsynced_persist_rate`].
+    pub fn unsynced_persist_rate(mut self, rate: f64) -> Self {
+        self.config.fs.unsynced_persist_rate = rate;
+        self
+    }
+
+    /// Sets the probability that the last unsynced write to a file is torn on power failure.
+    /// See [`fs::Config::torn_write_rate`].
+    pub fn torn_write_rate(mut self, rate: f64) -> Self {
+        self.config.fs.torn_write_rate = rate;
+        self
+    }
+
+    /// Sets the write cache of disks. See [`fs::WriteCache`].
+    pub fn write_cache(mut self, cache: fs::WriteCache) -> Self {
+        self.config.fs.write_cache = cache;
+        self
+    }
+
+    /// Sets how to report access to the real file system. See [`fs::RealFsAudit`].
+    pub fn real_fs_audit(mut self, mode: fs::RealFsAudit) -> Self {
+        self.config.fs.real_fs_audit = mode;
+        self
+    }
+
+    /// Sets the order of entries returned by `read_dir`. See [`fs::ReadDirOrder`].
+    pub fn read_dir_order(mut self, order: fs::ReadDirOrder) -> Self {
+        self.config.fs.read_dir_order = order;
+        self
+    }
+
+    /// Sets when changes of directory entries become durable. See [`fs::DirSync`].
+    pub fn dir_sync(mut self, dir_sync: fs::DirSync) -> Self {
+        self.config.fs.dir_sync = dir_sync;
+        self
+    }
+
+    /// Emits instrumentation for tokio-console. See [`task::Config::console`].
+    pub fn console(mut self) -> Self {
+        self.config.task.console = true;
+        self
+    }
+
+    /// Sets the range of simulated CPU time of each poll. See [`task::Config::poll_time`].
+    pub fn poll_time(mut self, time: Range<Duration>) -> Self {
+        self.config.task.poll_time = Some(time);
+        self
+    }
+
+    /// Sets the maximum number of task polls.
+    pub fn max_steps(mut self, steps: u64) -> Self {
+        self.config.task.max_steps = Some(steps);
+        self
+    }
+
+    /// Sets the maximum simulated time.
+    pub fn max_time(mut self, time: Duration) -> Self {
+        self.config.task.max_time = Some(time);
+        self
+    }
+
+    /// Sets the range of simulated durations of blocking operations. It must not be empty.
+    pub fn blocking_latency(mut self, latency: Range<Duration>) -> Self {
+        self.config.task.blocking_latency = latency;
+        self
+    }
+
+    /// Sets the scheduling weight of the node with the given name.
+    pub fn node_weight(mut self, name: impl Into<String>, weight: f64) -> Self {
+        self.config.task.node_weights.insert(name.into(), weight);
+        self
+    }
+
+    /// Sets the scheduling weight of tasks with the given name.
+    pub fn task_weight(mut self, name: impl Into<String>, weight: f64) -> Self {
+        self.config.task.task_weights.insert(name.into(), weight);
+        self
+    }
+
+    /// Enables the wall-clock watchdog.
+    pub fn watchdog(mut self, timeout: Duration, abort: bool) -> Self {
+        self.config.watchdog = Some(Watchdog { timeout, abort });
+        self
+    }
+
+    /// Sets the task scheduler.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.config.scheduler = scheduler;
+        self
+    }
+
+    /// Adds a node created when the runtime starts.
+    pub fn node(mut self, node: NodeConfig) -> Self {
+        self.config.nodes.push(node);
+        self
+    }
+
+    /// Overrides the configuration of links from nodes selected by `src` to nodes selected by `dst`.
+    pub fn link(
+        mut self,
+        src: impl Into<NodeSelector>,
+        dst: impl Into<NodeSelector>,
+        config: impl Into<LinkConfig>,
+    ) -> Self {
+        self.config.links.push(LinkRule {
+            src: src.into(),
+            dst: dst.into(),
+            profile: None,
+            config: config.into(),
+        });
+        self
+    }
+
+    /// Injects a fault at the given time since the simulation starts.
+    pub fn fault(mut self, at: Duration, kind: FaultKind) -> Self {
+        self.config.faults.push(Fault { at, kind });
+        self
+    }
+
+    /// Validates and builds the config.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl NodeConfig {
+    /// Creates a node config with the given name.
+    pub fn new(name: impl Into<String>) -> Self {
+        NodeConfig {
+            name: name.into(),
+            ip: None,
+            zone: None,
+            labels: BTreeMap::new(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the IP address of the node.
+    pub fn ip(mut self, ip: IpAddr) -> Self {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Sets the zone of the node.
+    pub fn zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// Sets a label of the node.
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets an environment variable of the node.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+}
+
+fn check_loss_rate(field: &str, rate: f64) -> Result<(), ConfigError> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(invalid(
+            field,
+            format!("packet loss rate must be in [0, 1], got {rate}"),
+        ));
+    }
+    Ok(())
+}
+
+fn check_latency(field: &str, latency: &Range<Duration>) -> Result<(), ConfigError> {
+    if latency.start >= latency.end {
+        return Err(invalid(
+            field,
+            format!(
+                "min latency {:?} must be less than max latency {:?}",
+                latency.start, latency.end
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn invalid(field: impl Into<String>, msg: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        field: field.into(),
+        msg: msg.into(),
+    }
+}
+
+/// An error when loading or validating a config.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// Failed to read the config file.
+    Io(io::Error),
+    /// Failed to parse the config file.
+    Parse(String),
+    /// The config is invalid.
+    Invalid {
+        /// The path of the invalid field.
+        field: String,
+        /// The error message.
+        msg: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config file: {e}"),
+            ConfigError::Parse(e) => write!(f, "failed to parse config file: {e}"),
+            ConfigError::Invalid { field, msg } => write!(f, "invalid config `{field}`: {msg}"),
+        }
+    }
 }
 
+impl std::error::Error for ConfigError {}
+
 /// Parse a config from TOML.
 impl FromStr for Config {
     type Err = toml::de::Error;
@@ -59,7 +826,7 @@ mod tests {
         [net]
         packet_loss_rate = 0.1
         send_latency = { start = { secs = 0, nanos = 1000000 }, end = { secs = 0, nanos = 10000000 } }
-        
+
         [tcp]
         "#
         .parse()
@@ -69,10 +836,167 @@ mod tests {
             Config {
                 net: net::Config {
                     packet_loss_rate: 0.1,
-                    send_latency: Duration::from_millis(1)..Duration::from_millis(10)
+                    send_latency: Duration::from_millis(1)..Duration::from_millis(10),
+                    ..Default::default()
+                },
+                tcp: tcp::TcpConfig {},
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_scenario() {
+        let config: Config = r#"
+        [[nodes]]
+        name = "server"
+        ip = "10.0.0.1"
+        zone = "us-east"
+
+        [[nodes]]
+        name = "client"
+        ip = "10.0.0.2"
+        labels = { role = "client" }
+        env = { LOG_LEVEL = "debug" }
+
+        [[links]]
+        src = { label = { key = "zone", value = "us-*" } }
+        dst = "any"
+        packet_loss_rate = 0.5
+        bandwidth = 1000
+
+        [[faults]]
+        at = { secs = 10, nanos = 0 }
+        action = "clog_link"
+        src = "server"
+        dst = "client"
+        "#
+        .parse()
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.nodes[0].zone.as_deref(), Some("us-east"));
+        assert_eq!(config.nodes[1].labels["role"], "client");
+        assert_eq!(config.nodes[1].env["LOG_LEVEL"], "debug");
+        assert_eq!(
+            config.links[0],
+            LinkRule {
+                src: NodeSelector::label("zone", "us-*"),
+                dst: NodeSelector::Any,
+                profile: None,
+                config: LinkConfig {
+                    packet_loss_rate: Some(0.5),
+                    bandwidth: Some(1000),
+                    ..Default::default()
+                },
+            }
+        );
+        assert_eq!(
+            config.faults[0],
+            Fault {
+                at: Duration::from_secs(10),
+                kind: FaultKind::ClogLink {
+                    src: "server".into(),
+                    dst: "client".into(),
                 },
-                tcp: tcp::TcpConfig {}
             }
         );
     }
+
+    #[test]
+    fn apply() {
+        let config: Config = r#"
+        [[nodes]]
+        name = "server"
+        ip = "10.0.0.1"
+
+        [[faults]]
+        at = { secs = 1, nanos = 0 }
+        action = "kill"
+        node = "server"
+        "#
+        .parse()
+        .unwrap();
+        let runtime = crate::runtime::Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async {
+            let handle = crate::runtime::Handle::current();
+            let node = handle.get_node("server").unwrap();
+            node.spawn(std::future::pending::<()>());
+            crate::time::sleep(Duration::from_millis(500)).await;
+            assert!(!handle.is_exit("server"));
+            crate::time::sleep(Duration::from_secs(1)).await;
+            assert!(handle.is_exit("server"));
+        });
+    }
+
+    #[test]
+    fn builder() {
+        let config = ConfigBuilder::unreliable()
+            .node(NodeConfig::new("a").ip([10, 0, 0, 1].into()))
+            .node(NodeConfig::new("b").zone("z1").label("role", "client"))
+            .fault(
+                Duration::from_secs(1),
+                FaultKind::Kill {
+                    node: "a".to_string(),
+                },
+            )
+            .build()
+            .unwrap();
+        assert_eq!(config.net.packet_loss_rate, 0.1);
+        assert_eq!(config.nodes[1].labels["role"], "client");
+        assert_eq!(config.faults.len(), 1);
+
+        let err = Config::builder()
+            .node(NodeConfig::new("a"))
+            .node(NodeConfig::new("a"))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config `nodes[1].name`: duplicate node name \"a\""
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let mut config = Config::default();
+        config.net.send_latency = Duration::from_millis(10)..Duration::from_millis(1);
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config `net.send_latency`: min latency 10ms must be less than max latency 1ms"
+        );
+
+        let mut config = Config::default();
+        config.links.push(LinkRule {
+            src: NodeSelector::Any,
+            dst: NodeSelector::Any,
+            profile: None,
+            config: LinkConfig {
+                packet_loss_rate: Some(1.5),
+                ..Default::default()
+            },
+        });
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config `links[0].packet_loss_rate`: packet loss rate must be in [0, 1], got 1.5"
+        );
+
+        let err = Config::builder()
+            .node(NodeConfig::new("a"))
+            .fault(
+                Duration::from_secs(1),
+                FaultKind::ClogLink {
+                    src: "a".into(),
+                    dst: "b".into(),
+                },
+            )
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid config `faults[0].dst`: node \"b\" is not declared in nodes"
+        );
+    }
 }
@@ -0,0 +1,32 @@
+//! Interesting-state hooks for guided seed exploration.
+//!
+//! When tests are run with `MADSIM_TEST_GUIDED`, runs that reach states not seen before are
+//! kept in a corpus. New runs are derived from them by keeping the random decisions up to the
+//! novel state and reseeding afterwards, so the search goes deeper instead of sampling each
+//! run independently. See [`Builder::from_env`](crate::runtime::Builder::from_env).
+//!
+//! # Example
+//!
+//! ```
+//! # #[derive(Hash)] enum Role { Leader }
+//! # let (term, role) = (1, Role::Leader);
+//! // mark the state reached by a consensus node
+//! madsim::coverage::hit((term, role));
+//! ```
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// Records that the current run has reached the state.
+///
+/// States are compared by their hashes. It does nothing outside the simulation.
+pub fn hit(state: impl Hash) {
+    let Some(rng) = crate::rand::try_thread_rng() else {
+        return;
+    };
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    rng.hit(hasher.finish());
+}
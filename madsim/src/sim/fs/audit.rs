@@ -0,0 +1,261 @@
+//! Detection of real file system access.
+
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, ffi::CStr};
+
+/// How to report access to the real file system from simulated tasks, e.g. by [`std::fs`].
+///
+/// Such access escapes the simulated file system, so it is neither isolated between nodes nor
+/// rolled back on crash, and makes runs depend on the state of the host. Use [`crate::fs`]
+/// instead. Calls are reported but not redirected.
+///
+/// The following libc functions are detected on Linux: `open`, `open64`, `opendir`, `mkdir`,
+/// `unlink`, `unlinkat`, `rmdir`, `rename` and `statx`, including `statx` invoked by `syscall`.
+/// Paths under `/dev`, `/proc` and `/sys` are ignored. Paths relative to a directory file
+/// descriptor, as passed to `unlinkat` and `statx`, are reported as they are.
+///
+/// Other functions are not detected, e.g. `openat`, `stat` and `readlink`. Reading a directory
+/// that is already open is not detected either.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RealFsAudit {
+    /// Do not report.
+    #[default]
+    Off,
+    /// Log a warning with the backtrace.
+    Warn,
+    /// Panic with the backtrace.
+    ///
+    /// The libc function returns normally, and the task panics after the poll.
+    Panic,
+}
+
+thread_local! {
+    /// Whether a report is in progress. Capturing the backtrace opens files itself.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+    /// The report of an access in the current poll, in panic mode.
+    static REPORT: Cell<Option<String>> = const { Cell::new(None) };
+    /// The number of accesses reported on this thread.
+    static REPORTED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Takes the report of an access made in the current poll, which fails the task.
+pub(crate) fn take_report() -> Option<String> {
+    REPORT.with(|report| report.take())
+}
+
+/// Reports an access to `path` by the libc function `func` inside a simulated task.
+///
+/// # Safety
+///
+/// `path` must be null or a valid C string.
+pub(crate) unsafe fn audit(func: &str, path: *const libc::c_char) {
+    if path.is_null() || crate::context::try_current_task().is_none() {
+        return;
+    }
+    let Some(mode) = crate::context::try_current(|h| h.config.fs.real_fs_audit) else {
+        return;
+    };
+    if mode == RealFsAudit::Off || REPORTING.with(|r| r.replace(true)) {
+        return;
+    }
+    let path = String::from_utf8_lossy(CStr::from_ptr(path).to_bytes());
+    let ignored = ["/dev/", "/proc/", "/sys/"];
+    if !ignored.iter().any(|p| path.starts_with(p)) {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        let report =
+            format!("real file system accessed by `{func}({path:?})` in simulation\n{backtrace}");
+        REPORTED.with(|n| n.set(n.get() + 1));
+        match mode {
+            RealFsAudit::Off => {}
+            RealFsAudit::Warn => tracing::warn!("{report}"),
+            // panicking here would unwind through `extern "C"` and abort. keep the first one
+            RealFsAudit::Panic => REPORT.with(|r| {
+                let first = r.take().unwrap_or(report);
+                r.set(Some(first));
+            }),
+        }
+    }
+    REPORTING.with(|r| r.set(false));
+}
+
+macro_rules! intercept {
+    ($(#[$attr:meta])* fn $name:ident($path:ident $(, $arg:ident: $ty:ty)*) -> $ret:ty) => {
+        $(#[$attr])*
+        ///
+        /// # Safety
+        ///
+        #[doc = concat!("Same as `", stringify!($name), "`.")]
+        #[cfg(target_os = "linux")]
+        #[no_mangle]
+        #[inline(never)]
+        unsafe extern "C" fn $name($path: *const libc::c_char $(, $arg: $ty)*) -> $ret {
+            audit(stringify!($name), $path);
+            lazy_static::lazy_static! {
+                static ref REAL: unsafe extern "C" fn(*const libc::c_char $(, $ty)*) -> $ret = unsafe {
+                    let name = concat!(stringify!($name), "\0");
+                    let ptr = libc::dlsym(libc::RTLD_NEXT, name.as_ptr() as _);
+                    assert!(!ptr.is_null());
+                    std::mem::transmute(ptr)
+                };
+            }
+            REAL($path $(, $arg)*)
+        }
+    };
+}
+
+intercept! {
+    /// Override the libc `opendir` function to audit it. For `std::fs::read_dir`.
+    fn opendir(path) -> *mut libc::DIR
+}
+
+intercept! {
+    /// Override the libc `mkdir` function to audit it. For `std::fs::create_dir`.
+    fn mkdir(path, mode: libc::mode_t) -> libc::c_int
+}
+
+intercept! {
+    /// Override the libc `unlink` function to audit it. For `std::fs::remove_file`.
+    fn unlink(path) -> libc::c_int
+}
+
+intercept! {
+    /// Override the libc `rmdir` function to audit it. For `std::fs::remove_dir`.
+    fn rmdir(path) -> libc::c_int
+}
+
+intercept! {
+    /// Override the libc `rename` function to audit it. For `std::fs::rename`.
+    fn rename(path, new: *const libc::c_char) -> libc::c_int
+}
+
+/// Override the libc `unlinkat` function to audit it. For `std::fs::remove_dir_all`.
+///
+/// # Safety
+///
+/// Same as `unlinkat`.
+#[cfg(target_os = "linux")]
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn unlinkat(
+    dirfd: libc::c_int,
+    path: *const libc::c_char,
+    flags: libc::c_int,
+) -> libc::c_int {
+    audit("unlinkat", path);
+    lazy_static::lazy_static! {
+        static ref UNLINKAT: unsafe extern "C" fn(
+            libc::c_int,
+            *const libc::c_char,
+            libc::c_int,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"unlinkat\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    UNLINKAT(dirfd, path, flags)
+}
+
+/// Override the libc `statx` function to audit it. For `std::fs::metadata`.
+///
+/// # Safety
+///
+/// Same as `statx`.
+#[cfg(target_os = "linux")]
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn statx(
+    dirfd: libc::c_int,
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mask: libc::c_uint,
+    buf: *mut libc::statx,
+) -> libc::c_int {
+    audit("statx", path);
+    lazy_static::lazy_static! {
+        static ref STATX: unsafe extern "C" fn(
+            libc::c_int,
+            *const libc::c_char,
+            libc::c_int,
+            libc::c_uint,
+            *mut libc::statx,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"statx\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    STATX(dirfd, path, flags, mask, buf)
+}
+
+/// Audits file system calls invoked by `syscall`, which std uses for `statx`.
+///
+/// # Safety
+///
+/// The arguments must be valid for the system call.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn audit_syscall(num: libc::c_long, a2: libc::c_long) {
+    if num == libc::SYS_statx {
+        audit("statx", a2 as _);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    fn runtime(mode: RealFsAudit) -> Runtime {
+        let mut config = crate::Config::default();
+        config.fs.real_fs_audit = mode;
+        Runtime::with_seed_and_config(1, config)
+    }
+
+    fn reported() -> u64 {
+        REPORTED.with(|n| n.get())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn warn_on_access() {
+        let dir = std::env::temp_dir().join("madsim-real-fs-audit");
+        runtime(RealFsAudit::Warn).block_on(async move {
+            let n = reported();
+            std::fs::create_dir_all(&dir).unwrap();
+            assert!(reported() > n, "mkdir is not reported");
+            let n = reported();
+            std::fs::write(dir.join("file"), b"data").unwrap();
+            assert!(reported() > n, "open is not reported");
+            let n = reported();
+            std::fs::read_dir(&dir).unwrap();
+            assert!(reported() > n, "opendir is not reported");
+            let n = reported();
+            std::fs::remove_dir_all(&dir).unwrap();
+            assert!(reported() > n, "remove_dir_all is not reported");
+            // ignored
+            let n = reported();
+            std::fs::read_to_string("/proc/self/stat").unwrap();
+            assert_eq!(reported(), n);
+        });
+        assert!(!dir.exists());
+        // not in a task
+        let n = reported();
+        std::fs::metadata(std::env::temp_dir()).unwrap();
+        assert_eq!(reported(), n);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[should_panic(
+        expected = "real file system accessed by `unlink(\"/tmp/madsim-real-fs-audit-missing\")`"
+    )]
+    fn panic_on_access() {
+        runtime(RealFsAudit::Panic).block_on(async {
+            // the call returns, and the task panics after the poll
+            let res = std::fs::remove_file("/tmp/madsim-real-fs-audit-missing");
+            assert!(res.is_err());
+        });
+    }
+}
@@ -0,0 +1,148 @@
+//! Disk performance model.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    hash::{Hash, Hasher},
+    io::Result,
+    ops::Range,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+use super::{IoFault, IoOp};
+
+use crate::{
+    net::TailLatency,
+    rand::GlobalRng,
+    time::{Sleep, TimeHandle},
+};
+
+/// Performance parameters of a disk.
+///
+/// A disk serves one operation at a time in the order they are submitted, so concurrent
+/// operations on a node queue up behind each other. By default, all operations are instant.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// The latency range of each operation before transferring data, e.g. the seek time.
+    pub latency: Range<Duration>,
+    /// The throughput in bytes per second, or `None` if unlimited.
+    ///
+    /// The transfer time of the data is added to the latency.
+    pub throughput: Option<u64>,
+    /// The maximum number of operations per second, or `None` if unlimited.
+    pub iops: Option<u64>,
+    /// The latency range of syncing a file or directory.
+    pub sync_latency: Range<Duration>,
+    /// The latency of slow syncs.
+    ///
+    /// By default, there is no tail latency.
+    pub sync_tail_latency: Option<TailLatency>,
+    /// The capacity in bytes, or `None` if unlimited.
+    ///
+    /// Writes that would grow the total size of files beyond it fail with `ENOSPC`.
+    pub capacity: Option<u64>,
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for DiskConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.latency.hash(state);
+        self.throughput.hash(state);
+        self.iops.hash(state);
+        self.sync_latency.hash(state);
+        self.sync_tail_latency.hash(state);
+        self.capacity.hash(state);
+    }
+}
+
+/// An operation on the disk.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Io {
+    /// Reads or writes `n` bytes. Operations on metadata transfer no data.
+    Data(u64),
+    /// Syncs a file or directory.
+    Sync,
+}
+
+/// The disk of a node.
+pub(super) struct Disk {
+    pub config: DiskConfig,
+    /// Injected I/O errors.
+    pub faults: Vec<IoFault>,
+    rand: GlobalRng,
+    time: TimeHandle,
+    /// The time when all submitted operations complete.
+    busy_until: Option<Instant>,
+}
+
+impl Disk {
+    pub fn new(config: DiskConfig, rand: &GlobalRng, time: &TimeHandle) -> Self {
+        Disk {
+            config,
+            faults: vec![],
+            rand: rand.clone(),
+            time: time.clone(),
+            busy_until: None,
+        }
+    }
+
+    /// Submits an operation. Returns a future that completes with the operation, or `None` if
+    /// the operation completes immediately.
+    pub fn submit(&mut self, io: Io) -> Option<Sleep> {
+        let service = self.service_time(io);
+        if service.is_zero() {
+            return None;
+        }
+        let now = self.time.now_instant();
+        let start = self.busy_until.map_or(now, |t| t.max(now));
+        let end = start + service;
+        self.busy_until = Some(end);
+        Some(self.time.sleep_until(end))
+    }
+
+    /// Returns an error if an injected fault fires on the operation.
+    pub fn inject(&self, op: IoOp, path: &Path) -> Result<()> {
+        for fault in self.faults.iter().filter(|f| f.matches(op, path)) {
+            if self.rand.with(|rng| rng.gen_bool(fault.probability)) {
+                debug!(?op, ?path, errno = fault.errno, "inject I/O error");
+                return Err(fault.error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the time to serve the operation.
+    fn service_time(&self, io: Io) -> Duration {
+        let config = &self.config;
+        let service = match io {
+            Io::Data(len) => {
+                let transfer = config.throughput.map_or(Duration::ZERO, |tp| {
+                    Duration::from_nanos((len as u128 * 1_000_000_000 / tp.max(1) as u128) as u64)
+                });
+                self.sample(&config.latency) + transfer
+            }
+            Io::Sync => match &config.sync_tail_latency {
+                Some(tail) if self.rand.with(|rng| rng.gen_bool(tail.probability)) => {
+                    self.sample(&tail.latency)
+                }
+                _ => self.sample(&config.sync_latency),
+            },
+        };
+        // each operation occupies at least one slot of the IOPS budget
+        let slot = (config.iops).map_or(Duration::ZERO, |iops| {
+            Duration::from_nanos(1_000_000_000 / iops.max(1))
+        });
+        service.max(slot)
+    }
+
+    fn sample(&self, range: &Range<Duration>) -> Duration {
+        if range.is_empty() {
+            return range.start;
+        }
+        self.rand.with(|rng| rng.gen_range(range.clone()))
+    }
+}
@@ -0,0 +1,110 @@
+//! I/O error injection.
+
+use std::{io::Error, path::Path, time::Duration};
+
+/// A policy that makes operations on matching paths fail at random.
+///
+/// # Example
+///
+/// ```ignore
+/// // reads of SST files fail with EIO 1% of the time
+/// fs.add_io_fault(node, IoFault::new("*.sst", libc::EIO).ops([IoOp::Read]).probability(0.01));
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IoFault {
+    /// The pattern of paths with `*` wildcards, which also match `/`.
+    ///
+    /// The pattern matches the whole path, so use a leading `*` to match any directory.
+    pub path: String,
+    /// The operations that fail. Empty means all operations.
+    pub ops: Vec<IoOp>,
+    /// The probability that each operation fails.
+    pub probability: f64,
+    /// The OS error code of the failures, e.g. `EIO`.
+    pub errno: i32,
+}
+
+impl IoFault {
+    /// Creates a policy that makes all operations on `path` fail with `errno`.
+    pub fn new(path: impl Into<String>, errno: i32) -> Self {
+        IoFault {
+            path: path.into(),
+            ops: vec![],
+            probability: 1.0,
+            errno,
+        }
+    }
+
+    /// Sets the operations that fail.
+    pub fn ops(mut self, ops: impl IntoIterator<Item = IoOp>) -> Self {
+        self.ops = ops.into_iter().collect();
+        self
+    }
+
+    /// Sets the probability that each operation fails.
+    pub fn probability(mut self, probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "invalid probability: {probability}"
+        );
+        self.probability = probability;
+        self
+    }
+
+    /// Returns whether the policy applies to the operation on the path.
+    pub(super) fn matches(&self, op: IoOp, path: &Path) -> bool {
+        (self.ops.is_empty() || self.ops.contains(&op))
+            && crate::net::wildcard_match(&self.path, &path.to_string_lossy())
+    }
+
+    pub(super) fn error(&self) -> Error {
+        Error::from_raw_os_error(self.errno)
+    }
+}
+
+/// Operations of the file system that can fail.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IoOp {
+    /// Opening or creating a file.
+    Open,
+    /// Reading a file.
+    Read,
+    /// Writing or truncating a file.
+    Write,
+    /// Syncing a file or directory.
+    Sync,
+    /// Creating, removing or renaming a file or directory. The path of a rename is the source.
+    Namespace,
+}
+
+/// Silent corruption of data at rest, injected by [`FsSim::rot`](super::FsSim::rot).
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitRot {
+    /// The pattern of paths with `*` wildcards, which also match `/`.
+    pub path: String,
+    /// The number of bits to flip.
+    pub bits: usize,
+    /// Only bytes not written within this duration are corrupted.
+    pub min_age: Option<Duration>,
+}
+
+impl BitRot {
+    /// Creates a corruption that flips `bits` random bits in files matching `path`.
+    pub fn new(path: impl Into<String>, bits: usize) -> Self {
+        BitRot {
+            path: path.into(),
+            bits,
+            min_age: None,
+        }
+    }
+
+    /// Only corrupts bytes not written within `min_age`.
+    pub fn min_age(mut self, min_age: Duration) -> Self {
+        self.min_age = Some(min_age);
+        self
+    }
+}
@@ -0,0 +1,2296 @@
+//! Asynchronous file system.
+//!
+//! # Crash model
+//!
+//! Each node has its own file system, which survives [`kill`](crate::runtime::Handle::kill) and
+//! [`restart`](crate::runtime::Handle::restart) of the node, while all in-memory state of the
+//! node is lost. Killing a node is a power failure: the content of each file is rolled back to
+//! the last [`File::sync_all`], and writes that were not synced are lost, unless the page cache
+//! happened to write them back. See [`Config::unsynced_persist_rate`]. The last write before the
+//! crash may also be torn, see [`Config::torn_write_rate`].
+//!
+//! Creating, removing and renaming files are durable immediately by default. With
+//! [`DirSync::Required`], they are durable only after the containing directory is synced, by
+//! opening the directory with [`File::open`] and calling [`File::sync_all`] on it.
+//!
+//! By default, data is stable once it reaches the disk. With a volatile write cache in the disk,
+//! data may be lost even after a sync, see [`WriteCache`].
+//!
+//! Files opened with `O_DIRECT` by [`OpenOptions::custom_flags`] bypass the page cache: buffers,
+//! offsets and lengths of reads and writes must be aligned to [`Config::sector_size`], and
+//! writes are persisted on disk when they complete.
+//!
+//! # Performance
+//!
+//! File operations take simulated time according to the [`DiskConfig`] of the node, and
+//! operations on the same node contend for its disk. Queries of metadata and directory
+//! entries are served from memory and are instant.
+
+use rand::{
+    seq::{index::sample, SliceRandom},
+    Rng,
+};
+use serde::{Deserialize, Serialize};
+use spin::{Mutex, RwLock};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::OsString,
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result, SeekFrom},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tracing::*;
+
+use crate::{
+    plugin::{node, simulator, Simulator},
+    rand::GlobalRng,
+    task::NodeId,
+    time::{Instant, Sleep, TimeHandle},
+};
+
+pub(crate) use self::audit::audit as audit_real_fs;
+#[cfg(target_os = "linux")]
+pub(crate) use self::audit::audit_syscall as audit_real_fs_syscall;
+pub(crate) use self::audit::take_report as take_real_fs_report;
+pub use self::audit::RealFsAudit;
+pub use self::disk::DiskConfig;
+use self::disk::{Disk, Io};
+pub use self::fault::{BitRot, IoFault, IoOp};
+
+mod audit;
+mod disk;
+mod fault;
+
+/// File system configurations.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Config {
+    /// The probability that each write not synced is persisted anyway on power failure.
+    ///
+    /// The page cache may write back dirty data at any time, so after a crash, each write
+    /// since the last sync independently may or may not be on disk. 0 means all of them are lost.
+    #[serde(default)]
+    pub unsynced_persist_rate: f64,
+    /// The probability that the last unsynced write to a file is torn on power failure.
+    ///
+    /// A torn write persists only part of its data: either a prefix of random length, or a
+    /// random subset of the sectors it covers. Otherwise the write is persisted or lost as a
+    /// whole like other unsynced writes.
+    #[serde(default)]
+    pub torn_write_rate: f64,
+    /// The sector size of the disk in bytes, which is the unit of torn writes.
+    #[serde(default = "default_sector_size")]
+    pub sector_size: u64,
+    /// When changes of directory entries become durable.
+    #[serde(default)]
+    pub dir_sync: DirSync,
+    /// The order of entries returned by [`read_dir`].
+    #[serde(default)]
+    pub read_dir_order: ReadDirOrder,
+    /// The write cache of disks.
+    #[serde(default)]
+    pub write_cache: WriteCache,
+    /// How to report access to the real file system from simulated tasks.
+    #[serde(default)]
+    pub real_fs_audit: RealFsAudit,
+    /// The disk of each node, unless overridden by [`FsSim::set_disk`].
+    #[serde(default)]
+    pub disk: DiskConfig,
+}
+
+/// When changes of directory entries, i.e. creating, removing and renaming files and
+/// directories, become durable.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DirSync {
+    /// Changes are durable immediately.
+    #[default]
+    Implicit,
+    /// Changes are durable only after the containing directory is synced. Otherwise they are
+    /// rolled back on power failure.
+    Required,
+}
+
+/// The write cache of a disk, which decides when file contents reach stable storage.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteCache {
+    /// Data is stable once it reaches the disk, including writes written back by the page cache
+    /// and direct writes.
+    #[default]
+    WriteThrough,
+    /// The disk has a volatile cache, which is flushed on sync. Only synced data survives power
+    /// failure, except unsynced writes persisted by chance like in the page cache.
+    /// See [`Config::unsynced_persist_rate`] and [`Config::torn_write_rate`].
+    WriteBack,
+    /// The disk has a volatile cache and ignores flushes, so synced data is also lost on power
+    /// failure until the cache is destaged by [`FsSim::flush_disk_cache`].
+    Volatile,
+}
+
+/// The order of entries returned by [`read_dir`].
+///
+/// Real file systems do not guarantee any order, so code should not rely on it.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadDirOrder {
+    /// In the order of file names.
+    #[default]
+    Sorted,
+    /// In a random order drawn from the seed on each call.
+    Shuffled,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            unsynced_persist_rate: 0.0,
+            torn_write_rate: 0.0,
+            sector_size: default_sector_size(),
+            dir_sync: DirSync::default(),
+            read_dir_order: ReadDirOrder::default(),
+            write_cache: WriteCache::default(),
+            real_fs_audit: RealFsAudit::default(),
+            disk: DiskConfig::default(),
+        }
+    }
+}
+
+const fn default_sector_size() -> u64 {
+    512
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for Config {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.unsynced_persist_rate.to_bits().hash(state);
+        self.torn_write_rate.to_bits().hash(state);
+        self.sector_size.hash(state);
+        self.dir_sync.hash(state);
+        self.read_dir_order.hash(state);
+        self.write_cache.hash(state);
+        self.real_fs_audit.hash(state);
+        self.disk.hash(state);
+    }
+}
+
+/// File system simulator.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub struct FsSim {
+    rand: GlobalRng,
+    time: TimeHandle,
+    config: Config,
+    handles: Mutex<HashMap<NodeId, FsNodeHandle>>,
+}
+
+impl Simulator for FsSim {
+    fn new(rand: &GlobalRng, time: &TimeHandle, config: &crate::Config) -> Self {
+        FsSim {
+            rand: rand.clone(),
+            time: time.clone(),
+            config: config.fs.clone(),
+            handles: Default::default(),
+        }
+    }
+
+    fn create_node(&self, id: NodeId) {
+        let mut handles = self.handles.lock();
+        let disk = Disk::new(self.config.disk.clone(), &self.rand, &self.time);
+        handles.insert(id, FsNodeHandle::new(&self.config, disk));
+    }
+
+    fn reset_node(&self, id: NodeId) {
+        self.power_fail(id);
+    }
+}
+
+impl FsSim {
+    /// Get [`FsSim`] of the current simulator.
+    pub fn current() -> Arc<Self> {
+        simulator()
+    }
+
+    /// Set the disk of the node.
+    ///
+    /// Operations submitted before this call are not affected.
+    pub fn set_disk(&self, id: NodeId, config: DiskConfig) {
+        self.get_node(id).disk.lock().config = config;
+    }
+
+    /// Set the capacity of the disk of the node in bytes, or `None` if unlimited.
+    ///
+    /// Shrinking the capacity below the used space does not remove any data, but further
+    /// writes that grow files fail with `ENOSPC`.
+    pub fn set_disk_capacity(&self, id: NodeId, capacity: Option<u64>) {
+        self.get_node(id).disk.lock().config.capacity = capacity;
+    }
+
+    /// Inject I/O errors to operations on the node.
+    ///
+    /// Policies are checked in the order they are added, and the first one that fires decides
+    /// the error. They are kept across restarts of the node.
+    pub fn add_io_fault(&self, id: NodeId, fault: IoFault) {
+        self.get_node(id).disk.lock().faults.push(fault);
+    }
+
+    /// Remove all I/O errors injected by [`add_io_fault`](FsSim::add_io_fault) on the node.
+    pub fn clear_io_faults(&self, id: NodeId) {
+        self.get_node(id).disk.lock().faults.clear();
+    }
+
+    /// Flip random bits in files on the node, both in the page cache and on disk, so that
+    /// reading them back returns corrupted data without any error.
+    ///
+    /// Returns the number of bits flipped, which is less than requested if there are not
+    /// enough bytes to corrupt.
+    pub fn rot(&self, id: NodeId, rot: BitRot) -> usize {
+        let handle = self.get_node(id);
+        let fs = handle.fs.lock();
+        let now = self.time.now_instant();
+        let written_before = rot.min_age.and_then(|age| now.checked_sub(age));
+        let mut files: Vec<_> = (fs.files.iter())
+            .filter(|(path, _)| crate::net::wildcard_match(&rot.path, &path.to_string_lossy()))
+            .map(|(path, inode)| (path, inode.stale_ranges(written_before)))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let total: u64 = files
+            .iter()
+            .flat_map(|(_, ranges)| ranges)
+            .map(|r| r.end - r.start)
+            .sum();
+        if total == 0 {
+            return 0;
+        }
+        let bits = rot.bits.min(total as usize * 8);
+        // distinct bits, so that none is flipped back
+        let positions = (self.rand).with(|rng| sample(rng, total as usize * 8, bits));
+        for pos in positions {
+            let (mut index, bit) = (pos as u64 / 8, (pos % 8) as u8);
+            for (path, ranges) in &files {
+                let Some(range) = ranges.iter().find(|r| {
+                    let len = r.end - r.start;
+                    let found = index < len;
+                    if !found {
+                        index -= len;
+                    }
+                    found
+                }) else {
+                    continue;
+                };
+                let offset = range.start + index;
+                trace!(?path, offset, bit, "flip bit");
+                fs.files[*path].flip_bit(offset, bit);
+                break;
+            }
+        }
+        debug!(node = %id, bits, "bit rot");
+        bits
+    }
+
+    /// Destage the volatile write cache of the disk of the node, so that data synced before this
+    /// call survives power failure. See [`WriteCache::Volatile`].
+    pub fn flush_disk_cache(&self, id: NodeId) {
+        self.get_node(id).fs.lock().flush_cache();
+    }
+
+    /// Take a snapshot of the file system of the node.
+    ///
+    /// The snapshot contains the files and directories visible at this moment, including
+    /// writes that have not been synced.
+    pub fn snapshot(&self, id: NodeId) -> FsSnapshot {
+        self.get_node(id).fs.lock().snapshot()
+    }
+
+    /// Replace the file system of the node with the snapshot, which may be taken from any node.
+    ///
+    /// All content of the snapshot is persisted on disk. Files opened before the restore are
+    /// detached from the file system, so it is best done while the node is killed.
+    pub fn restore(&self, id: NodeId, snapshot: &FsSnapshot) {
+        self.get_node(id).fs.lock().restore(snapshot);
+        debug!(node = %id, files = snapshot.files.len(), "restore file system");
+    }
+
+    /// Get the total size of files on the node in bytes.
+    pub fn used_space(&self, id: NodeId) -> u64 {
+        self.get_node(id).fs.lock().used_space()
+    }
+
+    /// Return a handle of the specified node.
+    fn get_node(&self, id: NodeId) -> FsNodeHandle {
+        let handles = self.handles.lock();
+        handles[&id].clone()
+    }
+
+    /// Simulate a power failure. All data that does not reach the disk will be lost.
+    pub fn power_fail(&self, id: NodeId) {
+        let handle = self.get_node(id);
+        let mut fs = handle.fs.lock();
+        fs.power_fail();
+        let mut paths: Vec<_> = fs.files.keys().collect();
+        paths.sort();
+        for path in paths {
+            fs.files[path].power_fail(&self.rand, &self.config);
+        }
+        debug!(node = %id, files = fs.files.len(), "power failure");
+    }
+
+    /// Get the size of given file.
+    pub fn get_file_size(&self, node: NodeId, path: impl AsRef<Path>) -> Result<u64> {
+        let path = path.as_ref();
+        let handle = self.get_node(node);
+        let fs = handle.fs.lock();
+        Ok(fs.file(path)?.metadata().len())
+    }
+}
+
+/// File system simulator for a node.
+#[derive(Clone)]
+struct FsNodeHandle {
+    fs: Arc<Mutex<FileSystem>>,
+    disk: Arc<Mutex<Disk>>,
+}
+
+/// The file system of a node.
+///
+/// The root directory always exists and is not stored.
+#[derive(Default)]
+struct FileSystem {
+    /// Files by path.
+    files: HashMap<PathBuf, Arc<INode>>,
+    /// Directories by path.
+    dirs: BTreeSet<PathBuf>,
+    /// Directory entries persisted on disk. `None` if changes are durable immediately.
+    durable: Option<Entries>,
+    /// The alignment of direct I/O.
+    sector_size: u64,
+    write_cache: WriteCache,
+}
+
+/// Directory entries.
+#[derive(Default, Clone)]
+struct Entries {
+    files: HashMap<PathBuf, Arc<INode>>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+/// A snapshot of the file system of a node. Created by [`FsSim::snapshot`].
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsSnapshot {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl FsSnapshot {
+    /// Returns the content of the file in the snapshot.
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<&[u8]> {
+        self.files
+            .get(&normalize(path.as_ref()))
+            .map(|data| &data[..])
+    }
+
+    /// Returns the paths of files in the snapshot, in order.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(|path| path.as_path())
+    }
+}
+
+/// Normalizes the path by removing `.` and redundant separators.
+fn normalize(path: &Path) -> PathBuf {
+    (path.components())
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("no such file or directory: {path:?}"),
+    )
+}
+
+fn already_exists(path: &Path) -> Error {
+    Error::new(ErrorKind::AlreadyExists, format!("file exists: {path:?}"))
+}
+
+impl FileSystem {
+    fn is_root(path: &Path) -> bool {
+        path.parent().is_none()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        Self::is_root(path) || self.dirs.contains(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_dir(path) || self.files.contains_key(path)
+    }
+
+    fn file(&self, path: &Path) -> Result<&Arc<INode>> {
+        let path = normalize(path);
+        if self.is_dir(&path) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("is a directory: {path:?}"),
+            ));
+        }
+        self.files.get(&path).ok_or_else(|| not_found(&path))
+    }
+
+    /// Checks that the parent directory of the path exists.
+    fn check_parent(&self, path: &Path) -> Result<()> {
+        match path.parent() {
+            Some(parent) if !self.is_dir(parent) => Err(not_found(parent)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the paths of files and directories in the directory.
+    fn children(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut children: Vec<_> = (self.files.keys())
+            .chain(self.dirs.iter())
+            .filter(|p| p.parent() == Some(dir))
+            .cloned()
+            .collect();
+        children.sort();
+        children
+    }
+
+    fn open(&mut self, path: &Path, opts: &OpenOptions, handle: &FsNodeHandle) -> Result<File> {
+        let path = normalize(path);
+        if opts.create_new && self.exists(&path) {
+            return Err(already_exists(&path));
+        }
+        if self.is_dir(&path) {
+            if opts.write || opts.append {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("is a directory: {path:?}"),
+                ));
+            }
+            return Ok(File {
+                inode: Arc::new(INode::new(&path)),
+                handle: handle.clone(),
+                is_dir: true,
+                can_write: false,
+                append: false,
+                direct: None,
+                pos: Mutex::new(0),
+                delay: None,
+            });
+        }
+        let inode = match self.files.get(&path) {
+            Some(inode) => inode.clone(),
+            None if opts.create || opts.create_new => {
+                if self.is_dir(&path) {
+                    return Err(already_exists(&path));
+                }
+                self.check_parent(&path)?;
+                let inode = Arc::new(INode::new(&path));
+                self.files.insert(path.clone(), inode.clone());
+                inode
+            }
+            None => return Err(self.file(&path).err().unwrap()),
+        };
+        if opts.truncate {
+            inode.truncate();
+        }
+        Ok(File {
+            inode,
+            handle: handle.clone(),
+            is_dir: false,
+            can_write: opts.write || opts.append,
+            append: opts.append,
+            direct: (opts.custom_flags & O_DIRECT != 0).then_some(self.sector_size),
+            pos: Mutex::new(0),
+            delay: None,
+        })
+    }
+
+    fn create_dir(&mut self, path: &Path) -> Result<()> {
+        let path = normalize(path);
+        if self.exists(&path) {
+            return Err(already_exists(&path));
+        }
+        self.check_parent(&path)?;
+        self.dirs.insert(path);
+        Ok(())
+    }
+
+    fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        let path = normalize(path);
+        for dir in path.ancestors().filter(|p| !Self::is_root(p)) {
+            if self.files.contains_key(dir) {
+                return Err(already_exists(dir));
+            }
+        }
+        let mut dirs: Vec<_> = path.ancestors().filter(|p| !Self::is_root(p)).collect();
+        dirs.reverse();
+        for dir in dirs {
+            self.dirs.insert(dir.into());
+        }
+        Ok(())
+    }
+
+    fn remove_file(&mut self, path: &Path) -> Result<()> {
+        let path = normalize(path);
+        self.file(&path)?;
+        self.files.remove(&path);
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, path: &Path, recursive: bool) -> Result<()> {
+        let path = normalize(path);
+        if !self.dirs.contains(&path) {
+            if self.files.contains_key(&path) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("not a directory: {path:?}"),
+                ));
+            }
+            return Err(not_found(&path));
+        }
+        if !recursive && !self.children(&path).is_empty() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("directory not empty: {path:?}"),
+            ));
+        }
+        self.files.retain(|p, _| !p.starts_with(&path));
+        self.dirs.retain(|p| !p.starts_with(&path));
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        if !self.exists(&from) || Self::is_root(&from) {
+            return Err(not_found(&from));
+        }
+        self.check_parent(&to)?;
+        if from == to {
+            return Ok(());
+        }
+        if let Some(inode) = self.files.get(&from).cloned() {
+            if self.is_dir(&to) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("is a directory: {to:?}"),
+                ));
+            }
+            self.files.remove(&from);
+            self.files.insert(to, inode);
+            return Ok(());
+        }
+        // rename a directory
+        if to.starts_with(&from) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("can not move {from:?} into itself"),
+            ));
+        }
+        if self.files.contains_key(&to) || !self.children(&to).is_empty() {
+            return Err(already_exists(&to));
+        }
+        let moved = |p: &Path| to.join(p.strip_prefix(&from).unwrap());
+        let files: Vec<_> = (self.files.keys())
+            .filter(|p| p.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in files {
+            let inode = self.files.remove(&path).unwrap();
+            self.files.insert(moved(&path), inode);
+        }
+        let dirs: Vec<_> = (self.dirs.iter())
+            .filter(|p| p.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in dirs {
+            self.dirs.remove(&path);
+            self.dirs.insert(moved(&path));
+        }
+        Ok(())
+    }
+
+    /// Persists the entries of the directory.
+    fn sync_dir(&mut self, dir: &Path) {
+        let Some(durable) = &mut self.durable else {
+            return;
+        };
+        trace!(?dir, "sync directory");
+        let in_dir = |p: &Path| p.parent() == Some(dir);
+        durable.files.retain(|p, _| !in_dir(p));
+        durable.dirs.retain(|p| !in_dir(p));
+        for (path, inode) in self.files.iter().filter(|(p, _)| in_dir(p)) {
+            durable.files.insert(path.clone(), inode.clone());
+        }
+        for path in self.dirs.iter().filter(|p| in_dir(p)) {
+            durable.dirs.insert(path.clone());
+        }
+    }
+
+    /// Rolls back directory entries that have not been persisted.
+    fn power_fail(&mut self) {
+        let Some(durable) = &self.durable else {
+            return;
+        };
+        // entries are lost if any of their ancestors is lost.
+        // parents are visited before children in order.
+        let mut dirs = BTreeSet::new();
+        for dir in &durable.dirs {
+            if dir
+                .parent()
+                .map_or(true, |p| Self::is_root(p) || dirs.contains(p))
+            {
+                dirs.insert(dir.clone());
+            }
+        }
+        let files: HashMap<_, _> = (durable.files.iter())
+            .filter(|(p, _)| {
+                p.parent()
+                    .map_or(true, |p| Self::is_root(p) || dirs.contains(p))
+            })
+            .map(|(p, inode)| (p.clone(), inode.clone()))
+            .collect();
+        self.files = files.clone();
+        self.dirs = dirs.clone();
+        self.durable = Some(Entries { files, dirs });
+    }
+
+    fn snapshot(&self) -> FsSnapshot {
+        FsSnapshot {
+            files: (self.files.iter())
+                .map(|(path, inode)| (path.clone(), inode.data.read().clone()))
+                .collect(),
+            dirs: self.dirs.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &FsSnapshot) {
+        self.files = (snapshot.files.iter())
+            .map(|(path, data)| {
+                let inode = INode::new(path);
+                *inode.data.write() = data.clone();
+                *inode.synced.write() = data.clone();
+                (path.clone(), Arc::new(inode))
+            })
+            .collect();
+        self.dirs = snapshot.dirs.clone();
+        if self.durable.is_some() {
+            self.durable = Some(Entries {
+                files: self.files.clone(),
+                dirs: self.dirs.clone(),
+            });
+        }
+    }
+
+    /// Destages the volatile write cache of the disk.
+    fn flush_cache(&self) {
+        for inode in self.files.values() {
+            inode.flush_cache();
+        }
+    }
+
+    /// Returns the total size of files.
+    fn used_space(&self) -> u64 {
+        self.files
+            .values()
+            .map(|inode| inode.metadata().len())
+            .sum()
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let path = normalize(path);
+        if self.is_dir(&path) {
+            return Ok(Metadata {
+                len: 0,
+                is_dir: true,
+            });
+        }
+        Ok(self.file(&path)?.metadata())
+    }
+}
+
+impl FsNodeHandle {
+    fn new(config: &Config, disk: Disk) -> Self {
+        let fs = FileSystem {
+            durable: (config.dir_sync == DirSync::Required).then(Entries::default),
+            sector_size: config.sector_size,
+            write_cache: config.write_cache,
+            ..Default::default()
+        };
+        FsNodeHandle {
+            fs: Arc::new(Mutex::new(fs)),
+            disk: Arc::new(Mutex::new(disk)),
+        }
+    }
+
+    fn current() -> Self {
+        simulator::<FsSim>().get_node(node())
+    }
+
+    /// Checks that there is enough space to grow the file to `len` bytes.
+    fn reserve(&self, inode: &INode, len: u64) -> Result<()> {
+        let Some(capacity) = self.disk.lock().config.capacity else {
+            return Ok(());
+        };
+        let size = inode.metadata().len();
+        if len <= size {
+            return Ok(());
+        }
+        let used = self.fs.lock().used_space();
+        if used + (len - size) > capacity {
+            trace!(path = ?inode.path, used, capacity, "no space left on device");
+            return Err(Error::from_raw_os_error(libc::ENOSPC));
+        }
+        Ok(())
+    }
+
+    /// Returns an error if an injected fault fires on the operation.
+    fn inject(&self, op: IoOp, path: &Path) -> Result<()> {
+        self.disk.lock().inject(op, &normalize(path))
+    }
+
+    /// Performs an operation on the disk.
+    async fn io(&self, io: Io) {
+        if UNTIMED.with(|untimed| untimed.get()) {
+            return;
+        }
+        let delay = self.disk.lock().submit(io);
+        if let Some(delay) = delay {
+            delay.await;
+        }
+    }
+}
+
+struct INode {
+    path: PathBuf,
+    /// The content seen by readers, including writes in the page cache.
+    data: RwLock<Vec<u8>>,
+    /// The content persisted on disk.
+    synced: RwLock<Vec<u8>>,
+    /// Modifications since the last sync, in order.
+    pending: Mutex<Vec<Modification>>,
+    /// Modifications in the volatile write cache of the disk, in order.
+    cached: Mutex<Vec<Modification>>,
+    /// The time of the last write of disjoint byte ranges, by start offset.
+    written: Mutex<BTreeMap<u64, (u64, Instant)>>,
+}
+
+/// A modification of file content.
+enum Modification {
+    Write { offset: u64, data: Vec<u8> },
+    SetLen(u64),
+}
+
+impl Modification {
+    /// Returns the parts of the modification that are persisted if it is torn.
+    fn tear(&self, sector_size: u64, rng: &mut impl Rng) -> Vec<Modification> {
+        let Modification::Write { offset, data } = self else {
+            // metadata updates are atomic
+            return vec![];
+        };
+        if data.is_empty() {
+            return vec![];
+        }
+        if rng.gen_bool(0.5) {
+            // a prefix
+            let len = rng.gen_range(0..data.len());
+            if len == 0 {
+                return vec![];
+            }
+            return vec![Modification::Write {
+                offset: *offset,
+                data: data[..len].to_vec(),
+            }];
+        }
+        // a subset of sectors
+        let end = offset + data.len() as u64;
+        let mut parts = vec![];
+        let mut start = *offset;
+        while start < end {
+            let next = ((start / sector_size + 1) * sector_size).min(end);
+            if rng.gen_bool(0.5) {
+                parts.push(Modification::Write {
+                    offset: start,
+                    data: data[(start - offset) as usize..(next - offset) as usize].to_vec(),
+                });
+            }
+            start = next;
+        }
+        parts
+    }
+
+    /// Returns whether the modification may change bytes in the range.
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        match self {
+            Modification::Write { offset, data } => {
+                *offset < range.end && range.start < offset + data.len() as u64
+            }
+            // size changes are kept in order with all writes
+            Modification::SetLen(_) => true,
+        }
+    }
+
+    fn apply(&self, data: &mut Vec<u8>) {
+        match self {
+            Modification::Write { offset, data: buf } => {
+                let offset = *offset as usize;
+                if data.len() < offset + buf.len() {
+                    data.resize(offset + buf.len(), 0);
+                }
+                data[offset..offset + buf.len()].copy_from_slice(buf);
+            }
+            Modification::SetLen(len) => data.resize(*len as usize, 0),
+        }
+    }
+}
+
+impl INode {
+    fn new(path: &Path) -> Self {
+        INode {
+            path: path.into(),
+            data: RwLock::new(Vec::new()),
+            synced: RwLock::new(Vec::new()),
+            pending: Mutex::new(Vec::new()),
+            cached: Mutex::new(Vec::new()),
+            written: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Writes back the page cache to disk.
+    fn sync(&self, cache: WriteCache) {
+        let mut pending = self.pending.lock();
+        match cache {
+            WriteCache::WriteThrough | WriteCache::WriteBack => {
+                self.synced.write().clone_from(&self.data.read());
+                pending.clear();
+                self.cached.lock().clear();
+            }
+            WriteCache::Volatile => self.cached.lock().append(&mut pending),
+        }
+    }
+
+    /// Destages the volatile write cache of the disk.
+    fn flush_cache(&self) {
+        let mut synced = self.synced.write();
+        for m in self.cached.lock().drain(..) {
+            m.apply(&mut synced);
+        }
+    }
+
+    /// Discards modifications that have not been persisted, except those written back or
+    /// destaged by chance. The last one may be torn.
+    fn power_fail(&self, rand: &GlobalRng, config: &Config) {
+        let chance = |rate: f64| rate > 0.0 && rand.with(|rng| rng.gen_bool(rate));
+        let mut synced = self.synced.write();
+        // writes in the volatile cache of the disk come before those in the page cache
+        let mut unstable = std::mem::take(&mut *self.cached.lock());
+        unstable.append(&mut self.pending.lock());
+        let last = unstable.len().wrapping_sub(1);
+        for (i, m) in unstable.into_iter().enumerate() {
+            if i == last && chance(config.torn_write_rate) {
+                trace!(path = ?self.path, "torn write");
+                for part in rand.with(|rng| m.tear(config.sector_size, rng)) {
+                    part.apply(&mut synced);
+                }
+            } else if chance(config.unsynced_persist_rate) {
+                m.apply(&mut synced);
+            }
+        }
+        self.data.write().clone_from(&synced);
+    }
+
+    fn modify(&self, m: Modification) {
+        m.apply(&mut self.data.write());
+        self.pending.lock().push(m);
+    }
+
+    fn truncate(&self) {
+        self.modify(Modification::SetLen(0));
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            len: self.data.read().len() as u64,
+            is_dir: false,
+        }
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> usize {
+        let data = self.data.read();
+        let offset = (offset as usize).min(data.len());
+        let end = data.len().min(offset + buf.len());
+        let len = end - offset;
+        buf[..len].copy_from_slice(&data[offset..end]);
+        len
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) {
+        self.modify(Modification::Write {
+            offset,
+            data: buf.to_vec(),
+        });
+        self.record_write(offset..offset + buf.len() as u64);
+    }
+
+    /// Writes to disk bypassing the page cache.
+    fn write_direct(&self, buf: &[u8], offset: u64, cache: WriteCache) {
+        let m = Modification::Write {
+            offset,
+            data: buf.to_vec(),
+        };
+        m.apply(&mut self.data.write());
+        // like Linux, which writes back cached data overlapping the write first, along with the
+        // earlier modifications those depend on
+        let mut pending = self.pending.lock();
+        let mut flushed = vec![m];
+        let mut kept = vec![];
+        for p in pending.drain(..).rev() {
+            let range = match &p {
+                Modification::Write { offset, data } => *offset..offset + data.len() as u64,
+                Modification::SetLen(_) => 0..u64::MAX,
+            };
+            if flushed.iter().any(|f| f.overlaps(&range)) {
+                flushed.push(p);
+            } else {
+                kept.push(p);
+            }
+        }
+        pending.extend(kept.into_iter().rev());
+        drop(pending);
+        let flushed = flushed.into_iter().rev();
+        if cache == WriteCache::WriteThrough {
+            let mut synced = self.synced.write();
+            flushed.for_each(|m| m.apply(&mut synced));
+        } else {
+            self.cached.lock().extend(flushed);
+        }
+        self.record_write(offset..offset + buf.len() as u64);
+    }
+
+    /// Records the time of writing the range.
+    fn record_write(&self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let mut written = self.written.lock();
+        // ranges are disjoint, so those overlapping are the last ones starting before the end
+        let overlapping: Vec<_> = (written.range(..range.end).rev())
+            .take_while(|(_, (end, _))| *end > range.start)
+            .map(|(&start, &(end, time))| (start, end, time))
+            .collect();
+        for (start, end, time) in overlapping {
+            written.remove(&start);
+            if start < range.start {
+                written.insert(start, (range.start, time));
+            }
+            if end > range.end {
+                written.insert(range.end, (end, time));
+            }
+        }
+        written.insert(range.start, (range.end, Instant::now()));
+    }
+
+    /// Returns the byte ranges not written after `written_before`, or all bytes if `None`.
+    fn stale_ranges(&self, written_before: Option<Instant>) -> Vec<Range<u64>> {
+        let len = self.data.read().len().min(self.synced.read().len()) as u64;
+        let Some(before) = written_before else {
+            return vec![0..len];
+        };
+        let mut ranges = vec![];
+        let mut pos = 0;
+        for (&start, &(end, time)) in self.written.lock().iter() {
+            if time <= before {
+                continue;
+            }
+            if start > pos {
+                ranges.push(pos..start.min(len));
+            }
+            pos = pos.max(end);
+        }
+        if pos < len {
+            ranges.push(pos..len);
+        }
+        ranges.retain(|r| !r.is_empty());
+        ranges
+    }
+
+    /// Flips a bit both in the page cache and on disk.
+    fn flip_bit(&self, offset: u64, bit: u8) {
+        self.data.write()[offset as usize] ^= 1 << bit;
+        self.synced.write()[offset as usize] ^= 1 << bit;
+    }
+}
+
+/// A reference to an open file on the filesystem.
+///
+/// Besides positional reads and writes, it implements [`AsyncRead`], [`AsyncWrite`] and
+/// [`AsyncSeek`] with a cursor, like [`tokio::fs::File`].
+pub struct File {
+    inode: Arc<INode>,
+    handle: FsNodeHandle,
+    is_dir: bool,
+    can_write: bool,
+    append: bool,
+    /// The alignment if opened with `O_DIRECT`.
+    direct: Option<u64>,
+    /// The cursor position.
+    pos: Mutex<u64>,
+    /// The pending disk operation of [`AsyncRead`] or [`AsyncWrite`].
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("File")
+            .field("path", &self.inode.path)
+            .finish()
+    }
+}
+
+impl File {
+    /// Attempts to open a file in read-only mode.
+    pub async fn open(path: impl AsRef<Path>) -> Result<File> {
+        OpenOptions::new().read(true).open(path).await
+    }
+
+    /// Opens a file in write-only mode.
+    ///
+    /// This function will create a file if it does not exist, and will truncate it if it does.
+    pub async fn create(path: impl AsRef<Path>) -> Result<File> {
+        (OpenOptions::new().write(true).create(true).truncate(true))
+            .open(path)
+            .await
+    }
+
+    /// Returns a new [`OpenOptions`] object.
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    /// Reads a number of bytes starting from a given offset.
+    #[instrument(skip(buf), fields(len = buf.len()))]
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.check_file()?;
+        self.check_aligned(buf.as_ptr(), buf.len(), offset)?;
+        self.handle.inject(IoOp::Read, &self.inode.path)?;
+        let len = self.inode.read_at(buf, offset);
+        self.handle.io(Io::Data(len as u64)).await;
+        Ok(len)
+    }
+
+    /// Attempts to write an entire buffer starting from a given offset.
+    #[instrument(skip(buf), fields(len = buf.len()))]
+    pub async fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<()> {
+        self.check_write()?;
+        self.check_aligned(buf.as_ptr(), buf.len(), offset)?;
+        self.handle.inject(IoOp::Write, &self.inode.path)?;
+        // the write takes effect when it completes
+        self.handle.io(Io::Data(buf.len() as u64)).await;
+        (self.handle).reserve(&self.inode, offset + buf.len() as u64)?;
+        self.write_at(buf, offset);
+        Ok(())
+    }
+
+    /// Truncates or extends the underlying file, updating the size of this file to become `size`.
+    #[instrument]
+    pub async fn set_len(&self, size: u64) -> Result<()> {
+        self.check_write()?;
+        self.handle.inject(IoOp::Write, &self.inode.path)?;
+        self.handle.io(Io::Data(0)).await;
+        self.handle.reserve(&self.inode, size)?;
+        self.inode.modify(Modification::SetLen(size));
+        Ok(())
+    }
+
+    /// Attempts to sync all OS-internal metadata to disk.
+    ///
+    /// Data written before this call survives a power failure of the node. If this is a
+    /// directory, its entries are persisted. See [`DirSync`].
+    #[instrument]
+    pub async fn sync_all(&self) -> Result<()> {
+        self.handle.inject(IoOp::Sync, &self.inode.path)?;
+        // data is not durable until the sync completes
+        self.handle.io(Io::Sync).await;
+        self.sync();
+        Ok(())
+    }
+
+    /// This function is similar to [`sync_all`](File::sync_all), except that it may not
+    /// synchronize file metadata to the filesystem.
+    #[instrument]
+    pub async fn sync_data(&self) -> Result<()> {
+        self.handle.inject(IoOp::Sync, &self.inode.path)?;
+        self.handle.io(Io::Sync).await;
+        self.sync();
+        Ok(())
+    }
+
+    /// Queries metadata about the underlying file.
+    #[instrument]
+    pub async fn metadata(&self) -> Result<Metadata> {
+        if self.is_dir {
+            return Ok(Metadata {
+                len: 0,
+                is_dir: true,
+            });
+        }
+        Ok(self.inode.metadata())
+    }
+
+    fn sync(&self) {
+        let mut fs = self.handle.fs.lock();
+        if self.is_dir {
+            fs.sync_dir(&self.inode.path);
+            return;
+        }
+        self.inode.sync(fs.write_cache);
+        // the flush command applies to the whole cache
+        if fs.write_cache == WriteCache::WriteBack {
+            fs.flush_cache();
+        }
+    }
+
+    /// Polls the pending disk operation, submitting `io` if there is none.
+    fn poll_io(&mut self, cx: &mut Context<'_>, io: Io) -> Poll<()> {
+        if self.delay.is_none() {
+            let Some(delay) = self.handle.disk.lock().submit(io) else {
+                return Poll::Ready(());
+            };
+            self.delay = Some(Box::pin(delay));
+        }
+        ready!(self.delay.as_mut().unwrap().as_mut().poll(cx));
+        self.delay = None;
+        Poll::Ready(())
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) {
+        match self.direct {
+            Some(_) => (self.inode).write_direct(buf, offset, self.handle.fs.lock().write_cache),
+            None => self.inode.write_at(buf, offset),
+        }
+    }
+
+    /// Checks the alignment of direct I/O.
+    fn check_aligned(&self, buf: *const u8, len: usize, offset: u64) -> Result<()> {
+        let Some(align) = self.direct else {
+            return Ok(());
+        };
+        if buf as u64 % align != 0 || len as u64 % align != 0 || offset % align != 0 {
+            return Err(Error::from_raw_os_error(libc::EINVAL));
+        }
+        Ok(())
+    }
+
+    fn check_file(&self) -> Result<()> {
+        if self.is_dir {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("is a directory: {:?}", self.inode.path),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_write(&self) -> Result<()> {
+        if !self.can_write {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "the file is read only",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for File {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        this.check_file()?;
+        let unfilled = buf.initialize_unfilled();
+        this.check_aligned(unfilled.as_ptr(), unfilled.len(), *this.pos.lock())?;
+        if this.delay.is_none() {
+            this.handle.inject(IoOp::Read, &this.inode.path)?;
+        }
+        let remaining = (this.inode.metadata().len()).saturating_sub(*this.pos.lock());
+        let len = remaining.min(buf.remaining() as u64);
+        ready!(this.poll_io(cx, Io::Data(len)));
+        let mut pos = this.pos.lock();
+        let len = this.inode.read_at(buf.initialize_unfilled(), *pos);
+        buf.advance(len);
+        *pos += len as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for File {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        this.check_write()?;
+        this.check_aligned(buf.as_ptr(), buf.len(), *this.pos.lock())?;
+        if this.delay.is_none() {
+            this.handle.inject(IoOp::Write, &this.inode.path)?;
+        }
+        ready!(this.poll_io(cx, Io::Data(buf.len() as u64)));
+        let mut pos = this.pos.lock();
+        if this.append {
+            *pos = this.inode.metadata().len();
+        }
+        (this.handle).reserve(&this.inode, *pos + buf.len() as u64)?;
+        this.write_at(buf, *pos);
+        *pos += buf.len() as u64;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for File {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+        let mut pos = self.pos.lock();
+        let new = match position {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.inode.metadata().len().checked_add_signed(n),
+            SeekFrom::Current(n) => pos.checked_add_signed(n),
+        };
+        *pos = new.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<u64>> {
+        Poll::Ready(Ok(*self.pos.lock()))
+    }
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    custom_flags: i32,
+}
+
+#[cfg(target_os = "linux")]
+const O_DIRECT: i32 = libc::O_DIRECT;
+#[cfg(not(target_os = "linux"))]
+const O_DIRECT: i32 = 0;
+
+impl OpenOptions {
+    /// Creates a blank new set of options ready for configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Sets the option for the append mode.
+    ///
+    /// Writes by [`AsyncWrite`] always go to the end of the file.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Sets the option for truncating a previous file.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets the option for creating a new file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option to always create a new file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets the flags passed to `open`.
+    ///
+    /// Only `O_DIRECT` is supported, on Linux. Other flags are ignored.
+    pub fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    /// Opens a file at `path` with the options specified by `self`.
+    pub async fn open(&self, path: impl AsRef<Path>) -> Result<File> {
+        let path = path.as_ref();
+        trace!(?path, opts = ?self, "open file");
+        if !self.read && !self.write && !self.append {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "no access mode is specified",
+            ));
+        }
+        if (self.truncate || self.create || self.create_new) && !(self.write || self.append) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "creating or truncating a file requires write access",
+            ));
+        }
+        let handle = FsNodeHandle::current();
+        handle.inject(IoOp::Open, path)?;
+        let mut fs = handle.fs.lock();
+        fs.open(path, self, &handle)
+    }
+}
+
+/// Read the entire contents of a file into a bytes vector.
+pub async fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Read, path.as_ref())?;
+    let fs = handle.fs.lock();
+    let data = fs.file(path.as_ref())?.data.read().clone();
+    drop(fs);
+    handle.io(Io::Data(data.len() as u64)).await;
+    Ok(data)
+}
+
+/// Read the entire contents of a file into a string.
+pub async fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
+    let data = read(path).await?;
+    String::from_utf8(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Creates a file if it does not exist, and replaces its entire contents with `contents`.
+pub async fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let file = File::create(path).await?;
+    file.write_all_at(contents.as_ref(), 0).await
+}
+
+/// Copies the contents of one file to another, overwriting the destination.
+///
+/// Returns the number of bytes copied.
+pub async fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    let data = read(from).await?;
+    write(to, &data).await?;
+    Ok(data.len() as u64)
+}
+
+/// Given a path, query the file system to get information about a file, directory, etc.
+pub async fn metadata(path: impl AsRef<Path>) -> Result<Metadata> {
+    let handle = FsNodeHandle::current();
+    let fs = handle.fs.lock();
+    fs.metadata(path.as_ref())
+}
+
+/// Returns `Ok(true)` if the path points at an existing entity.
+pub async fn try_exists(path: impl AsRef<Path>) -> Result<bool> {
+    let handle = FsNodeHandle::current();
+    let fs = handle.fs.lock();
+    Ok(fs.exists(&normalize(path.as_ref())))
+}
+
+/// Creates a new, empty directory at the provided path.
+pub async fn create_dir(path: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, path.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.create_dir(path.as_ref())?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+/// Recursively creates a directory and all of its parent components if they are missing.
+pub async fn create_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, path.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.create_dir_all(path.as_ref())?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+/// Removes a file from the filesystem.
+pub async fn remove_file(path: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, path.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.remove_file(path.as_ref())?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+/// Removes an existing, empty directory.
+pub async fn remove_dir(path: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, path.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.remove_dir(path.as_ref(), false)?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+/// Removes a directory at this path, after removing all its contents.
+pub async fn remove_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, path.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.remove_dir(path.as_ref(), true)?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+/// Renames a file or directory to a new name, replacing the original file if `to` already
+/// exists.
+pub async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    let handle = FsNodeHandle::current();
+    handle.inject(IoOp::Namespace, from.as_ref())?;
+    let mut fs = handle.fs.lock();
+    fs.rename(from.as_ref(), to.as_ref())?;
+    drop(fs);
+    handle.io(Io::Data(0)).await;
+    Ok(())
+}
+
+thread_local! {
+    /// Whether operations skip the disk time, inside [`run_untimed`].
+    static UNTIMED: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Runs a file system operation synchronously, without charging its disk time.
+///
+/// For simulators of crates with a blocking file system API. Panics if the operation is not
+/// an operation of this module.
+#[doc(hidden)]
+pub fn run_untimed<T>(f: impl Future<Output = T>) -> T {
+    use futures_util::FutureExt;
+
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            UNTIMED.with(|untimed| untimed.set(self.0));
+        }
+    }
+    let _reset = Reset(UNTIMED.with(|untimed| untimed.replace(true)));
+    f.now_or_never()
+        .expect("file system operation is not synchronous")
+}
+
+/// Returns a stream over the entries within a directory.
+pub async fn read_dir(path: impl AsRef<Path>) -> Result<ReadDir> {
+    let path = normalize(path.as_ref());
+    let handle = FsNodeHandle::current();
+    let fs = handle.fs.lock();
+    if !fs.is_dir(&path) {
+        return Err(fs.file(&path).err().unwrap_or_else(|| {
+            Error::new(ErrorKind::Other, format!("not a directory: {path:?}"))
+        }));
+    }
+    let mut children = fs.children(&path);
+    let sim = simulator::<FsSim>();
+    if sim.config.read_dir_order == ReadDirOrder::Shuffled {
+        sim.rand.with(|rng| children.shuffle(rng));
+    }
+    let entries = children.into_iter().map(|path| DirEntry {
+        path,
+        handle: handle.clone(),
+    });
+    Ok(ReadDir {
+        entries: entries.collect::<Vec<_>>().into_iter(),
+    })
+}
+
+/// Reads the entries in a directory. Created by [`read_dir`].
+///
+/// Entries are returned in the order of file names by default. See [`ReadDirOrder`].
+pub struct ReadDir {
+    entries: std::vec::IntoIter<DirEntry>,
+}
+
+impl ReadDir {
+    /// Returns the next entry in the directory stream.
+    pub async fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        Ok(self.entries.next())
+    }
+}
+
+/// Entries returned by the [`ReadDir`] stream.
+pub struct DirEntry {
+    path: PathBuf,
+    handle: FsNodeHandle,
+}
+
+impl DirEntry {
+    /// Returns the full path to the file that this entry represents.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns the bare file name of this directory entry without any other leading path
+    /// component.
+    pub fn file_name(&self) -> OsString {
+        self.path.file_name().unwrap().to_owned()
+    }
+
+    /// Returns the metadata for the file that this entry points at.
+    pub async fn metadata(&self) -> Result<Metadata> {
+        self.handle.fs.lock().metadata(&self.path)
+    }
+}
+
+/// Metadata information about a file.
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    len: u64,
+    is_dir: bool,
+}
+
+impl Metadata {
+    /// Returns the size of the file, in bytes, this metadata is for.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if this metadata is for a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Returns `true` if this metadata is for a regular file.
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::collections::HashSet;
+
+    #[test]
+    fn create_open_read_write() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let f = node.spawn(async move {
+            assert_eq!(
+                File::open("file").await.err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+            let file = File::create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+
+            let mut buf = [0u8; 10];
+            let read_len = file.read_at(&mut buf, 2).await.unwrap();
+            assert_eq!(read_len, 3);
+            assert_eq!(&buf[..3], b"llo");
+            drop(file);
+
+            // writing to a read-only file should be denied
+            let rofile = File::open("file").await.unwrap();
+            assert_eq!(
+                rofile.write_all_at(b"gg", 0).await.err().unwrap().kind(),
+                ErrorKind::PermissionDenied
+            );
+
+            // create should truncate existing file
+            let file = File::create("file").await.unwrap();
+            let read_len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(read_len, 0);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn persist_unsynced_writes_partially() {
+        let mut outcomes = HashSet::new();
+        for seed in 0..40 {
+            let mut config = crate::Config::default();
+            config.fs.unsynced_persist_rate = 0.5;
+            let runtime = Runtime::with_seed_and_config(seed, config);
+            let node = runtime.create_node().build();
+            let data = runtime.block_on(async move {
+                node.spawn(async move {
+                    let file = File::create("file").await.unwrap();
+                    file.write_all_at(b"a", 0).await.unwrap();
+                    file.sync_data().await.unwrap();
+                    file.write_all_at(b"b", 1).await.unwrap();
+                    file.write_all_at(b"c", 2).await.unwrap();
+                })
+                .await
+                .unwrap();
+                node.kill();
+                node.restart();
+                node.spawn(async { read("file").await.unwrap() })
+                    .await
+                    .unwrap()
+            });
+            assert!(data.starts_with(b"a"));
+            outcomes.insert(data);
+        }
+        // the 2 unsynced writes are persisted independently
+        assert_eq!(outcomes.len(), 4, "{outcomes:?}");
+    }
+
+    #[test]
+    fn torn_write() {
+        let mut outcomes = HashSet::new();
+        for seed in 0..40 {
+            let mut config = crate::Config::default();
+            config.fs.torn_write_rate = 1.0;
+            config.fs.sector_size = 4;
+            let runtime = Runtime::with_seed_and_config(seed, config);
+            let node = runtime.create_node().build();
+            let data = runtime.block_on(async move {
+                node.spawn(async move {
+                    let file = File::create("wal").await.unwrap();
+                    file.write_all_at(b"0000", 0).await.unwrap();
+                    file.sync_all().await.unwrap();
+                    file.write_all_at(b"11112222", 2).await.unwrap();
+                })
+                .await
+                .unwrap();
+                node.kill();
+                node.restart();
+                node.spawn(async { read("wal").await.unwrap() })
+                    .await
+                    .unwrap()
+            });
+            outcomes.insert(data);
+        }
+
+        // persist the ranges of the write on top of the synced data
+        let overlay = |ranges: &[(usize, usize)]| {
+            let full = b"0011112222";
+            let mut data = b"0000".to_vec();
+            for &(start, end) in ranges {
+                data.resize(data.len().max(end), 0);
+                data[start..end].copy_from_slice(&full[start..end]);
+            }
+            data
+        };
+        // prefixes of the write
+        let mut valid: HashSet<_> = (0..8).map(|len| overlay(&[(2, 2 + len)])).collect();
+        // subsets of sectors [2, 4), [4, 8) and [8, 10)
+        let sectors = [(2, 4), (4, 8), (8, 10)];
+        for mask in 0..8 {
+            let ranges: Vec<_> = (0..3)
+                .filter(|i| mask & (1 << i) != 0)
+                .map(|i| sectors[i])
+                .collect();
+            valid.insert(overlay(&ranges));
+        }
+        assert!(outcomes.is_subset(&valid), "{outcomes:?}");
+        assert!(outcomes.len() >= 4, "{outcomes:?}");
+    }
+
+    #[test]
+    fn disk_contention() {
+        use crate::time::Instant;
+        use std::time::Duration;
+
+        let ms = Duration::from_millis;
+        // the executor advances the clock slightly on each poll
+        let assert_elapsed = |t0: Instant, expected: Duration| {
+            let elapsed = t0.elapsed();
+            assert!(
+                elapsed >= expected && elapsed < expected + ms(1),
+                "{elapsed:?}"
+            );
+        };
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime.handle().simulator::<FsSim>().set_disk(
+            node.id(),
+            DiskConfig {
+                latency: ms(1)..ms(1),
+                throughput: Some(1 << 20),
+                iops: Some(100),
+                sync_latency: ms(20)..ms(20),
+                ..Default::default()
+            },
+        );
+        let f = node.spawn(async move {
+            let file = Arc::new(File::create("file").await.unwrap());
+
+            // 1ms latency + 1s transfer
+            let t0 = Instant::now();
+            file.write_all_at(&vec![0; 1 << 20], 0).await.unwrap();
+            assert_elapsed(t0, ms(1001));
+
+            // small writes are limited by IOPS and queue up
+            let t0 = Instant::now();
+            let tasks: Vec<_> = (0..10)
+                .map(|i| {
+                    let file = file.clone();
+                    crate::task::spawn(async move { file.write_all_at(b"x", i).await.unwrap() })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_elapsed(t0, ms(100));
+
+            let t0 = Instant::now();
+            file.sync_all().await.unwrap();
+            assert_elapsed(t0, ms(20));
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn no_space() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let fs = runtime.handle().simulator::<FsSim>();
+        fs.set_disk_capacity(node.id(), Some(10));
+        let enospc = |r: Result<()>| r.unwrap_err().raw_os_error() == Some(libc::ENOSPC);
+
+        runtime
+            .block_on(node.spawn(async move {
+                write("a", b"12345678").await.unwrap();
+                assert!(enospc(write("b", b"1234").await));
+                write("b", b"12").await.unwrap();
+                // overwriting does not take more space
+                let file = OpenOptions::new().write(true).open("a").await.unwrap();
+                file.write_all_at(b"abcd", 4).await.unwrap();
+                assert!(enospc(file.set_len(9).await));
+            }))
+            .unwrap();
+        assert_eq!(fs.used_space(node.id()), 10);
+
+        // shrink the available space
+        fs.set_disk_capacity(node.id(), Some(9));
+        runtime
+            .block_on(node.spawn(async move {
+                assert!(enospc(write("c", b"1").await));
+                remove_file("b").await.unwrap();
+                write("c", b"1").await.unwrap();
+            }))
+            .unwrap();
+    }
+
+    #[test]
+    fn crash_during_sync() {
+        use std::time::Duration;
+
+        let mut config = crate::Config::default();
+        config.fs.disk.sync_latency = Duration::from_millis(10)..Duration::from_millis(11);
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let node = runtime.create_node().build();
+        runtime.block_on(async {
+            node.spawn(async {
+                let file = File::create("wal").await.unwrap();
+                file.write_all_at(b"commit", 0).await.unwrap();
+                file.sync_all().await.unwrap();
+            });
+            crate::time::sleep(Duration::from_millis(5)).await;
+        });
+        // power failure before the sync completes
+        node.kill();
+        node.restart();
+        let data = runtime
+            .block_on(node.spawn(async { read("wal").await.unwrap() }))
+            .unwrap();
+        assert_eq!(data, b"");
+    }
+
+    #[test]
+    fn io_fault() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let fs = runtime.handle().simulator::<FsSim>();
+        fs.add_io_fault(
+            node.id(),
+            IoFault::new("*.sst", libc::EIO).ops([IoOp::Read]),
+        );
+        fs.add_io_fault(
+            node.id(),
+            IoFault::new("data/wal/*", libc::EIO)
+                .ops([IoOp::Write])
+                .probability(0.5),
+        );
+        let eio = |e: Error| e.raw_os_error() == Some(libc::EIO);
+
+        let failed_writes = runtime.block_on(node.spawn(async move {
+            create_dir_all("data/wal").await.unwrap();
+            write("data/1.sst", b"sst").await.unwrap();
+            assert!(eio(read("data/1.sst").await.unwrap_err()));
+            let file = File::open("data/1.sst").await.unwrap();
+            assert!(eio(file.read_at(&mut [0; 3], 0).await.unwrap_err()));
+            // other paths and operations are not affected
+            write("data/1.log", b"log").await.unwrap();
+            assert_eq!(read("data/1.log").await.unwrap(), b"log");
+
+            let file = File::create("data/wal/0001").await.unwrap();
+            let mut failed = 0;
+            for i in 0..100 {
+                if let Err(e) = file.write_all_at(b"x", i).await {
+                    assert!(eio(e));
+                    failed += 1;
+                }
+            }
+            failed
+        }));
+        assert!((20..80).contains(&failed_writes.unwrap()));
+
+        fs.clear_io_faults(node.id());
+        let data = runtime.block_on(node.spawn(async { read("data/1.sst").await.unwrap() }));
+        assert_eq!(data.unwrap(), b"sst");
+    }
+
+    #[test]
+    fn bit_rot() {
+        use std::time::Duration;
+
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let fs = runtime.handle().simulator::<FsSim>();
+        let zeros = vec![0u8; 100];
+        let data = zeros.clone();
+        runtime
+            .block_on(node.spawn(async move {
+                for path in ["old", "other"] {
+                    let file = File::create(path).await.unwrap();
+                    file.write_all_at(&data, 0).await.unwrap();
+                    file.sync_all().await.unwrap();
+                }
+                crate::time::sleep(Duration::from_secs(10)).await;
+                // rewrite the second half
+                let file = OpenOptions::new().write(true).open("old").await.unwrap();
+                file.write_all_at(&data[50..], 50).await.unwrap();
+                file.sync_all().await.unwrap();
+            }))
+            .unwrap();
+
+        let rot = BitRot::new("old", 20).min_age(Duration::from_secs(5));
+        assert_eq!(fs.rot(node.id(), rot), 20);
+        let (old, other) = runtime
+            .block_on(
+                node.spawn(async { (read("old").await.unwrap(), read("other").await.unwrap()) }),
+            )
+            .unwrap();
+        // each bit is flipped at most once
+        let flipped: u32 = old[..50].iter().map(|b| b.count_ones()).sum();
+        assert_eq!(flipped, 20);
+        assert_eq!(old[50..], zeros[50..]);
+        assert_eq!(other, zeros);
+
+        // corruption survives restart
+        node.kill();
+        node.restart();
+        let old1 = runtime
+            .block_on(node.spawn(async { read("old").await.unwrap() }))
+            .unwrap();
+        assert_eq!(old1, old);
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let runtime = Runtime::new();
+        let node1 = runtime.create_node().build();
+        let node2 = runtime.create_node().build();
+        let fs = runtime.handle().simulator::<FsSim>();
+        runtime
+            .block_on(node1.spawn(async {
+                create_dir("data").await.unwrap();
+                write("data/state", b"v1").await.unwrap();
+            }))
+            .unwrap();
+        let backup = fs.snapshot(node1.id());
+        assert_eq!(backup.file("data/state"), Some(&b"v1"[..]));
+        assert_eq!(
+            backup.paths().collect::<Vec<_>>(),
+            [Path::new("data/state")]
+        );
+
+        runtime
+            .block_on(node1.spawn(async {
+                write("data/state", b"v2").await.unwrap();
+                write("data/new", b"").await.unwrap();
+            }))
+            .unwrap();
+
+        // restore into another node, and survive power failure without sync
+        fs.restore(node2.id(), &backup);
+        node2.kill();
+        node2.restart();
+        let state = runtime
+            .block_on(node2.spawn(async { read("data/state").await.unwrap() }))
+            .unwrap();
+        assert_eq!(state, b"v1");
+
+        // restore a stale snapshot into the same node
+        node1.kill();
+        fs.restore(node1.id(), &backup);
+        node1.restart();
+        let exists = runtime
+            .block_on(node1.spawn(async { try_exists("data/new").await.unwrap() }))
+            .unwrap();
+        assert!(!exists);
+    }
+
+    #[test]
+    fn shuffle_read_dir() {
+        let names = |seed| {
+            let config = crate::Config {
+                fs: Config {
+                    read_dir_order: ReadDirOrder::Shuffled,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let runtime = Runtime::with_seed_and_config(seed, config);
+            let node = runtime.create_node().build();
+            runtime
+                .block_on(node.spawn(async {
+                    for i in 0..10 {
+                        write(i.to_string(), b"").await.unwrap();
+                    }
+                    let mut entries = read_dir(".").await.unwrap();
+                    let mut names = vec![];
+                    while let Some(entry) = entries.next_entry().await.unwrap() {
+                        names.push(entry.file_name().into_string().unwrap());
+                    }
+                    names
+                }))
+                .unwrap()
+        };
+        assert_eq!(names(1), names(1));
+        assert_ne!(names(1), names(2));
+        let mut sorted = names(1);
+        sorted.sort();
+        assert_eq!(sorted, ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn direct_io() {
+        #[repr(align(512))]
+        struct Aligned([u8; 1024]);
+
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime
+            .block_on(node.spawn(async {
+                let file = (OpenOptions::new().read(true).write(true).create(true))
+                    .custom_flags(libc::O_DIRECT)
+                    .open("data")
+                    .await
+                    .unwrap();
+                let mut buf = Aligned([1; 1024]);
+                let einval = |e: Error| e.raw_os_error() == Some(libc::EINVAL);
+                assert!(einval(
+                    file.write_all_at(&buf.0[..100], 0).await.unwrap_err()
+                ));
+                assert!(einval(
+                    file.write_all_at(&buf.0[1..513], 0).await.unwrap_err()
+                ));
+                assert!(einval(
+                    file.write_all_at(&buf.0[..512], 1).await.unwrap_err()
+                ));
+                assert!(einval(file.read_at(&mut buf.0[..1], 0).await.unwrap_err()));
+                file.write_all_at(&buf.0, 0).await.unwrap();
+                assert_eq!(file.read_at(&mut buf.0[..512], 512).await.unwrap(), 512);
+
+                // buffered writes are not affected
+                let buffered = OpenOptions::new().write(true).open("data").await.unwrap();
+                buffered.write_all_at(b"x", 1024).await.unwrap();
+            }))
+            .unwrap();
+
+        // direct writes survive power failure without sync
+        node.kill();
+        node.restart();
+        let data = runtime
+            .block_on(node.spawn(async { read("data").await.unwrap() }))
+            .unwrap();
+        assert_eq!(data, [1; 1024]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn direct_io_writes_back_overlapping() {
+        #[repr(align(512))]
+        struct Aligned([u8; 512]);
+
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime
+            .block_on(node.spawn(async {
+                let file = File::create("data").await.unwrap();
+                file.write_all_at(&[2; 1024], 0).await.unwrap();
+                let other = File::create("other").await.unwrap();
+                other.write_all_at(b"x", 0).await.unwrap();
+                let direct = (OpenOptions::new().write(true))
+                    .custom_flags(libc::O_DIRECT)
+                    .open("data")
+                    .await
+                    .unwrap();
+                file.write_all_at(b"y", 1024).await.unwrap();
+                direct.write_all_at(&Aligned([1; 512]).0, 0).await.unwrap();
+            }))
+            .unwrap();
+
+        node.kill();
+        node.restart();
+        let (data, other) = runtime
+            .block_on(
+                node.spawn(async { (read("data").await.unwrap(), read("other").await.unwrap()) }),
+            )
+            .unwrap();
+        // the overlapping buffered write was written back first, the later one was not
+        let mut expected = vec![1; 512];
+        expected.resize(1024, 2);
+        assert_eq!(data, expected);
+        assert_eq!(other, b"");
+    }
+
+    #[test]
+    fn volatile_write_cache() {
+        for flush in [false, true] {
+            let mut config = crate::Config::default();
+            config.fs.write_cache = WriteCache::Volatile;
+            let runtime = Runtime::with_seed_and_config(1, config);
+            let node = runtime.create_node().build();
+            runtime
+                .block_on(node.spawn(async {
+                    let file = File::create("wal").await.unwrap();
+                    file.write_all_at(b"commit", 0).await.unwrap();
+                    file.sync_all().await.unwrap();
+                }))
+                .unwrap();
+            if flush {
+                (runtime.handle().simulator::<FsSim>()).flush_disk_cache(node.id());
+            }
+            node.kill();
+            node.restart();
+            let data = runtime
+                .block_on(node.spawn(async { read("wal").await.unwrap() }))
+                .unwrap();
+            // the sync only reached the volatile cache
+            assert_eq!(data, if flush { &b"commit"[..] } else { b"" });
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn write_back_cache() {
+        #[repr(align(512))]
+        struct Aligned([u8; 512]);
+
+        for rate in [0.0, 1.0] {
+            let mut config = crate::Config::default();
+            config.fs.write_cache = WriteCache::WriteBack;
+            config.fs.unsynced_persist_rate = rate;
+            let runtime = Runtime::with_seed_and_config(1, config);
+            let node = runtime.create_node().build();
+            runtime
+                .block_on(node.spawn(async {
+                    let direct = (OpenOptions::new().write(true).create(true))
+                        .custom_flags(libc::O_DIRECT)
+                        .open("direct")
+                        .await
+                        .unwrap();
+                    direct.write_all_at(&Aligned([1; 512]).0, 0).await.unwrap();
+                    write("buffered", b"data").await.unwrap();
+                    let synced = File::create("synced").await.unwrap();
+                    synced.write_all_at(b"data", 0).await.unwrap();
+                    synced.sync_all().await.unwrap();
+                }))
+                .unwrap();
+            node.kill();
+            node.restart();
+            let lens = runtime
+                .block_on(node.spawn(async {
+                    let mut lens = vec![];
+                    for path in ["direct", "buffered", "synced"] {
+                        lens.push(read(path).await.unwrap().len());
+                    }
+                    lens
+                }))
+                .unwrap();
+            if rate == 0.0 {
+                // unsynced writes reached the volatile cache at most
+                assert_eq!(lens, [0, 0, 4]);
+            } else {
+                // unsynced writes in both caches may be persisted by chance
+                assert_eq!(lens, [512, 4, 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn rename_requires_dir_sync() {
+        for sync_dir in [false, true] {
+            let mut config = crate::Config::default();
+            config.fs.dir_sync = DirSync::Required;
+            let runtime = Runtime::with_seed_and_config(1, config);
+            let node = runtime.create_node().build();
+            let exists = runtime.block_on(async move {
+                node.spawn(async move {
+                    create_dir("data").await.unwrap();
+                    File::open(".").await.unwrap().sync_all().await.unwrap();
+                    let file = File::create("data/tmp").await.unwrap();
+                    file.write_all_at(b"state", 0).await.unwrap();
+                    file.sync_all().await.unwrap();
+                    rename("data/tmp", "data/current").await.unwrap();
+                    if sync_dir {
+                        File::open("data").await.unwrap().sync_all().await.unwrap();
+                    }
+                })
+                .await
+                .unwrap();
+                node.kill();
+                node.restart();
+                node.spawn(async move {
+                    assert!(try_exists("data").await.unwrap());
+                    let current = read("data/current").await.ok();
+                    if sync_dir {
+                        assert_eq!(current.unwrap(), b"state");
+                    }
+                    current.is_some()
+                })
+                .await
+                .unwrap()
+            });
+            assert_eq!(exists, sync_dir);
+        }
+    }
+
+    #[test]
+    fn directories() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let f = node.spawn(async move {
+            let kind = |r: Result<()>| r.unwrap_err().kind();
+            assert_eq!(kind(write("data/file", b"x").await), ErrorKind::NotFound);
+            create_dir_all("data/wal").await.unwrap();
+            write("data/wal/0001", b"log").await.unwrap();
+            write("data/wal/0002", b"log").await.unwrap();
+            write("./data/meta", b"meta").await.unwrap();
+            assert_eq!(kind(create_dir("data").await), ErrorKind::AlreadyExists);
+            assert!(metadata("data").await.unwrap().is_dir());
+            assert!(metadata("data/meta").await.unwrap().is_file());
+
+            let mut entries = read_dir("data").await.unwrap();
+            let mut names = vec![];
+            while let Some(entry) = entries.next_entry().await.unwrap() {
+                names.push(entry.file_name().into_string().unwrap());
+            }
+            assert_eq!(names, ["meta", "wal"]);
+
+            assert_eq!(kind(remove_dir("data/wal").await), ErrorKind::Other);
+            remove_file("data/wal/0001").await.unwrap();
+            assert!(!try_exists("data/wal/0001").await.unwrap());
+
+            rename("data/meta", "data/meta.old").await.unwrap();
+            assert_eq!(read_to_string("data/meta.old").await.unwrap(), "meta");
+            assert_eq!(copy("data/meta.old", "meta").await.unwrap(), 4);
+            rename("data", "backup").await.unwrap();
+            assert_eq!(read("backup/wal/0002").await.unwrap(), b"log");
+            assert!(!try_exists("data").await.unwrap());
+
+            remove_dir_all("backup").await.unwrap();
+            assert!(!try_exists("backup/wal/0002").await.unwrap());
+            assert!(try_exists("meta").await.unwrap());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn open_options_and_cursor() {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let f = node.spawn(async move {
+            let mut opts = OpenOptions::new();
+            opts.append(true).create(true);
+            let mut file = opts.open("log").await.unwrap();
+            file.write_all(b"hello").await.unwrap();
+            let mut file = opts.open("log").await.unwrap();
+            file.write_all(b" world").await.unwrap();
+            assert_eq!(read("log").await.unwrap(), b"hello world");
+
+            let err = File::options()
+                .write(true)
+                .create_new(true)
+                .open("log")
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+            let mut file = File::open("log").await.unwrap();
+            file.seek(SeekFrom::Start(6)).await.unwrap();
+            let mut s = String::new();
+            file.read_to_string(&mut s).await.unwrap();
+            assert_eq!(s, "world");
+            assert_eq!(file.seek(SeekFrom::Current(-5)).await.unwrap(), 6);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn lose_unsynced_writes_on_kill() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime.block_on(async move {
+            node.spawn(async move {
+                let file = File::create("file").await.unwrap();
+                file.write_all_at(b"hello", 0).await.unwrap();
+                file.sync_all().await.unwrap();
+                file.write_all_at(b" world", 5).await.unwrap();
+                assert_eq!(read("file").await.unwrap(), b"hello world");
+            })
+            .await
+            .unwrap();
+
+            node.kill();
+            node.restart();
+            let data = node.spawn(async { read("file").await.unwrap() });
+            assert_eq!(data.await.unwrap(), b"hello");
+        });
+    }
+}
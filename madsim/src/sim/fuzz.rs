@@ -0,0 +1,56 @@
+//! Fuzzer-driven simulation.
+//!
+//! The random decisions of the simulator, including task scheduling, network latencies,
+//! packet loss and `rand` calls, are drawn from the input of a coverage-guided fuzzer such as
+//! libFuzzer or AFL. The fuzzer can then steer the schedule directly and shrink failing inputs.
+//! When the input is exhausted, random values are drawn from the streams of seed 0.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // fuzz/fuzz_targets/raft.rs
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     madsim::fuzz::run(data, || async {
+//!         // run the cluster and check invariants
+//!     });
+//! });
+//! ```
+
+use crate::{runtime::Runtime, Config};
+use std::future::Future;
+
+/// Run the future with random decisions drawn from the fuzzer input.
+pub fn run<F: Future>(data: &[u8], f: impl FnOnce() -> F) -> F::Output {
+    run_with_config(data, Config::default(), f)
+}
+
+/// Run the future with the config and random decisions drawn from the fuzzer input.
+pub fn run_with_config<F: Future>(data: &[u8], config: Config, f: impl FnOnce() -> F) -> F::Output {
+    let rt = Runtime::with_seed_and_config(0, config);
+    rt.handle().rand.set_fuzz_input(data);
+    rt.block_on(f())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::Rng;
+
+    fn draw(data: &[u8]) -> Vec<u32> {
+        run(data, || async {
+            let mut rng = crate::rand::thread_rng();
+            (0..4).map(|_| rng.gen_range(0..1000)).collect()
+        })
+    }
+
+    #[test]
+    fn input() {
+        let data: Vec<u8> = (0..255).collect();
+        assert_eq!(draw(&data), draw(&data));
+        let other: Vec<u8> = data.iter().rev().copied().collect();
+        assert_ne!(draw(&data), draw(&other));
+        // exhausted input falls back to the seeded streams
+        assert_eq!(draw(&[]), draw(&[]));
+    }
+}
@@ -0,0 +1,143 @@
+//! Deterministic hashing.
+//!
+//! The iteration order of `std::collections::HashMap` depends on the random keys of its
+//! [`std::collections::hash_map::RandomState`]. madsim seeds them on the thread that creates
+//! the runtime, but maps created on other threads, or by dependencies that bring their own
+//! hasher, may still iterate in a different order on each run.
+//!
+//! [`RandomState`] in this module draws its keys from the random stream of the current node,
+//! so maps built with it iterate in the same order in every run with the same seed.
+//! Use the [`HashMap`] and [`HashSet`] aliases in simulated code. [`HashMapExt`] and
+//! [`HashSetExt`] provide the `new` and `with_capacity` constructors:
+//!
+//! ```
+//! use madsim::hash::{HashMap, HashMapExt};
+//!
+//! let mut map = HashMap::new();
+//! map.insert(1, 2);
+//! ```
+//!
+//! To forbid the std types in your crate, add the following to `clippy.toml`:
+//!
+//! ```toml
+//! disallowed-types = ["std::collections::HashMap", "std::collections::HashSet"]
+//! ```
+
+use crate::rand::Rng;
+use std::hash::BuildHasher;
+
+/// A [`HashMap`](std::collections::HashMap) using [`RandomState`].
+pub type HashMap<K, V> = std::collections::HashMap<K, V, RandomState>;
+
+/// A [`HashSet`](std::collections::HashSet) using [`RandomState`].
+pub type HashSet<T> = std::collections::HashSet<T, RandomState>;
+
+/// Constructors of [`HashMap`], which std only provides for its own hasher.
+pub trait HashMapExt {
+    /// Creates an empty map.
+    fn new() -> Self;
+
+    /// Creates an empty map with at least the specified capacity.
+    fn with_capacity(capacity: usize) -> Self;
+}
+
+impl<K, V> HashMapExt for HashMap<K, V> {
+    fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+/// Constructors of [`HashSet`], which std only provides for its own hasher.
+pub trait HashSetExt {
+    /// Creates an empty set.
+    fn new() -> Self;
+
+    /// Creates an empty set with at least the specified capacity.
+    fn with_capacity(capacity: usize) -> Self;
+}
+
+impl<T> HashSetExt for HashSet<T> {
+    fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+/// The hasher created by [`RandomState`].
+pub type DefaultHasher = ahash::AHasher;
+
+/// A [`BuildHasher`] with keys drawn from the simulation seed.
+///
+/// Outside the simulation, the keys are fixed.
+#[derive(Clone, Debug)]
+pub struct RandomState(ahash::RandomState);
+
+impl RandomState {
+    /// Creates a new `RandomState` with keys drawn from the random stream of the current node.
+    pub fn new() -> Self {
+        let keys: [u64; 4] = match crate::rand::try_thread_rng() {
+            Some(mut rng) => rng.gen(),
+            None => [0; 4],
+        };
+        RandomState(ahash::RandomState::with_seeds(
+            keys[0], keys[1], keys[2], keys[3],
+        ))
+    }
+}
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for RandomState {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        self.0.build_hasher()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn deterministic_order() {
+        fn run(seed: u64) -> Vec<u32> {
+            let runtime = Runtime::with_seed_and_config(seed, crate::Config::default());
+            let node = runtime.create_node().build();
+            runtime.block_on(async move {
+                node.spawn(async {
+                    let set: HashSet<u32> = (0..100).collect();
+                    set.into_iter().collect()
+                })
+                .await
+                .unwrap()
+            })
+        }
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn constructors() {
+        let mut map = HashMap::new();
+        map.insert(1, 2);
+        assert_eq!(map[&1], 2);
+        assert!(HashMap::<u32, u32>::with_capacity(10).capacity() >= 10);
+        let mut set = HashSet::new();
+        set.insert(1);
+        assert!(set.contains(&1));
+        assert!(HashSet::<u32>::with_capacity(10).capacity() >= 10);
+    }
+}
@@ -0,0 +1,166 @@
+//! Simulated memory accounting.
+//!
+//! Memory used by a node is accounted explicitly by [`alloc`]. If the node has a memory limit,
+//! set by [`NodeBuilder::memory_limit`], it is killed by the simulated OOM killer once the usage
+//! exceeds the limit, and its [`join`](crate::runtime::NodeHandle::join) returns
+//! [`NodeExit::OutOfMemory`]. All memory of a node is freed when it is killed or restarted.
+//!
+//! [`NodeBuilder::memory_limit`]: crate::runtime::NodeBuilder::memory_limit
+//! [`NodeExit::OutOfMemory`]: crate::runtime::NodeExit::OutOfMemory
+//!
+//! # Example
+//!
+//! ```
+//! use madsim::{memory, runtime::{NodeExit, Runtime}};
+//!
+//! let runtime = Runtime::new();
+//! let node = runtime.create_node().memory_limit(1 << 20).build();
+//! runtime.block_on(async move {
+//!     node.spawn(async move {
+//!         let mut cache = vec![];
+//!         loop {
+//!             cache.push(memory::alloc(1024));
+//!             madsim::task::yield_now().await;
+//!         }
+//!     });
+//!     assert_eq!(node.join().await, NodeExit::OutOfMemory);
+//! });
+//! ```
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tracing::warn;
+
+/// Memory accounted to the current node, freed on drop.
+#[derive(Debug)]
+pub struct Allocation {
+    usage: Arc<AtomicUsize>,
+    size: usize,
+}
+
+impl Allocation {
+    /// Returns the size of the allocation in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.usage.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+/// The panic payload to unwind the task that runs out of memory.
+pub(crate) struct OutOfMemory;
+
+/// Accounts `size` bytes of memory to the current node until the returned value is dropped.
+///
+/// If the usage of the node exceeds its memory limit, the node is killed and the current task
+/// does not return from this function.
+///
+/// # Panics
+///
+/// This function panics if called outside a simulated task.
+pub fn alloc(size: usize) -> Allocation {
+    let task = crate::context::current_task();
+    let node = &task.node;
+    let usage = node.memory_usage().clone();
+    // a usage overflowing `usize` is out of memory even without a limit
+    let limit = node.memory_limit().unwrap_or(usize::MAX);
+    let total = usage.load(Ordering::Relaxed).checked_add(size);
+    if !total.is_some_and(|total| total <= limit) {
+        warn!(node = %node.id, ?total, limit, "out of memory");
+        // unwind without calling the panic hook. the executor kills the node.
+        std::panic::resume_unwind(Box::new(OutOfMemory));
+    }
+    usage.fetch_add(size, Ordering::Relaxed);
+    Allocation { usage, size }
+}
+
+/// Returns the memory usage of the current node in bytes.
+///
+/// # Panics
+///
+/// This function panics if called outside a simulated task.
+pub fn usage() -> usize {
+    let task = crate::context::current_task();
+    task.node.memory_usage().load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{Handle, NodeExit, Runtime},
+        time,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn free_on_drop() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().memory_limit(100).build();
+        runtime.block_on(async move {
+            node.spawn(async move {
+                let a = alloc(60);
+                assert_eq!(usage(), 60);
+                drop(a);
+                let _b = alloc(60);
+                assert_eq!(usage(), 60);
+            })
+            .await
+            .unwrap();
+            assert_eq!(
+                Handle::current()
+                    .metrics()
+                    .node(node.id())
+                    .unwrap()
+                    .memory_usage,
+                0
+            );
+        });
+    }
+
+    #[test]
+    fn overflow() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime.block_on(async move {
+            node.spawn(async {
+                let _a = alloc(usize::MAX);
+                let _b = alloc(1);
+            });
+            assert_eq!(node.join().await, NodeExit::OutOfMemory);
+        });
+    }
+
+    #[test]
+    fn restart_on_oom() {
+        let runtime = Runtime::new();
+        let node = runtime
+            .create_node()
+            .memory_limit(1000)
+            .restart_on_oom()
+            .init(|| async {
+                let mut cache = vec![];
+                loop {
+                    cache.push(alloc(100));
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+            })
+            .build();
+        runtime.block_on(async move {
+            assert_eq!(node.join().await, NodeExit::OutOfMemory);
+            let t0 = time::Instant::now();
+            // restarted with no memory
+            time::sleep(Duration::from_secs(10)).await;
+            let usage = node.spawn(async { usage() }).await.unwrap();
+            assert!(usage <= 1000, "{usage}");
+            assert_eq!(node.join().await, NodeExit::OutOfMemory);
+            assert!(t0.elapsed() > Duration::from_secs(10));
+        });
+    }
+}
@@ -7,9 +7,15 @@ pub(crate) use self::runtime::context;
 #[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
 pub use madsim_macros::{main, test, tokio_main, tokio_test};
 
+pub mod bridge;
 pub mod buggify;
-mod config;
+pub mod config;
+pub mod coverage;
 pub mod fs;
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub mod fuzz;
+pub mod hash;
+pub mod memory;
 pub mod net;
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 pub mod plugin;
@@ -19,4 +25,5 @@ pub mod runtime;
 pub mod signal;
 pub mod task;
 pub mod time;
+mod trace;
 mod utils;
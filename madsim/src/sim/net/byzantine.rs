@@ -0,0 +1,215 @@
+//! Byzantine message tampering.
+//!
+//! Tampering functions are installed on a link by [`NetSim::tamper_link`].
+//! They can mutate payloads, forge sender addresses, withhold packets to reorder them,
+//! and replay packets that have been delivered before.
+//!
+//! [`NetSim::tamper_link`]: super::NetSim::tamper_link
+
+use super::{hook::clone_payload, PacketMeta, Payload};
+use std::{collections::VecDeque, fmt, net::SocketAddr};
+
+/// The maximum number of packets kept in history for replay.
+const HISTORY_LEN: usize = 64;
+
+pub(super) type TamperFn = Box<dyn FnMut(&mut Tamper<'_>) + Send>;
+
+/// Tampering state of a link.
+pub(super) struct TamperLink {
+    f: TamperFn,
+    buffer: LinkBuffer,
+}
+
+#[derive(Default)]
+struct LinkBuffer {
+    /// Packets withheld by the tampering function.
+    held: Vec<(SocketAddr, Payload)>,
+    /// Recently delivered packets. The front is the latest.
+    history: VecDeque<(SocketAddr, Payload)>,
+}
+
+impl TamperLink {
+    pub fn new(f: TamperFn) -> Self {
+        TamperLink {
+            f,
+            buffer: LinkBuffer::default(),
+        }
+    }
+
+    /// Pass a packet through the tampering function.
+    ///
+    /// Returns the packets to deliver in order.
+    pub fn process(&mut self, meta: PacketMeta, msg: Payload) -> Vec<(SocketAddr, Payload)> {
+        let mut tamper = Tamper {
+            src: meta.src,
+            meta,
+            msg: Some(msg),
+            buffer: &mut self.buffer,
+            release: false,
+            replays: vec![],
+        };
+        (self.f)(&mut tamper);
+
+        let Tamper {
+            src,
+            msg,
+            release,
+            replays,
+            ..
+        } = tamper;
+        let mut packets = vec![];
+        packets.extend(msg.map(|msg| (src, msg)));
+        if release {
+            packets.append(&mut self.buffer.held);
+        }
+        packets.extend(replays);
+        for (src, msg) in &packets {
+            if let Some(msg) = clone_payload(msg) {
+                self.buffer.history.push_front((*src, msg));
+                self.buffer.history.truncate(HISTORY_LEN);
+            }
+        }
+        packets
+    }
+}
+
+/// A packet being tampered with.
+pub struct Tamper<'a> {
+    meta: PacketMeta,
+    src: SocketAddr,
+    /// The packet. `None` if it has been withheld.
+    msg: Option<Payload>,
+    buffer: &'a mut LinkBuffer,
+    release: bool,
+    replays: Vec<(SocketAddr, Payload)>,
+}
+
+impl fmt::Debug for Tamper<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tamper")
+            .field("meta", &self.meta)
+            .field("src", &self.src)
+            .field("held", &self.msg.is_none())
+            .finish()
+    }
+}
+
+impl Tamper<'_> {
+    /// Returns the metadata of the original packet.
+    pub fn meta(&self) -> &PacketMeta {
+        &self.meta
+    }
+
+    /// Returns a mutable reference to the payload bytes.
+    ///
+    /// Returns `None` if the packet has been withheld or the payload is not raw bytes.
+    pub fn data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        let (_tag, data) = self.msg.as_mut()?.downcast_mut::<(u64, Payload)>()?;
+        data.downcast_mut::<Vec<u8>>()
+    }
+
+    /// Forges the sender address seen by the receiver.
+    pub fn forge_src(&mut self, src: SocketAddr) {
+        self.src = src;
+    }
+
+    /// Withholds the packet on the link until [`release_held`] is called.
+    ///
+    /// [`release_held`]: Tamper::release_held
+    pub fn hold(&mut self) {
+        if let Some(msg) = self.msg.take() {
+            self.buffer.held.push((self.src, msg));
+        }
+    }
+
+    /// Returns the number of withheld packets on the link.
+    pub fn num_held(&self) -> usize {
+        self.buffer.held.len()
+    }
+
+    /// Releases all withheld packets after this one, in the order they were withheld.
+    pub fn release_held(&mut self) {
+        self.release = true;
+    }
+
+    /// Replays the `n`-th latest packet delivered on the link after this one.
+    ///
+    /// `n = 0` is the latest one. Returns `false` if there is no such packet in history.
+    /// Only raw byte payloads are kept in history.
+    pub fn replay(&mut self, n: usize) -> bool {
+        let Some((src, msg)) = self.buffer.history.get(n) else {
+            return false;
+        };
+        let msg = clone_payload(msg).expect("history payload must be cloneable");
+        self.replays.push((*src, msg));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::{Endpoint, NetSim},
+        runtime::Runtime,
+        time::{sleep, timeout, Duration},
+    };
+    use std::sync::Arc;
+    use tokio::sync::Barrier;
+
+    #[test]
+    fn tamper() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let forged = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+
+        (runtime.handle().simulator::<NetSim>()).tamper_link(node1.id(), node2.id(), move |t| {
+            let first = t.data_mut().unwrap()[0];
+            match first {
+                // mutate payload and forge sender
+                0 => {
+                    t.data_mut().unwrap()[0] = 10;
+                    t.forge_src(forged);
+                }
+                // reorder 1 and 2
+                1 => t.hold(),
+                2 => t.release_held(),
+                // replay 2
+                3 => assert!(t.replay(1)),
+                _ => unreachable!(),
+            }
+        });
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            for i in 0..4 {
+                net.send_to(addr2, 1, &[i]).await.unwrap();
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+
+            let mut buf = vec![0; 0x10];
+            let mut recv = vec![];
+            while let Ok(res) = timeout(Duration::from_secs(5), net.recv_from(1, &mut buf)).await {
+                let (_, from) = res.unwrap();
+                recv.push((buf[0], from));
+            }
+            assert_eq!(
+                recv,
+                [(10, forged), (2, addr1), (1, addr1), (3, addr1), (2, addr1)]
+            );
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Correlation IDs of messages.
+//!
+//! Every message sent by [`Endpoint`] or [`TcpStream`] carries a correlation ID, so that a
+//! logical request can be followed across nodes in the log.
+//!
+//! - When a task sends a message, the message carries the current correlation ID of the task.
+//!   If the task has no correlation ID, a new one is generated for the message.
+//! - When a task receives a message, the correlation ID of the message becomes the current
+//!   correlation ID of the task, and is recorded in the `correlation_id` field of the task span.
+//! - Spawned tasks inherit the correlation ID of their parent.
+//!
+//! Since a task keeps its correlation ID after receiving a message, applications that handle
+//! unrelated requests in the same task can call [`set`] to start a new logical request.
+//!
+//! [`Endpoint`]: super::Endpoint
+//! [`TcpStream`]: super::TcpStream
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+thread_local! {
+    /// The correlation ID of the message being delivered.
+    static DELIVERING: Cell<Option<u64>> = Cell::new(None);
+}
+
+/// Returns the correlation ID of the current task.
+///
+/// # Panics
+///
+/// This function panics if called outside of a task.
+pub fn current() -> Option<u64> {
+    crate::context::current_task().correlation_id()
+}
+
+/// Sets the correlation ID of the current task.
+///
+/// Set to `None` to generate a new ID for the next message.
+///
+/// # Panics
+///
+/// This function panics if called outside of a task.
+pub fn set(id: Option<u64>) {
+    crate::context::current_task().set_correlation_id(id);
+}
+
+/// A generator of correlation IDs.
+#[derive(Debug, Default)]
+pub(super) struct IdGenerator(AtomicU64);
+
+impl IdGenerator {
+    /// Returns the correlation ID for a message sent by the current task.
+    pub fn outgoing(&self) -> u64 {
+        let current = crate::context::try_current_task().and_then(|t| t.correlation_id());
+        current.unwrap_or_else(|| self.0.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+}
+
+/// Adopts the correlation ID of a message received by the current task.
+pub(super) fn incoming(id: Option<u64>) {
+    if let (Some(id), Some(task)) = (id, crate::context::try_current_task()) {
+        task.set_correlation_id(Some(id));
+    }
+}
+
+/// Calls `f` while delivering a message with the correlation ID.
+pub(super) fn delivering<T>(id: u64, f: impl FnOnce() -> T) -> T {
+    let old = DELIVERING.with(|c| c.replace(Some(id)));
+    let ret = f();
+    DELIVERING.with(|c| c.set(old));
+    ret
+}
+
+/// Returns the correlation ID of the message being delivered.
+pub(super) fn delivering_id() -> Option<u64> {
+    DELIVERING.with(|c| c.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net::Endpoint, runtime::Runtime};
+    use std::{net::SocketAddr, sync::Arc};
+    use tokio::sync::Barrier;
+
+    #[test]
+    fn propagate() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let addr3 = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let node3 = runtime.create_node().ip(addr3.ip()).build();
+        let barrier = Arc::new(Barrier::new(3));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            set(Some(42));
+            ep.send_to(addr2, 1, b"ping").await.unwrap();
+        });
+
+        let barrier_ = barrier.clone();
+        node2.spawn(async move {
+            let ep = Endpoint::bind(addr2).await.unwrap();
+            barrier_.wait().await;
+            assert_eq!(current(), None);
+            ep.recv_from(1, &mut []).await.unwrap();
+            assert_eq!(current(), Some(42));
+            // forward in a spawned task
+            crate::task::spawn(async move {
+                ep.send_to(addr3, 1, b"ping").await.unwrap();
+            })
+            .await
+            .unwrap();
+        });
+
+        let f = node3.spawn(async move {
+            let ep = Endpoint::bind(addr3).await.unwrap();
+            barrier.wait().await;
+            ep.recv_from(1, &mut []).await.unwrap();
+            assert_eq!(current(), Some(42));
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use super::{IpProtocol::Udp, *};
+use crate::task::{WaitGuard, Waiting};
 use futures_util::{Stream, StreamExt};
 use std::{
     fmt,
@@ -16,6 +17,8 @@ pub struct Endpoint {
     pub(super) peer: Arc<Mutex<Option<SocketAddr>>>,
     /// Incoming connections.
     conn_rx: async_channel::Receiver<(PayloadSender, PayloadReceiver, SocketAddr)>,
+    #[cfg(feature = "rpc")]
+    pub(super) rpc_options: Arc<Mutex<super::rpc::RpcOptions>>,
 }
 
 impl Endpoint {
@@ -32,6 +35,8 @@ impl Endpoint {
             socket,
             peer: Arc::new(Mutex::new(None)),
             conn_rx,
+            #[cfg(feature = "rpc")]
+            rpc_options: Default::default(),
         })
     }
 
@@ -48,6 +53,19 @@ impl Endpoint {
         Ok(self.guard.addr)
     }
 
+    /// Returns the network simulator and the node of this endpoint.
+    #[cfg(feature = "rpc")]
+    pub(super) fn net_node(&self) -> (&Arc<NetSim>, NodeId) {
+        (&self.guard.net, self.guard.node.id)
+    }
+
+    /// Returns the number of pending receive requests and unreceived messages.
+    #[cfg(all(test, feature = "rpc"))]
+    pub(super) fn mailbox_len(&self) -> (usize, usize) {
+        let mailbox = self.socket.mailbox.lock();
+        (mailbox.registered.len(), mailbox.msgs.len())
+    }
+
     /// Returns the socket address of the remote peer this socket was connected to.
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         (self.peer.lock())
@@ -84,14 +102,57 @@ impl Endpoint {
     /// });
     /// ```
     pub async fn recv_from(&self, tag: u64, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
-        let (data, from) = self.recv_from_raw(tag).await?;
+        let (data, from) = self.recv_from_bytes(tag).await?;
         // copy to buffer
-        let data = data.downcast::<Vec<u8>>().expect("message is not data");
         let len = buf.len().min(data.len());
         buf[..len].copy_from_slice(&data[..len]);
         Ok((len, from))
     }
 
+    /// Sends bytes with tag on the socket to the given address, without copying them.
+    pub async fn send_to_bytes(
+        &self,
+        dst: impl ToSocketAddrs,
+        tag: u64,
+        data: Bytes,
+    ) -> io::Result<()> {
+        let dst = lookup_host(dst).await?.next().unwrap();
+        self.send_to_raw(dst, tag, Box::new(data)).await
+    }
+
+    /// Sends a message of multiple segments with tag on the socket to the given address,
+    /// without copying them.
+    ///
+    /// The receiver gets the concatenation of the segments.
+    pub async fn send_to_bytes_vectored(
+        &self,
+        dst: impl ToSocketAddrs,
+        tag: u64,
+        segments: Vec<Bytes>,
+    ) -> io::Result<()> {
+        let dst = lookup_host(dst).await?.next().unwrap();
+        self.send_to_raw(dst, tag, Box::new(segments)).await
+    }
+
+    /// Receives a single message with given tag on the socket, without copying it.
+    /// On success, returns the message and the origin.
+    ///
+    /// Messages sent in multiple segments are copied into one.
+    pub async fn recv_from_bytes(&self, tag: u64) -> io::Result<(Bytes, SocketAddr)> {
+        let (data, from) = self.recv_from_raw(tag).await?;
+        let data = match data.downcast::<Vec<u8>>() {
+            Ok(data) => Bytes::from(*data),
+            Err(data) => match data.downcast::<Bytes>() {
+                Ok(data) => *data,
+                Err(data) => match *data.downcast::<Vec<Bytes>>().expect("message is not data") {
+                    segments if segments.len() == 1 => segments.into_iter().next().unwrap(),
+                    segments => Bytes::from(segments.concat()),
+                },
+            },
+        };
+        Ok((data, from))
+    }
+
     /// Sends data on the socket to the remote address to which it is connected.
     pub async fn send(&self, tag: u64, buf: &[u8]) -> io::Result<()> {
         let peer = self.peer_addr()?;
@@ -116,6 +177,7 @@ impl Endpoint {
     /// It is provided for use by other simulators.
     #[cfg_attr(docsrs, doc(cfg(madsim)))]
     pub async fn send_to_raw(&self, dst: SocketAddr, tag: u64, data: Payload) -> io::Result<()> {
+        crate::task::consume_budget().await;
         trace!("send: {} -> {dst}, tag={tag}", self.guard.addr);
         self.guard
             .net
@@ -136,13 +198,26 @@ impl Endpoint {
     /// It is provided for use by other simulators.
     #[cfg_attr(docsrs, doc(cfg(madsim)))]
     pub async fn recv_from_raw(&self, tag: u64) -> io::Result<(Payload, SocketAddr)> {
+        crate::task::consume_budget().await;
         let recver = self.socket.mailbox.lock().recv(tag);
+        let wait = WaitGuard::new(Waiting::Recv {
+            addr: self.guard.addr,
+            tag,
+        });
         let msg = recver
             .await
             .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "network is down"))?;
+        drop(wait);
         self.guard.net.rand_delay().await?;
 
-        trace!("recv: {} <- {}, tag={}", self.guard.addr, msg.from, msg.tag);
+        correlation::incoming(msg.correlation_id);
+        trace!(
+            correlation_id = msg.correlation_id,
+            "recv: {} <- {}, tag={}",
+            self.guard.addr,
+            msg.from,
+            msg.tag
+        );
         Ok((msg.data, msg.from))
     }
 
@@ -254,6 +329,8 @@ impl Sender {
 impl Receiver {
     #[doc(hidden)]
     pub async fn recv(&mut self) -> io::Result<Payload> {
+        crate::task::consume_budget().await;
+        let _wait = WaitGuard::new(Waiting::Connection);
         (self.rx.next().await)
             .ok_or_else(|| io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"))
     }
@@ -287,6 +364,7 @@ struct Message {
     tag: u64,
     data: Payload,
     from: SocketAddr,
+    correlation_id: Option<u64>,
 }
 
 type Payload = Box<dyn Any + Send + Sync>;
@@ -306,12 +384,17 @@ struct EndpointSocket {
 }
 
 impl Socket for EndpointSocket {
+    fn queued(&self) -> usize {
+        self.mailbox.lock().msgs.len()
+    }
+
     fn deliver(&self, src: SocketAddr, _dst: SocketAddr, msg: Payload) {
         let (tag, data) = *msg.downcast::<(u64, Payload)>().unwrap();
         self.mailbox.lock().deliver(Message {
             tag,
             data,
             from: src,
+            correlation_id: correlation::delivering_id(),
         });
     }
 
@@ -354,6 +437,8 @@ impl Mailbox {
             let msg = self.msgs.swap_remove(idx);
             tx.send(msg).ok().unwrap();
         } else {
+            // forget receivers that have been dropped
+            self.registered.retain(|(_, tx)| !tx.is_closed());
             self.registered.push((tag, tx));
         }
         rx
@@ -405,6 +490,47 @@ mod tests {
         runtime.block_on(f).unwrap();
     }
 
+    #[test]
+    fn send_recv_bytes() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+        let data = Bytes::from(vec![1; 0x1000]);
+
+        let barrier_ = barrier.clone();
+        let data_ = data.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+
+            net.send_to_bytes(addr2, 1, data_.clone()).await.unwrap();
+            let segments = vec![Bytes::from_static(b"head"), data_];
+            net.send_to_bytes_vectored(addr2, 2, segments)
+                .await
+                .unwrap();
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+
+            let (bytes, from) = net.recv_from_bytes(1).await.unwrap();
+            assert_eq!(from, addr1);
+            // not copied
+            assert_eq!(bytes.as_ptr(), data.as_ptr());
+
+            let mut buf = vec![0; 0x10];
+            let (len, _) = net.recv_from(2, &mut buf).await.unwrap();
+            assert_eq!(len, 0x10);
+            assert_eq!(&buf[..6], b"head\x01\x01");
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
     #[test]
     fn receiver_drop() {
         let runtime = Runtime::new();
@@ -441,6 +567,306 @@ mod tests {
         runtime.block_on(f).unwrap();
     }
 
+    #[test]
+    fn delivery_order() {
+        fn run(order: DeliveryOrder) -> Vec<u8> {
+            let runtime = Runtime::new();
+            let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+            let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+            let node1 = runtime.create_node().ip(addr1.ip()).build();
+            let node2 = runtime.create_node().ip(addr2.ip()).build();
+            (runtime.handle().simulator::<NetSim>()).update_config(|c| c.delivery_order = order);
+            let barrier = Arc::new(Barrier::new(2));
+
+            let barrier_ = barrier.clone();
+            node1.spawn(async move {
+                let net = Endpoint::bind(addr1).await.unwrap();
+                barrier_.wait().await;
+                for i in 0..20 {
+                    net.send_to(addr2, 1, &[i]).await.unwrap();
+                }
+            });
+
+            let f = node2.spawn(async move {
+                let net = Endpoint::bind(addr2).await.unwrap();
+                barrier.wait().await;
+                let mut recv = vec![];
+                let mut buf = [0];
+                for _ in 0..20 {
+                    net.recv_from(1, &mut buf).await.unwrap();
+                    recv.push(buf[0]);
+                }
+                recv
+            });
+            runtime.block_on(f).unwrap()
+        }
+        let sent: Vec<u8> = (0..20).collect();
+        assert_ne!(run(DeliveryOrder::Arbitrary), sent);
+        assert_eq!(run(DeliveryOrder::PerConnection), sent);
+        assert_eq!(run(DeliveryOrder::PerLink), sent);
+    }
+
+    #[test]
+    fn link_config() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let addr3 = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+        let node1 = (runtime.create_node().ip(addr1.ip()))
+            .label("region", "us-east")
+            .build();
+        let node2 = (runtime.create_node().ip(addr2.ip()))
+            .label("region", "us-west")
+            .build();
+        let node3 = (runtime.create_node().ip(addr3.ip()))
+            .label("region", "eu-west")
+            .build();
+        runtime.handle().simulator::<NetSim>().set_link_config(
+            NodeSelector::label("region", "us-*"),
+            NodeSelector::label("region", "eu-*"),
+            LinkConfig {
+                send_latency: Some(Duration::from_millis(100)..Duration::from_millis(101)),
+                ..Default::default()
+            },
+        );
+        let barrier = Arc::new(Barrier::new(3));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            net.send_to(addr2, 1, &[1]).await.unwrap();
+            net.send_to(addr3, 1, &[1]).await.unwrap();
+        });
+
+        let barrier_ = barrier.clone();
+        let f2 = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier_.wait().await;
+            timeout(Duration::from_millis(50), net.recv_from(1, &mut []))
+                .await
+                .expect("intra-region link should be fast")
+                .unwrap();
+        });
+
+        let f3 = node3.spawn(async move {
+            let net = Endpoint::bind(addr3).await.unwrap();
+            barrier.wait().await;
+            timeout(Duration::from_millis(50), net.recv_from(1, &mut []))
+                .await
+                .expect_err("cross-region link should be slow");
+            net.recv_from(1, &mut []).await.unwrap();
+        });
+
+        runtime.block_on(f2).unwrap();
+        runtime.block_on(f3).unwrap();
+    }
+
+    #[test]
+    fn freeze_thaw() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            net.send_to(addr2, 1, &[1]).await.unwrap();
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+
+            let net_sim = simulator::<NetSim>();
+            net_sim.freeze();
+            assert!(net_sim.is_frozen());
+            timeout(Duration::from_secs(5), net.recv_from(1, &mut []))
+                .await
+                .expect_err("message should be held by frozen network");
+
+            net_sim.thaw();
+            let (_, from) = net.recv_from(1, &mut []).await.unwrap();
+            assert_eq!(from, addr1);
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn stat() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let (id1, id2) = (node1.id(), node2.id());
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            net.send_to(addr2, 1, &[1]).await.unwrap();
+            net.send_to(addr2, 1, &[2, 3]).await.unwrap();
+
+            let stat = simulator::<NetSim>().stat().link(id1, id2);
+            assert_eq!(stat.in_flight, 2);
+
+            sleep(Duration::from_secs(1)).await;
+            simulator::<NetSim>().clog_link(id1, id2);
+            net.send_to(addr2, 1, &[4]).await.unwrap();
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+            sleep(Duration::from_secs(2)).await;
+
+            let stat = simulator::<NetSim>().stat();
+            let expected = LinkStat {
+                delivered: 2,
+                delivered_bytes: 3,
+                dropped_clogged: 1,
+                ..Default::default()
+            };
+            assert_eq!(stat.link(id1, id2), expected);
+            assert_eq!(stat.link(id2, id1), LinkStat::default());
+            assert_eq!(stat.total, expected);
+            drop(net);
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn stat_closed() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let (id1, id2) = (node1.id(), node2.id());
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            net.send_to(addr2, 1, &[1]).await.unwrap();
+
+            sleep(Duration::from_secs(1)).await;
+            let (tx, _rx) = net.connect1(addr2).await.unwrap();
+            tx.send(Box::new(1)).await.unwrap();
+            tx.send(Box::new(2)).await.unwrap();
+            sleep(Duration::from_secs(10)).await;
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+            // close the socket before the packet arrives
+            drop(net);
+
+            let net = Endpoint::bind(addr2).await.unwrap();
+            let (_tx, rx, _) = net.accept1().await.unwrap();
+            sleep(Duration::from_secs(1)).await;
+            // close the connection without receiving
+            drop(rx);
+            sleep(Duration::from_secs(1)).await;
+
+            let stat = simulator::<NetSim>().stat().link(id1, id2);
+            let expected = LinkStat {
+                dropped_closed: 3,
+                ..Default::default()
+            };
+            assert_eq!(stat, expected);
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn stat_overflow() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let (id1, id2) = (node1.id(), node2.id());
+        (runtime.handle().simulator::<NetSim>()).update_config(|c| c.recv_buffer = Some(2));
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            for i in 0..3 {
+                net.send_to(addr2, 1, &[i]).await.unwrap();
+            }
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+            sleep(Duration::from_secs(1)).await;
+
+            let stat = simulator::<NetSim>().stat().link(id1, id2);
+            assert_eq!(stat.delivered, 2);
+            assert_eq!(stat.dropped_overflow, 1);
+            let mut buf = [0; 1];
+            for _ in 0..2 {
+                net.recv_from(1, &mut buf).await.unwrap();
+            }
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn subscribe() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let (id1, id2) = (
+            node1.id(),
+            runtime.create_node().ip(addr2.ip()).build().id(),
+        );
+
+        let f = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            let mut events = simulator::<NetSim>().subscribe();
+
+            simulator::<NetSim>().clog_link(id1, id2);
+            net.send_to(addr2, 1, &[1]).await.unwrap();
+            assert_eq!(
+                events.recv().await.unwrap(),
+                NetEvent::PacketDropped {
+                    src: id1,
+                    dst: id2,
+                    reason: DropReason::Clogged,
+                }
+            );
+
+            let addr3 = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+            net.send_to(addr3, 1, &[1]).await.unwrap();
+            assert_eq!(
+                events.recv().await.unwrap(),
+                NetEvent::NodeUnreachable {
+                    node: id1,
+                    dst: addr3,
+                }
+            );
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
     #[test]
     #[ignore] // TODO: rethink what happens when network "resets"
     fn reset() {
@@ -0,0 +1,164 @@
+//! Message flow log.
+//!
+//! The log records every message delivered or lost in the network, and can be rendered
+//! into a sequence diagram in Mermaid or PlantUML format.
+
+use super::{pcap::payload_len, Payload};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+/// A log of messages in the network.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone)]
+pub struct MessageFlow {
+    events: Vec<FlowEvent>,
+}
+
+/// A message in the [`MessageFlow`].
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowEvent {
+    /// The simulated time elapsed when the message is delivered or lost.
+    pub time: Duration,
+    /// The source address.
+    pub src: SocketAddr,
+    /// The destination address.
+    pub dst: SocketAddr,
+    /// The message tag, if the message is sent by an [`Endpoint`](super::Endpoint).
+    pub tag: Option<u64>,
+    /// The length of payload in bytes.
+    pub len: usize,
+    /// Whether the message is delivered.
+    pub delivered: bool,
+}
+
+impl MessageFlow {
+    pub(super) fn record(
+        &mut self,
+        time: Duration,
+        src: SocketAddr,
+        dst: SocketAddr,
+        msg: &Payload,
+        delivered: bool,
+    ) {
+        self.events.push(FlowEvent {
+            time,
+            src,
+            dst,
+            tag: msg.downcast_ref::<(u64, Payload)>().map(|(tag, _)| *tag),
+            len: payload_len(msg),
+            delivered,
+        });
+    }
+
+    /// Returns all messages in order of time.
+    pub fn events(&self) -> &[FlowEvent] {
+        &self.events
+    }
+
+    /// Returns IP addresses of all participants in order of appearance.
+    fn participants(&self) -> Vec<IpAddr> {
+        let mut seen = BTreeSet::new();
+        let mut ips = vec![];
+        for e in &self.events {
+            for ip in [e.src.ip(), e.dst.ip()] {
+                if seen.insert(ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+        ips
+    }
+
+    /// Renders the log into a Mermaid sequence diagram.
+    pub fn to_mermaid(&self) -> String {
+        let ips = self.participants();
+        let id = |ip: IpAddr| ips.iter().position(|x| *x == ip).unwrap();
+        let mut s = String::from("sequenceDiagram\n");
+        for (i, ip) in ips.iter().enumerate() {
+            writeln!(s, "    participant p{i} as {ip}").unwrap();
+        }
+        for e in &self.events {
+            let arrow = if e.delivered { "->>" } else { "-x" };
+            let (src, dst) = (id(e.src.ip()), id(e.dst.ip()));
+            writeln!(s, "    p{src}{arrow}p{dst}: {}", e.label()).unwrap();
+        }
+        s
+    }
+
+    /// Renders the log into a PlantUML sequence diagram.
+    pub fn to_plantuml(&self) -> String {
+        let ips = self.participants();
+        let id = |ip: IpAddr| ips.iter().position(|x| *x == ip).unwrap();
+        let mut s = String::from("@startuml\n");
+        for (i, ip) in ips.iter().enumerate() {
+            writeln!(s, "participant \"{ip}\" as p{i}").unwrap();
+        }
+        for e in &self.events {
+            let arrow = if e.delivered { "->" } else { "->x" };
+            let (src, dst) = (id(e.src.ip()), id(e.dst.ip()));
+            writeln!(s, "p{src} {arrow} p{dst}: {}", e.label()).unwrap();
+        }
+        s.push_str("@enduml\n");
+        s
+    }
+}
+
+impl FlowEvent {
+    fn label(&self) -> String {
+        let mut s = format!(
+            "[{:.6}s] :{} → :{}",
+            self.time.as_secs_f64(),
+            self.src.port(),
+            self.dst.port()
+        );
+        if let Some(tag) = self.tag {
+            write!(s, " tag={tag}").unwrap();
+        }
+        if self.len != 0 {
+            write!(s, " len={}", self.len).unwrap();
+        }
+        if !self.delivered {
+            s.push_str(" (lost)");
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let a = "10.0.0.1:1".parse().unwrap();
+        let b = "10.0.0.2:2".parse().unwrap();
+        let mut flow = MessageFlow::default();
+        let msg: Payload = Box::new((1u64, Box::new(vec![0u8; 3]) as Payload));
+        flow.record(Duration::from_millis(1), a, b, &msg, true);
+        flow.record(Duration::from_millis(2), b, a, &msg, false);
+        assert_eq!(
+            flow.to_mermaid(),
+            "sequenceDiagram
+    participant p0 as 10.0.0.1
+    participant p1 as 10.0.0.2
+    p0->>p1: [0.001000s] :1 → :2 tag=1 len=3
+    p1-xp0: [0.002000s] :2 → :1 tag=1 len=3 (lost)
+"
+        );
+        assert_eq!(
+            flow.to_plantuml(),
+            "@startuml
+participant \"10.0.0.1\" as p0
+participant \"10.0.0.2\" as p1
+p0 -> p1: [0.001000s] :1 → :2 tag=1 len=3
+p1 ->x p0: [0.002000s] :2 → :1 tag=1 len=3 (lost)
+@enduml
+"
+        );
+    }
+}
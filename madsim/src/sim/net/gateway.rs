@@ -0,0 +1,162 @@
+//! Gateway to the real network.
+//!
+//! A mostly simulated cluster can include an external dependency that can not be simulated,
+//! e.g. a database running in a container. Connections to designated addresses are opened
+//! on real OS sockets instead of the simulated network.
+//!
+//! Each socket operation runs on a blocking thread like [`spawn_blocking`], so its result
+//! re-enters the simulation at a deterministic point. Reading waits for data in real time, by
+//! [`READ_POLL_INTERVAL`] at a time.
+
+use super::*;
+use crate::task::spawn_blocking;
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpStream},
+};
+
+/// How long a read waits for data in real time before letting the simulation continue.
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The size of the buffer for each read.
+const READ_BUF_SIZE: usize = 0x10000;
+
+impl NetSim {
+    /// Maps the address to the real network.
+    ///
+    /// TCP connections to the address from any node are opened on a real OS socket. The link
+    /// configuration, clogging and other faults of the simulated network do not apply to them.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use madsim::{net::{NetSim, TcpStream}, runtime::Runtime};
+    /// # Runtime::new().block_on(async {
+    /// let postgres = "127.0.0.1:5432".parse().unwrap();
+    /// NetSim::current().add_gateway(postgres);
+    /// let stream = TcpStream::connect(postgres).await.unwrap();
+    /// # });
+    /// ```
+    pub fn add_gateway(&self, addr: SocketAddr) {
+        self.gateways.lock().insert(addr);
+    }
+
+    /// Stops mapping the address to the real network.
+    ///
+    /// Connections opened before are not affected.
+    pub fn remove_gateway(&self, addr: SocketAddr) {
+        self.gateways.lock().remove(&addr);
+    }
+
+    /// Returns whether the address is mapped to the real network.
+    pub(crate) fn is_gateway(&self, addr: SocketAddr) -> bool {
+        self.gateways.lock().contains(&addr)
+    }
+
+    /// Opens a real TCP connection to the destination.
+    pub(crate) async fn connect_gateway(
+        self: &Arc<Self>,
+        dst: SocketAddr,
+    ) -> io::Result<(PayloadSender, PayloadReceiver, SocketAddr)> {
+        let stream = spawn_blocking(move || TcpStream::connect(dst))
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+        stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+        let src = stream.local_addr()?;
+        debug!(%src, %dst, "connected through gateway");
+        let stream = Arc::new(stream);
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<(Payload, State, u64)>();
+        let writer = stream.clone();
+        crate::task::spawn(async move {
+            while let Some((value, _, _)) = rx.recv().await {
+                let data = *value.downcast::<Bytes>().unwrap();
+                let writer = writer.clone();
+                match spawn_blocking(move || (&*writer).write_all(&data)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        debug!(%dst, "failed to write to gateway: {e}");
+                        return;
+                    }
+                    Err(_) => return,
+                }
+            }
+            // the stream is dropped
+            _ = writer.shutdown(Shutdown::Write);
+        });
+        let net = self.clone();
+        let sender = PayloadSender {
+            net: self.clone(),
+            link: None,
+            test_link: Arc::new(move |_| (net.time.now_instant(), Some(Duration::ZERO))),
+            tx,
+        };
+
+        let recver = async_stream::stream! {
+            loop {
+                let reader = stream.clone();
+                let res = spawn_blocking(move || {
+                    let mut buf = vec![0; READ_BUF_SIZE];
+                    let len = (&*reader).read(&mut buf)?;
+                    buf.truncate(len);
+                    Ok::<_, io::Error>(buf)
+                })
+                .await;
+                match res {
+                    // end of file
+                    Ok(Ok(buf)) if buf.is_empty() => break,
+                    Ok(Ok(buf)) => yield Box::new(Bytes::from(buf)) as Payload,
+                    Ok(Err(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                    Ok(Err(e)) => {
+                        debug!(%dst, "failed to read from gateway: {e}");
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }
+        .boxed();
+        Ok((sender, recver, src))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn echo() {
+        // a real echo server
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 0x100];
+            loop {
+                match stream.read(&mut buf).unwrap() {
+                    0 => return,
+                    n => stream.write_all(&buf[..n]).unwrap(),
+                }
+            }
+        });
+
+        let runtime = Runtime::new();
+        let node = runtime
+            .create_node()
+            .ip("10.0.0.1".parse().unwrap())
+            .build();
+        let f = node.spawn(async move {
+            NetSim::current().add_gateway(server);
+            let mut stream = crate::net::TcpStream::connect(server).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), server);
+            stream.write_all(b"hello").await.unwrap();
+            stream.flush().await.unwrap();
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+        runtime.block_on(f).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! Per-packet hooks.
+
+use super::{pcap::payload_len, Payload};
+use crate::task::NodeId;
+use bytes::Bytes;
+use std::{net::SocketAddr, time::Duration};
+
+/// Metadata of a packet passed to the packet hook.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketMeta {
+    /// The source node.
+    pub src_node: NodeId,
+    /// The destination node.
+    pub dst_node: NodeId,
+    /// The source address.
+    pub src: SocketAddr,
+    /// The destination address.
+    pub dst: SocketAddr,
+    /// The message tag, if the packet is sent by an [`Endpoint`](super::Endpoint).
+    pub tag: Option<u64>,
+    /// The length of payload in bytes.
+    ///
+    /// This is 0 for payloads that are not raw bytes, such as RPC requests.
+    pub len: usize,
+}
+
+impl PacketMeta {
+    pub(super) fn new(
+        src_node: NodeId,
+        dst_node: NodeId,
+        src: SocketAddr,
+        dst: SocketAddr,
+        msg: &Payload,
+    ) -> Self {
+        PacketMeta {
+            src_node,
+            dst_node,
+            src,
+            dst,
+            tag: msg.downcast_ref::<(u64, Payload)>().map(|(tag, _)| *tag),
+            len: payload_len(msg),
+        }
+    }
+}
+
+/// The action to take on a packet.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Action {
+    /// Deliver the packet normally.
+    #[default]
+    Deliver,
+    /// Drop the packet.
+    Drop,
+    /// Deliver the packet after an extra delay.
+    Delay(Duration),
+    /// Deliver the packet along with the given number of copies.
+    ///
+    /// Only raw byte payloads can be duplicated. Other payloads are delivered once.
+    Duplicate(usize),
+}
+
+pub(super) type PacketHookFn = Box<dyn FnMut(PacketMeta, &mut Action) + Send>;
+
+/// Clones a payload if it consists of raw bytes.
+pub(super) fn clone_payload(msg: &Payload) -> Option<Payload> {
+    if let Some(bytes) = msg.downcast_ref::<Bytes>() {
+        return Some(Box::new(bytes.clone()));
+    }
+    if let Some((tag, data)) = msg.downcast_ref::<(u64, Payload)>() {
+        if let Some(data) = data.downcast_ref::<Vec<u8>>() {
+            return Some(Box::new((*tag, Box::new(data.clone()) as Payload)));
+        }
+        if let Some(data) = data.downcast_ref::<Bytes>() {
+            return Some(Box::new((*tag, Box::new(data.clone()) as Payload)));
+        }
+        if let Some(segments) = data.downcast_ref::<Vec<Bytes>>() {
+            return Some(Box::new((*tag, Box::new(segments.clone()) as Payload)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::{Endpoint, NetSim},
+        runtime::Runtime,
+        time::{sleep, timeout},
+    };
+    use std::sync::Arc;
+    use tokio::sync::Barrier;
+
+    #[test]
+    fn drop_and_duplicate() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let mut count = 0;
+        (runtime.handle().simulator::<NetSim>()).set_packet_hook(move |meta, action| {
+            assert_eq!(meta.tag, Some(1));
+            assert_eq!(meta.len, 1);
+            count += 1;
+            match count {
+                2 => *action = Action::Drop,
+                3 => *action = Action::Duplicate(1),
+                _ => {}
+            }
+        });
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            for i in 0..3 {
+                net.send_to(addr2, 1, &[i]).await.unwrap();
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+
+        let f = node2.spawn(async move {
+            let net = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+
+            let mut buf = vec![0; 0x10];
+            let mut recv = vec![];
+            while let Ok(res) = timeout(Duration::from_secs(5), net.recv_from(1, &mut buf)).await {
+                let (len, from) = res.unwrap();
+                assert_eq!(len, 1);
+                assert_eq!(from, addr1);
+                recv.push(buf[0]);
+            }
+            assert_eq!(recv, [0, 2, 2]);
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+}
@@ -36,17 +36,19 @@
 //! ```
 
 use bytes::Bytes;
-use futures_util::{stream::BoxStream, StreamExt};
+use futures_util::{select_biased, stream::BoxStream, FutureExt, StreamExt};
 use spin::Mutex;
 use std::{
     any::Any,
-    collections::HashMap,
-    io,
-    net::{IpAddr, SocketAddr},
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufWriter},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
     sync::Arc,
     time::Instant,
 };
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{mpsc, oneshot, watch};
 use tracing::*;
 
 use crate::{
@@ -55,29 +57,50 @@ use crate::{
     rand::{GlobalRng, Rng},
     task::{NodeId, NodeInfo, Spawner},
     time::{sleep, sleep_until, Duration, TimeHandle},
+    trace::EventKind,
 };
 
 mod addr;
+pub mod byzantine;
+pub mod correlation;
 mod dns;
 mod endpoint;
+mod flow;
+mod gateway;
+mod hook;
 pub mod ipvs;
 mod network;
+mod pcap;
+mod profile;
 #[cfg(feature = "rpc")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rpc")))]
 pub mod rpc;
 pub mod tcp;
 mod udp;
 pub mod unix;
+mod weather;
 
 pub use self::addr::{lookup_host, ToSocketAddrs};
+use self::byzantine::{Tamper, TamperLink};
+use self::correlation::IdGenerator;
 use self::dns::DnsServer;
 pub use self::endpoint::{Endpoint, Receiver, Sender};
+pub use self::flow::{FlowEvent, MessageFlow};
+use self::hook::PacketHookFn;
+pub use self::hook::{Action, PacketMeta};
 use self::ipvs::{IpVirtualServer, ServiceAddr};
-pub use self::network::{Config, Stat};
+pub(crate) use self::network::wildcard_match;
+pub use self::network::{
+    Config, DeliveryOrder, DropReason, LinkConfig, LinkStat, NetEvent, NodeSelector, Stat,
+    TailLatency,
+};
 use self::network::{Direction, IpProtocol, Network, Socket};
+use self::pcap::PcapWriter;
+pub use self::profile::NetProfile;
 pub use self::tcp::{TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 pub use self::unix::{UnixDatagram, UnixListener, UnixStream};
+pub use self::weather::Weather;
 
 /// Network simulator.
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
@@ -89,6 +112,35 @@ pub struct NetSim {
     time: TimeHandle,
     hooks_req: Mutex<HashMap<NodeId, MsgHookFn>>,
     hooks_rsp: Mutex<HashMap<NodeId, MsgHookFn>>,
+    pcap: Mutex<Option<PcapWriter<BufWriter<File>>>>,
+    flow: Mutex<Option<MessageFlow>>,
+    correlation_ids: IdGenerator,
+    /// The generation and config of network weather.
+    weather: Mutex<(u64, Option<Weather>)>,
+    packet_hook: Mutex<Option<PacketHookFn>>,
+    tamper: Mutex<HashMap<(NodeId, NodeId), TamperLink>>,
+    frozen: watch::Sender<bool>,
+    /// Notified when the config is updated.
+    config_updated: watch::Sender<()>,
+    /// Deliveries deferred by freezing.
+    frozen_deliveries: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+    /// The last arrival time of messages that are delivered in order.
+    arrivals: Mutex<HashMap<OrderKey, Instant>>,
+    /// Addresses mapped to the real network.
+    gateways: Mutex<HashSet<SocketAddr>>,
+    /// Injected RPC faults.
+    #[cfg(feature = "rpc")]
+    rpc_faults: Mutex<Vec<rpc::RpcFault>>,
+    /// Protocol versions of nodes for RPC.
+    #[cfg(feature = "rpc")]
+    rpc_versions: Mutex<rpc::RpcVersions>,
+}
+
+/// Messages with the same key are delivered in order.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum OrderKey {
+    Link(NodeId, NodeId),
+    Sockets(SocketAddr, SocketAddr),
 }
 
 /// Message sent to a network socket.
@@ -110,6 +162,21 @@ impl plugin::Simulator for NetSim {
             time: time.clone(),
             hooks_req: Default::default(),
             hooks_rsp: Default::default(),
+            pcap: Default::default(),
+            flow: Default::default(),
+            correlation_ids: Default::default(),
+            weather: Default::default(),
+            packet_hook: Default::default(),
+            tamper: Default::default(),
+            frozen: watch::channel(false).0,
+            config_updated: watch::channel(()).0,
+            frozen_deliveries: Default::default(),
+            arrivals: Default::default(),
+            gateways: Default::default(),
+            #[cfg(feature = "rpc")]
+            rpc_faults: Default::default(),
+            #[cfg(feature = "rpc")]
+            rpc_versions: Default::default(),
         }
     }
 
@@ -134,10 +201,23 @@ impl NetSim {
         self.network.lock().stat().clone()
     }
 
+    /// Subscribe to network events.
+    ///
+    /// Events happened after this call are sent to the returned receiver.
+    /// Dropping the receiver cancels the subscription.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<NetEvent> {
+        self.network.lock().subscribe()
+    }
+
     /// Update network configurations.
+    ///
+    /// The new configurations take effect immediately. Messages that have been sent on
+    /// established connections but not yet arrived will have their latency resampled.
     pub fn update_config(&self, f: impl FnOnce(&mut Config)) {
         let mut network = self.network.lock();
         network.update_config(f);
+        drop(network);
+        self.config_updated.send_replace(());
     }
 
     /// Reset a node.
@@ -154,6 +234,141 @@ impl NetSim {
         network.set_ip(node, ip);
     }
 
+    /// Set a label of a node.
+    ///
+    /// Labels can be used to select nodes in [`set_link_config`](NetSim::set_link_config).
+    /// Use [`Handle::set_node_label`](crate::runtime::Handle::set_node_label) to make the label
+    /// visible to node queries as well.
+    pub fn set_node_label(&self, node: NodeId, key: impl Into<String>, value: impl Into<String>) {
+        (self.network.lock()).set_label(node, key.into(), value.into());
+    }
+
+    /// Override the configurations of links from nodes selected by `src` to nodes selected by `dst`.
+    ///
+    /// The override applies on top of the global [`Config`]. If multiple overrides match a link,
+    /// the later ones take precedence.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // cross-region links have higher latency
+    /// net.set_link_config(
+    ///     NodeSelector::label("region", "us-*"),
+    ///     NodeSelector::label("region", "eu-*"),
+    ///     LinkConfig {
+    ///         send_latency: Some(Duration::from_millis(80)..Duration::from_millis(100)),
+    ///         ..Default::default()
+    ///     },
+    /// );
+    /// ```
+    pub fn set_link_config(
+        &self,
+        src: impl Into<NodeSelector>,
+        dst: impl Into<NodeSelector>,
+        config: impl Into<LinkConfig>,
+    ) {
+        (self.network.lock()).set_link_config(src.into(), dst.into(), config.into());
+    }
+
+    /// Use a network profile for links between nodes in zone `a` and nodes in zone `b`,
+    /// in both directions.
+    ///
+    /// Zones are the values of node label `zone`, and may contain `*` wildcards.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// net.set_zone_profile("us-*", "us-*", NetProfile::CROSS_ZONE);
+    /// net.set_zone_profile("us-*", "eu-*", NetProfile::CROSS_REGION_US_EU);
+    /// ```
+    pub fn set_zone_profile(&self, a: &str, b: &str, profile: NetProfile) {
+        let (a, b) = (
+            NodeSelector::label("zone", a),
+            NodeSelector::label("zone", b),
+        );
+        let mut network = self.network.lock();
+        network.set_link_config(a.clone(), b.clone(), profile.clone().into());
+        network.set_link_config(b, a, profile.into());
+    }
+
+    /// Start the network weather generator.
+    ///
+    /// Random links will be degraded from time to time. See [`Weather`] for details.
+    /// It replaces the weather started before.
+    ///
+    /// Note that the weather generator keeps timers running, so a simulation that is stuck
+    /// will not be detected as deadlock until the time limit is reached.
+    pub fn start_weather(self: &Arc<Self>, weather: Weather) {
+        assert!(
+            weather.intensity > 0.0 && weather.intensity <= 1.0,
+            "intensity must be in (0, 1]"
+        );
+        let generation = {
+            let mut state = self.weather.lock();
+            state.0 += 1;
+            state.1 = Some(weather);
+            state.0
+        };
+        self.network.lock().clear_weather();
+        self.schedule_weather(generation);
+    }
+
+    /// Stop the network weather generator and restore all degraded links.
+    pub fn stop_weather(&self) {
+        let mut state = self.weather.lock();
+        state.0 += 1;
+        state.1 = None;
+        self.network.lock().clear_weather();
+    }
+
+    /// Schedule the next weather event.
+    fn schedule_weather(self: &Arc<Self>, generation: u64) {
+        let weather = match &*self.weather.lock() {
+            (g, Some(weather)) if *g == generation => weather.clone(),
+            _ => return,
+        };
+        let delay = self.rand.with(|rng| weather.next_interval(rng));
+        let net = Arc::downgrade(self);
+        self.time.add_timer(delay, move || {
+            if let Some(net) = net.upgrade() {
+                net.weather_event(generation, &weather);
+            }
+        });
+    }
+
+    /// Degrade a random link and schedule the next event.
+    fn weather_event(self: &Arc<Self>, generation: u64, weather: &Weather) {
+        if self.weather.lock().0 != generation {
+            return;
+        }
+        let mut network = self.network.lock();
+        let nodes = network.nodes_with_ip();
+        if nodes.len() >= 2 {
+            let (src, dst, kind, duration) = self.rand.with(|rng| {
+                let src = rng.gen_range(0..nodes.len());
+                let dst = (src + rng.gen_range(1..nodes.len())) % nodes.len();
+                let (kind, duration) = weather.next_event(rng);
+                (nodes[src], nodes[dst], kind, duration)
+            });
+            let config = weather.degrade(kind, &network.link_config(src, dst));
+            debug!(%src, %dst, ?kind, ?duration, "weather");
+            let id = network.add_weather(src, dst, config);
+            let net = Arc::downgrade(self);
+            self.time.add_timer(duration, move || {
+                if let Some(net) = net.upgrade() {
+                    net.network.lock().remove_weather(id);
+                }
+            });
+        }
+        drop(network);
+        self.schedule_weather(generation);
+    }
+
+    /// Remove all link configuration overrides.
+    pub fn clear_link_configs(&self) {
+        self.network.lock().clear_link_configs();
+    }
+
     /// Connect a node to the network.
     #[deprecated(since = "0.3.0", note = "use `unclog_node` instead")]
     pub fn connect(&self, id: NodeId) {
@@ -196,6 +411,11 @@ impl NetSim {
         self.network.lock().clog_node(id, Direction::Out);
     }
 
+    /// Returns whether the node is clogged in either direction.
+    pub(crate) fn is_node_clogged(&self, id: NodeId) -> bool {
+        self.network.lock().is_node_clogged(id)
+    }
+
     /// Connect a pair of nodes.
     #[deprecated(since = "0.3.0", note = "call `unclog_link` twice instead")]
     pub fn connect2(&self, node1: NodeId, node2: NodeId) {
@@ -222,6 +442,51 @@ impl NetSim {
         self.network.lock().clog_link(src, dst);
     }
 
+    /// Freeze the network.
+    ///
+    /// All in-flight messages are held without being dropped until [`thaw`] is called.
+    /// Messages sent during freezing are also held when they arrive.
+    ///
+    /// [`thaw`]: NetSim::thaw
+    pub fn freeze(&self) {
+        debug!("freeze network");
+        self.frozen.send_replace(true);
+    }
+
+    /// Thaw the network. All held messages are delivered immediately.
+    pub fn thaw(&self) {
+        debug!("thaw network");
+        self.frozen.send_replace(false);
+        let deliveries = std::mem::take(&mut *self.frozen_deliveries.lock());
+        for deliver in deliveries {
+            deliver();
+        }
+    }
+
+    /// Returns whether the network is frozen.
+    pub fn is_frozen(&self) -> bool {
+        *self.frozen.borrow()
+    }
+
+    /// Run the delivery function, or defer it until thawed if the network is frozen.
+    fn deliver_unless_frozen(&self, f: impl FnOnce() + Send + 'static) {
+        if self.is_frozen() {
+            self.frozen_deliveries.lock().push(Box::new(f));
+        } else {
+            f();
+        }
+    }
+
+    /// Wait until the network is not frozen.
+    async fn wait_thawed(&self) {
+        let mut rx = self.frozen.subscribe();
+        while *rx.borrow() {
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
     /// Add a DNS record for the cluster.
     pub fn add_dns_record(&self, hostname: &str, ip: IpAddr) {
         self.dns.lock().add(hostname, ip);
@@ -237,6 +502,81 @@ impl NetSim {
         &self.ipvs
     }
 
+    /// Record all delivered packets to a pcap file at `path`.
+    ///
+    /// Packets are written with synthesized IP and UDP/TCP headers and timestamped with the
+    /// simulated time. Only byte payloads are recorded, message tags and RPC payloads are not.
+    pub fn enable_pcap(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let writer = PcapWriter::new(BufWriter::new(File::create(path)?))?;
+        if let Some(mut old) = self.pcap.lock().replace(writer) {
+            old.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Stop recording packets and flush the pcap file.
+    pub fn disable_pcap(&self) -> io::Result<()> {
+        match self.pcap.lock().take() {
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Start recording the message flow.
+    ///
+    /// Previously recorded messages are discarded.
+    /// The log can be rendered into a sequence diagram to visualize the communication.
+    pub fn enable_flow_log(&self) {
+        *self.flow.lock() = Some(MessageFlow::default());
+    }
+
+    /// Stop recording the message flow and returns the log.
+    pub fn take_flow_log(&self) -> Option<MessageFlow> {
+        self.flow.lock().take()
+    }
+
+    /// Returns a snapshot of the message flow log.
+    pub fn flow_log(&self) -> Option<MessageFlow> {
+        self.flow.lock().clone()
+    }
+
+    /// Set a hook function that decides the action to take on each packet.
+    ///
+    /// The hook is called with the metadata of every datagram sent through the network, and
+    /// can set the action to deliver, drop, delay or duplicate the packet. It replaces any
+    /// previously set hook. Packets on stream connections (e.g. TCP) are not affected.
+    ///
+    /// The hook is called with an internal lock held, so it must not call back into [`NetSim`].
+    pub fn set_packet_hook(&self, f: impl FnMut(PacketMeta, &mut Action) + Send + 'static) {
+        *self.packet_hook.lock() = Some(Box::new(f));
+    }
+
+    /// Remove the packet hook.
+    pub fn clear_packet_hook(&self) {
+        *self.packet_hook.lock() = None;
+    }
+
+    /// Install a tampering function on the link from `src` to `dst`.
+    ///
+    /// The function is called on every datagram sent through the link, after the packet hook.
+    /// It can mutate the payload, forge the sender address, withhold packets to reorder them,
+    /// or replay packets delivered before. See [`Tamper`] for details.
+    pub fn tamper_link(
+        &self,
+        src: NodeId,
+        dst: NodeId,
+        f: impl FnMut(&mut Tamper<'_>) + Send + 'static,
+    ) {
+        (self.tamper.lock()).insert((src, dst), TamperLink::new(Box::new(f)));
+    }
+
+    /// Remove the tampering function on the link from `src` to `dst`.
+    ///
+    /// Withheld packets on the link are dropped.
+    pub fn clear_tamper_link(&self, src: NodeId, dst: NodeId) {
+        self.tamper.lock().remove(&(src, dst));
+    }
+
     /// Add a hook function for RPC requests.
     ///
     /// If the hook function returns `false`, the request will be dropped.
@@ -294,9 +634,31 @@ impl NetSim {
         Ok(())
     }
 
+    /// Update the statistics of a link.
+    fn record(&self, src: NodeId, dst: NodeId, f: impl Fn(&mut LinkStat)) {
+        self.network.lock().stat_mut().record(src, dst, f);
+    }
+
+    /// Record a message in the flow log if enabled.
+    fn log_flow(&self, src: SocketAddr, dst: SocketAddr, msg: &Payload, delivered: bool) {
+        if let Some(flow) = &mut *self.flow.lock() {
+            flow.record(self.time.elapsed(), src, dst, msg, delivered);
+        }
+    }
+
+    /// Write a delivered packet to the pcap file if enabled.
+    fn capture(&self, src: SocketAddr, dst: SocketAddr, protocol: IpProtocol, msg: &Payload) {
+        if let Some(writer) = &mut *self.pcap.lock() {
+            let data = pcap::payload_bytes(msg);
+            if let Err(e) = writer.write_packet(self.time.now_time(), src, dst, protocol, &data) {
+                warn!("failed to write pcap: {e}");
+            }
+        }
+    }
+
     /// Send a message to the destination.
     pub(crate) async fn send(
-        &self,
+        self: &Arc<Self>,
         node: NodeId,
         port: u16,
         mut dst: SocketAddr,
@@ -315,21 +677,129 @@ impl NetSim {
         {
             dst = addr.parse().expect("invalid socket address");
         }
-        if let Some((ip, dst_node, socket, latency)) =
-            self.network.lock().try_send(node, dst, protocol)
-        {
-            trace!(?latency, "delay");
-            let hook = self.hooks_rsp.lock().get(&dst_node).cloned();
-            self.time.add_timer(latency, move || {
+        let len = pcap::payload_len(&msg);
+        let res = self.network.lock().try_send(node, dst, protocol, len);
+        let Some((ip, dst_node, socket, latency)) = res else {
+            let ip = self.network.lock().ip(node);
+            let src = (ip.unwrap_or(Ipv4Addr::UNSPECIFIED.into()), port).into();
+            self.log_flow(src, dst, &msg, false);
+            return Ok(());
+        };
+        let src = SocketAddr::from((ip, port));
+        let cid = self.correlation_ids.outgoing();
+        trace!(correlation_id = cid, "send");
+        let mut action = Action::Deliver;
+        if let Some(hook) = &mut *self.packet_hook.lock() {
+            hook(PacketMeta::new(node, dst_node, src, dst, &msg), &mut action);
+        }
+        let (latency, copies) = match action {
+            Action::Deliver => (latency, 0),
+            Action::Drop => {
+                trace!("dropped by packet hook");
+                self.log_flow(src, dst, &msg, false);
+                self.network
+                    .lock()
+                    .drop_packet(node, dst_node, DropReason::Hook);
+                return Ok(());
+            }
+            Action::Delay(delay) => (latency + delay, 0),
+            Action::Duplicate(n) => (latency, n),
+        };
+        let mut msgs = vec![];
+        for _ in 0..copies {
+            let Some(msg) = hook::clone_payload(&msg) else {
+                warn!("payload can not be duplicated");
+                break;
+            };
+            msgs.push(msg);
+        }
+        msgs.push(msg);
+        let mut packets = vec![];
+        for msg in msgs {
+            match self.tamper.lock().get_mut(&(node, dst_node)) {
+                Some(link) => {
+                    let meta = PacketMeta::new(node, dst_node, src, dst, &msg);
+                    packets.extend(link.process(meta, msg));
+                }
+                None => packets.push((src, msg)),
+            }
+        }
+        // keep the order of packets
+        for (i, (src, msg)) in packets.into_iter().enumerate() {
+            let link = (node, dst_node);
+            let latency = latency + Duration::from_nanos(i as u64);
+            let latency = self.ordered_latency(link, src, dst, latency, false);
+            let socket = socket.clone();
+            self.deliver_after(latency, link, src, dst, protocol, socket, msg, cid);
+        }
+        Ok(())
+    }
+
+    /// Deliver a message to the socket after `latency`.
+    #[allow(clippy::too_many_arguments)]
+    fn deliver_after(
+        self: &Arc<Self>,
+        latency: Duration,
+        (src_node, dst_node): (NodeId, NodeId),
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: IpProtocol,
+        socket: Arc<dyn Socket>,
+        msg: Payload,
+        correlation_id: u64,
+    ) {
+        trace!(?latency, "delay");
+        let hook = self.hooks_rsp.lock().get(&dst_node).cloned();
+        let net = self.clone();
+        self.record(src_node, dst_node, |s| s.in_flight += 1);
+        self.time.add_timer(latency, move || {
+            let net1 = net.clone();
+            net.deliver_unless_frozen(move || {
                 if let Some(hook) = hook {
                     if !hook(&msg) {
+                        let mut network = net1.network.lock();
+                        network
+                            .stat_mut()
+                            .record(src_node, dst_node, |s| s.in_flight -= 1);
+                        network.drop_packet(src_node, dst_node, DropReason::Hook);
+                        drop(network);
+                        net1.log_flow(src, dst, &msg, false);
                         return;
                     }
                 }
-                socket.deliver((ip, port).into(), dst, msg);
+                let mut network = net1.network.lock();
+                if !network.is_bound(dst_node, &socket) {
+                    // the socket was closed after the packet was sent
+                    network
+                        .stat_mut()
+                        .record(src_node, dst_node, |s| s.in_flight -= 1);
+                    network.drop_packet(src_node, dst_node, DropReason::Closed);
+                    drop(network);
+                    net1.log_flow(src, dst, &msg, false);
+                    return;
+                }
+                if network.is_full(&socket) {
+                    network
+                        .stat_mut()
+                        .record(src_node, dst_node, |s| s.in_flight -= 1);
+                    network.drop_packet(src_node, dst_node, DropReason::Overflow);
+                    drop(network);
+                    net1.log_flow(src, dst, &msg, false);
+                    return;
+                }
+                drop(network);
+                let len = pcap::payload_len(&msg) as u64;
+                net1.record(src_node, dst_node, |s| {
+                    s.in_flight -= 1;
+                    s.delivered += 1;
+                    s.delivered_bytes += len;
+                });
+                net1.rand.trace(EventKind::Deliver { src, dst, len });
+                net1.log_flow(src, dst, &msg, true);
+                net1.capture(src, dst, protocol, &msg);
+                correlation::delivering(correlation_id, || socket.deliver(src, dst, msg));
             });
-        }
-        Ok(())
+        });
     }
 
     /// Opens a new connection to destination.
@@ -348,13 +818,21 @@ impl NetSim {
         {
             dst = addr.parse().expect("invalid socket address");
         }
-        let (ip, dst_node, socket, latency) = (self.network.lock().try_send(node, dst, protocol))
+        let mut network = self.network.lock();
+        let (ip, dst_node, socket, latency) = (network.try_send(node, dst, protocol, 0))
             .ok_or_else(|| {
-            io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused")
-        })?;
+                io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused")
+            })?;
         let src = (ip, port).into();
-        let (tx1, rx1) = self.channel(node, dst, protocol);
-        let (tx2, rx2) = self.channel(dst_node, src, protocol);
+        network.emit(NetEvent::ConnectionEstablished {
+            src_node: node,
+            dst_node,
+            src,
+            dst,
+        });
+        drop(network);
+        let (tx1, rx1) = self.channel((node, dst_node), src, dst, protocol);
+        let (tx2, rx2) = self.channel((dst_node, node), dst, src, protocol);
         trace!(?latency, "delay");
         // FIXME: delay
         // self.time.add_timer(latency, move || {
@@ -366,58 +844,142 @@ impl NetSim {
     /// Create a reliable, ordered channel between two endpoints.
     fn channel(
         self: &Arc<Self>,
-        node: NodeId,
+        (node, dst_node): (NodeId, NodeId),
+        src: SocketAddr,
         dst: SocketAddr,
         protocol: IpProtocol,
     ) -> (PayloadSender, PayloadReceiver) {
-        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut rx = ConnReceiver {
+            net: self.clone(),
+            link: (node, dst_node),
+            rx,
+            receiving: false,
+        };
         let net = self.clone();
-        let test_link = Arc::new(move || {
-            net.network
-                .lock()
-                .try_send(node, dst, protocol)
-                .map(|(_, _, _, latency)| net.time.now_instant() + latency)
+        let net1 = self.clone();
+        let test_link = Arc::new(move |len| {
+            let latency = (net1.network.lock().try_send(node, dst, protocol, len))
+                .map(|(_, _, _, latency)| latency)
+                .map(|latency| net1.ordered_latency((node, dst_node), src, dst, latency, true));
+            (net1.time.now_instant(), latency)
         });
         let sender = PayloadSender {
+            net: self.clone(),
+            link: Some((node, dst_node)),
             test_link: test_link.clone(),
             tx,
         };
         let recver = async_stream::stream! {
-            while let Some((value, mut state)) = rx.recv().await {
-                // wait until the link is ready
-                let mut backoff = Duration::from_millis(1);
-                let arrive_time = loop {
-                    if let Some(arrive_time) = state {
-                        break arrive_time;
-                    }
-                    // backoff
-                    sleep(backoff).await;
-                    backoff = (backoff * 2).min(Duration::from_secs(10));
-                    // retry
-                    state = test_link();
-                };
-                sleep_until(arrive_time).await;
+            let mut config_updated = net.config_updated.subscribe();
+            while let Some((value, state, cid)) = rx.rx.recv().await {
+                rx.receiving = true;
+                let len = pcap::payload_len(&value);
+                net.wait_arrival(&*test_link, len, state, &mut config_updated).await;
+                net.wait_thawed().await;
+                rx.receiving = false;
+                net.record(node, dst_node, |s| {
+                    s.in_flight -= 1;
+                    s.delivered += 1;
+                    s.delivered_bytes += len as u64;
+                });
+                net.rand.trace(EventKind::Deliver {
+                    src,
+                    dst,
+                    len: len as u64,
+                });
+                net.log_flow(src, dst, &value, true);
+                net.capture(src, dst, protocol, &value);
+                correlation::incoming(Some(cid));
+                trace!(correlation_id = cid, "recv");
                 yield value;
             }
         }
         .boxed();
         (sender, recver)
     }
+
+    /// Returns the latency delayed to keep the delivery order, and records the arrival.
+    ///
+    /// `connected` is true for messages on a connection, which are delivered in order.
+    fn ordered_latency(
+        &self,
+        (src_node, dst_node): (NodeId, NodeId),
+        src: SocketAddr,
+        dst: SocketAddr,
+        latency: Duration,
+        connected: bool,
+    ) -> Duration {
+        let key = match self.network.lock().delivery_order() {
+            DeliveryOrder::Arbitrary => return latency,
+            DeliveryOrder::PerConnection if connected => return latency,
+            DeliveryOrder::PerConnection => OrderKey::Sockets(src, dst),
+            DeliveryOrder::PerLink => OrderKey::Link(src_node, dst_node),
+        };
+        let now = self.time.now_instant();
+        let mut arrivals = self.arrivals.lock();
+        let last = arrivals.entry(key).or_insert(now);
+        // timers with the same deadline may fire in any order
+        let arrival = (now + latency).max(*last + Duration::from_nanos(1));
+        *last = arrival;
+        arrival - now
+    }
+
+    /// Wait until a message arrives on a reliable channel.
+    ///
+    /// If the link is unavailable, retry with exponential backoff.
+    /// If the config is updated during waiting, the latency will be resampled.
+    async fn wait_arrival(
+        &self,
+        test_link: &(dyn Fn(usize) -> State + Send + Sync),
+        len: usize,
+        mut state: State,
+        config_updated: &mut watch::Receiver<()>,
+    ) {
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            let (sent_time, Some(latency)) = state else {
+                // backoff
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(10));
+                // retry
+                state = test_link(len);
+                continue;
+            };
+            select_biased! {
+                _ = sleep_until(sent_time + latency).fuse() => return,
+                _ = config_updated.changed().fuse() => {
+                    trace!("config updated, resample latency");
+                    state = (sent_time, test_link(len).1);
+                }
+            }
+        }
+    }
 }
 
 #[doc(hidden)]
 pub struct PayloadSender {
-    test_link: Arc<dyn Fn() -> State + Send + Sync>,
-    tx: mpsc::UnboundedSender<(Payload, State)>,
+    net: Arc<NetSim>,
+    /// The source and destination node. `None` for gateway connections.
+    link: Option<(NodeId, NodeId)>,
+    test_link: Arc<dyn Fn(usize) -> State + Send + Sync>,
+    tx: mpsc::UnboundedSender<(Payload, State, u64)>,
 }
 
 /// The link state when sending a packet.
-type State = Option<Instant>;
+///
+/// The time when the packet is sent, and the latency or `None` if the link is unavailable.
+type State = (Instant, Option<Duration>);
 
 impl PayloadSender {
     fn send(&self, value: Payload) -> Option<()> {
-        let state = (self.test_link)();
-        self.tx.send((value, state)).ok()
+        let state = (self.test_link)(pcap::payload_len(&value));
+        let cid = self.net.correlation_ids.outgoing();
+        self.tx.send((value, state, cid)).ok()?;
+        if let Some((src, dst)) = self.link {
+            self.net.record(src, dst, |s| s.in_flight += 1);
+        }
+        Some(())
     }
 
     fn is_closed(&self) -> bool {
@@ -432,6 +994,40 @@ impl PayloadSender {
 #[doc(hidden)]
 pub type PayloadReceiver = BoxStream<'static, Payload>;
 
+/// The receiving end of a connection channel.
+///
+/// Packets still in flight when it is dropped are counted as dropped.
+struct ConnReceiver {
+    net: Arc<NetSim>,
+    link: (NodeId, NodeId),
+    rx: mpsc::UnboundedReceiver<(Payload, State, u64)>,
+    /// Whether a packet has been received but not delivered yet.
+    receiving: bool,
+}
+
+impl Drop for ConnReceiver {
+    fn drop(&mut self) {
+        self.rx.close();
+        let mut dropped = self.receiving as u64;
+        while self.rx.try_recv().is_ok() {
+            dropped += 1;
+        }
+        if dropped == 0 {
+            return;
+        }
+        // the network may be locked when sockets are closed
+        let net = self.net.clone();
+        let (src, dst) = self.link;
+        self.net.time.add_timer(Duration::ZERO, move || {
+            let mut network = net.network.lock();
+            for _ in 0..dropped {
+                network.stat_mut().record(src, dst, |s| s.in_flight -= 1);
+                network.drop_packet(src, dst, DropReason::Closed);
+            }
+        });
+    }
+}
+
 /// An RAII structure used to release the bound port.
 pub(crate) struct BindGuard {
     net: Arc<NetSim>,
@@ -3,7 +3,7 @@ use crate::{rand::*, task::NodeId};
 use serde::{Deserialize, Serialize};
 use std::{
     any::Any,
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     hash::{Hash, Hasher},
     io,
     net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -11,6 +11,7 @@ use std::{
     sync::Arc,
     time::Duration,
 };
+use tokio::sync::mpsc;
 use tracing::*;
 
 /// A simulated network.
@@ -27,6 +28,14 @@ pub(crate) struct Network {
     clogged_node_in: HashSet<NodeId>,
     clogged_node_out: HashSet<NodeId>,
     clogged_link: HashSet<(NodeId, NodeId)>,
+    /// Configuration overrides for links. Later ones take precedence.
+    link_configs: Vec<(NodeSelector, NodeSelector, LinkConfig)>,
+    /// Temporary degradations of links by the weather generator, keyed by ID.
+    /// They take precedence over `link_configs`.
+    weather: BTreeMap<u64, (NodeId, NodeId, LinkConfig)>,
+    next_weather_id: u64,
+    /// Subscribers of network events.
+    subscribers: Vec<mpsc::UnboundedSender<NetEvent>>,
 }
 
 /// A node in the network.
@@ -38,6 +47,8 @@ struct Node {
     ip: Option<IpAddr>,
     /// Sockets in the node.
     sockets: HashMap<(SocketAddr, IpProtocol), Arc<dyn Socket>>,
+    /// Labels of the node.
+    labels: HashMap<String, String>,
 }
 
 #[non_exhaustive]
@@ -52,6 +63,11 @@ pub trait Socket: Any + Send + Sync {
     /// Deliver a message from other socket.
     fn deliver(&self, _src: SocketAddr, _dst: SocketAddr, _msg: Payload) {}
 
+    /// Returns the number of delivered messages not yet received by the application.
+    fn queued(&self) -> usize {
+        0
+    }
+
     /// A new connection request.
     fn new_connection(
         &self,
@@ -73,6 +89,15 @@ pub struct Config {
     /// The latency range of sending packets.
     #[serde(default = "default_send_latency")]
     pub send_latency: Range<Duration>,
+    /// The order in which messages are delivered.
+    #[serde(default)]
+    pub delivery_order: DeliveryOrder,
+    /// The maximum number of messages queued on a receiving socket.
+    ///
+    /// Further messages are dropped until the application receives some.
+    /// By default, the receive buffer is unlimited.
+    #[serde(default)]
+    pub recv_buffer: Option<usize>,
 }
 
 impl Default for Config {
@@ -80,6 +105,8 @@ impl Default for Config {
         Config {
             packet_loss_rate: 0.0,
             send_latency: default_send_latency(),
+            delivery_order: DeliveryOrder::default(),
+            recv_buffer: None,
         }
     }
 }
@@ -93,6 +120,138 @@ impl Hash for Config {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.packet_loss_rate.to_bits().hash(state);
         self.send_latency.hash(state);
+        self.delivery_order.hash(state);
+        self.recv_buffer.hash(state);
+    }
+}
+
+/// The order in which messages are delivered.
+///
+/// Toggle it to find out which parts of a protocol depend on FIFO channels.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryOrder {
+    /// Messages on a connection are delivered in order.
+    /// Connectionless messages may be reordered arbitrarily.
+    #[default]
+    Arbitrary,
+    /// Messages between the same pair of sockets are delivered in order,
+    /// including connectionless ones. Messages between different pairs may be reordered.
+    PerConnection,
+    /// All messages from a node to another are delivered in order.
+    PerLink,
+}
+
+/// Configuration overrides for the links between a set of node pairs.
+///
+/// Fields set to `None` fall back to the global [`Config`].
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkConfig {
+    /// Possibility of packet loss.
+    pub packet_loss_rate: Option<f64>,
+    /// The latency range of sending packets.
+    pub send_latency: Option<Range<Duration>>,
+    /// The bandwidth in bytes per second.
+    ///
+    /// The transmission delay of each packet is added to the latency.
+    /// By default, the bandwidth is unlimited.
+    pub bandwidth: Option<u64>,
+    /// The latency of slow packets.
+    ///
+    /// By default, there is no tail latency.
+    pub tail_latency: Option<TailLatency>,
+}
+
+impl LinkConfig {
+    /// Returns a config with fields unset in `self` taken from `base`.
+    pub fn or(self, base: LinkConfig) -> LinkConfig {
+        LinkConfig {
+            packet_loss_rate: self.packet_loss_rate.or(base.packet_loss_rate),
+            send_latency: self.send_latency.or(base.send_latency),
+            bandwidth: self.bandwidth.or(base.bandwidth),
+            tail_latency: self.tail_latency.or(base.tail_latency),
+        }
+    }
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for LinkConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.packet_loss_rate.map(f64::to_bits).hash(state);
+        self.send_latency.hash(state);
+        self.bandwidth.hash(state);
+        self.tail_latency.hash(state);
+    }
+}
+
+/// A small fraction of packets that take much longer to arrive.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TailLatency {
+    /// Possibility of a packet being slow.
+    pub probability: f64,
+    /// The latency range of slow packets.
+    pub latency: Range<Duration>,
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for TailLatency {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.probability.to_bits().hash(state);
+        self.latency.hash(state);
+    }
+}
+
+/// Selects a set of nodes.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeSelector {
+    /// Any node.
+    Any,
+    /// The node with the given ID.
+    Id(NodeId),
+    /// Nodes whose label `key` matches `value`.
+    ///
+    /// The value may contain `*` wildcards that match any sequence of characters.
+    Label {
+        /// The label key.
+        key: String,
+        /// The pattern of label value.
+        value: String,
+    },
+}
+
+impl NodeSelector {
+    /// Selects nodes whose label `key` matches `value`.
+    pub fn label(key: impl Into<String>, value: impl Into<String>) -> Self {
+        NodeSelector::Label {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl From<NodeId> for NodeSelector {
+    fn from(id: NodeId) -> Self {
+        NodeSelector::Id(id)
+    }
+}
+
+/// Returns whether `s` matches the `pattern` with `*` wildcards.
+pub(crate) fn wildcard_match(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let Some(s) = s.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=s.len())
+                .filter(|&i| s.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &s[i..]))
+        }
     }
 }
 
@@ -102,6 +261,100 @@ impl Hash for Config {
 pub struct Stat {
     /// Total number of messages.
     pub msg_count: u64,
+    /// Aggregate statistics of all links.
+    pub total: LinkStat,
+    /// Statistics of each link, keyed by `(src, dst)`.
+    pub links: BTreeMap<(NodeId, NodeId), LinkStat>,
+}
+
+impl Stat {
+    /// Returns the statistics of the link from `src` to `dst`.
+    pub fn link(&self, src: NodeId, dst: NodeId) -> LinkStat {
+        self.links.get(&(src, dst)).cloned().unwrap_or_default()
+    }
+
+    /// Update the statistics of a link and the aggregate.
+    pub(crate) fn record(&mut self, src: NodeId, dst: NodeId, f: impl Fn(&mut LinkStat)) {
+        f(&mut self.total);
+        f(self.links.entry((src, dst)).or_default());
+    }
+}
+
+/// Statistics of packets on a link.
+///
+/// For reliable connections, retransmissions of a lost packet are counted as separate drops.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkStat {
+    /// Number of packets delivered.
+    pub delivered: u64,
+    /// Number of bytes delivered.
+    ///
+    /// Only raw byte payloads are counted. RPC requests have no size.
+    pub delivered_bytes: u64,
+    /// Number of packets dropped due to random packet loss.
+    pub dropped_loss: u64,
+    /// Number of packets dropped because the link is clogged or partitioned.
+    pub dropped_clogged: u64,
+    /// Number of packets dropped by hooks.
+    pub dropped_hook: u64,
+    /// Number of packets dropped because the receiving socket or connection was closed.
+    pub dropped_closed: u64,
+    /// Number of packets dropped because the receive buffer of the socket was full.
+    pub dropped_overflow: u64,
+    /// Number of packets currently in flight.
+    pub in_flight: u64,
+}
+
+/// An event happened in the network.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NetEvent {
+    /// A packet from `src` to `dst` was dropped.
+    PacketDropped {
+        /// The source node.
+        src: NodeId,
+        /// The destination node.
+        dst: NodeId,
+        /// Why the packet was dropped.
+        reason: DropReason,
+    },
+    /// A connection was established from `src_node` to `dst_node`.
+    ConnectionEstablished {
+        /// The source node.
+        src_node: NodeId,
+        /// The destination node.
+        dst_node: NodeId,
+        /// The local address of the connection.
+        src: SocketAddr,
+        /// The peer address of the connection.
+        dst: SocketAddr,
+    },
+    /// `node` tried to send to an address that does not belong to any node.
+    NodeUnreachable {
+        /// The source node.
+        node: NodeId,
+        /// The destination address.
+        dst: SocketAddr,
+    },
+}
+
+/// The reason of a packet drop.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// Random packet loss.
+    Loss,
+    /// The link is clogged or partitioned.
+    Clogged,
+    /// Dropped by a hook.
+    Hook,
+    /// The receiving socket or connection was closed.
+    Closed,
+    /// The receive buffer of the socket was full. See [`Config::recv_buffer`].
+    Overflow,
 }
 
 /// Direction of a link.
@@ -123,9 +376,22 @@ impl Network {
             clogged_node_in: HashSet::new(),
             clogged_node_out: HashSet::new(),
             clogged_link: HashSet::new(),
+            link_configs: Vec::new(),
+            weather: BTreeMap::new(),
+            next_weather_id: 0,
+            subscribers: Vec::new(),
         }
     }
 
+    pub fn delivery_order(&self) -> DeliveryOrder {
+        self.config.delivery_order
+    }
+
+    /// Returns whether the receive buffer of the socket is full.
+    pub fn is_full(&self, socket: &Arc<dyn Socket>) -> bool {
+        (self.config.recv_buffer).is_some_and(|size| socket.queued() >= size)
+    }
+
     pub fn update_config(&mut self, f: impl FnOnce(&mut Config)) {
         f(&mut self.config);
     }
@@ -134,6 +400,33 @@ impl Network {
         &self.stat
     }
 
+    pub fn stat_mut(&mut self) -> &mut Stat {
+        &mut self.stat
+    }
+
+    pub fn subscribe(&mut self) -> mpsc::UnboundedReceiver<NetEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Send an event to all subscribers.
+    pub fn emit(&mut self, event: NetEvent) {
+        self.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    /// Record a dropped packet.
+    pub fn drop_packet(&mut self, src: NodeId, dst: NodeId, reason: DropReason) {
+        self.stat.record(src, dst, |s| match reason {
+            DropReason::Loss => s.dropped_loss += 1,
+            DropReason::Clogged => s.dropped_clogged += 1,
+            DropReason::Hook => s.dropped_hook += 1,
+            DropReason::Closed => s.dropped_closed += 1,
+            DropReason::Overflow => s.dropped_overflow += 1,
+        });
+        self.emit(NetEvent::PacketDropped { src, dst, reason });
+    }
+
     pub fn insert_node(&mut self, id: NodeId) {
         debug!(%id, "insert_node");
         self.nodes.insert(id, Default::default());
@@ -159,6 +452,85 @@ impl Network {
         // TODO: what if we change the IP when there are opening sockets?
     }
 
+    pub fn ip(&self, id: NodeId) -> Option<IpAddr> {
+        self.nodes.get(&id).expect("node not found").ip
+    }
+
+    pub fn set_label(&mut self, id: NodeId, key: String, value: String) {
+        debug!(%id, %key, %value, "set_node_label");
+        let node = self.nodes.get_mut(&id).expect("node not found");
+        node.labels.insert(key, value);
+    }
+
+    pub fn set_link_config(&mut self, src: NodeSelector, dst: NodeSelector, config: LinkConfig) {
+        debug!(?src, ?dst, ?config, "set_link_config");
+        self.link_configs.push((src, dst, config));
+    }
+
+    pub fn clear_link_configs(&mut self) {
+        self.link_configs.clear();
+    }
+
+    /// Degrade a link temporarily. Returns an ID to remove it.
+    pub fn add_weather(&mut self, src: NodeId, dst: NodeId, config: LinkConfig) -> u64 {
+        let id = self.next_weather_id;
+        self.next_weather_id += 1;
+        self.weather.insert(id, (src, dst, config));
+        id
+    }
+
+    pub fn remove_weather(&mut self, id: u64) {
+        self.weather.remove(&id);
+    }
+
+    pub fn clear_weather(&mut self) {
+        self.weather.clear();
+    }
+
+    /// Returns IDs of nodes with an IP address in order.
+    pub fn nodes_with_ip(&self) -> Vec<NodeId> {
+        let mut ids: Vec<_> = (self.nodes.iter())
+            .filter(|(_, node)| node.ip.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns whether the node is selected by the selector.
+    pub(super) fn select(&self, selector: &NodeSelector, id: NodeId) -> bool {
+        match selector {
+            NodeSelector::Any => true,
+            NodeSelector::Id(x) => *x == id,
+            NodeSelector::Label { key, value } => {
+                let labels = &self.nodes.get(&id).expect("node not found").labels;
+                matches!(labels.get(key), Some(v) if wildcard_match(value, v))
+            }
+        }
+    }
+
+    /// Returns the effective config of the link.
+    ///
+    /// `packet_loss_rate` and `send_latency` are always set.
+    pub fn link_config(&self, src: NodeId, dst: NodeId) -> LinkConfig {
+        let mut result = LinkConfig::default();
+        for (s, d, config) in self.weather.values().rev() {
+            if (*s, *d) == (src, dst) {
+                result = result.or(config.clone());
+            }
+        }
+        for (s, d, config) in self.link_configs.iter().rev() {
+            if self.select(s, src) && self.select(d, dst) {
+                result = result.or(config.clone());
+            }
+        }
+        result.or(LinkConfig {
+            packet_loss_rate: Some(self.config.packet_loss_rate),
+            send_latency: Some(self.config.send_latency.clone()),
+            ..Default::default()
+        })
+    }
+
     pub fn clog_node(&mut self, id: NodeId, direction: Direction) {
         assert!(self.nodes.contains_key(&id), "node not found");
         debug!(%id, ?direction, "clog_node");
@@ -181,6 +553,11 @@ impl Network {
         }
     }
 
+    /// Returns whether the node is clogged in either direction.
+    pub fn is_node_clogged(&self, id: NodeId) -> bool {
+        self.clogged_node_in.contains(&id) || self.clogged_node_out.contains(&id)
+    }
+
     pub fn clog_link(&mut self, src: NodeId, dst: NodeId) {
         assert!(self.nodes.contains_key(&src), "node not found");
         assert!(self.nodes.contains_key(&dst), "node not found");
@@ -257,14 +634,44 @@ impl Network {
         node.sockets.remove(&(addr, protocol));
     }
 
-    /// Returns the latency of sending a packet. If packet loss, returns `None`.
-    fn test_link(&mut self, src: NodeId, dst: NodeId) -> Option<Duration> {
-        if self.link_clogged(src, dst) || self.rand.gen_bool(self.config.packet_loss_rate) {
+    /// Returns whether the socket is still bound on the node.
+    pub fn is_bound(&self, node: NodeId, socket: &Arc<dyn Socket>) -> bool {
+        let ptr = Arc::as_ptr(socket) as *const ();
+        (self.nodes.get(&node)).is_some_and(|n| {
+            n.sockets
+                .values()
+                .any(|s| Arc::as_ptr(s) as *const () == ptr)
+        })
+    }
+
+    /// Returns the latency of sending a packet of `len` bytes. If packet loss, returns `None`.
+    fn test_link(&mut self, src: NodeId, dst: NodeId, len: usize) -> Option<Duration> {
+        let config = self.link_config(src, dst);
+        if self.link_clogged(src, dst) {
+            self.drop_packet(src, dst, DropReason::Clogged);
             None
         } else {
+            let mut rand = self.rand.clone();
+            let latency = self.rand.decide_link(src, dst, || {
+                if rand.gen_bool(config.packet_loss_rate.unwrap()) {
+                    return None;
+                }
+                // TODO: special value for loopback
+                let latency = match config.tail_latency {
+                    Some(tail) if rand.gen_bool(tail.probability) => tail.latency,
+                    _ => config.send_latency.clone().unwrap(),
+                };
+                Some(rand.gen_range(latency))
+            });
+            let Some(mut latency) = latency else {
+                self.drop_packet(src, dst, DropReason::Loss);
+                return None;
+            };
             self.stat.msg_count += 1;
-            // TODO: special value for loopback
-            Some(self.rand.gen_range(self.config.send_latency.clone()))
+            if let Some(bandwidth) = config.bandwidth {
+                latency += Duration::from_secs_f64(len as f64 / bandwidth as f64);
+            }
+            Some(latency)
         }
     }
 
@@ -298,9 +705,13 @@ impl Network {
         node: NodeId,
         dst: SocketAddr,
         protocol: IpProtocol,
+        len: usize,
     ) -> Option<(IpAddr, NodeId, Arc<dyn Socket>, Duration)> {
-        let dst_node = self.resolve_dest_node(node, dst, protocol)?;
-        let latency = self.test_link(node, dst_node)?;
+        let Some(dst_node) = self.resolve_dest_node(node, dst, protocol) else {
+            self.emit(NetEvent::NodeUnreachable { node, dst });
+            return None;
+        };
+        let latency = self.test_link(node, dst_node, len)?;
         let sockets = &self.nodes.get(&dst_node)?.sockets;
         let ep = (sockets.get(&(dst, protocol)))
             .or_else(|| sockets.get(&((Ipv4Addr::UNSPECIFIED, dst.port()).into(), protocol)))?;
@@ -312,3 +723,19 @@ impl Network {
         Some((src_ip, dst_node, ep.clone(), latency))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard() {
+        assert!(wildcard_match("us-east", "us-east"));
+        assert!(!wildcard_match("us-east", "us-west"));
+        assert!(wildcard_match("us-*", "us-east"));
+        assert!(wildcard_match("*-east", "us-east"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("u*-*t", "us-east"));
+        assert!(!wildcard_match("eu-*", "us-east"));
+    }
+}
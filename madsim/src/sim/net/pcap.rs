@@ -0,0 +1,230 @@
+//! Export simulated traffic in pcap format.
+//!
+//! Each delivered packet is written with synthesized IP and UDP/TCP headers,
+//! timestamped with the simulated system time, so that the capture can be
+//! inspected with tools like Wireshark.
+
+use super::{IpProtocol, Payload};
+use bytes::Bytes;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::SystemTime,
+};
+
+/// Magic number of pcap files with nanosecond timestamps.
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+/// Raw IP; the packet begins with an IPv4 or IPv6 header.
+const LINKTYPE_RAW: u32 = 101;
+/// Maximum length of a captured packet.
+const SNAPLEN: u32 = 65535;
+
+/// A writer of pcap files.
+pub(crate) struct PcapWriter<W: Write> {
+    writer: W,
+    /// Next TCP sequence number of each flow.
+    tcp_seq: HashMap<(SocketAddr, SocketAddr), u32>,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a new writer and writes the pcap global header.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&MAGIC_NANOS.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // version major
+        writer.write_all(&4u16.to_le_bytes())?; // version minor
+        writer.write_all(&0i32.to_le_bytes())?; // thiszone
+        writer.write_all(&0u32.to_le_bytes())?; // sigfigs
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        Ok(PcapWriter {
+            writer,
+            tcp_seq: HashMap::new(),
+        })
+    }
+
+    /// Writes a packet from `src` to `dst` at `time`.
+    pub fn write_packet(
+        &mut self,
+        time: SystemTime,
+        src: SocketAddr,
+        dst: SocketAddr,
+        protocol: IpProtocol,
+        data: &[u8],
+    ) -> io::Result<()> {
+        // pcap timestamps are unsigned 32-bit seconds since the Unix epoch
+        let ts = (time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .filter(|ts| ts.as_secs() <= u32::MAX as u64)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("time out of the range of pcap: {time:?}"),
+                )
+            })?;
+        let mut transport = Vec::with_capacity(20 + data.len());
+        transport.extend_from_slice(&src.port().to_be_bytes());
+        transport.extend_from_slice(&dst.port().to_be_bytes());
+        let proto = match protocol {
+            IpProtocol::Udp => {
+                transport.extend_from_slice(&((8 + data.len()) as u16).to_be_bytes());
+                transport.extend_from_slice(&0u16.to_be_bytes()); // checksum
+                17
+            }
+            IpProtocol::Tcp => {
+                let seq = self.tcp_seq.entry((src, dst)).or_default();
+                transport.extend_from_slice(&seq.to_be_bytes());
+                *seq = seq.wrapping_add(data.len() as u32);
+                transport.extend_from_slice(&0u32.to_be_bytes()); // ack
+                transport.extend_from_slice(&[5 << 4, 0x18]); // data offset, PSH|ACK
+                transport.extend_from_slice(&u16::MAX.to_be_bytes()); // window
+                transport.extend_from_slice(&0u32.to_be_bytes()); // checksum, urgent pointer
+                6
+            }
+        };
+        transport.extend_from_slice(data);
+
+        let packet = match (src.ip(), dst.ip()) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                let mut header = [0u8; 20];
+                header[0] = 0x45;
+                header[2..4].copy_from_slice(&((20 + transport.len()) as u16).to_be_bytes());
+                header[6] = 0x40; // don't fragment
+                header[8] = 64; // ttl
+                header[9] = proto;
+                header[12..16].copy_from_slice(&src.octets());
+                header[16..20].copy_from_slice(&dst.octets());
+                let checksum = ipv4_checksum(&header);
+                header[10..12].copy_from_slice(&checksum.to_be_bytes());
+                [&header[..], &transport[..]].concat()
+            }
+            (src, dst) => {
+                let mut header = [0u8; 40];
+                header[0] = 0x60;
+                header[4..6].copy_from_slice(&(transport.len() as u16).to_be_bytes());
+                header[6] = proto;
+                header[7] = 64; // hop limit
+                header[8..24].copy_from_slice(&to_ipv6(src).octets());
+                header[24..40].copy_from_slice(&to_ipv6(dst).octets());
+                [&header[..], &transport[..]].concat()
+            }
+        };
+
+        let incl_len = packet.len().min(SNAPLEN as usize);
+        self.writer
+            .write_all(&(ts.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&ts.subsec_nanos().to_le_bytes())?;
+        self.writer.write_all(&(incl_len as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&packet[..incl_len])
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn ipv4_checksum(header: &[u8; 20]) -> u16 {
+    let mut sum = header
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]) as u32)
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Extracts the raw bytes carried by a payload.
+///
+/// Only byte payloads (TCP segments and datagrams sent by `send_to` and its variants) are
+/// recognized. Message tags and other payloads, such as RPC requests, are not recorded.
+pub(crate) fn payload_bytes(msg: &Payload) -> Cow<'_, [u8]> {
+    if let Some(bytes) = msg.downcast_ref::<Bytes>() {
+        return Cow::Borrowed(bytes);
+    }
+    if let Some((_tag, data)) = msg.downcast_ref::<(u64, Payload)>() {
+        if let Some(data) = data.downcast_ref::<Vec<u8>>() {
+            return Cow::Borrowed(data);
+        }
+        if let Some(data) = data.downcast_ref::<Bytes>() {
+            return Cow::Borrowed(data);
+        }
+        if let Some(segments) = data.downcast_ref::<Vec<Bytes>>() {
+            return Cow::Owned(segments.concat());
+        }
+    }
+    Cow::Borrowed(&[])
+}
+
+/// Returns the length of the raw bytes carried by a payload, without copying them.
+pub(crate) fn payload_len(msg: &Payload) -> usize {
+    if let Some((_tag, data)) = msg.downcast_ref::<(u64, Payload)>() {
+        if let Some(segments) = data.downcast_ref::<Vec<Bytes>>() {
+            return segments.iter().map(|s| s.len()).sum();
+        }
+    }
+    payload_bytes(msg).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_packets() {
+        let mut buf = vec![];
+        let mut writer = PcapWriter::new(&mut buf).unwrap();
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_nanos(1_000_000_123);
+        let src = "10.0.0.1:1".parse().unwrap();
+        let dst = "10.0.0.2:2".parse().unwrap();
+        writer
+            .write_packet(time, src, dst, IpProtocol::Udp, b"hello")
+            .unwrap();
+        writer
+            .write_packet(time, src, dst, IpProtocol::Tcp, b"world")
+            .unwrap();
+        drop(writer);
+
+        assert_eq!(&buf[0..4], &MAGIC_NANOS.to_le_bytes());
+        assert_eq!(&buf[20..24], &LINKTYPE_RAW.to_le_bytes());
+        // first record: 16B record header + 20B IPv4 + 8B UDP + 5B data
+        let rec = &buf[24..];
+        assert_eq!(&rec[0..4], &1u32.to_le_bytes());
+        assert_eq!(&rec[4..8], &123u32.to_le_bytes());
+        assert_eq!(&rec[8..12], &33u32.to_le_bytes());
+        assert_eq!(ipv4_checksum(rec[16..36].try_into().unwrap()), 0);
+        assert_eq!(&rec[44..49], b"hello");
+        // second record: 16B record header + 20B IPv4 + 20B TCP + 5B data
+        let rec = &rec[49..];
+        assert_eq!(&rec[8..12], &45u32.to_le_bytes());
+        assert_eq!(&rec[56..61], b"world");
+        assert_eq!(rec.len(), 61);
+    }
+
+    #[test]
+    fn time_out_of_range() {
+        let mut buf = vec![];
+        let mut writer = PcapWriter::new(&mut buf).unwrap();
+        let src = "10.0.0.1:1".parse().unwrap();
+        let dst = "10.0.0.2:2".parse().unwrap();
+        let before_epoch = SystemTime::UNIX_EPOCH - std::time::Duration::from_secs(1);
+        let after_2106 = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 32);
+        for time in [before_epoch, after_2106] {
+            let err = (writer.write_packet(time, src, dst, IpProtocol::Udp, b"hello")).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        drop(writer);
+        // nothing is written after the file header
+        assert_eq!(buf.len(), 24);
+    }
+}
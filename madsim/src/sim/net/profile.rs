@@ -0,0 +1,170 @@
+//! Realistic network profiles.
+
+use super::{LinkConfig, TailLatency};
+use std::{ops::Range, time::Duration};
+
+/// A named combination of latency, jitter, loss and bandwidth of a kind of network.
+///
+/// Profiles can be assigned to links with [`NetSim::set_link_config`], or to zones with
+/// [`NetSim::set_zone_profile`].
+///
+/// The numbers are rough estimates of typical networks. Tail latency is included, so that
+/// tests do not only see the happy path.
+///
+/// [`NetSim::set_link_config`]: super::NetSim::set_link_config
+/// [`NetSim::set_zone_profile`]: super::NetSim::set_zone_profile
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetProfile {
+    /// The name of the profile.
+    pub name: &'static str,
+    /// The latency range of sending packets. The width of the range is the jitter.
+    pub send_latency: Range<Duration>,
+    /// The latency of slow packets.
+    pub tail_latency: Option<TailLatency>,
+    /// Possibility of packet loss.
+    pub packet_loss_rate: f64,
+    /// The bandwidth in bytes per second, or `None` if unlimited.
+    pub bandwidth: Option<u64>,
+}
+
+const fn us(n: u64) -> Duration {
+    Duration::from_micros(n)
+}
+
+const fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+const fn tail(probability: f64, latency: Range<Duration>) -> Option<TailLatency> {
+    Some(TailLatency {
+        probability,
+        latency,
+    })
+}
+
+impl NetProfile {
+    /// Communication within a host.
+    pub const LOOPBACK: Self = NetProfile {
+        name: "loopback",
+        send_latency: us(10)..us(50),
+        tail_latency: None,
+        packet_loss_rate: 0.0,
+        bandwidth: None,
+    };
+
+    /// A local area network in a datacenter. 10 Gbps.
+    pub const LAN: Self = NetProfile {
+        name: "lan",
+        send_latency: us(100)..us(500),
+        tail_latency: tail(0.001, ms(1)..ms(5)),
+        packet_loss_rate: 0.0,
+        bandwidth: Some(1_250_000_000),
+    };
+
+    /// Between availability zones in the same region. 10 Gbps.
+    pub const CROSS_ZONE: Self = NetProfile {
+        name: "cross_zone",
+        send_latency: us(500)..ms(2),
+        tail_latency: tail(0.005, ms(5)..ms(20)),
+        packet_loss_rate: 0.0001,
+        bandwidth: Some(1_250_000_000),
+    };
+
+    /// A wide area network over the Internet. 100 Mbps.
+    pub const WAN: Self = NetProfile {
+        name: "wan",
+        send_latency: ms(20)..ms(60),
+        tail_latency: tail(0.01, ms(100)..ms(300)),
+        packet_loss_rate: 0.001,
+        bandwidth: Some(12_500_000),
+    };
+
+    /// Between regions in US and EU. 1 Gbps.
+    pub const CROSS_REGION_US_EU: Self = NetProfile {
+        name: "cross_region_us_eu",
+        send_latency: ms(70)..ms(90),
+        tail_latency: tail(0.01, ms(150)..ms(400)),
+        packet_loss_rate: 0.0005,
+        bandwidth: Some(125_000_000),
+    };
+
+    /// Between regions in US and Asia. 1 Gbps.
+    pub const CROSS_REGION_US_ASIA: Self = NetProfile {
+        name: "cross_region_us_asia",
+        send_latency: ms(140)..ms(180),
+        tail_latency: tail(0.01, ms(250)..ms(600)),
+        packet_loss_rate: 0.001,
+        bandwidth: Some(125_000_000),
+    };
+
+    /// A geostationary satellite link. 25 Mbps.
+    pub const SATELLITE: Self = NetProfile {
+        name: "satellite",
+        send_latency: ms(550)..ms(650),
+        tail_latency: tail(0.05, ms(800)..ms(1500)),
+        packet_loss_rate: 0.01,
+        bandwidth: Some(3_125_000),
+    };
+
+    /// All built-in profiles.
+    pub const ALL: &'static [Self] = &[
+        Self::LOOPBACK,
+        Self::LAN,
+        Self::CROSS_ZONE,
+        Self::WAN,
+        Self::CROSS_REGION_US_EU,
+        Self::CROSS_REGION_US_ASIA,
+        Self::SATELLITE,
+    ];
+
+    /// Returns the built-in profile with the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|p| p.name == name).cloned()
+    }
+}
+
+impl From<NetProfile> for LinkConfig {
+    fn from(p: NetProfile) -> Self {
+        LinkConfig {
+            packet_loss_rate: Some(p.packet_loss_rate),
+            send_latency: Some(p.send_latency),
+            bandwidth: p.bandwidth,
+            tail_latency: p.tail_latency,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::LinkRule, net::NodeSelector, Config};
+
+    #[test]
+    fn valid() {
+        for profile in NetProfile::ALL {
+            assert_eq!(NetProfile::from_name(profile.name).as_ref(), Some(profile));
+            Config::builder()
+                .link(NodeSelector::Any, NodeSelector::Any, profile.clone())
+                .build()
+                .unwrap_or_else(|e| panic!("invalid profile {}: {e}", profile.name));
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let rule = LinkRule {
+            src: NodeSelector::Any,
+            dst: NodeSelector::Any,
+            profile: Some("wan".into()),
+            config: LinkConfig {
+                packet_loss_rate: Some(0.5),
+                ..Default::default()
+            },
+        };
+        let config = rule.resolve();
+        assert_eq!(config.packet_loss_rate, Some(0.5));
+        assert_eq!(config.send_latency, Some(NetProfile::WAN.send_latency));
+        assert_eq!(config.bandwidth, NetProfile::WAN.bandwidth);
+    }
+}
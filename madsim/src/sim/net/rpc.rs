@@ -6,9 +6,53 @@
 //!
 //! - [`call`][Endpoint::call]
 //! - [`call_with_data`][Endpoint::call_with_data]
+//! - [`call_with_bytes`][Endpoint::call_with_bytes]
 //! - [`call_timeout`][Endpoint::call_timeout]
 //! - [`add_rpc_handler`][Endpoint::add_rpc_handler]
 //! - [`add_rpc_handler_with_data`][Endpoint::add_rpc_handler_with_data]
+//! - [`add_rpc_handler_with_bytes`][Endpoint::add_rpc_handler_with_bytes]
+//! - [`call_stream`][Endpoint::call_stream]
+//! - [`call_server_stream`][Endpoint::call_server_stream]
+//! - [`add_stream_handler`][Endpoint::add_stream_handler]
+//!
+//! # Deadlines
+//!
+//! A call fails with [`DeadlineExceeded`] if the response does not arrive within the timeout of
+//! [`call_timeout`][Endpoint::call_timeout], or the default deadline of the endpoint set by
+//! [`set_rpc_deadline`][Endpoint::set_rpc_deadline]. The call is then cancelled as below, so the
+//! handler stops and no late response is sent.
+//!
+//! # Payload sizes
+//!
+//! An endpoint may limit the size of requests and responses of its calls with
+//! [`set_rpc_max_request_size`][Endpoint::set_rpc_max_request_size] and
+//! [`set_rpc_max_response_size`][Endpoint::set_rpc_max_response_size]. Calls that exceed them
+//! fail with [`PayloadTooLarge`].
+//!
+//! # Interceptors
+//!
+//! Interceptors added by [`add_rpc_interceptor`][Endpoint::add_rpc_interceptor] see each
+//! incoming call before its handler, and may [reject](Verdict::Reject) or
+//! [delay](Verdict::Delay) it, or add annotations that the handler reads by [`current_call`].
+//!
+//! # Fault injection
+//!
+//! [`NetSim::add_rpc_fault`] injects latency or errors into calls of specific requests between
+//! specific nodes. See [`RpcFault`].
+//!
+//! # Version skew
+//!
+//! Messages are passed between nodes without serialization, unless the nodes run different
+//! protocol versions set by [`NetSim::set_rpc_version`]. Then requests and responses are encoded
+//! in the wire format of the sender's version and decoded in that of the receiver's, which are
+//! registered by [`NetSim::add_rpc_codec`]. Calls that cannot be decoded fail with
+//! [`InvalidData`](io::ErrorKind::InvalidData), so mixed-version clusters can be tested for
+//! wire compatibility.
+//!
+//! # Cancellation
+//!
+//! If the caller drops an in-flight call, e.g. on timeout, the future of the handler is dropped
+//! on the remote host and no response is sent.
 //!
 //! # Examples
 //!
@@ -64,10 +108,21 @@ use super::*;
 use crate::rand::random;
 #[doc(no_inline)]
 pub use bytes::Bytes;
-use futures_util::FutureExt;
+use futures_util::{ready, select_biased, FutureExt, Stream};
 #[doc(no_inline)]
 pub use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{any::Any, future::Future};
+use std::{
+    any::{Any, TypeId},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    future::Future,
+    marker::PhantomData,
+    ops::Range,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+use tokio::sync::{mpsc, oneshot};
 
 /// A RPC request.
 pub trait Request: Serialize + DeserializeOwned + Any + Send + Sync {
@@ -78,6 +133,310 @@ pub trait Request: Serialize + DeserializeOwned + Any + Send + Sync {
     const ID: u64;
 }
 
+/// The tag of messages that cancel calls, which must not collide with any [`Request::ID`].
+const CANCEL_TAG: u64 = u64::MAX;
+
+/// How long a cancellation that arrives before its call is kept.
+const EARLY_CANCEL_TTL: Duration = Duration::from_secs(10);
+
+/// The error of a call that did not complete before its deadline.
+///
+/// It is the inner error of an [`io::Error`] of kind [`TimedOut`](io::ErrorKind::TimedOut).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl DeadlineExceeded {
+    /// Returns whether the error is caused by an exceeded deadline.
+    pub fn matches(err: &io::Error) -> bool {
+        err.get_ref().is_some_and(|e| e.is::<DeadlineExceeded>())
+    }
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// The error of a call whose request or response exceeds the size limit of the endpoint.
+///
+/// It is the inner error of an [`io::Error`] of kind [`InvalidInput`](io::ErrorKind::InvalidInput)
+/// for requests, or [`InvalidData`](io::ErrorKind::InvalidData) for responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadTooLarge {
+    /// The size of the payload in bytes.
+    pub size: usize,
+    /// The limit in bytes.
+    pub limit: usize,
+}
+
+impl PayloadTooLarge {
+    /// Returns an error if `size` exceeds `limit`.
+    fn check(size: usize, limit: usize, kind: io::ErrorKind) -> io::Result<()> {
+        if size > limit {
+            return Err(io::Error::new(kind, PayloadTooLarge { size, limit }));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RPC payload of {} bytes exceeds the limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+/// The error of a call rejected by an interceptor of the remote endpoint.
+///
+/// It is the inner error of an [`io::Error`] of kind
+/// [`PermissionDenied`](io::ErrorKind::PermissionDenied).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejected;
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC rejected by the remote endpoint")
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// An incoming call seen by interceptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallMeta {
+    /// The address of the caller.
+    pub peer: SocketAddr,
+    /// The tag of the request, i.e. [`Request::ID`].
+    pub tag: u64,
+    /// The size of the request and its data in bytes.
+    pub size: usize,
+    /// Annotations added by interceptors.
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The decision of an interceptor on an incoming call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verdict {
+    /// Pass the call to the next interceptor, or the handler.
+    #[default]
+    Accept,
+    /// Fail the call with [`Rejected`] without calling the handler.
+    Reject,
+    /// Pass the call on after the given delay.
+    Delay(Duration),
+}
+
+type Interceptor = Arc<dyn Fn(&mut CallMeta) -> Verdict + Send + Sync>;
+
+tokio::task_local! {
+    static CURRENT_CALL: CallMeta;
+}
+
+/// Returns the incoming call handled by the current task, or `None` if not in a handler.
+///
+/// Only the future returned by the handler can see the call, not the tasks it spawns.
+pub fn current_call() -> Option<CallMeta> {
+    CURRENT_CALL.try_with(|call| call.clone()).ok()
+}
+
+/// RPC options and statistics of an endpoint.
+#[derive(Default)]
+pub(crate) struct RpcOptions {
+    /// The default deadline of calls.
+    deadline: Option<Duration>,
+    /// The maximum size of requests in bytes.
+    max_request_size: Option<usize>,
+    /// The maximum size of responses in bytes.
+    max_response_size: Option<usize>,
+    /// Interceptors of incoming calls in order.
+    interceptors: Vec<Interceptor>,
+    /// Statistics of finished calls.
+    metrics: RpcMetrics,
+    /// Calls waiting for responses by their sequence numbers.
+    in_flight: BTreeMap<u64, InFlightCall>,
+    /// The sequence number of the next call.
+    next_call: u64,
+    /// Incoming calls that the caller may cancel, by the caller and response tag.
+    cancels: HashMap<(SocketAddr, u64), oneshot::Sender<()>>,
+    /// Cancellations of calls not yet received, with their expiry times.
+    early_cancels: HashMap<(SocketAddr, u64), crate::time::Instant>,
+    /// Whether the task receiving cancellations has been spawned.
+    cancel_listener: bool,
+    /// Handlers of streaming calls by the [`StreamRequest::ID`].
+    stream_handlers: HashMap<u64, StreamHandler>,
+    /// Whether the task accepting streaming calls has been spawned.
+    stream_listener: bool,
+}
+
+/// Starts a streaming call with the request and the connection.
+type StreamHandler = Arc<dyn Fn(Payload, Sender, Receiver) + Send + Sync>;
+
+/// Statistics of unary calls from an endpoint.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Default)]
+pub struct RpcMetrics {
+    /// Statistics of all calls.
+    pub total: RpcStats,
+    /// Statistics of calls by the [`Request::ID`].
+    pub requests: BTreeMap<u64, RpcStats>,
+}
+
+impl RpcMetrics {
+    /// Returns the statistics of calls of request `R`.
+    pub fn request<R: Request>(&self) -> RpcStats {
+        self.requests.get(&R::ID).cloned().unwrap_or_default()
+    }
+}
+
+/// Statistics of a set of calls.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Default)]
+pub struct RpcStats {
+    /// The number of finished calls.
+    pub calls: u64,
+    /// The number of calls that failed.
+    pub errors: u64,
+    /// The number of calls that were dropped before finishing.
+    pub cancelled: u64,
+    /// The latency of finished calls in simulated time.
+    pub latency: LatencyHistogram,
+}
+
+impl RpcStats {
+    fn record(&mut self, latency: Duration, ok: bool) {
+        self.calls += 1;
+        if !ok {
+            self.errors += 1;
+        }
+        self.latency.record(latency);
+    }
+}
+
+/// A histogram of latencies in exponential buckets.
+///
+/// Bucket `i` counts latencies below `2^i` microseconds.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    buckets: [u64; 64],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: [0; 64],
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count)
+            .field("mean", &self.mean())
+            .field("p99", &self.quantile(0.99))
+            .field("max", &self.max)
+            .finish()
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (64 - micros.leading_zeros() as usize).min(63);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// Returns the number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the maximum latency.
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns the mean latency.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => self.sum / n as u32,
+        }
+    }
+
+    /// Returns an upper bound of the `q`-quantile of latencies, where `q` is in `[0, 1]`.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_micros(1u64 << i).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// A call waiting for its response.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone)]
+pub struct InFlightCall {
+    /// The address of the server.
+    pub dst: SocketAddr,
+    /// The [`Request::ID`].
+    pub tag: u64,
+    /// When the call started.
+    pub start: crate::time::Instant,
+}
+
+impl InFlightCall {
+    /// Returns the time elapsed since the call started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A streaming RPC request.
+///
+/// A call opens a stream of [`Item`](StreamRequest::Item)s from the caller to the handler, and a
+/// stream of [`Response`](StreamRequest::Response)s back. Server-streaming calls send no items.
+///
+/// The ID must not collide with any [`Request::ID`].
+pub trait StreamRequest: Serialize + DeserializeOwned + Any + Send + Sync {
+    /// A message from the caller.
+    type Item: Serialize + DeserializeOwned + Any + Send + Sync;
+
+    /// A message from the handler.
+    type Response: Serialize + DeserializeOwned + Any + Send + Sync;
+
+    /// A unique ID.
+    const ID: u64;
+}
+
+/// The maximum number of messages of a stream that are sent but not yet read by the receiver.
+///
+/// Once the window is full, [`StreamSender::send`] waits for the receiver to catch up.
+pub const STREAM_WINDOW: u64 = 16;
+
 #[doc(hidden)]
 pub const fn hash_str(s: &str) -> u64 {
     // simple hash33
@@ -92,16 +451,123 @@ pub const fn hash_str(s: &str) -> u64 {
 }
 
 impl Endpoint {
+    /// Sets the default deadline of calls from this endpoint, or `None` to wait forever.
+    ///
+    /// It does not apply to [`call_timeout`](Endpoint::call_timeout), which has its own.
+    pub fn set_rpc_deadline(&self, deadline: Option<Duration>) {
+        self.rpc_options.lock().deadline = deadline;
+    }
+
+    /// Returns the default deadline of calls from this endpoint.
+    pub fn rpc_deadline(&self) -> Option<Duration> {
+        self.rpc_options.lock().deadline
+    }
+
+    /// Add an interceptor of incoming calls to the handlers of this endpoint.
+    ///
+    /// Interceptors run in the order they are added. A call is rejected if any interceptor
+    /// rejects it, and delayed by the sum of the delays. Interceptors only see unary calls.
+    pub fn add_rpc_interceptor(
+        &self,
+        f: impl Fn(&mut CallMeta) -> Verdict + Send + Sync + 'static,
+    ) {
+        self.rpc_options.lock().interceptors.push(Arc::new(f));
+    }
+
+    /// Runs the interceptors on an incoming call. Returns the delay, or `None` if rejected.
+    fn intercept(&self, call: &mut CallMeta) -> Option<Duration> {
+        let interceptors = self.rpc_options.lock().interceptors.clone();
+        let mut delay = Duration::ZERO;
+        for interceptor in interceptors {
+            match interceptor(call) {
+                Verdict::Accept => {}
+                Verdict::Reject => return None,
+                Verdict::Delay(d) => delay += d,
+            }
+        }
+        Some(delay)
+    }
+
+    /// Sets the maximum size of requests in calls from this endpoint, or `None` if unlimited.
+    ///
+    /// The size is that of the serialized request plus the data.
+    pub fn set_rpc_max_request_size(&self, size: Option<usize>) {
+        self.rpc_options.lock().max_request_size = size;
+    }
+
+    /// Sets the maximum size of responses in calls from this endpoint, or `None` if unlimited.
+    ///
+    /// The size is that of the serialized response plus the data.
+    pub fn set_rpc_max_response_size(&self, size: Option<usize>) {
+        self.rpc_options.lock().max_response_size = size;
+    }
+
+    /// Returns the statistics of calls from this endpoint.
+    #[cfg_attr(docsrs, doc(cfg(madsim)))]
+    pub fn rpc_metrics(&self) -> RpcMetrics {
+        self.rpc_options.lock().metrics.clone()
+    }
+
+    /// Clears the statistics of calls from this endpoint.
+    #[cfg_attr(docsrs, doc(cfg(madsim)))]
+    pub fn reset_rpc_metrics(&self) {
+        self.rpc_options.lock().metrics = RpcMetrics::default();
+    }
+
+    /// Returns the calls from this endpoint that are waiting for responses, oldest first.
+    #[cfg_attr(docsrs, doc(cfg(madsim)))]
+    pub fn rpc_in_flight(&self) -> Vec<InFlightCall> {
+        self.rpc_options
+            .lock()
+            .in_flight
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Records a call in the statistics.
+    async fn tracked<T>(
+        &self,
+        dst: SocketAddr,
+        tag: u64,
+        call: impl Future<Output = io::Result<T>>,
+    ) -> io::Result<T> {
+        let start = crate::time::Instant::now();
+        let seq = {
+            let mut options = self.rpc_options.lock();
+            let seq = options.next_call;
+            options.next_call += 1;
+            (options.in_flight).insert(seq, InFlightCall { dst, tag, start });
+            seq
+        };
+        let mut guard = InFlightGuard {
+            net: self,
+            seq,
+            done: false,
+        };
+        let res = call.await;
+        guard.done = true;
+        let latency = start.elapsed();
+        let mut options = self.rpc_options.lock();
+        options.in_flight.remove(&seq);
+        let metrics = &mut options.metrics;
+        metrics.total.record(latency, res.is_ok());
+        (metrics.requests.entry(tag).or_default()).record(latency, res.is_ok());
+        res
+    }
+
     /// Call function on a remote host with timeout.
+    ///
+    /// Returns [`DeadlineExceeded`] if the response does not arrive in time.
     pub async fn call_timeout<R: Request>(
         &self,
         dst: SocketAddr,
         request: R,
         timeout: Duration,
     ) -> io::Result<R::Response> {
-        crate::time::timeout(timeout, self.call(dst, request))
-            .await
-            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "RPC timeout"))?
+        let call = with_deadline(Some(timeout), self.call_inner(dst, request, Bytes::new()));
+        let (rsp, _data) = self.tracked(dst, R::ID, call).await?;
+        Ok(rsp)
     }
 
     /// Call function on a remote host.
@@ -116,17 +582,74 @@ impl Endpoint {
         dst: SocketAddr,
         request: R,
         data: &[u8],
+    ) -> io::Result<(R::Response, Bytes)> {
+        self.call_with_bytes(dst, request, Bytes::copy_from_slice(data))
+            .await
+    }
+
+    /// Call function on a remote host, sending and receiving data without copying it.
+    pub async fn call_with_bytes<R: Request>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+        data: Bytes,
+    ) -> io::Result<(R::Response, Bytes)> {
+        let deadline = self.rpc_deadline();
+        let call = with_deadline(deadline, self.call_inner(dst, request, data));
+        self.tracked(dst, R::ID, call).await
+    }
+
+    async fn call_inner<R: Request>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+        data: Bytes,
     ) -> io::Result<(R::Response, Bytes)> {
         let req_tag = R::ID;
         let rsp_tag = random::<u64>();
-        let data = Bytes::copy_from_slice(data);
+        let (max_request_size, max_response_size) = {
+            let options = self.rpc_options.lock();
+            (options.max_request_size, options.max_response_size)
+        };
+        if let Some(limit) = max_request_size {
+            let size = bincode::serialized_size(&request).unwrap() as usize + data.len();
+            PayloadTooLarge::check(size, limit, io::ErrorKind::InvalidInput)?;
+        }
+        let (net, node) = self.net_node();
+        let (delay, error) = net.inject_rpc_fault(node, dst, req_tag);
+        if !delay.is_zero() {
+            crate::time::sleep(delay).await;
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
         self.send_to_raw(dst, req_tag, Box::new((rsp_tag, request, data)))
             .await?;
+        let mut guard = CancelGuard {
+            net: self,
+            dst,
+            tag: rsp_tag,
+            done: false,
+        };
         let (rsp, from) = self.recv_from_raw(rsp_tag).await?;
+        guard.done = true;
         assert_eq!(from, dst);
-        let (rsp, data) = *rsp
-            .downcast::<(R::Response, Bytes)>()
-            .expect("message type mismatch");
+        let (rsp, data) = match rsp.downcast::<(R::Response, Bytes)>() {
+            Ok(rsp) => *rsp,
+            Err(rsp) => match rsp.downcast::<Incompatible>() {
+                Ok(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.0)),
+                Err(rsp) => {
+                    rsp.downcast::<Rejected>().expect("message type mismatch");
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, Rejected));
+                }
+            },
+        };
+        let rsp = (net.transcode(node, from, rsp))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(limit) = max_response_size {
+            let size = bincode::serialized_size(&rsp).unwrap() as usize + data.len();
+            PayloadTooLarge::check(size, limit, io::ErrorKind::InvalidData)?;
+        }
         Ok((rsp, data))
     }
 
@@ -144,8 +667,20 @@ impl Endpoint {
     where
         AsyncFn: FnMut(R, Bytes) -> Fut + Send + 'static,
         Fut: Future<Output = (R::Response, Vec<u8>)> + Send + 'static,
+    {
+        self.add_rpc_handler_with_bytes(move |req, data| {
+            f(req, data).map(|(rsp, data)| (rsp, Bytes::from(data)))
+        })
+    }
+
+    /// Add a RPC handler that send and receive data without copying it.
+    pub fn add_rpc_handler_with_bytes<R: Request, AsyncFn, Fut>(&self, mut f: AsyncFn)
+    where
+        AsyncFn: FnMut(R, Bytes) -> Fut + Send + 'static,
+        Fut: Future<Output = (R::Response, Bytes)> + Send + 'static,
     {
         let req_tag = R::ID;
+        self.listen_cancels();
         let net = self.clone();
         crate::task::spawn(async move {
             loop {
@@ -153,15 +688,1329 @@ impl Endpoint {
                 let (rsp_tag, req, data) = *data
                     .downcast::<(u64, R, Bytes)>()
                     .expect("message type mismatch");
+                let (sim, node) = net.net_node();
+                let req = match sim.transcode(node, from, req) {
+                    Ok(req) => req,
+                    Err(e) => {
+                        warn!(%from, tag = req_tag, "incompatible RPC request: {e}");
+                        let net = net.clone();
+                        crate::task::spawn(async move {
+                            net.send_to_raw(from, rsp_tag, Box::new(Incompatible(e)))
+                                .await
+                                .unwrap();
+                        });
+                        continue;
+                    }
+                };
+                let mut call = CallMeta {
+                    peer: from,
+                    tag: req_tag,
+                    size: 0,
+                    annotations: BTreeMap::new(),
+                };
+                if !net.rpc_options.lock().interceptors.is_empty() {
+                    call.size = bincode::serialized_size(&req).unwrap() as usize + data.len();
+                }
+                let Some(delay) = net.intercept(&mut call) else {
+                    debug!(%from, tag = req_tag, "RPC rejected");
+                    let net = net.clone();
+                    crate::task::spawn(async move {
+                        net.send_to_raw(from, rsp_tag, Box::new(Rejected))
+                            .await
+                            .unwrap();
+                    });
+                    continue;
+                };
                 let rsp_future = f(req, data);
+                let rsp_future = async move {
+                    if !delay.is_zero() {
+                        crate::time::sleep(delay).await;
+                    }
+                    CURRENT_CALL.scope(call, rsp_future).await
+                };
                 let net = net.clone();
+                let cancelled = Cancellable::new(&net, from, rsp_tag);
                 crate::task::spawn(async move {
-                    let (rsp, data) = rsp_future.await;
-                    net.send_to_raw(from, rsp_tag, Box::new((rsp, Bytes::from(data))))
+                    let (rsp, data) = select_biased! {
+                        rsp = rsp_future.fuse() => rsp,
+                        _ = cancelled.fuse() => {
+                            debug!(%from, tag = req_tag, "RPC cancelled");
+                            return;
+                        }
+                    };
+                    net.send_to_raw(from, rsp_tag, Box::new((rsp, data)))
                         .await
                         .unwrap();
                 });
             }
         });
     }
+
+    /// Spawns the task that cancels incoming calls, if not yet spawned.
+    ///
+    /// The caller sends the response tag of a call with [`CANCEL_TAG`] if it drops the call.
+    /// Since messages may be reordered, a cancellation of an unknown call is kept for
+    /// [`EARLY_CANCEL_TTL`] in case the call arrives later.
+    fn listen_cancels(&self) {
+        let mut options = self.rpc_options.lock();
+        if std::mem::replace(&mut options.cancel_listener, true) {
+            return;
+        }
+        drop(options);
+        let net = self.clone();
+        crate::task::spawn(async move {
+            loop {
+                let (data, from) = net.recv_from_raw(CANCEL_TAG).await.unwrap();
+                let rsp_tag = *data.downcast::<u64>().expect("message type mismatch");
+                let mut options = net.rpc_options.lock();
+                let key = (from, rsp_tag);
+                if let Some(tx) = options.cancels.remove(&key) {
+                    _ = tx.send(());
+                } else {
+                    // the call is finished, or has not arrived yet
+                    let now = crate::time::Instant::now();
+                    options.early_cancels.retain(|_, expiry| *expiry > now);
+                    options.early_cancels.insert(key, now + EARLY_CANCEL_TTL);
+                }
+            }
+        });
+    }
+
+    /// Open a bidirectional stream to a remote host.
+    ///
+    /// Returns the sender of items to the handler and the receiver of its responses.
+    pub async fn call_stream<R: StreamRequest>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+    ) -> io::Result<(StreamSender<R::Item>, StreamReceiver<R::Response>)> {
+        // each stream runs on its own connection, starting with the request
+        let (tx, rx) = self.connect1(dst).await?;
+        let request: Payload = Box::new(request);
+        tx.send(Box::new((R::ID, request))).await?;
+        Ok(split(tx, rx))
+    }
+
+    /// Open a stream of responses from a remote host.
+    pub async fn call_server_stream<R: StreamRequest>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+    ) -> io::Result<StreamReceiver<R::Response>> {
+        let (_tx, rx) = self.call_stream(dst, request).await?;
+        Ok(rx)
+    }
+
+    /// Add a streaming RPC handler.
+    ///
+    /// The handler is called with the request, the receiver of items from the caller and the
+    /// sender of responses. The response stream ends when the sender is dropped.
+    ///
+    /// Streams run on connections of their own, which a background task accepts from this
+    /// endpoint. That task takes every incoming connection, so an endpoint with stream handlers
+    /// must not be used with [`accept1`](Endpoint::accept1) as well; bind a dedicated endpoint
+    /// for the streaming service instead.
+    pub fn add_stream_handler<R: StreamRequest, AsyncFn, Fut>(&self, mut f: AsyncFn)
+    where
+        AsyncFn:
+            FnMut(R, StreamReceiver<R::Item>, StreamSender<R::Response>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let f = Mutex::new(f);
+        let handler: StreamHandler = Arc::new(move |req: Payload, tx: Sender, rx: Receiver| {
+            let req = *req.downcast::<R>().expect("message type mismatch");
+            let (tx, rx) = split(tx, rx);
+            crate::task::spawn((f.lock())(req, rx, tx));
+        });
+        let mut options = self.rpc_options.lock();
+        options.stream_handlers.insert(R::ID, handler);
+        if std::mem::replace(&mut options.stream_listener, true) {
+            return;
+        }
+        drop(options);
+        let net = self.clone();
+        crate::task::spawn(async move {
+            loop {
+                let (tx, mut rx, from) = net.accept1().await.unwrap();
+                let net = net.clone();
+                crate::task::spawn(async move {
+                    let Ok(req) = rx.recv().await else {
+                        return;
+                    };
+                    let (tag, req) = *req
+                        .downcast::<(u64, Payload)>()
+                        .expect("message type mismatch");
+                    let handler = net.rpc_options.lock().stream_handlers.get(&tag).cloned();
+                    match handler {
+                        Some(handler) => handler(req, tx, rx),
+                        None => warn!(%from, tag, "no handler for streaming call"),
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// A rule that injects latency or errors into RPC calls.
+///
+/// Both apply on the caller before the request is sent, so failed calls do not reach the handler.
+///
+/// # Example
+///
+/// ```ignore
+/// // AppendEntries from node 2 to node 3 fails 5% of the time
+/// net.add_rpc_fault(
+///     RpcFault::new()
+///         .request::<AppendEntries>()
+///         .between(node2, node3)
+///         .fail(0.05, io::ErrorKind::ConnectionReset),
+/// );
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcFault {
+    /// The tag of calls, i.e. [`Request::ID`], or `None` for all calls.
+    pub tag: Option<u64>,
+    /// The calling nodes.
+    pub src: NodeSelector,
+    /// The called nodes.
+    pub dst: NodeSelector,
+    /// The range of extra latency of calls.
+    pub latency: Range<Duration>,
+    /// The probability that a call fails.
+    pub failure_rate: f64,
+    /// The kind of errors of failed calls.
+    pub error: io::ErrorKind,
+}
+
+impl Default for RpcFault {
+    fn default() -> Self {
+        RpcFault {
+            tag: None,
+            src: NodeSelector::Any,
+            dst: NodeSelector::Any,
+            latency: Duration::ZERO..Duration::ZERO,
+            failure_rate: 0.0,
+            error: io::ErrorKind::Other,
+        }
+    }
+}
+
+impl RpcFault {
+    /// Creates a rule for all calls that injects nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only applies to calls of request `R`.
+    pub fn request<R: Request>(mut self) -> Self {
+        self.tag = Some(R::ID);
+        self
+    }
+
+    /// Only applies to calls from nodes selected by `src` to nodes selected by `dst`.
+    pub fn between(mut self, src: impl Into<NodeSelector>, dst: impl Into<NodeSelector>) -> Self {
+        self.src = src.into();
+        self.dst = dst.into();
+        self
+    }
+
+    /// Adds a random latency in `range` to calls.
+    pub fn latency(mut self, range: Range<Duration>) -> Self {
+        self.latency = range;
+        self
+    }
+
+    /// Makes calls fail with `error` at the given probability.
+    pub fn fail(mut self, probability: f64, error: io::ErrorKind) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "invalid probability: {probability}"
+        );
+        self.failure_rate = probability;
+        self.error = error;
+        self
+    }
+}
+
+impl NetSim {
+    /// Add a rule to inject latency or errors into RPC calls.
+    ///
+    /// If multiple rules match a call, the latencies add up and any of them may fail it.
+    pub fn add_rpc_fault(&self, fault: RpcFault) {
+        self.rpc_faults.lock().push(fault);
+    }
+
+    /// Remove all rules added by [`add_rpc_fault`](NetSim::add_rpc_fault).
+    pub fn clear_rpc_faults(&self) {
+        self.rpc_faults.lock().clear();
+    }
+
+    /// Returns the extra latency and the injected error of a call.
+    fn inject_rpc_fault(
+        &self,
+        src: NodeId,
+        dst: SocketAddr,
+        tag: u64,
+    ) -> (Duration, Option<io::Error>) {
+        let faults = self.rpc_faults.lock();
+        if faults.is_empty() {
+            return (Duration::ZERO, None);
+        }
+        let network = self.network.lock();
+        let Some(dst) = network.resolve_dest_node(src, dst, IpProtocol::Udp) else {
+            return (Duration::ZERO, None);
+        };
+        let mut delay = Duration::ZERO;
+        let mut error = None;
+        for fault in faults.iter() {
+            if fault.tag.is_some_and(|t| t != tag)
+                || !network.select(&fault.src, src)
+                || !network.select(&fault.dst, dst)
+            {
+                continue;
+            }
+            delay += if fault.latency.is_empty() {
+                fault.latency.start
+            } else {
+                self.rand.with(|rng| rng.gen_range(fault.latency.clone()))
+            };
+            if fault.failure_rate > 0.0
+                && error.is_none()
+                && self.rand.with(|rng| rng.gen_bool(fault.failure_rate))
+            {
+                debug!(%src, %dst, tag, kind = ?fault.error, "inject RPC failure");
+                error = Some(io::Error::new(fault.error, "injected RPC failure"));
+            }
+        }
+        (delay, error)
+    }
+}
+
+/// The response to a request that the handler failed to decode.
+struct Incompatible(String);
+
+/// An error of decoding a message.
+pub type DecodeError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The wire format of a message type in a protocol version.
+struct Codec {
+    encode: Arc<dyn Fn(&dyn Any) -> Vec<u8> + Send + Sync>,
+    decode: Arc<dyn Fn(&[u8]) -> Result<Box<dyn Any>, DecodeError> + Send + Sync>,
+}
+
+/// Protocol versions of nodes and the wire formats of messages in each version.
+#[derive(Default)]
+pub(super) struct RpcVersions {
+    nodes: HashMap<NodeId, u32>,
+    codecs: HashMap<(TypeId, u32), Codec>,
+}
+
+impl NetSim {
+    /// Set the protocol version of a node for RPC. Nodes are of version 0 by default.
+    pub fn set_rpc_version(&self, node: NodeId, version: u32) {
+        self.rpc_versions.lock().nodes.insert(node, version);
+    }
+
+    /// Register the wire format of message type `T` in a protocol version.
+    ///
+    /// Messages without a registered format are encoded by [`bincode`] as the current type.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // version 1 nodes send `RequestV1` and know nothing about the new field
+    /// net.add_rpc_codec::<Request>(
+    ///     1,
+    ///     |req| bincode::serialize(&RequestV1::from(req)).unwrap(),
+    ///     |buf| Ok(bincode::deserialize::<RequestV1>(buf)?.into()),
+    /// );
+    /// net.set_rpc_version(node1, 1);
+    /// ```
+    pub fn add_rpc_codec<T: Any>(
+        &self,
+        version: u32,
+        encode: impl Fn(&T) -> Vec<u8> + Send + Sync + 'static,
+        decode: impl Fn(&[u8]) -> Result<T, DecodeError> + Send + Sync + 'static,
+    ) {
+        let codec = Codec {
+            encode: Arc::new(move |msg: &dyn Any| encode(msg.downcast_ref::<T>().unwrap())),
+            decode: Arc::new(move |buf: &[u8]| Ok(Box::new(decode(buf)?) as Box<dyn Any>)),
+        };
+        let key = (TypeId::of::<T>(), version);
+        self.rpc_versions.lock().codecs.insert(key, codec);
+    }
+
+    /// Passes a message from `peer` to `node` through the wire formats of their versions.
+    fn transcode<T: Serialize + DeserializeOwned + Any>(
+        &self,
+        node: NodeId,
+        peer: SocketAddr,
+        msg: T,
+    ) -> Result<T, String> {
+        let versions = self.rpc_versions.lock();
+        if versions.nodes.is_empty() {
+            return Ok(msg);
+        }
+        let peer = (self.network.lock()).resolve_dest_node(node, peer, IpProtocol::Udp);
+        let version = |node| versions.nodes.get(&node).copied().unwrap_or(0);
+        let (src, dst) = (peer.map_or(0, version), version(node));
+        if src == dst {
+            return Ok(msg);
+        }
+        let id = TypeId::of::<T>();
+        let buf = match versions.codecs.get(&(id, src)) {
+            Some(codec) => (codec.encode)(&msg),
+            None => bincode::serialize(&msg).unwrap(),
+        };
+        let res = match versions.codecs.get(&(id, dst)) {
+            Some(codec) => (codec.decode)(&buf).map(|msg| *msg.downcast::<T>().unwrap()),
+            None => bincode::deserialize(&buf).map_err(DecodeError::from),
+        };
+        res.map_err(|e| {
+            let name = std::any::type_name::<T>();
+            format!("failed to decode {name} from version {src} in version {dst}: {e}")
+        })
+    }
+}
+
+/// Awaits a call, failing with [`DeadlineExceeded`] if it does not complete within `deadline`.
+async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    call: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    let Some(deadline) = deadline else {
+        return call.await;
+    };
+    crate::time::timeout(deadline, call)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, DeadlineExceeded))?
+}
+
+/// Records a call as cancelled if dropped before it finishes.
+struct InFlightGuard<'a> {
+    net: &'a Endpoint,
+    seq: u64,
+    done: bool,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut options = self.net.rpc_options.lock();
+        if let Some(call) = options.in_flight.remove(&self.seq) {
+            let metrics = &mut options.metrics;
+            metrics.total.cancelled += 1;
+            metrics.requests.entry(call.tag).or_default().cancelled += 1;
+        }
+    }
+}
+
+/// Cancels the handler of a call if dropped before the response arrives.
+struct CancelGuard<'a> {
+    net: &'a Endpoint,
+    dst: SocketAddr,
+    tag: u64,
+    done: bool,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        // the handler has finished, or the node is killed
+        let alive = crate::context::try_current_task().is_some_and(|t| !t.node.is_killed());
+        if self.done || !alive {
+            return;
+        }
+        let (net, dst, tag) = (self.net.clone(), self.dst, self.tag);
+        crate::task::spawn(async move {
+            _ = net.send_to_raw(dst, CANCEL_TAG, Box::new(tag)).await;
+        });
+    }
+}
+
+/// Completes when an incoming call is cancelled by the caller.
+///
+/// The call is forgotten by the endpoint when this is dropped.
+struct Cancellable {
+    net: Endpoint,
+    key: (SocketAddr, u64),
+    rx: oneshot::Receiver<()>,
+}
+
+impl Cancellable {
+    fn new(net: &Endpoint, from: SocketAddr, rsp_tag: u64) -> Self {
+        let (tx, rx) = oneshot::channel();
+        let key = (from, rsp_tag);
+        let mut options = net.rpc_options.lock();
+        let early = options.early_cancels.remove(&key);
+        if early.is_some_and(|expiry| expiry > crate::time::Instant::now()) {
+            _ = tx.send(());
+        } else {
+            options.cancels.insert(key, tx);
+        }
+        drop(options);
+        Cancellable {
+            net: net.clone(),
+            key,
+            rx,
+        }
+    }
+}
+
+impl Future for Cancellable {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match ready!(self.rx.poll_unpin(cx)) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Cancellable {
+    fn drop(&mut self) {
+        self.net.rpc_options.lock().cancels.remove(&self.key);
+    }
+}
+
+/// A control frame of a stream. Other frames are messages.
+enum Control {
+    /// The sender has been dropped, and no more messages follow.
+    End,
+    /// The receiver has read this many messages.
+    Ack(u64),
+    /// The receiver has been dropped.
+    Close,
+}
+
+/// Splits the connection of a stream into the sender and the receiver.
+fn split<In: Any + Send + Sync, Out: Any + Send + Sync>(
+    tx: Sender,
+    mut rx: Receiver,
+) -> (StreamSender<Out>, StreamReceiver<In>) {
+    let tx = Arc::new(tx);
+    let (ack_tx, ack_rx) = mpsc::unbounded_channel();
+    let (msg_tx, msg_rx) = mpsc::unbounded_channel();
+    let closed = Arc::new(AtomicBool::new(false));
+    // the connection is closed once both halves are dropped
+    let (close_tx, mut close_rx) = oneshot::channel::<()>();
+    let close_tx = Arc::new(close_tx);
+    let closed0 = closed.clone();
+    crate::task::spawn(async move {
+        // dropping the channels ends the stream and wakes the sender
+        let (mut msg_tx, mut ack_tx) = (Some(msg_tx), Some(ack_tx));
+        loop {
+            let frame = select_biased! {
+                _ = (&mut close_rx).fuse() => return,
+                frame = rx.recv().fuse() => frame,
+            };
+            let Ok(frame) = frame else {
+                return;
+            };
+            match frame.downcast::<Control>() {
+                Ok(control) => match *control {
+                    Control::End => msg_tx = None,
+                    Control::Ack(acked) => {
+                        if let Some(ack_tx) = &ack_tx {
+                            _ = ack_tx.send(acked);
+                        }
+                    }
+                    Control::Close => {
+                        closed0.store(true, Ordering::Relaxed);
+                        ack_tx = None;
+                    }
+                },
+                Err(msg) => {
+                    let msg = *msg.downcast::<In>().expect("message type mismatch");
+                    if let Some(msg_tx) = &msg_tx {
+                        _ = msg_tx.send(msg);
+                    }
+                }
+            }
+        }
+    });
+    let sender = StreamSender {
+        tx: tx.clone(),
+        acks: ack_rx,
+        sent: 0,
+        acked: 0,
+        closed,
+        _close: close_tx.clone(),
+        _phantom: PhantomData,
+    };
+    let receiver = StreamReceiver {
+        tx,
+        msgs: msg_rx,
+        received: 0,
+        _close: close_tx,
+    };
+    (sender, receiver)
+}
+
+/// Sends a control frame when a half of a stream is dropped.
+fn send_on_drop(tx: &Arc<Sender>, control: Control) {
+    // no one to notify if the node is killed
+    let alive = crate::context::try_current_task().is_some_and(|t| !t.node.is_killed());
+    if !alive {
+        return;
+    }
+    let tx = tx.clone();
+    crate::task::spawn(async move {
+        _ = tx.send(Box::new(control)).await;
+    });
+}
+
+/// The sending half of a stream.
+///
+/// The stream ends when the sender is dropped.
+pub struct StreamSender<T: Any + Send + Sync> {
+    tx: Arc<Sender>,
+    /// Acknowledgements from the receiver.
+    acks: mpsc::UnboundedReceiver<u64>,
+    /// The number of messages sent.
+    sent: u64,
+    /// The number of messages read by the receiver.
+    acked: u64,
+    /// Whether the receiver has been dropped.
+    closed: Arc<AtomicBool>,
+    _close: Arc<oneshot::Sender<()>>,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<T: Any + Send + Sync> StreamSender<T> {
+    /// Sends a message, waiting for the receiver to catch up if the window is full.
+    ///
+    /// Fails with [`BrokenPipe`](io::ErrorKind::BrokenPipe) once the receiver is dropped.
+    pub async fn send(&mut self, msg: T) -> io::Result<()> {
+        while self.sent - self.acked >= STREAM_WINDOW {
+            let Some(acked) = self.acks.recv().await else {
+                break;
+            };
+            self.acked = self.acked.max(acked);
+        }
+        if self.closed.load(Ordering::Relaxed) {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stream receiver dropped",
+            ));
+        }
+        self.tx.send(Box::new(msg)).await?;
+        self.sent += 1;
+        Ok(())
+    }
+}
+
+impl<T: Any + Send + Sync> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        send_on_drop(&self.tx, Control::End);
+    }
+}
+
+/// The receiving half of a stream.
+///
+/// Messages are yielded in the order they were sent.
+pub struct StreamReceiver<T: Any + Send + Sync> {
+    /// Acknowledgements are sent back on the connection.
+    tx: Arc<Sender>,
+    msgs: mpsc::UnboundedReceiver<T>,
+    /// The number of messages read.
+    received: u64,
+    _close: Arc<oneshot::Sender<()>>,
+}
+
+impl<T: Any + Send + Sync> Unpin for StreamReceiver<T> {}
+
+impl<T: Any + Send + Sync> StreamReceiver<T> {
+    /// Receives the next message, or `None` if the stream has ended.
+    pub async fn recv(&mut self) -> Option<T> {
+        futures_util::StreamExt::next(self).await
+    }
+}
+
+impl<T: Any + Send + Sync> Stream for StreamReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let msg = ready!(self.msgs.poll_recv(cx));
+        if msg.is_some() {
+            self.received += 1;
+            // tell the sender how many messages have been read
+            if self.received % (STREAM_WINDOW / 2) == 0 {
+                let (tx, acked) = (self.tx.clone(), self.received);
+                crate::task::spawn(async move {
+                    _ = tx.send(Box::new(Control::Ack(acked))).await;
+                });
+            }
+        }
+        Poll::Ready(msg)
+    }
+}
+
+impl<T: Any + Send + Sync> Drop for StreamReceiver<T> {
+    fn drop(&mut self) {
+        send_on_drop(&self.tx, Control::Close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, time::*};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Serialize, Deserialize)]
+    struct Wait(u64);
+
+    impl Request for Wait {
+        type Response = ();
+        const ID: u64 = 3;
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        /// Counts the handlers that have been dropped.
+        struct Guard(Arc<AtomicU64>);
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let dropped = Arc::new(AtomicU64::new(0));
+        let dropped0 = dropped.clone();
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(move |req: Wait| {
+                let guard = Guard(dropped0.clone());
+                async move {
+                    sleep(Duration::from_secs(req.0)).await;
+                    drop(guard);
+                }
+            });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            net.call(addr1, Wait(1)).await.unwrap();
+            assert_eq!(dropped.load(Ordering::Relaxed), 1);
+
+            let t0 = Instant::now();
+            let err = net
+                .call_timeout(addr1, Wait(10), Duration::from_secs(1))
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            sleep(Duration::from_secs(1)).await;
+            // the handler is dropped long before it would have finished
+            assert_eq!(dropped.load(Ordering::Relaxed), 2);
+            assert!(t0.elapsed() < Duration::from_secs(3));
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn cancel_before_call() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        /// Counts the handlers that have been dropped.
+        struct Guard(Arc<AtomicU64>);
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let dropped = Arc::new(AtomicU64::new(0));
+        let dropped0 = dropped.clone();
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(move |req: Wait| {
+                let guard = Guard(dropped0.clone());
+                async move {
+                    sleep(Duration::from_secs(req.0)).await;
+                    drop(guard);
+                }
+            });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let server = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            // the cancellation overtakes the request
+            let rsp_tag = 42;
+            net.send_to_raw(addr1, CANCEL_TAG, Box::new(rsp_tag))
+                .await
+                .unwrap();
+            sleep(Duration::from_millis(100)).await;
+            let request = (rsp_tag, Wait(10), Bytes::new());
+            net.send_to_raw(addr1, Wait::ID, Box::new(request))
+                .await
+                .unwrap();
+            sleep(Duration::from_millis(100)).await;
+            // the handler was dropped as soon as the call arrived
+            assert_eq!(dropped.load(Ordering::Relaxed), 1);
+            let options = server.rpc_options.lock();
+            assert!(options.cancels.is_empty());
+            assert!(options.early_cancels.is_empty());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn mailbox_bounded() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(|req: Wait| sleep(Duration::from_secs(req.0)));
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let server = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            for _ in 0..100 {
+                net.call(addr1, Wait(0)).await.unwrap();
+            }
+            for _ in 0..20 {
+                (net.call_timeout(addr1, Wait(10), Duration::from_secs(1)))
+                    .await
+                    .unwrap_err();
+            }
+            sleep(Duration::from_secs(1)).await;
+            // only the handler and the cancellation listener are waiting
+            assert_eq!(server.mailbox_len(), (2, 0));
+            assert!(server.rpc_options.lock().cancels.is_empty());
+            assert_eq!(net.mailbox_len().1, 0);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn deadline() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(|req: Wait| sleep(Duration::from_secs(req.0)));
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            net.set_rpc_deadline(Some(Duration::from_secs(2)));
+            net.call(addr1, Wait(1)).await.unwrap();
+
+            let t0 = Instant::now();
+            let err = net.call(addr1, Wait(10)).await.unwrap_err();
+            assert!(DeadlineExceeded::matches(&err), "{err}");
+            assert!(t0.elapsed() >= Duration::from_secs(2));
+            assert!(t0.elapsed() < Duration::from_secs(3));
+
+            // the timeout of the call overrides the default
+            let err = (net.call_timeout(addr1, Wait(2), Duration::from_secs(1)))
+                .await
+                .unwrap_err();
+            assert!(DeadlineExceeded::matches(&err), "{err}");
+            net.call_timeout(addr1, Wait(2), Duration::from_secs(5))
+                .await
+                .unwrap();
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn metrics() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(|req: Wait| sleep(Duration::from_secs(req.0)));
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            net.call(addr1, Wait(1)).await.unwrap();
+            net.call(addr1, Wait(3)).await.unwrap();
+            let err = net.call_timeout(addr1, Wait(10), Duration::from_secs(2));
+            err.await.unwrap_err();
+
+            let net1 = net.clone();
+            let call = crate::task::spawn(async move { net1.call(addr1, Wait(10)).await });
+            sleep(Duration::from_secs(1)).await;
+            let in_flight = net.rpc_in_flight();
+            assert_eq!(in_flight.len(), 1);
+            assert_eq!(in_flight[0].dst, addr1);
+            assert_eq!(in_flight[0].tag, Wait::ID);
+            assert!(in_flight[0].elapsed() >= Duration::from_secs(1));
+            call.abort();
+            sleep(Duration::from_secs(1)).await;
+            assert!(net.rpc_in_flight().is_empty());
+
+            let metrics = net.rpc_metrics();
+            let stats = metrics.request::<Wait>();
+            assert_eq!(stats.calls, 3);
+            assert_eq!(stats.errors, 1);
+            assert_eq!(stats.cancelled, 1);
+            assert_eq!(stats.latency.count(), 3);
+            assert!(stats.latency.max() >= Duration::from_secs(3));
+            assert!(stats.latency.max() < Duration::from_secs(4));
+            assert!(stats.latency.quantile(0.5) >= Duration::from_secs(2));
+            assert_eq!(metrics.total.calls, 3);
+
+            net.reset_rpc_metrics();
+            assert_eq!(net.rpc_metrics().total.calls, 0);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Echo(Vec<u8>);
+
+    impl Request for Echo {
+        type Response = Vec<u8>;
+        const ID: u64 = 4;
+    }
+
+    #[test]
+    fn max_payload_size() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            // responds with twice the bytes
+            net.add_rpc_handler(|req: Echo| async move { [&req.0[..], &req.0[..]].concat() });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            net.set_rpc_max_request_size(Some(100));
+            net.set_rpc_max_response_size(Some(150));
+            // a `Vec<u8>` is serialized with a length prefix of 8 bytes
+            net.call(addr1, Echo(vec![0; 50])).await.unwrap();
+
+            let err = net.call(addr1, Echo(vec![0; 100])).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let inner = err.get_ref().unwrap().downcast_ref::<PayloadTooLarge>();
+            assert_eq!(
+                inner,
+                Some(&PayloadTooLarge {
+                    size: 108,
+                    limit: 100
+                })
+            );
+
+            let err = net.call(addr1, Echo(vec![0; 80])).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let inner = err.get_ref().unwrap().downcast_ref::<PayloadTooLarge>();
+            assert_eq!(
+                inner,
+                Some(&PayloadTooLarge {
+                    size: 168,
+                    limit: 150
+                })
+            );
+
+            net.set_rpc_max_request_size(None);
+            net.set_rpc_max_response_size(None);
+            net.call(addr1, Echo(vec![0; 1000])).await.unwrap();
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn call_with_bytes() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            // returns the data as is
+            net.add_rpc_handler_with_bytes(|_: Wait, data| async move { ((), data) });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            let data = Bytes::from(vec![1; 0x1000]);
+            let ((), rsp) = net
+                .call_with_bytes(addr1, Wait(0), data.clone())
+                .await
+                .unwrap();
+            // not copied on the way there and back
+            assert_eq!(rsp.as_ptr(), data.as_ptr());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn interceptors() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_interceptor(|call| {
+                let user = format!("user-{}", call.peer.ip());
+                call.annotations.insert("user".into(), user);
+                Verdict::Accept
+            });
+            net.add_rpc_interceptor(|call| match call.tag {
+                Echo::ID if call.size > 100 => Verdict::Reject,
+                Wait::ID => Verdict::Delay(Duration::from_secs(1)),
+                _ => Verdict::Accept,
+            });
+            // responds with the annotated user
+            net.add_rpc_handler(|_: Echo| async move {
+                let call = current_call().unwrap();
+                call.annotations["user"].as_bytes().to_vec()
+            });
+            net.add_rpc_handler(|req: Wait| sleep(Duration::from_secs(req.0)));
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            let rsp = net.call(addr1, Echo(vec![0; 10])).await.unwrap();
+            assert_eq!(rsp, b"user-10.0.0.2");
+
+            let err = net.call(addr1, Echo(vec![0; 100])).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+            assert!(err.get_ref().unwrap().is::<Rejected>());
+
+            let t0 = Instant::now();
+            net.call(addr1, Wait(0)).await.unwrap();
+            assert!(t0.elapsed() >= Duration::from_secs(1));
+            assert!(t0.elapsed() < Duration::from_secs(2));
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn rpc_fault() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let addr3 = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let node3 = runtime.create_node().ip(addr3.ip()).build();
+
+        let (id1, id2) = (node1.id(), node2.id());
+
+        let server = node1.spawn(async move {
+            let sim = NetSim::current();
+            sim.add_rpc_fault(
+                RpcFault::new()
+                    .request::<Echo>()
+                    .between(id2, id1)
+                    .fail(0.5, io::ErrorKind::ConnectionReset),
+            );
+            sim.add_rpc_fault(
+                RpcFault::new()
+                    .request::<Wait>()
+                    .latency(Duration::from_secs(1)..Duration::from_secs(2)),
+            );
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(|req: Echo| async move { req.0 });
+            net.add_rpc_handler(|req: Wait| sleep(Duration::from_secs(req.0)));
+            net
+        });
+        let server = async move { server.await.unwrap() }.shared();
+
+        let server1 = server.clone();
+        let f2 = node2.spawn(async move {
+            let _net = server1.await;
+            let net = Endpoint::bind(addr2).await.unwrap();
+            let mut failed = 0;
+            for _ in 0..100 {
+                if let Err(e) = net.call(addr1, Echo(vec![])).await {
+                    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+                    failed += 1;
+                }
+            }
+            assert!((30..70).contains(&failed), "{failed}");
+
+            let t0 = Instant::now();
+            net.call(addr1, Wait(0)).await.unwrap();
+            assert!(t0.elapsed() >= Duration::from_secs(1));
+            assert!(t0.elapsed() < Duration::from_secs(2) + Duration::from_millis(100));
+        });
+        // other links are not affected
+        let f3 = node3.spawn(async move {
+            let _net = server.await;
+            let net = Endpoint::bind(addr3).await.unwrap();
+            for _ in 0..100 {
+                net.call(addr1, Echo(vec![])).await.unwrap();
+            }
+        });
+        runtime.block_on(async move {
+            f2.await.unwrap();
+            f3.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn version_skew() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let id2 = node2.id();
+
+        /// The request in version 1.
+        #[derive(Serialize, Deserialize)]
+        struct PutV1 {
+            key: u32,
+        }
+
+        /// The request in the current version with a new field.
+        #[derive(Serialize, Deserialize)]
+        struct Put {
+            key: u32,
+            ttl: Option<u32>,
+        }
+
+        impl Request for Put {
+            type Response = u32;
+            const ID: u64 = 5;
+        }
+
+        let server = node1.spawn(async move {
+            let sim = NetSim::current();
+            sim.set_rpc_version(id2, 1);
+            sim.add_rpc_codec::<Put>(
+                1,
+                |req| bincode::serialize(&PutV1 { key: req.key }).unwrap(),
+                |buf| {
+                    let req: PutV1 = bincode::deserialize(buf)?;
+                    Ok(Put {
+                        key: req.key,
+                        ttl: None,
+                    })
+                },
+            );
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_rpc_handler(|req: Put| async move { req.key });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            // the current version can not decode requests from version 1
+            let err = net
+                .call(addr1, Put { key: 1, ttl: None })
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            // until both formats are accepted
+            NetSim::current().add_rpc_codec::<Put>(
+                0,
+                |req| bincode::serialize(req).unwrap(),
+                |buf| match bincode::deserialize::<Put>(buf) {
+                    Ok(req) => Ok(req),
+                    Err(_) => {
+                        let req: PutV1 = bincode::deserialize(buf)?;
+                        Ok(Put {
+                            key: req.key,
+                            ttl: None,
+                        })
+                    }
+                },
+            );
+            let rsp = net.call(addr1, Put { key: 1, ttl: None }).await.unwrap();
+            assert_eq!(rsp, 1);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Sum;
+
+    impl StreamRequest for Sum {
+        type Item = u64;
+        type Response = u64;
+        const ID: u64 = 1;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Count(u64);
+
+    impl StreamRequest for Count {
+        type Item = ();
+        type Response = u64;
+        const ID: u64 = 2;
+    }
+
+    #[test]
+    fn stream() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            // replies with the running sum of items
+            net.add_stream_handler(|_: Sum, mut rx, mut tx| async move {
+                let mut sum = 0;
+                while let Some(x) = rx.recv().await {
+                    sum += x;
+                    tx.send(sum).await.unwrap();
+                }
+            });
+            net.add_stream_handler(|req: Count, _rx, mut tx| async move {
+                for i in 0..req.0 {
+                    tx.send(i).await.unwrap();
+                }
+            });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+
+            let (mut tx, mut rx) = net.call_stream(addr1, Sum).await.unwrap();
+            for i in 1..=100 {
+                tx.send(i).await.unwrap();
+                assert_eq!(rx.recv().await, Some(i * (i + 1) / 2));
+            }
+            drop(tx);
+            assert_eq!(rx.recv().await, None);
+
+            let rx = net.call_server_stream(addr1, Count(100)).await.unwrap();
+            let items: Vec<u64> = futures_util::StreamExt::collect(rx).await;
+            assert_eq!(items, (0..100).collect::<Vec<_>>());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn stream_backpressure() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let sent0 = sent.clone();
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_stream_handler(move |req: Count, _rx, mut tx| {
+                let sent = sent0.clone();
+                async move {
+                    for i in 0..req.0 {
+                        tx.send(i).await.unwrap();
+                        sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            let mut rx = net.call_server_stream(addr1, Count(1000)).await.unwrap();
+            sleep(Duration::from_secs(1)).await;
+            // the handler stops once the window is full
+            assert_eq!(sent.load(Ordering::Relaxed), STREAM_WINDOW);
+            for i in 0..1000 {
+                assert_eq!(rx.recv().await, Some(i));
+                assert!(sent.load(Ordering::Relaxed) <= i + 1 + STREAM_WINDOW);
+            }
+            assert_eq!(rx.recv().await, None);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn stream_receiver_dropped() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+
+        let (err_tx, err_rx) = oneshot::channel();
+        let err_tx = Mutex::new(Some(err_tx));
+        let server = node1.spawn(async move {
+            let net = Endpoint::bind(addr1).await.unwrap();
+            net.add_stream_handler(move |_: Sum, _rx, mut tx| {
+                let err_tx = err_tx.lock().take().unwrap();
+                async move {
+                    for i in 0.. {
+                        if let Err(e) = tx.send(i).await {
+                            err_tx.send(e.kind()).unwrap();
+                            return;
+                        }
+                    }
+                }
+            });
+            net
+        });
+
+        let f = node2.spawn(async move {
+            let _net = server.await.unwrap();
+            let net = Endpoint::bind(addr2).await.unwrap();
+            // keep the sender so that the connection stays open
+            let (_tx, mut rx) = net.call_stream(addr1, Sum).await.unwrap();
+            for i in 0..3 {
+                assert_eq!(rx.recv().await, Some(i));
+            }
+            drop(rx);
+            let kind = timeout(Duration::from_secs(1), err_rx).await.unwrap();
+            assert_eq!(kind.unwrap(), io::ErrorKind::BrokenPipe);
+        });
+        runtime.block_on(f).unwrap();
+    }
 }
@@ -178,6 +178,50 @@ mod tests {
         runtime.block_on(f2).unwrap();
     }
 
+    #[test]
+    fn update_config_on_established_connection() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_ = barrier.clone();
+
+        let f1 = node1.spawn(async move {
+            let listener = TcpListener::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let net = plugin::simulator::<NetSim>();
+            net.update_config(|cfg| {
+                cfg.send_latency = Duration::from_secs(10)..Duration::from_secs(11);
+            });
+            stream.write_all(b"hello world").await.unwrap();
+            stream.flush().await.unwrap();
+
+            // speed up the network while the message is in flight
+            crate::time::sleep(Duration::from_secs(1)).await;
+            net.update_config(|cfg| {
+                cfg.send_latency = Duration::from_millis(1)..Duration::from_millis(10);
+            });
+            stream
+        });
+
+        let f2 = node2.spawn(async move {
+            barrier.wait().await;
+            let mut stream = TcpStream::connect(addr1).await.unwrap();
+            let mut buf = [0; 20];
+            let len = timeout(Duration::from_secs(5), stream.read(&mut buf))
+                .await
+                .expect("config should apply to the in-flight message")
+                .unwrap();
+            assert_eq!(&buf[0..len], b"hello world");
+        });
+
+        runtime.block_on(f1).unwrap();
+        runtime.block_on(f2).unwrap();
+    }
+
     #[test]
     fn close() {
         let runtime = Runtime::new();
@@ -73,6 +73,19 @@ impl TcpStream {
         let net = plugin::simulator::<NetSim>();
         net.rand_delay().await?;
 
+        if net.is_gateway(addr) {
+            let (tx, rx, local_addr) = net.connect_gateway(addr).await?;
+            return Ok(TcpStream {
+                guard: None,
+                addr: local_addr,
+                peer: addr,
+                write_buf: Default::default(),
+                read_buf: Default::default(),
+                tx,
+                rx,
+            });
+        }
+
         // send a request to listener and wait for TcpStream
         // FIXME: the port it uses should not be exclusive
         let guard = BindGuard::bind("0.0.0.0:0", Tcp, Arc::new(TcpStreamSocket)).await?;
@@ -0,0 +1,135 @@
+//! Deterministic network weather.
+//!
+//! The weather generator degrades random links from time to time, so that long-running
+//! simulations experience non-stationary network conditions. It is driven by the random
+//! seed, thus deterministic.
+
+use super::LinkConfig;
+use crate::rand::Rng;
+use std::time::Duration;
+
+/// Configuration of the network weather generator.
+///
+/// Weather events happen as a Poisson process. Each event degrades a random link for a while
+/// with one of the following:
+///
+/// - Congestion: latency increases and bandwidth decreases.
+/// - Loss burst: packet loss rate increases.
+/// - Latency spike: a fixed extra latency is added.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weather {
+    /// The intensity in `(0, 1]`. Higher intensity makes events more frequent and more severe.
+    pub intensity: f64,
+    /// The mean interval between events at full intensity.
+    pub mean_interval: Duration,
+    /// The mean duration of events.
+    pub mean_duration: Duration,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather {
+            intensity: 0.5,
+            mean_interval: Duration::from_secs(10),
+            mean_duration: Duration::from_secs(2),
+        }
+    }
+}
+
+/// The kind of a weather event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Congestion,
+    LossBurst,
+    LatencySpike,
+}
+
+/// The bandwidth assumed for links with unlimited bandwidth during congestion. 100 Mbps.
+const CONGESTION_BANDWIDTH: u64 = 12_500_000;
+
+impl Weather {
+    /// Returns the interval until the next event.
+    pub(super) fn next_interval(&self, rng: &mut impl Rng) -> Duration {
+        let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+        (self.mean_interval.div_f64(self.intensity)).mul_f64(-u.ln())
+    }
+
+    /// Returns a random kind and duration of an event.
+    pub(super) fn next_event(&self, rng: &mut impl Rng) -> (Kind, Duration) {
+        let kind = match rng.gen_range(0..3) {
+            0 => Kind::Congestion,
+            1 => Kind::LossBurst,
+            _ => Kind::LatencySpike,
+        };
+        let duration = self.mean_duration.mul_f64(rng.gen_range(0.5..1.5));
+        (kind, duration)
+    }
+
+    /// Returns the degraded config of a link.
+    ///
+    /// `base` is the effective config of the link, with loss rate and latency set.
+    pub(super) fn degrade(&self, kind: Kind, base: &LinkConfig) -> LinkConfig {
+        let latency = base.send_latency.clone().unwrap();
+        let factor = 1.0 + 9.0 * self.intensity;
+        match kind {
+            Kind::Congestion => LinkConfig {
+                send_latency: Some(latency.start.mul_f64(factor)..latency.end.mul_f64(factor)),
+                bandwidth: Some(
+                    ((base.bandwidth.unwrap_or(CONGESTION_BANDWIDTH) as f64 / factor) as u64)
+                        .max(1),
+                ),
+                ..Default::default()
+            },
+            Kind::LossBurst => LinkConfig {
+                packet_loss_rate: Some(
+                    (base.packet_loss_rate.unwrap()).max(0.1 + 0.4 * self.intensity),
+                ),
+                ..Default::default()
+            },
+            Kind::LatencySpike => {
+                let spike = Duration::from_millis(100).mul_f64(factor);
+                LinkConfig {
+                    send_latency: Some(latency.start + spike..latency.end + spike),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{net::NetSim, plugin::simulator, runtime::Runtime, time::sleep};
+
+    #[test]
+    fn weather() {
+        let runtime = Runtime::new();
+        let node1 = runtime.create_node().ip([10, 0, 0, 1].into()).build();
+        let node2 = runtime.create_node().ip([10, 0, 0, 2].into()).build();
+        let (id1, id2) = (node1.id(), node2.id());
+
+        runtime.block_on(async move {
+            let net = simulator::<NetSim>();
+            let normal = net.network.lock().link_config(id1, id2);
+            net.start_weather(Weather {
+                intensity: 1.0,
+                mean_interval: Duration::from_secs(1),
+                mean_duration: Duration::from_secs(1),
+            });
+            let mut degraded = false;
+            for _ in 0..100 {
+                sleep(Duration::from_millis(100)).await;
+                let network = net.network.lock();
+                degraded |= network.link_config(id1, id2) != normal;
+                degraded |= network.link_config(id2, id1) != normal;
+            }
+            assert!(degraded);
+
+            net.stop_weather();
+            assert_eq!(net.network.lock().link_config(id1, id2), normal);
+            assert_eq!(net.network.lock().link_config(id2, id1), normal);
+        });
+    }
+}
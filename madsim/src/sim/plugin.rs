@@ -1,9 +1,6 @@
 //! Simulator plugin framework.
 
-use std::{
-    any::{Any, TypeId},
-    sync::Arc,
-};
+use std::{any::Any, sync::Arc};
 
 use downcast_rs::{impl_downcast, DowncastSync};
 
@@ -43,14 +40,7 @@ impl_downcast!(sync Simulator);
 
 /// Get the simulator.
 pub fn simulator<S: Simulator>() -> Arc<S> {
-    crate::context::current(|h| {
-        let sims = h.sims.lock();
-        sims[&TypeId::of::<S>()]
-            .clone()
-            .downcast_arc()
-            .ok()
-            .unwrap()
-    })
+    crate::context::current(|h| h.simulator())
 }
 
 /// Get the node ID of current task.
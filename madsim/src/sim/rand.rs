@@ -1,19 +1,42 @@
 //! Utilities for random number generation.
 //!
 //! This module re-exports the [`rand`] crate, except for the random number generators.
+//!
+//! Each node has its own random stream derived from the seed and the node ID, so that
+//! random calls in one node do not perturb random values observed by other nodes.
+//!
+//! Calls to `getrandom` and `getentropy` inside the simulation, including those made by
+//! dependencies such as `uuid`, `ring` and `ahash`, also return deterministic bytes.
+//! See [`Config::real_entropy`] to opt out.
+//!
+//! Entropy sources that are not intercepted can be reported by [`Config::entropy_audit`].
 
 use rand::{distributions::Standard, prelude::Distribution};
-use rand_xoshiro::Xoshiro256PlusPlus;
+use rand_xoshiro::{rand_core::impls, Xoshiro256PlusPlus};
+use serde::{Deserialize, Serialize};
 
+use crate::config::Scheduler;
+use crate::runtime::{
+    explore::Explorer,
+    guide::Fork,
+    pct::Pct,
+    schedule::{Decision, Replay, Schedule},
+};
+use crate::task::{self, NodeId};
+use crate::trace::{self, Event, EventKind};
 use spin::Mutex;
 use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[doc(no_inline)]
 pub use rand::{distributions, seq, CryptoRng, Error, Fill, Rng, RngCore, SeedableRng};
 
 /// Convenience re-export of common members
 pub mod prelude {
+    #[doc(no_inline)]
+    pub use super::rngs::{SmallRng, StdRng, ThreadRng};
     #[doc(no_inline)]
     pub use super::{random, thread_rng};
     #[doc(no_inline)]
@@ -22,21 +45,242 @@ pub mod prelude {
     };
 }
 
+/// Random number generators.
+///
+/// The generators seeded from entropy draw their seeds from [`thread_rng`], so they are
+/// deterministic in the simulation.
+pub mod rngs {
+    use super::*;
+
+    /// The type returned by [`thread_rng`].
+    pub type ThreadRng = GlobalRng;
+
+    /// A random number generator that retrieves randomness from the operating system.
+    ///
+    /// In the simulation, it is backed by [`thread_rng`].
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct OsRng;
+
+    impl RngCore for OsRng {
+        fn next_u32(&mut self) -> u32 {
+            thread_rng().next_u32()
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            thread_rng().next_u64()
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            thread_rng().fill_bytes(dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+            thread_rng().try_fill_bytes(dest)
+        }
+    }
+
+    impl CryptoRng for OsRng {}
+
+    macro_rules! seeded_rng {
+        ($(#[$attr:meta])* $name:ident) => {
+            $(#[$attr])*
+            #[derive(Clone, Debug, PartialEq, Eq)]
+            pub struct $name(rand::rngs::$name);
+
+            impl RngCore for $name {
+                fn next_u32(&mut self) -> u32 {
+                    self.0.next_u32()
+                }
+
+                fn next_u64(&mut self) -> u64 {
+                    self.0.next_u64()
+                }
+
+                fn fill_bytes(&mut self, dest: &mut [u8]) {
+                    self.0.fill_bytes(dest)
+                }
+
+                fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+                    self.0.try_fill_bytes(dest)
+                }
+            }
+
+            impl SeedableRng for $name {
+                type Seed = <rand::rngs::$name as SeedableRng>::Seed;
+
+                fn from_seed(seed: Self::Seed) -> Self {
+                    $name(rand::rngs::$name::from_seed(seed))
+                }
+
+                fn seed_from_u64(state: u64) -> Self {
+                    $name(rand::rngs::$name::seed_from_u64(state))
+                }
+
+                fn from_rng<R: RngCore>(rng: R) -> Result<Self, Error> {
+                    rand::rngs::$name::from_rng(rng).map($name)
+                }
+
+                fn from_entropy() -> Self {
+                    Self::from_rng(thread_rng()).unwrap()
+                }
+            }
+        };
+    }
+
+    seeded_rng!(
+        /// The standard random number generator.
+        ///
+        /// See [`rand::rngs::StdRng`].
+        StdRng
+    );
+    impl CryptoRng for StdRng {}
+
+    seeded_rng!(
+        /// A small-state, fast non-crypto random number generator.
+        ///
+        /// See [`rand::rngs::SmallRng`].
+        SmallRng
+    );
+}
+
+/// Random configurations.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct Config {
+    /// Crates that receive real entropy from `getrandom`.
+    ///
+    /// A call is made from a crate if any function of the crate is on the call stack.
+    /// Hyphens in crate names are treated as underscores.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub real_entropy: Vec<String>,
+    /// How to report entropy sources that break determinism.
+    #[serde(default)]
+    pub entropy_audit: EntropyAudit,
+}
+
+/// How to report entropy sources that are not intercepted by the simulator.
+///
+/// The following sources are detected inside the simulation:
+///
+/// - Opening `/dev/random` or `/dev/urandom` (Linux only).
+/// - The libc `time` function.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum EntropyAudit {
+    /// Do not report.
+    #[default]
+    Off,
+    /// Log a warning with the backtrace.
+    Warn,
+    /// Panic with the backtrace.
+    ///
+    /// Note that panics in intercepted libc functions abort the process.
+    Panic,
+}
+
+thread_local! {
+    /// The number of entropy sources reported on this thread.
+    static REPORTED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Reports an entropy source used inside the simulation.
+pub(crate) fn audit_entropy(source: &str) {
+    let Some(mode) = crate::context::try_current(|h| h.config.rand.entropy_audit) else {
+        return;
+    };
+    if mode != EntropyAudit::Off {
+        REPORTED.with(|n| n.set(n.get() + 1));
+    }
+    let report = || {
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        format!("non-deterministic entropy source `{source}` used in simulation\n{backtrace}")
+    };
+    match mode {
+        EntropyAudit::Off => {}
+        EntropyAudit::Warn => tracing::warn!("{}", report()),
+        EntropyAudit::Panic => panic!("{}", report()),
+    }
+}
+
 /// Global deterministic random number generator.
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 #[derive(Clone)]
 pub struct GlobalRng {
     inner: Arc<Mutex<Inner>>,
+    /// The node whose stream is used. `None` for the global stream.
+    node: Option<NodeId>,
 }
 
 struct Inner {
     seed: u64,
     rng: Xoshiro256PlusPlus,
-    log: Option<Vec<u8>>,
-    check: Option<(Vec<u8>, usize)>,
+    /// Random streams of nodes.
+    nodes: BTreeMap<NodeId, Xoshiro256PlusPlus>,
+    /// The number of random values generated.
+    draws: u64,
+    /// Forks to apply, ordered by position.
+    forks: VecDeque<Fork>,
+    /// The salt of node streams created after forks.
+    salt: u64,
+    /// States reached, and the number of random values generated when first reached.
+    states: BTreeMap<u64, u64>,
+    /// The fuzzer input to draw random values from before the streams.
+    fuzz: Option<FuzzInput>,
+    /// Path prefixes of crates that receive real entropy.
+    real_entropy: Vec<String>,
+    /// The running hash of the execution trace. Only updated if log or check is enabled.
+    trace_hash: u64,
+    log: Option<trace::Log>,
+    check: Option<trace::Check>,
+    /// The schedule being recorded.
+    schedule: Option<Schedule>,
+    /// The schedule being replayed.
+    replay: Option<Replay>,
+    /// The PCT scheduler. `None` for the random scheduler.
+    pct: Option<Pct>,
+    /// The state of exhaustive exploration.
+    explore: Option<Arc<Mutex<Explorer>>>,
     buggify: bool,
 }
 
+impl Inner {
+    /// Adds a step to the execution trace, and logs or checks the running hash.
+    fn record(&mut self, kind: EventKind, data: u64) {
+        if self.log.is_none() && self.check.is_none() {
+            return;
+        }
+        let t = crate::time::TimeHandle::try_current().map(|t| t.elapsed());
+        let time = t.unwrap_or_default().as_nanos() as u64;
+        // the running hash makes a divergence visible in all following steps,
+        // so logging a byte per step is enough.
+        self.trace_hash = mix(self.trace_hash ^ data ^ mix(time));
+        let event = Event::new(kind, t);
+        if let Some(log) = &mut self.log {
+            log.push(self.trace_hash, event);
+        } else if let Some(check) = &mut self.check {
+            check.check(self.trace_hash, event);
+        }
+    }
+
+    /// Reseeds all random streams with the salt.
+    fn fork(&mut self, salt: u64) {
+        let reseed = |rng: &mut Xoshiro256PlusPlus| {
+            *rng = SeedableRng::seed_from_u64(rng.gen::<u64>() ^ salt);
+        };
+        reseed(&mut self.rng);
+        self.nodes.values_mut().for_each(reseed);
+        self.salt = mix(self.salt ^ salt);
+    }
+}
+
+/// The finalizer of SplitMix64.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
 impl GlobalRng {
     /// Create a new RNG using the given seed.
     pub(crate) fn new_with_seed(seed: u64) -> Self {
@@ -51,63 +295,245 @@ impl GlobalRng {
         let inner = Inner {
             seed,
             rng: SeedableRng::seed_from_u64(seed),
+            nodes: BTreeMap::new(),
+            draws: 0,
+            forks: VecDeque::new(),
+            salt: 0,
+            states: BTreeMap::new(),
+            fuzz: None,
+            real_entropy: vec![],
+            trace_hash: 0,
             log: None,
             check: None,
+            schedule: None,
+            replay: None,
+            pct: None,
+            explore: None,
             buggify: false,
         };
         GlobalRng {
             inner: Arc::new(Mutex::new(inner)),
+            node: None,
+        }
+    }
+
+    /// Returns the RNG of the node's stream.
+    pub(crate) fn node(&self, id: NodeId) -> Self {
+        GlobalRng {
+            inner: self.inner.clone(),
+            node: Some(id),
         }
     }
 
     /// Call function on the inner RNG.
-    pub(crate) fn with<T>(&self, f: impl FnOnce(&mut Xoshiro256PlusPlus) -> T) -> T {
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&mut SimRng<'_>) -> T) -> T {
         let mut lock = self.inner.lock();
-        let ret = f(&mut lock.rng);
-        // log or check
-        if lock.log.is_some() || lock.check.is_some() {
-            let t = crate::time::TimeHandle::try_current().map(|t| t.elapsed());
-            fn hash_u128(x: u128) -> u8 {
-                x.to_ne_bytes().iter().fold(0, |a, b| a ^ b)
-            }
-            let v = lock.rng.clone().gen::<u8>() ^ hash_u128(t.unwrap_or_default().as_nanos());
-            if let Some(log) = &mut lock.log {
-                log.push(v);
+        let inner = &mut *lock;
+        let (ret, next) = match inner.fuzz.as_mut().filter(|input| !input.is_empty()) {
+            Some(input) => {
+                let ret = f(&mut SimRng::Fuzz(input));
+                (ret, input.peek())
             }
-            if let Some((check, i)) = &mut lock.check {
-                if check.get(*i) != Some(&v) {
-                    if let Some(time) = t {
-                        panic!("non-determinism detected at {time:?}");
+            None => {
+                let rng = match self.node {
+                    Some(id) => {
+                        let seed = inner.seed ^ inner.salt;
+                        (inner.nodes.entry(id)).or_insert_with(|| node_rng(seed, id))
                     }
-                    panic!("non-determinism detected");
-                }
-                *i += 1;
+                    None => &mut inner.rng,
+                };
+                let ret = f(&mut SimRng::Seeded(rng));
+                (ret, rng.clone().gen::<u8>())
             }
+        };
+        lock.record(EventKind::Rand, next as u64);
+        lock.draws += 1;
+        if lock.forks.front().map(|f| f.at) == Some(lock.draws) {
+            let fork = lock.forks.pop_front().unwrap();
+            lock.fork(fork.salt);
         }
         ret
     }
 
+    /// Draws random values from the fuzzer input until it is exhausted.
+    pub(crate) fn set_fuzz_input(&self, data: &[u8]) {
+        let mut lock = self.inner.lock();
+        lock.fuzz = Some(FuzzInput {
+            data: data.to_vec(),
+            pos: 0,
+        });
+    }
+
+    /// Reseeds all random streams at the forks.
+    pub(crate) fn set_forks(&self, forks: &[Fork]) {
+        let mut lock = self.inner.lock();
+        lock.forks = forks.iter().copied().collect();
+    }
+
+    /// Reseeds all random streams with the salt now.
+    pub(crate) fn fork_now(&self, salt: u64) {
+        self.inner.lock().fork(salt);
+    }
+
+    /// Records that the state is reached.
+    pub(crate) fn hit(&self, state: u64) {
+        let mut lock = self.inner.lock();
+        let draws = lock.draws;
+        lock.states.entry(state).or_insert(draws);
+    }
+
+    /// Takes the states reached.
+    pub(crate) fn take_states(&self) -> BTreeMap<u64, u64> {
+        let mut lock = self.inner.lock();
+        std::mem::take(&mut lock.states)
+    }
+
+    /// Adds a scheduling or delivery decision to the execution trace for determinism check.
+    pub(crate) fn trace(&self, kind: EventKind) {
+        let mut lock = self.inner.lock();
+        if lock.log.is_none() && lock.check.is_none() {
+            return;
+        }
+        let data = trace::hash(&kind);
+        lock.record(kind, data);
+    }
+
+    /// Panics if the checked run has fewer steps than the logged one.
+    pub(crate) fn finish_check(&self) {
+        let lock = self.inner.lock();
+        if let Some(check) = &lock.check {
+            check.finish();
+        }
+    }
+
     pub(crate) fn seed(&self) -> u64 {
         let lock = self.inner.lock();
         lock.seed
     }
 
-    pub(crate) fn enable_check(&self, log: Log) {
+    pub(crate) fn enable_check(&self, log: trace::Log) {
         let mut lock = self.inner.lock();
-        lock.check = Some((log.0, 0));
+        lock.check = Some(trace::Check::new(log));
     }
 
     pub(crate) fn enable_log(&self) {
         let mut lock = self.inner.lock();
-        lock.log = Some(Vec::new());
+        lock.log = Some(trace::Log::default());
     }
 
-    pub(crate) fn take_log(&self) -> Option<Log> {
+    pub(crate) fn take_log(&self) -> Option<trace::Log> {
         let mut lock = self.inner.lock();
         lock.log
             .take()
-            .or_else(|| lock.check.take().map(|(s, _)| s))
-            .map(Log)
+            .or_else(|| lock.check.take().map(trace::Check::into_log))
+    }
+
+    pub(crate) fn enable_schedule_record(&self) {
+        let mut lock = self.inner.lock();
+        lock.schedule = Some(Schedule::default());
+    }
+
+    pub(crate) fn take_schedule(&self) -> Option<Schedule> {
+        let mut lock = self.inner.lock();
+        lock.schedule.take()
+    }
+
+    pub(crate) fn replay_schedule(&self, schedule: Schedule) {
+        let mut lock = self.inner.lock();
+        lock.replay = Some(Replay::new(schedule));
+    }
+
+    pub(crate) fn enable_explore(&self, explorer: Arc<Mutex<Explorer>>) {
+        let mut lock = self.inner.lock();
+        lock.explore = Some(explorer);
+    }
+
+    /// Chooses a task to poll among `len` ready ones.
+    ///
+    /// `task` returns the ID and node of the i-th task, or `None` if it has been dropped.
+    /// If `weights` is given, tasks are chosen with probabilities proportional to them.
+    pub(crate) fn choose_task(
+        &self,
+        len: usize,
+        task: impl Fn(usize) -> Option<(task::Id, NodeId)>,
+        weights: Option<Vec<f64>>,
+    ) -> usize {
+        let id = |i| task(i).map(|(id, _)| id);
+        // always draw a random index to keep the random stream aligned when replaying
+        let mut index = self.with(|rng| {
+            match weights.and_then(|w| distributions::WeightedIndex::new(w).ok()) {
+                Some(dist) => rng.sample(dist),
+                None => rng.gen_range(0..len),
+            }
+        });
+        let mut lock = self.inner.lock();
+        if let Some(pct) = &mut lock.pct {
+            index = pct.choose(len, id);
+        }
+        if let Some(explorer) = &lock.explore {
+            index = explorer.lock().choose(len, &task);
+        }
+        // a dropped task is not polled: no decision is recorded or replayed for it
+        if id(index).is_none() {
+            return index;
+        }
+        if let Some(replayed) = lock.replay.as_mut().and_then(Replay::next_poll) {
+            // the task may not be ready if the code has changed
+            if let Some(i) = (0..len).find(|&i| id(i) == Some(replayed)) {
+                index = i;
+            }
+        }
+        if let (Some(schedule), Some(id)) = (&mut lock.schedule, id(index)) {
+            schedule.push(Decision::Poll(id));
+        }
+        index
+    }
+
+    /// Decides the latency of a packet on the link. `None` if the packet is lost.
+    ///
+    /// `sample` is always called to keep the random stream aligned when replaying.
+    pub(crate) fn decide_link(
+        &self,
+        src: NodeId,
+        dst: NodeId,
+        sample: impl FnOnce() -> Option<Duration>,
+    ) -> Option<Duration> {
+        let mut latency = sample();
+        let mut lock = self.inner.lock();
+        if let Some(replayed) = lock.replay.as_mut().and_then(|r| r.next_link(src, dst)) {
+            latency = replayed;
+        }
+        if let Some(schedule) = &mut lock.schedule {
+            schedule.push(Decision::Link(src, dst, latency));
+        }
+        latency
+    }
+
+    pub(crate) fn set_scheduler(&self, scheduler: &Scheduler) {
+        let mut lock = self.inner.lock();
+        lock.pct = match *scheduler {
+            Scheduler::Random => None,
+            Scheduler::Pct { depth, steps } => Some(Pct::new(lock.rng.gen(), depth, steps)),
+        };
+    }
+
+    pub(crate) fn set_real_entropy(&self, crates: &[String]) {
+        let mut lock = self.inner.lock();
+        lock.real_entropy = (crates.iter())
+            .map(|name| format!("{}::", name.replace('-', "_")))
+            .collect();
+    }
+
+    /// Returns true if the caller should receive real entropy.
+    fn is_real_entropy(&self) -> bool {
+        let crates = self.inner.lock().real_entropy.clone();
+        if crates.is_empty() {
+            return false;
+        }
+        let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+        crates
+            .iter()
+            .any(|prefix| backtrace.contains(prefix.as_str()))
     }
 
     pub(crate) fn enable_buggify(&self) {
@@ -134,11 +560,111 @@ impl GlobalRng {
     }
 }
 
+/// The generator passed to [`GlobalRng::with`].
+pub(crate) enum SimRng<'a> {
+    /// A random stream derived from the seed.
+    Seeded(&'a mut Xoshiro256PlusPlus),
+    /// The fuzzer input.
+    Fuzz(&'a mut FuzzInput),
+}
+
+impl RngCore for SimRng<'_> {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SimRng::Seeded(rng) => rng.next_u32(),
+            SimRng::Fuzz(input) => input.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SimRng::Seeded(rng) => rng.next_u64(),
+            SimRng::Fuzz(input) => input.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            SimRng::Seeded(rng) => rng.fill_bytes(dest),
+            SimRng::Fuzz(input) => input.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Random bytes supplied by a fuzzer.
+pub(crate) struct FuzzInput {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl FuzzInput {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Returns the next byte without consuming it.
+    fn peek(&self) -> u8 {
+        self.data.get(self.pos).copied().unwrap_or_default()
+    }
+}
+
+impl RngCore for FuzzInput {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    /// Copies the remaining input, padded with zeros.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let n = rest.len().min(dest.len());
+        dest[..n].copy_from_slice(&rest[..n]);
+        dest[n..].fill(0);
+        self.pos += dest.len();
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Derives the random stream of a node from the seed.
+fn node_rng(seed: u64, id: NodeId) -> Xoshiro256PlusPlus {
+    // `seed_from_u64` scrambles the seed with SplitMix64
+    SeedableRng::seed_from_u64(seed ^ id.as_u64().wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
 /// Retrieve the deterministic random number generator from the current madsim context.
+///
+/// Inside a task, it returns the random stream of the current node.
 pub fn thread_rng() -> GlobalRng {
-    crate::context::current(|h| h.rand.clone())
+    let rand = crate::context::current(|h| h.rand.clone());
+    match crate::context::try_current_task() {
+        Some(task) => rand.node(task.node.id),
+        None => rand,
+    }
+}
+
+/// Returns [`thread_rng`] if inside a madsim context.
+pub(crate) fn try_thread_rng() -> Option<GlobalRng> {
+    let rand = crate::context::try_current(|h| h.rand.clone())?;
+    Some(match crate::context::try_current_task() {
+        Some(task) => rand.node(task.node.id),
+        None => rand,
+    })
 }
 
+impl CryptoRng for GlobalRng {}
+
 impl RngCore for GlobalRng {
     fn next_u32(&mut self) -> u32 {
         self.with(|rng| rng.next_u32())
@@ -166,10 +692,6 @@ where
     thread_rng().gen()
 }
 
-/// Random log for determinism check.
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct Log(Vec<u8>);
-
 /// Initialize std `RandomState` with specified seed.
 ///
 /// You should call this function before constructing any `HashMap` or `HashSet` in a new thread.
@@ -200,8 +722,8 @@ unsafe extern "C" fn getrandom(mut buf: *mut u8, mut buflen: usize, _flags: u32)
         std::slice::from_raw_parts_mut(buf as *mut u64, 2).fill(seed);
         SEED.with(|s| s.set(None));
         return 16;
-    } else if let Some(rand) = crate::context::try_current(|h| h.rand.clone()) {
-        // inside a madsim context, use the global RNG.
+    } else if let Some(rand) = try_thread_rng().filter(|rand| !rand.is_real_entropy()) {
+        // inside a madsim context, use the RNG of the current node.
         let len = buflen;
         while buflen >= std::mem::size_of::<u64>() {
             (buf as *mut u64).write(rand.with(|rng| rng.gen()));
@@ -262,17 +784,160 @@ unsafe extern "C" fn getentropy(buf: *mut u8, buflen: usize) -> i32 {
     }
 }
 
+/// Override the libc `time` function to audit it.
+///
+/// Ref: <https://man7.org/linux/man-pages/man2/time.2.html>
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn time(tloc: *mut libc::time_t) -> libc::time_t {
+    audit_entropy("time");
+    lazy_static::lazy_static! {
+        static ref TIME: unsafe extern "C" fn(tloc: *mut libc::time_t) -> libc::time_t = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"time\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    TIME(tloc)
+}
+
+/// Audits opening random devices.
+#[cfg(target_os = "linux")]
+unsafe fn audit_open(path: *const libc::c_char) {
+    if path.is_null() {
+        return;
+    }
+    let path = std::ffi::CStr::from_ptr(path).to_bytes();
+    if path == b"/dev/urandom" || path == b"/dev/random" {
+        audit_entropy(&String::from_utf8_lossy(path));
+    }
+}
+
+/// Override the libc `open64` function to audit random devices and the real file system.
+/// For `std::fs::File::open`.
+///
+/// # Safety
+///
+/// Same as `open64`.
+///
+/// Ref: <https://man7.org/linux/man-pages/man2/open.2.html>
+#[cfg(target_os = "linux")]
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn open64(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_uint,
+) -> libc::c_int {
+    // NOTE: `open64` is variadic in C. See `syscall` for why this works.
+    audit_open(path);
+    crate::fs::audit_real_fs("open64", path);
+    lazy_static::lazy_static! {
+        static ref OPEN64: unsafe extern "C" fn(
+            path: *const libc::c_char,
+            flags: libc::c_int,
+            mode: libc::c_uint,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"open64\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    OPEN64(path, flags, mode)
+}
+
+/// Override the libc `open` function to audit random devices and the real file system.
+///
+/// # Safety
+///
+/// Same as `open`.
+///
+/// Ref: <https://man7.org/linux/man-pages/man2/open.2.html>
+#[cfg(target_os = "linux")]
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn open(
+    path: *const libc::c_char,
+    flags: libc::c_int,
+    mode: libc::c_uint,
+) -> libc::c_int {
+    // NOTE: `open` is variadic in C. See `syscall` for why this works.
+    audit_open(path);
+    crate::fs::audit_real_fs("open", path);
+    lazy_static::lazy_static! {
+        static ref OPEN: unsafe extern "C" fn(
+            path: *const libc::c_char,
+            flags: libc::c_int,
+            mode: libc::c_uint,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"open\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    OPEN(path, flags, mode)
+}
+
+/// Indirect system call.
+///
+/// `SYS_getrandom` is redirected to [`getrandom`], since crates like `getrandom` invoke
+/// the system call directly on Linux. Clock system calls are redirected to the simulated
+/// clock with a warning. Futex waits blocking a simulated task abort the process with a
+/// diagnostic. Other system calls are passed through.
+///
+/// # Safety
+///
+/// Same as the system call.
+///
+/// Ref: <https://man7.org/linux/man-pages/man2/syscall.2.html>
+#[cfg(target_os = "linux")]
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn syscall(
+    num: libc::c_long,
+    a1: libc::c_long,
+    a2: libc::c_long,
+    a3: libc::c_long,
+    a4: libc::c_long,
+    a5: libc::c_long,
+    a6: libc::c_long,
+) -> libc::c_long {
+    // NOTE: `syscall` is variadic in C. Defining it with 6 fixed arguments works
+    //       because the calling conventions are the same on supported platforms.
+    if num == libc::SYS_getrandom {
+        return getrandom(a1 as _, a2 as _, a3 as _) as _;
+    }
+    if let Some(ret) = crate::time::clock_syscall(num, a1, a2) {
+        return ret;
+    }
+    if let Some(ret) = crate::task::futex_syscall(num, [a1, a2, a3, a4, a5, a6]) {
+        return ret;
+    }
+    crate::fs::audit_real_fs_syscall(num, a2);
+    lazy_static::lazy_static! {
+        static ref SYSCALL: unsafe extern "C" fn(
+            num: libc::c_long,
+            a1: libc::c_long,
+            a2: libc::c_long,
+            a3: libc::c_long,
+            a4: libc::c_long,
+            a5: libc::c_long,
+            a6: libc::c_long,
+        ) -> libc::c_long = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"syscall\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    SYSCALL(num, a1, a2, a3, a4, a5, a6)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::runtime::Runtime;
     use std::collections::{BTreeSet, HashMap};
 
     #[test]
-    #[cfg_attr(target_os = "linux", ignore)]
-    // NOTE:
-    //   Deterministic rand is only available on macOS.
-    //   On linux, the call stack is `rand` -> `getrandom` -> `SYS_getrandom`,
-    //   which is hard to intercept.
     fn deterministic_rand() {
         let mut seqs = BTreeSet::new();
         for i in 0..9 {
@@ -305,4 +970,121 @@ mod tests {
         }
         assert_eq!(seqs.len(), 3, "hashmap is not deterministic");
     }
+
+    #[test]
+    #[should_panic(expected = "non-determinism detected at step")]
+    fn trace_scheduling() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        static SECOND: AtomicBool = AtomicBool::new(false);
+
+        // no random numbers are generated, but tasks are scheduled differently
+        Runtime::check_determinism(0, crate::Config::default(), || async {
+            let n = if SECOND.swap(true, Ordering::Relaxed) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..n {
+                crate::task::yield_now().await;
+            }
+        });
+    }
+
+    #[test]
+    fn real_entropy() {
+        fn run(real_entropy: Vec<String>) -> u64 {
+            std::thread::spawn(move || {
+                let mut config = crate::Config::default();
+                config.rand.real_entropy = real_entropy;
+                let runtime = Runtime::with_seed_and_config(1, config);
+                runtime.block_on(async {
+                    let mut buf = [0u8; 8];
+                    unsafe { super::getrandom(buf.as_mut_ptr(), buf.len(), 0) };
+                    u64::from_ne_bytes(buf)
+                })
+            })
+            .join()
+            .unwrap()
+        }
+        assert_eq!(run(vec![]), run(vec![]));
+        let name = vec![env!("CARGO_CRATE_NAME").into()];
+        assert_ne!(run(name.clone()), run(name));
+    }
+
+    #[test]
+    fn entropy_audit_warn() {
+        let mut config = crate::Config::default();
+        config.rand.entropy_audit = super::EntropyAudit::Warn;
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let reported = || super::REPORTED.with(|n| n.get());
+        runtime.block_on(async move {
+            let n = reported();
+            #[cfg(target_os = "linux")]
+            {
+                std::fs::File::open("/dev/urandom").unwrap();
+                assert_eq!(reported(), n + 1);
+            }
+            let n = reported();
+            unsafe { super::time(std::ptr::null_mut()) };
+            assert_eq!(reported(), n + 1);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "non-deterministic entropy source `test`")]
+    fn entropy_audit_panic() {
+        let mut config = crate::Config::default();
+        config.rand.entropy_audit = super::EntropyAudit::Panic;
+        let runtime = Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async { super::audit_entropy("test") });
+    }
+
+    #[test]
+    fn rand_api() {
+        use super::{prelude::*, rngs::OsRng};
+
+        fn run() -> Vec<u64> {
+            let runtime = Runtime::with_seed_and_config(1, crate::Config::default());
+            runtime.block_on(async {
+                let mut rng = thread_rng();
+                let mut bytes = [0u8; 8];
+                rng.fill_bytes(&mut bytes);
+                let mut v: Vec<u64> = vec![u64::from_ne_bytes(bytes)];
+                v.push(rand::distributions::Uniform::new(0, 100).sample(&mut rng));
+                v.push(StdRng::from_entropy().gen());
+                v.push(SmallRng::from_entropy().gen());
+                v.push(OsRng.gen());
+                let mut seq: Vec<u64> = (0..10).collect();
+                seq.shuffle(&mut rng);
+                v.push(*seq.choose(&mut rng).unwrap());
+                v.extend(seq);
+                v
+            })
+        }
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn node_streams() {
+        fn run(extra: usize) -> Vec<u64> {
+            let runtime = Runtime::with_seed_and_config(1, crate::Config::default());
+            let node1 = runtime.create_node().build();
+            let node2 = runtime.create_node().build();
+            runtime.block_on(async move {
+                node1
+                    .spawn(async move {
+                        for _ in 0..extra {
+                            super::random::<u64>();
+                        }
+                    })
+                    .await
+                    .unwrap();
+                node2
+                    .spawn(async { (0..10).map(|_| super::random::<u64>()).collect() })
+                    .await
+                    .unwrap()
+            })
+        }
+        assert_eq!(run(0), run(100));
+    }
 }
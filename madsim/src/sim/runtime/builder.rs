@@ -1,6 +1,9 @@
-use super::{Config, Runtime};
+use super::{guide::Guide, Config, Fork, Runtime, Schedule};
+use crate::net::NetSim;
 use futures_util::{stream, StreamExt};
 use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 /// Builds Madsim Runtime with custom configuration values.
@@ -17,6 +20,22 @@ pub struct Builder {
     pub time_limit: Option<Duration>,
     /// Enable determinism check.
     pub check: bool,
+    /// The path to record network traffic in pcap format.
+    pub pcap: Option<PathBuf>,
+    /// The path to write the message flow as a sequence diagram.
+    pub flow: Option<PathBuf>,
+    /// Print the report of simulated time versus wall-clock time at the end of each run.
+    pub time_report: bool,
+    /// The path to record the schedule.
+    pub record_schedule: Option<PathBuf>,
+    /// The path of the schedule to replay.
+    pub replay_schedule: Option<PathBuf>,
+    /// Prefer runs that reach novel states.
+    pub guided: bool,
+    /// Reseed the random streams at the forks.
+    pub forks: Vec<Fork>,
+    /// The path to write the minimized scenario on failure.
+    pub minimize: Option<PathBuf>,
 }
 
 impl Builder {
@@ -38,6 +57,8 @@ impl Builder {
     ///
     /// - `MADSIM_TEST_CONFIG`: Set the config file path.
     ///
+    ///     The file can be in TOML or YAML format. See [`Config::from_file`].
+    ///
     ///     By default, tests will use the default configuration.
     ///
     /// - `MADSIM_TEST_TIME_LIMIT`: Set the time limit for the test.
@@ -52,6 +73,73 @@ impl Builder {
     ///     If any non-determinism detected, it will panic as soon as possible.
     ///
     ///     By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_PCAP`: Record network traffic to a pcap file.
+    ///
+    ///     If more than one test is run, the seed will be appended to the file name.
+    ///
+    ///     By default, traffic is not recorded.
+    ///
+    /// - `MADSIM_TEST_FLOW`: Write the message flow to a file as a sequence diagram.
+    ///
+    ///     The diagram is in PlantUML format if the file name ends with `.puml`,
+    ///     or Mermaid format otherwise. It is also written if the test panics.
+    ///     If more than one test is run, the seed will be appended to the file name.
+    ///
+    ///     By default, the message flow is not recorded.
+    ///
+    /// - `MADSIM_TEST_TIME_REPORT`: Print the report of simulated time versus wall-clock time.
+    ///
+    ///     The report is printed to stderr at the end of each run. See [`TimeReport`].
+    ///
+    ///     By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_RECORD_SCHEDULE`: Record the schedule to a file.
+    ///
+    ///     The schedule is also written if the test panics.
+    ///     If more than one test is run, the seed will be appended to the file name.
+    ///     See [`Schedule`].
+    ///
+    ///     By default, the schedule is not recorded.
+    ///
+    /// - `MADSIM_TEST_REPLAY_SCHEDULE`: Replay the schedule in a file.
+    ///
+    ///     By default, no schedule is replayed.
+    ///
+    /// - `MADSIM_TEST_GUIDED`: Enable guided seed exploration.
+    ///
+    ///     Instead of running the seeds independently, runs reaching novel states marked by
+    ///     [`coverage::hit`] are forked: the random values are kept up to the novel state and
+    ///     reseeded afterwards. Runs are sequential and only the time limit is supported.
+    ///     On failure, the seed and the forks are printed to reproduce it.
+    ///
+    ///     By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_FORKS`: Reseed the random streams at the comma-separated forks.
+    ///
+    ///     See [`Fork`].
+    ///
+    ///     By default, there is no fork.
+    ///
+    /// - `MADSIM_TEST_MINIMIZE`: Minimize the failing run and write the scenario.
+    ///
+    ///     On failure, irrelevant faults and nodes are removed from the config and the
+    ///     schedule is cut to the shortest failing prefix. See [`Runtime::minimize`].
+    ///     The config is written to `<path>.toml` and the schedule to `<path>.schedule`.
+    ///
+    ///     By default, failures are not minimized.
+    ///
+    /// - `MADSIM_TEST_WATCHDOG`: Set the real time in seconds without progress after which the
+    ///     state of all tasks is dumped and the process is aborted.
+    ///
+    ///     With a `,report` suffix, e.g. `60,report`, the state is only dumped.
+    ///
+    ///     See [`Config::watchdog`].
+    ///
+    ///     By default, the watchdog in the config is used.
+    ///
+    /// [`TimeReport`]: super::TimeReport
+    /// [`coverage::hit`]: crate::coverage::hit
     pub fn from_env() -> Self {
         let seed: u64 = if let Ok(seed_str) = std::env::var("MADSIM_TEST_SEED") {
             seed_str
@@ -70,14 +158,22 @@ impl Builder {
         } else {
             1
         };
-        let config = if let Ok(config_path) = std::env::var("MADSIM_TEST_CONFIG") {
-            let content = std::fs::read_to_string(config_path).expect("failed to read config file");
-            content
-                .parse::<Config>()
-                .expect("failed to parse config file")
+        let mut config = if let Ok(config_path) = std::env::var("MADSIM_TEST_CONFIG") {
+            Config::from_file(config_path).unwrap_or_else(|e| panic!("{e}"))
         } else {
             Config::default()
         };
+        if let Ok(value) = std::env::var("MADSIM_TEST_WATCHDOG") {
+            let (secs, abort) = match value.strip_suffix(",report") {
+                Some(secs) => (secs, false),
+                None => (value.as_str(), true),
+            };
+            let secs = (secs.parse::<f64>()).expect("MADSIM_TEST_WATCHDOG should be an number");
+            config.watchdog = Some(crate::config::Watchdog {
+                timeout: Duration::from_secs_f64(secs),
+                abort,
+            });
+        }
         let mut count: u64 = if let Ok(num_str) = std::env::var("MADSIM_TEST_NUM") {
             num_str
                 .parse()
@@ -93,6 +189,22 @@ impl Builder {
             )
         });
         let check = std::env::var("MADSIM_TEST_CHECK_DETERMINISM").is_ok();
+        let pcap = std::env::var_os("MADSIM_TEST_PCAP").map(PathBuf::from);
+        let flow = std::env::var_os("MADSIM_TEST_FLOW").map(PathBuf::from);
+        let time_report = std::env::var("MADSIM_TEST_TIME_REPORT").is_ok();
+        let record_schedule = std::env::var_os("MADSIM_TEST_RECORD_SCHEDULE").map(PathBuf::from);
+        let replay_schedule = std::env::var_os("MADSIM_TEST_REPLAY_SCHEDULE").map(PathBuf::from);
+        let guided = std::env::var("MADSIM_TEST_GUIDED").is_ok();
+        let forks = match std::env::var("MADSIM_TEST_FORKS") {
+            Ok(s) => (s.split(',').filter(|s| !s.is_empty()))
+                .map(|s| {
+                    s.parse()
+                        .unwrap_or_else(|e| panic!("MADSIM_TEST_FORKS: {e}"))
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+        let minimize = std::env::var_os("MADSIM_TEST_MINIMIZE").map(PathBuf::from);
         if check {
             count = count.max(2);
         }
@@ -103,6 +215,14 @@ impl Builder {
             config,
             time_limit,
             check,
+            pcap,
+            flow,
+            time_report,
+            record_schedule,
+            replay_schedule,
+            guided,
+            forks,
+            minimize,
         }
     }
 
@@ -115,9 +235,28 @@ impl Builder {
         if self.check {
             return Runtime::check_determinism(self.seed, self.config, f);
         }
+        if self.guided {
+            return self.run_guided(f);
+        }
+        let replay = (self.replay_schedule.as_ref()).map(|path| {
+            Schedule::load(path)
+                .unwrap_or_else(|e| panic!("failed to load schedule from {path:?}: {e}"))
+        });
         let mut stream = stream::iter(self.seed..self.seed + self.count)
             .map(|seed| {
                 let config = self.config.clone();
+                let with_seed = |path: PathBuf| {
+                    let mut path = path.into_os_string();
+                    if self.count > 1 {
+                        path.push(format!(".{seed}"));
+                    }
+                    PathBuf::from(path)
+                };
+                let pcap = self.pcap.clone().map(with_seed);
+                let flow = self.flow.clone().map(with_seed);
+                let record = self.record_schedule.clone().map(with_seed);
+                let replay = replay.clone();
+                let forks = self.forks.clone();
                 async move {
                     let (tx, rx) = tokio::sync::oneshot::channel();
                     let handle = std::thread::spawn(move || {
@@ -125,7 +264,21 @@ impl Builder {
                         if let Some(limit) = self.time_limit {
                             rt.set_time_limit(limit);
                         }
+                        rt.rand.set_forks(&forks);
+                        if let Some(path) = pcap {
+                            (rt.handle().simulator::<NetSim>().enable_pcap(path))
+                                .expect("failed to create pcap file");
+                        }
+                        let _flow_guard = flow.map(|path| FlowGuard::new(&rt, path));
+                        if let Some(schedule) = replay {
+                            rt.replay_schedule(schedule);
+                        }
+                        let _schedule_guard = record.map(|path| ScheduleGuard::new(&rt, path));
                         let ret = rt.block_on(f());
+                        if self.time_report {
+                            let report = rt.handle().metrics().time_report();
+                            eprintln!("time report of seed {seed}:\n{report}");
+                        }
                         tx.send(()).unwrap();
                         ret
                     });
@@ -141,9 +294,132 @@ impl Builder {
         while let Some((seed, res)) = rt.block_on(stream.next()) {
             match res {
                 Ok(ret) => return_value = Some(ret),
-                Err(e) => super::panic_with_info(seed, e),
+                Err(e) => {
+                    if let Some(path) = &self.minimize {
+                        write_minimized(seed, self.config.clone(), f, path);
+                    }
+                    super::panic_with_info(seed, &self.forks, e)
+                }
             }
         }
         return_value.unwrap()
     }
+
+    /// Run the future with guided seed exploration.
+    fn run_guided<F>(self, f: fn() -> F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: Send,
+    {
+        let mut guide = Guide::new(self.seed);
+        let mut return_value = None;
+        for seed in self.seed..self.seed + self.count {
+            let (seed, forks) = guide.next(seed);
+            let config = self.config.clone();
+            let time_limit = self.time_limit;
+            let forks0 = forks.clone();
+            let res = std::thread::spawn(move || {
+                let mut rt = Runtime::with_seed_and_config(seed, config);
+                if let Some(limit) = time_limit {
+                    rt.set_time_limit(limit);
+                }
+                rt.rand.set_forks(&forks0);
+                let ret = rt.block_on(f());
+                (ret, rt.rand.take_states())
+            })
+            .join();
+            match res {
+                Ok((ret, states)) => {
+                    guide.report(seed, forks, states);
+                    return_value = Some(ret);
+                }
+                Err(e) => super::panic_with_info(seed, &forks, e),
+            }
+        }
+        return_value.unwrap()
+    }
+}
+
+/// Minimizes the failing run and writes the scenario to files.
+fn write_minimized<F>(seed: u64, config: Config, f: fn() -> F, path: &Path)
+where
+    F: Future + 'static,
+{
+    let Some(min) = Runtime::minimize(seed, config, f) else {
+        eprintln!("note: failed to minimize: the failure is not reproducible");
+        return;
+    };
+    let with_ext = |ext: &str| {
+        let mut path = path.as_os_str().to_owned();
+        path.push(ext);
+        PathBuf::from(path)
+    };
+    let (config_path, schedule_path) = (with_ext(".toml"), with_ext(".schedule"));
+    let result = std::fs::write(&config_path, min.config.to_string())
+        .and_then(|_| min.schedule.save(&schedule_path));
+    if let Err(e) = result {
+        eprintln!("failed to write minimized scenario to {path:?}: {e}");
+        return;
+    }
+    eprintln!(
+        "note: minimized in {} runs:\n{min}\n\
+         run with `MADSIM_TEST_SEED={seed} MADSIM_TEST_CONFIG={} MADSIM_TEST_REPLAY_SCHEDULE={}` \
+         environment variables to reproduce it",
+        min.runs,
+        config_path.display(),
+        schedule_path.display(),
+    );
+}
+
+/// Writes the message flow to a file when dropped, even on panic.
+struct FlowGuard {
+    net: Arc<NetSim>,
+    path: PathBuf,
+}
+
+impl FlowGuard {
+    fn new(rt: &Runtime, path: PathBuf) -> Self {
+        let net = rt.handle().simulator::<NetSim>();
+        net.enable_flow_log();
+        FlowGuard { net, path }
+    }
+}
+
+impl Drop for FlowGuard {
+    fn drop(&mut self) {
+        let Some(flow) = self.net.take_flow_log() else {
+            return;
+        };
+        let diagram = match self.path.extension() {
+            Some(ext) if ext == "puml" => flow.to_plantuml(),
+            _ => flow.to_mermaid(),
+        };
+        if let Err(e) = std::fs::write(&self.path, diagram) {
+            eprintln!("failed to write message flow to {:?}: {e}", self.path);
+        }
+    }
+}
+
+/// Writes the recorded schedule to a file when dropped, even on panic.
+struct ScheduleGuard<'a> {
+    rt: &'a Runtime,
+    path: PathBuf,
+}
+
+impl<'a> ScheduleGuard<'a> {
+    fn new(rt: &'a Runtime, path: PathBuf) -> Self {
+        rt.enable_schedule_record();
+        ScheduleGuard { rt, path }
+    }
+}
+
+impl Drop for ScheduleGuard<'_> {
+    fn drop(&mut self) {
+        let Some(schedule) = self.rt.take_schedule() else {
+            return;
+        };
+        if let Err(e) = schedule.save(&self.path) {
+            eprintln!("failed to write schedule to {:?}: {e}", self.path);
+        }
+    }
 }
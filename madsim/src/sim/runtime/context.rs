@@ -76,5 +76,15 @@ impl Drop for TaskEnterGuard {
     }
 }
 
+/// Runs the function outside of the current task, e.g. to spawn a system thread.
+pub(crate) fn exit_task<T>(f: impl FnOnce() -> T) -> T {
+    let old = TASK.with(|ctx| ctx.borrow_mut().take());
+    let _guard = TaskEnterGuard {
+        old,
+        _span: tracing::Span::none().entered(),
+    };
+    f()
+}
+
 const MSG: &str =
     "there is no reactor running, must be called from the context of a Madsim runtime";
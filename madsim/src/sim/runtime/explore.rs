@@ -0,0 +1,361 @@
+//! Bounded exhaustive exploration of schedules.
+//!
+//! Every run is a path in the tree of scheduling decisions. The tree is explored depth-first
+//! by rerunning from the start with a forced prefix of decisions. Other random decisions, such
+//! as network latencies, are fixed by the seed.
+//!
+//! The tree is pruned with sleep sets, a form of partial-order reduction: after the subtree of
+//! a task has been explored, the task is not chosen again until a dependent task is polled.
+//! Polls of tasks on different nodes are considered independent, since nodes only interact
+//! through the simulated network. State shared between nodes by other means is not tracked.
+//!
+//! Unlike dynamic partial-order reduction (DPOR), backtracking points are not derived from
+//! the dependencies observed in a run: every ready task that is not asleep is tried.
+
+use super::schedule::{Decision, Schedule};
+use crate::task::{self, NodeId};
+use std::fmt;
+
+/// Limits of [`Runtime::explore`](super::Runtime::explore).
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExploreConfig {
+    /// The maximum number of schedules to run.
+    pub max_schedules: usize,
+    /// The maximum number of delays in a schedule.
+    ///
+    /// A delay is a ready task skipped when choosing the next task to poll. Bugs found with
+    /// few delays are usually the easiest to understand. `None` for no limit.
+    pub max_delays: Option<usize>,
+}
+
+impl Default for ExploreConfig {
+    fn default() -> Self {
+        ExploreConfig {
+            max_schedules: 10_000,
+            max_delays: None,
+        }
+    }
+}
+
+/// The result of an exploration without violation.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exploration {
+    /// The number of schedules run.
+    pub schedules: usize,
+    /// Whether all schedules within the bound have been explored.
+    pub complete: bool,
+}
+
+/// A schedule that makes the test panic.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// The violating schedule.
+    ///
+    /// Reproduce it with [`Runtime::replay_schedule`](super::Runtime::replay_schedule)
+    /// using the same seed and config.
+    pub schedule: Schedule,
+    /// The panic message.
+    pub message: String,
+    /// The number of schedules run, including the violating one.
+    pub schedules: usize,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "violation found in schedule {}: {}",
+            self.schedules, self.message
+        )
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// The error of [`Runtime::explore`](super::Runtime::explore).
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ExploreError {
+    /// A schedule makes the test panic.
+    Violation(Violation),
+    /// A run did not follow the decisions of the previous runs, so the test is not
+    /// deterministic for the seed. It is not a bug found in the test.
+    NonDeterministic {
+        /// The number of schedules run, including the diverging one.
+        schedules: usize,
+    },
+}
+
+impl fmt::Display for ExploreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExploreError::Violation(v) => v.fmt(f),
+            ExploreError::NonDeterministic { schedules } => write!(
+                f,
+                "non-deterministic execution in schedule {schedules} during exploration"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExploreError {}
+
+/// A ready task.
+type Ready = (task::Id, NodeId);
+
+/// A scheduling decision on the current path.
+struct Frame {
+    /// The ready tasks.
+    ready: Vec<Ready>,
+    /// The index of the chosen task.
+    chosen: usize,
+    /// Tasks whose subtrees have been explored.
+    done: Vec<Ready>,
+    /// Tasks that need not be chosen.
+    sleep: Vec<Ready>,
+    /// The number of delays before this decision.
+    delays: usize,
+    /// All ready tasks were asleep at this or a previous decision,
+    /// so the rest of the path is covered by other paths.
+    redundant: bool,
+}
+
+impl Frame {
+    fn is_asleep(&self, t: Ready) -> bool {
+        self.sleep.iter().any(|s| s.0 == t.0)
+    }
+
+    /// Returns the number of delays of choosing the i-th task.
+    fn cost(&self, i: usize) -> usize {
+        self.ready[..i]
+            .iter()
+            .filter(|&&t| !self.is_asleep(t))
+            .count()
+    }
+}
+
+/// The state of exploration shared by all runs.
+pub(crate) struct Explorer {
+    max_delays: Option<usize>,
+    stack: Vec<Frame>,
+    /// The index of the next decision in this run.
+    step: usize,
+    /// Whether the run did not follow the path.
+    pub diverged: bool,
+}
+
+impl Explorer {
+    pub fn new(max_delays: Option<usize>) -> Self {
+        Explorer {
+            max_delays,
+            stack: vec![],
+            step: 0,
+            diverged: false,
+        }
+    }
+
+    /// Chooses a task to poll among `len` ready ones. `task` returns the i-th task.
+    pub fn choose(&mut self, len: usize, task: impl Fn(usize) -> Option<Ready>) -> usize {
+        let Some(ready) = (0..len).map(&task).collect::<Option<Vec<_>>>() else {
+            // tasks that have been dropped are removed first
+            return (0..len).find(|&i| task(i).is_none()).unwrap();
+        };
+        let step = self.step;
+        self.step += 1;
+        if let Some(frame) = self.stack.get(step) {
+            // follow the path
+            let id = frame.ready[frame.chosen].0;
+            let Some(i) = ready.iter().position(|t| t.0 == id) else {
+                self.diverged = true;
+                panic!("non-deterministic execution during exploration");
+            };
+            return i;
+        }
+        let (sleep, delays, redundant) = match self.stack.last() {
+            Some(parent) => {
+                let chosen = parent.ready[parent.chosen];
+                // tasks independent of the chosen one stay asleep
+                let sleep = (parent.sleep.iter().chain(&parent.done))
+                    .filter(|t| t.1 != chosen.1)
+                    .copied()
+                    .collect();
+                let delays = parent.delays + parent.cost(parent.chosen);
+                (sleep, delays, parent.redundant)
+            }
+            None => (vec![], 0, false),
+        };
+        let mut frame = Frame {
+            ready,
+            chosen: 0,
+            done: vec![],
+            sleep,
+            delays,
+            redundant,
+        };
+        match (0..frame.ready.len()).find(|&i| !frame.is_asleep(frame.ready[i])) {
+            Some(i) => frame.chosen = i,
+            None => frame.redundant = true,
+        }
+        let chosen = frame.chosen;
+        self.stack.push(frame);
+        chosen
+    }
+
+    /// Returns the schedule of the current path.
+    pub fn schedule(&self) -> Schedule {
+        let mut schedule = Schedule::default();
+        for frame in &self.stack[..self.step.min(self.stack.len())] {
+            schedule.push(Decision::Poll(frame.ready[frame.chosen].0));
+        }
+        schedule
+    }
+
+    /// Moves to the next path. Returns false if all paths have been explored.
+    pub fn backtrack(&mut self) -> bool {
+        // the run may end before following the whole path
+        self.stack.truncate(self.step);
+        self.step = 0;
+        while let Some(frame) = self.stack.last_mut() {
+            if !frame.redundant {
+                frame.done.push(frame.ready[frame.chosen]);
+                let max_delays = self.max_delays.unwrap_or(usize::MAX);
+                let next = (0..frame.ready.len()).find(|&i| {
+                    let t = frame.ready[i];
+                    !frame.is_asleep(t)
+                        && !frame.done.iter().any(|d| d.0 == t.0)
+                        && frame.delays + frame.cost(i) <= max_delays
+                });
+                if let Some(i) = next {
+                    frame.chosen = i;
+                    return true;
+                }
+            }
+            self.stack.pop();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, Config};
+    use std::future::Future;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    /// Two tasks increment a counter with a yield between read and write.
+    async fn lost_update(atomic: bool) {
+        let counter = Arc::new(AtomicU32::new(0));
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let counter = counter.clone();
+                crate::task::spawn(async move {
+                    if atomic {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    } else {
+                        let value = counter.load(Ordering::SeqCst);
+                        crate::task::yield_now().await;
+                        counter.store(value + 1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 2, "lost update");
+    }
+
+    #[test]
+    fn find_violation() {
+        let config = ExploreConfig::default();
+        let err = Runtime::explore(0, Config::default(), config.clone(), || lost_update(false))
+            .unwrap_err();
+        let ExploreError::Violation(violation) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert!(violation.message.contains("lost update"));
+
+        // the violating schedule is reproducible
+        let schedule = violation.schedule;
+        let result = std::thread::spawn(move || {
+            let runtime = Runtime::with_seed_and_config(0, Config::default());
+            runtime.replay_schedule(schedule);
+            runtime.block_on(lost_update(false));
+        })
+        .join();
+        assert!(result.is_err());
+
+        let exploration =
+            Runtime::explore(0, Config::default(), config, || lost_update(true)).unwrap();
+        assert!(exploration.complete);
+    }
+
+    /// Tasks on `nodes` nodes yield a few times.
+    async fn yields(nodes: usize) {
+        let handle = crate::runtime::Handle::current();
+        let nodes: Vec<_> = (0..nodes).map(|_| handle.create_node().build()).collect();
+        let tasks: Vec<_> = (0..3)
+            .map(|i| {
+                nodes[i % nodes.len()].spawn(async move {
+                    for _ in 0..2 {
+                        crate::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    fn explore<F: Future + 'static>(f: fn() -> F) -> Exploration {
+        Runtime::explore(0, Config::default(), ExploreConfig::default(), f).unwrap()
+    }
+
+    #[test]
+    fn reduction() {
+        let dependent = explore(|| yields(1));
+        let independent = explore(|| yields(3));
+        assert!(dependent.complete && independent.complete);
+        assert!(independent.schedules < dependent.schedules);
+    }
+
+    #[test]
+    fn non_deterministic() {
+        static RUNS: AtomicU32 = AtomicU32::new(0);
+
+        // only the first run spawns tasks
+        let err = Runtime::explore(0, Config::default(), ExploreConfig::default(), || async {
+            if RUNS.fetch_add(1, Ordering::SeqCst) == 0 {
+                yields(1).await;
+            } else {
+                for _ in 0..10 {
+                    crate::task::yield_now().await;
+                }
+            }
+        })
+        .unwrap_err();
+        assert!(
+            matches!(err, ExploreError::NonDeterministic { .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn delay_bound() {
+        let config = ExploreConfig {
+            max_delays: Some(0),
+            ..Default::default()
+        };
+        let exploration = Runtime::explore(0, Config::default(), config, || yields(1)).unwrap();
+        assert_eq!(exploration.schedules, 1);
+    }
+}
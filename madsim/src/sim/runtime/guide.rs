@@ -0,0 +1,154 @@
+//! Guided seed exploration.
+//!
+//! A run is identified by its seed and a list of forks. At each fork, all random streams are
+//! reseeded, so that runs with the same seed share the prefix before the fork.
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+/// A point where all random streams of a run are reseeded.
+///
+/// It is written as `<at>:<salt>`.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fork {
+    /// The number of random values generated before the fork.
+    pub at: u64,
+    /// The value mixed into the random streams.
+    pub salt: u64,
+}
+
+impl fmt::Display for Fork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.at, self.salt)
+    }
+}
+
+impl FromStr for Fork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid fork {s:?}, expected `<at>:<salt>`");
+        let (at, salt) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(Fork {
+            at: at.parse().map_err(|_| invalid())?,
+            salt: salt.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Formats forks as a comma-separated list.
+pub(crate) fn display_forks(forks: &[Fork]) -> String {
+    let forks: Vec<_> = forks.iter().map(|f| f.to_string()).collect();
+    forks.join(",")
+}
+
+/// A run that reached novel states.
+struct Entry {
+    seed: u64,
+    forks: Vec<Fork>,
+    /// The number of random values generated when each novel state was reached.
+    novel: Vec<u64>,
+}
+
+/// Chooses runs by the states reached by previous runs.
+pub(crate) struct Guide {
+    rng: Xoshiro256PlusPlus,
+    seen: HashSet<u64>,
+    corpus: Vec<Entry>,
+}
+
+impl Guide {
+    pub fn new(seed: u64) -> Self {
+        Guide {
+            rng: Xoshiro256PlusPlus::seed_from_u64(seed),
+            seen: HashSet::new(),
+            corpus: vec![],
+        }
+    }
+
+    /// Returns the forks of the next run with the seed.
+    ///
+    /// Half of the runs are new seeds. The others fork a run in the corpus at a novel state.
+    pub fn next(&mut self, seed: u64) -> (u64, Vec<Fork>) {
+        if self.corpus.is_empty() || self.rng.gen_bool(0.5) {
+            return (seed, vec![]);
+        }
+        let entry = self.corpus.choose(&mut self.rng).unwrap();
+        let at = *entry.novel.choose(&mut self.rng).unwrap();
+        let mut forks: Vec<_> = entry.forks.iter().filter(|f| f.at < at).copied().collect();
+        forks.push(Fork {
+            at,
+            salt: self.rng.gen(),
+        });
+        (entry.seed, forks)
+    }
+
+    /// Adds the run to the corpus if it reached novel states.
+    ///
+    /// `states` maps each state to the number of random values generated when first reached.
+    pub fn report(&mut self, seed: u64, forks: Vec<Fork>, states: BTreeMap<u64, u64>) {
+        let novel: Vec<_> = (states.into_iter())
+            .filter(|&(state, _)| self.seen.insert(state))
+            .map(|(_, at)| at)
+            .collect();
+        if !novel.is_empty() {
+            self.corpus.push(Entry { seed, forks, novel });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::Builder;
+
+    /// Panics if a rare event happens 3 times in a row.
+    async fn stages() {
+        let mut stage = 0;
+        for _ in 0..30 {
+            if crate::rand::random::<u8>() < 4 {
+                stage += 1;
+                crate::coverage::hit(stage);
+            } else {
+                stage = 0;
+            }
+            if stage == 3 {
+                panic!("found");
+            }
+        }
+    }
+
+    #[test]
+    fn guided() {
+        let builder = Builder {
+            seed: 0,
+            count: 20_000,
+            jobs: 1,
+            config: Default::default(),
+            time_limit: None,
+            check: false,
+            pcap: None,
+            flow: None,
+            time_report: false,
+            record_schedule: None,
+            replay_schedule: None,
+            guided: true,
+            forks: vec![],
+            minimize: None,
+        };
+        let err = std::panic::catch_unwind(move || builder.run(stages)).unwrap_err();
+        assert_eq!(panic_message::panic_message(&err), "found");
+    }
+
+    #[test]
+    fn parse() {
+        let fork: super::Fork = "12:34".parse().unwrap();
+        assert_eq!(fork.to_string(), "12:34");
+        assert!("12".parse::<super::Fork>().is_err());
+    }
+}
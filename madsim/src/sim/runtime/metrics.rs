@@ -2,9 +2,33 @@ use std::{collections::BTreeMap, fmt};
 
 use super::*;
 
+/// Metrics of a node. Counters are kept across restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeMetrics {
+    /// The number of alive tasks.
+    pub num_alive_tasks: usize,
+    /// The number of tasks spawned.
+    pub num_spawned_tasks: u64,
+    /// The number of times tasks have been polled.
+    pub num_polls: u64,
+    /// The number of times tasks have been scheduled to the ready queue.
+    pub num_wakeups: u64,
+    /// The number of tasks in the ready queue.
+    pub ready_queue_depth: usize,
+    /// The memory usage of the current incarnation in bytes. See [`crate::memory`].
+    pub memory_usage: usize,
+    /// The simulated CPU time consumed by polls of tasks.
+    ///
+    /// Each poll takes a [`task::Config::poll_time`] scaled by the CPU slowdown of the node, if
+    /// set, plus the 50-100ns by which the executor advances the clock.
+    pub cpu_time: Duration,
+}
+
 /// Runtime metrics.
 pub struct RuntimeMetrics {
     pub(super) task: task::TaskHandle,
+    pub(super) time: time::TimeHandle,
+    pub(super) phases: Arc<Mutex<report::Phases>>,
 }
 
 impl fmt::Debug for RuntimeMetrics {
@@ -13,6 +37,11 @@ impl fmt::Debug for RuntimeMetrics {
             .field("num_nodes", &self.num_nodes())
             .field("num_tasks", &self.num_tasks())
             .field("num_tasks_by_node", &self.num_tasks_by_node())
+            .field("num_spawned_tasks", &self.num_spawned_tasks())
+            .field("num_polls", &self.num_polls())
+            .field("num_wakeups", &self.num_wakeups())
+            .field("num_timers", &self.num_timers())
+            .field("ready_queue_depth", &self.ready_queue_depth())
             .finish()
     }
 }
@@ -33,6 +62,41 @@ impl RuntimeMetrics {
         self.task.num_tasks_by_node()
     }
 
+    /// Returns the number of times tasks have been polled.
+    pub fn num_polls(&self) -> u64 {
+        self.task.num_polls()
+    }
+
+    /// Returns the number of tasks spawned since the runtime was created.
+    pub fn num_spawned_tasks(&self) -> u64 {
+        self.task.num_spawned_tasks()
+    }
+
+    /// Returns the number of times tasks have been scheduled to the ready queue.
+    pub fn num_wakeups(&self) -> u64 {
+        self.task.num_wakeups()
+    }
+
+    /// Returns the number of pending timers, including those of messages in flight.
+    pub fn num_timers(&self) -> usize {
+        self.time.num_timers()
+    }
+
+    /// Returns the number of tasks in the ready queue.
+    pub fn ready_queue_depth(&self) -> usize {
+        self.task.ready_queue_depth()
+    }
+
+    /// Returns the metrics of the node, or `None` if the node does not exist.
+    pub fn node(&self, id: impl ToNodeId) -> Option<NodeMetrics> {
+        self.task.node_metrics(id)
+    }
+
+    /// Returns the report of simulated time versus wall-clock time per phase so far.
+    pub fn time_report(&self) -> TimeReport {
+        self.phases.lock().report(&self.time, &self.task)
+    }
+
     /// Returns the statistics of tasks by node by spawn.
     pub fn num_tasks_by_node_by_spawn(&self) -> String {
         self.task.num_tasks_by_node_by_spawn()
@@ -0,0 +1,205 @@
+//! Minimization of failing runs.
+//!
+//! A failing run is reduced by delta debugging: faults and nodes in the config are removed,
+//! then the recorded schedule is cut to the shortest prefix that still fails. A candidate
+//! is accepted only if it fails with the same panic message.
+
+use super::{schedule::Schedule, Runtime};
+use crate::Config;
+use std::{fmt, future::Future};
+
+/// A minimized failing scenario.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone)]
+pub struct Minimized {
+    /// The seed.
+    pub seed: u64,
+    /// The config with irrelevant faults and nodes removed.
+    pub config: Config,
+    /// The scheduling and network decisions to replay.
+    /// Decisions after the schedule are made randomly.
+    pub schedule: Schedule,
+    /// The panic message.
+    pub message: String,
+    /// The number of runs during minimization.
+    pub runs: usize,
+}
+
+impl fmt::Display for Minimized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed: {}", self.seed)?;
+        writeln!(f, "faults: {}", self.config.faults.len())?;
+        writeln!(f, "nodes: {}", self.config.nodes.len())?;
+        writeln!(f, "schedule: {} decisions", self.schedule.len())?;
+        write!(f, "panic: {}", self.message)
+    }
+}
+
+/// Runs the future and returns the panic message and the recorded schedule.
+fn run<F>(seed: u64, config: Config, replay: Option<Schedule>, f: fn() -> F) -> Run
+where
+    F: Future + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    let result = std::thread::spawn(move || {
+        let rt = Runtime::with_seed_and_config(seed, config);
+        rt.rand.enable_schedule_record();
+        if let Some(schedule) = replay {
+            rt.rand.replay_schedule(schedule);
+        }
+        // the runtime is dropped on panic, so keep the RNG to take the schedule
+        tx.send(rt.rand.clone()).unwrap();
+        rt.block_on(f());
+    })
+    .join();
+    let rand = rx.recv().ok();
+    Run {
+        message: result
+            .err()
+            .map(|e| panic_message::panic_message(&e).to_string()),
+        schedule: rand.and_then(|r| r.take_schedule()).unwrap_or_default(),
+    }
+}
+
+struct Run {
+    /// The panic message. `None` if the run succeeded.
+    message: Option<String>,
+    schedule: Schedule,
+}
+
+impl Runtime {
+    /// Search for a smaller reproduction of a failing run.
+    ///
+    /// Returns `None` if the run does not fail.
+    ///
+    /// Irrelevant faults and nodes are removed from the config, and the schedule is cut to the
+    /// shortest prefix that still fails with the same panic message. Reproduce the result by
+    /// running with the config and replaying the schedule by
+    /// [`replay_schedule`](Runtime::replay_schedule).
+    pub fn minimize<F>(seed: u64, config: Config, f: fn() -> F) -> Option<Minimized>
+    where
+        F: Future + 'static,
+    {
+        let message = run(seed, config.clone(), None, f).message?;
+        let mut runs = 1;
+        let mut fails = |config: &Config, replay: Option<Schedule>| {
+            runs += 1;
+            let run = run(seed, config.clone(), replay, f);
+            (run.message.as_ref() == Some(&message)).then_some(run.schedule)
+        };
+
+        let mut config = config;
+        let faults = ddmin(config.faults.clone(), |faults| {
+            let config = Config {
+                faults: faults.to_vec(),
+                ..config.clone()
+            };
+            fails(&config, None).is_some()
+        });
+        config.faults = faults;
+        let nodes = ddmin(config.nodes.clone(), |nodes| {
+            let config = Config {
+                nodes: nodes.to_vec(),
+                ..config.clone()
+            };
+            fails(&config, None).is_some()
+        });
+        config.nodes = nodes;
+
+        // cut the schedule by binary search on the prefix length
+        let mut schedule = fails(&config, None).expect("minimized run should fail");
+        let (mut lo, mut hi) = (0, schedule.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if fails(&config, Some(schedule.prefix(mid))).is_some() {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        if fails(&config, Some(schedule.prefix(hi))).is_some() {
+            schedule = schedule.prefix(hi);
+        }
+        Some(Minimized {
+            seed,
+            config,
+            schedule,
+            message,
+            runs,
+        })
+    }
+}
+
+/// Returns a 1-minimal subset of items on which `test` returns true.
+///
+/// `test` should return true on all items.
+fn ddmin<T: Clone>(mut items: Vec<T>, mut test: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut n = 2;
+    while !items.is_empty() {
+        if items.len() == 1 {
+            if test(&[]) {
+                items.clear();
+            }
+            break;
+        }
+        let n0 = n.min(items.len());
+        let chunk = (items.len() + n0 - 1) / n0;
+        let mut reduced = false;
+        // try removing each chunk
+        for i in (0..items.len()).step_by(chunk) {
+            let mut complement = items[..i].to_vec();
+            complement.extend_from_slice(&items[(i + chunk).min(items.len())..]);
+            if test(&complement) {
+                items = complement;
+                n = (n0 - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if n0 == items.len() {
+                break;
+            }
+            n = (n0 * 2).min(items.len());
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FaultKind, NodeConfig};
+    use std::time::Duration;
+
+    #[test]
+    fn ddmin_subset() {
+        let items: Vec<u32> = (0..20).collect();
+        let min = ddmin(items, |s| s.contains(&3) && s.contains(&15));
+        assert_eq!(min, vec![3, 15]);
+        assert_eq!(ddmin(vec![1, 2], |_| true), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn minimize() {
+        let mut builder = Config::builder();
+        for i in 0..5 {
+            builder = builder.node(NodeConfig::new(format!("n{i}")));
+        }
+        for i in 0..5 {
+            let node = format!("n{i}");
+            builder = builder.fault(Duration::from_secs(i + 1), FaultKind::Kill { node });
+        }
+        let config = builder.build().unwrap();
+        let min = Runtime::minimize(1, config, || async {
+            let handle = crate::runtime::Handle::current();
+            crate::time::sleep(Duration::from_secs(10)).await;
+            assert!(!handle.is_exit("n3"), "n3 is killed");
+        })
+        .unwrap();
+        assert_eq!(min.message, "n3 is killed");
+        assert_eq!(min.config.faults.len(), 1);
+        assert_eq!(min.config.nodes.len(), 1);
+        assert_eq!(min.config.nodes[0].name, "n3");
+    }
+}
@@ -1,7 +1,12 @@
 //! The madsim runtime.
 
 use super::*;
-use crate::task::{JoinHandle, NodeId, ToNodeId};
+use crate::{
+    config::FaultKind,
+    rand::Rng,
+    signal::Signal,
+    task::{JoinHandle, NodeId, ToNodeId},
+};
 use spin::Mutex;
 use std::{
     any::{Any, TypeId},
@@ -11,13 +16,32 @@ use std::{
     sync::Arc,
     time::Duration,
 };
+use tracing::debug;
 
 mod builder;
 pub(crate) mod context;
+pub(crate) mod explore;
+mod guide;
 mod metrics;
+mod minimize;
+mod pct;
+mod registry;
+mod report;
+mod rolling;
+pub(crate) mod schedule;
+mod snapshot;
+mod watchdog;
 
 pub use self::builder::Builder;
-pub use self::metrics::RuntimeMetrics;
+pub use self::explore::{Exploration, ExploreConfig, ExploreError, Violation};
+pub use self::guide::Fork;
+pub use self::metrics::{NodeMetrics, RuntimeMetrics};
+pub use self::minimize::Minimized;
+pub use self::registry::NodeEvent;
+pub use self::report::{PhaseReport, TimeReport};
+pub use self::rolling::{RollingRestart, UnhealthyNode};
+pub use self::schedule::Schedule;
+pub use self::snapshot::Snapshot;
 
 /// The madsim runtime.
 ///
@@ -51,18 +75,40 @@ impl Runtime {
     /// Create a new runtime instance with given seed and config.
     pub fn with_seed_and_config(seed: u64, config: Config) -> Self {
         let rand = rand::GlobalRng::new_with_seed(seed);
+        rand.set_real_entropy(&config.rand.real_entropy);
+        rand.set_scheduler(&config.scheduler);
         let sims = Arc::new(Mutex::new(HashMap::new()));
-        let task = task::Executor::new(rand.clone(), sims.clone());
+        let mut task = task::Executor::new(rand.clone(), sims.clone());
+        task.set_spurious_wakeup_rate(config.task.spurious_wakeup_rate);
+        task.set_budget(config.task.budget);
+        task.set_console(config.task.console);
+        task.set_poll_time(config.task.poll_time.clone());
+        if let Some(steps) = config.task.max_steps {
+            task.set_max_steps(steps);
+        }
+        if let Some(limit) = config.task.max_time {
+            task.set_time_limit(limit);
+        }
+        task.set_task_weights(&config.task.task_weights);
+        if let Some(epoch) = config.time.epoch {
+            task.time_handle().set_epoch(epoch);
+        }
+        if let Some(resolution) = config.time.clock_resolution {
+            task.time_handle().set_clock_resolution(resolution);
+        }
+        let phases = report::Phases::new(task.time_handle(), task.handle());
         let handle = Handle {
             rand: rand.clone(),
             time: task.time_handle().clone(),
             task: task.handle().clone(),
             sims,
             config,
+            phases: Arc::new(Mutex::new(phases)),
         };
         let rt = Runtime { rand, task, handle };
         rt.add_simulator::<fs::FsSim>();
         rt.add_simulator::<net::NetSim>();
+        rt.handle.apply_config();
         rt
     }
 
@@ -98,6 +144,11 @@ impl Runtime {
         self.handle.create_node()
     }
 
+    /// Returns a snapshot of live tasks on all nodes. See [`Handle::dump`].
+    pub fn dump(&self) -> task::Dump {
+        self.handle.dump()
+    }
+
     /// Run a future to completion on the runtime. This is the runtime’s entry point.
     ///
     /// This runs the given future on the current thread until it is complete.
@@ -121,11 +172,75 @@ impl Runtime {
     ///
     /// Runtime::new().block_on(pending::<()>());
     /// ```
+    #[track_caller]
     pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        if crate::context::try_current(|_| ()).is_some() {
+            panic!(
+                "`Runtime::block_on` is called inside a simulation\n\
+                 note: nested runtimes break determinism. await the future instead."
+            );
+        }
         let _guard = crate::context::enter(self.handle.clone());
+        let _watchdog = self.handle.config.watchdog.map(|config| {
+            let (task, time) = (self.handle.task.clone(), self.handle.time.clone());
+            watchdog::Watchdog::start(config, task, time)
+        });
         self.task.block_on(future)
     }
 
+    /// Runs a single scheduling decision: polls a ready task, or advances the time to the next
+    /// timer event if no task is ready. Returns what happened, or `None` if no task is ready
+    /// and no timer is pending.
+    ///
+    /// This allows to drive the simulation event by event and check invariants between steps.
+    /// Like [`block_on`](Runtime::block_on), it panics once the number of polls exceeds
+    /// [`task::Config::max_steps`] or the elapsed time reaches [`task::Config::max_time`] or
+    /// the [time limit](Runtime::set_time_limit).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::{Runtime, Step};
+    /// use std::time::Duration;
+    ///
+    /// let runtime = Runtime::new();
+    /// let node = runtime.create_node().build();
+    /// let task = node.spawn(async { madsim::time::sleep(Duration::from_secs(1)).await });
+    /// let mut steps = vec![];
+    /// while let Some(step) = runtime.step() {
+    ///     steps.push(step);
+    /// }
+    /// assert!(matches!(steps[0], Step::Poll { .. }));
+    /// assert!(matches!(steps[1], Step::Advance { elapsed } if elapsed >= Duration::from_secs(1)));
+    /// assert!(matches!(steps[2], Step::Poll { .. }));
+    /// assert!(task.is_finished());
+    /// ```
+    pub fn step(&self) -> Option<Step> {
+        let _guard = crate::context::enter(self.handle.clone());
+        self.task.step()
+    }
+
+    /// Records the scheduling and network decisions of this runtime.
+    ///
+    /// Get the recorded schedule by [`take_schedule`](Runtime::take_schedule).
+    pub fn enable_schedule_record(&self) {
+        self.rand.enable_schedule_record();
+    }
+
+    /// Takes the recorded schedule. Returns `None` if recording is not enabled.
+    pub fn take_schedule(&self) -> Option<Schedule> {
+        self.rand.take_schedule()
+    }
+
+    /// Replays the decisions of a recorded schedule.
+    ///
+    /// The run is reproduced even if random numbers are consumed differently, for example
+    /// after unrelated code changes. Decisions that no longer apply are skipped, and new
+    /// decisions are made randomly.
+    pub fn replay_schedule(&self, schedule: Schedule) {
+        self.rand.replay_schedule(schedule);
+    }
+
     /// Set a time limit of the execution.
     ///
     /// The runtime will panic when time limit exceeded.
@@ -148,6 +263,11 @@ impl Runtime {
 
     /// Check determinism of the future.
     ///
+    /// The future is run twice with the same seed. Every random number generated, task polled
+    /// and message delivered is a step of the execution trace. The second run panics at the
+    /// first step whose running hash differs from the first run, showing the first differing
+    /// event (sim time, node, task and event type) and the surrounding events of both runs.
+    ///
     /// # Example
     ///
     /// ```should_panic
@@ -177,24 +297,92 @@ impl Runtime {
             rt.rand.take_log().unwrap()
         })
         .join()
-        .map_err(|e| panic_with_info(seed, e))
+        .map_err(|e| panic_with_info(seed, &[], e))
         .unwrap();
 
         std::thread::spawn(move || {
             let rt = Runtime::with_seed_and_config(seed, config);
             rt.rand.enable_check(log);
-            rt.block_on(f())
+            let output = rt.block_on(f());
+            rt.rand.finish_check();
+            output
         })
         .join()
-        .map_err(|e| panic_with_info(seed, e))
+        .map_err(|e| panic_with_info(seed, &[], e))
         .unwrap()
     }
+
+    /// Explore the schedules of the future exhaustively within the limits.
+    ///
+    /// The future is run repeatedly with the same seed, each time polling ready tasks in a
+    /// different order, until a run panics or all schedules have been explored. Schedules that
+    /// only reorder polls of tasks on different nodes are skipped.
+    ///
+    /// Returns the first schedule that panics, which can be replayed by
+    /// [`replay_schedule`](Runtime::replay_schedule), or an error if a run does not follow the
+    /// decisions of previous runs.
+    ///
+    /// This is only feasible for small models, since the number of schedules grows
+    /// exponentially with the number of polls.
+    pub fn explore<F>(
+        seed: u64,
+        config: Config,
+        limits: ExploreConfig,
+        f: fn() -> F,
+    ) -> Result<Exploration, ExploreError>
+    where
+        F: Future + 'static,
+    {
+        let explorer = Arc::new(Mutex::new(explore::Explorer::new(limits.max_delays)));
+        let mut schedules = 0;
+        loop {
+            schedules += 1;
+            let config = config.clone();
+            let explorer0 = explorer.clone();
+            let result = std::thread::spawn(move || {
+                let rt = Runtime::with_seed_and_config(seed, config);
+                rt.rand.enable_explore(explorer0);
+                rt.block_on(f());
+            })
+            .join();
+            let mut explorer = explorer.lock();
+            if let Err(e) = result {
+                if explorer.diverged {
+                    return Err(ExploreError::NonDeterministic { schedules });
+                }
+                return Err(ExploreError::Violation(Violation {
+                    schedule: explorer.schedule(),
+                    message: panic_message::panic_message(&e).to_string(),
+                    schedules,
+                }));
+            }
+            if !explorer.backtrack() {
+                return Ok(Exploration {
+                    schedules,
+                    complete: true,
+                });
+            }
+            if schedules >= limits.max_schedules {
+                return Ok(Exploration {
+                    schedules,
+                    complete: false,
+                });
+            }
+        }
+    }
 }
 
-fn panic_with_info(seed: u64, payload: Box<dyn Any + Send>) -> ! {
-    eprintln!(
-        "note: run with `MADSIM_TEST_SEED={seed}` environment variable to reproduce this error"
-    );
+fn panic_with_info(seed: u64, forks: &[Fork], payload: Box<dyn Any + Send>) -> ! {
+    if forks.is_empty() {
+        eprintln!(
+            "note: run with `MADSIM_TEST_SEED={seed}` environment variable to reproduce this error"
+        );
+    } else {
+        eprintln!(
+            "note: run with `MADSIM_TEST_SEED={seed} MADSIM_TEST_FORKS={}` environment variables to reproduce this error",
+            guide::display_forks(forks),
+        );
+    }
     std::panic::resume_unwind(payload);
 }
 
@@ -207,6 +395,7 @@ pub struct Handle {
     pub(crate) sims: Arc<Simulators>,
 
     pub(crate) config: Config,
+    pub(crate) phases: Arc<Mutex<report::Phases>>,
 }
 
 /// A collection of simulators.
@@ -241,7 +430,7 @@ impl Handle {
     /// Kill a node.
     ///
     /// - All tasks spawned on this node will be killed immediately.
-    /// - All data that has not been flushed to the disk will be lost.
+    /// - All data that has not been synced to the disk will be lost. See [`crate::fs`].
     pub fn kill(&self, id: impl ToNodeId) {
         self.task.kill(&id);
     }
@@ -261,6 +450,36 @@ impl Handle {
         self.task.resume(id);
     }
 
+    /// Set the scheduling weight of a node. The default weight is 1.
+    ///
+    /// Ready tasks are chosen with probabilities proportional to their weights, which are the
+    /// products of the node weight and the weight of the task name in
+    /// [`task::Config::task_weights`](crate::task::Config::task_weights).
+    /// A task with weight `w < 1` is also deferred by up to 1ms with probability `1 - w` each
+    /// time it is about to be polled, so it runs about `1 / w` times less often.
+    /// A weight of 0 starves the node completely.
+    ///
+    /// Targeted starvation reproduces liveness bugs that uniform scheduling rarely hits.
+    pub fn set_schedule_weight(&self, id: impl ToNodeId, weight: f64) {
+        self.task.set_schedule_weight(id, weight);
+    }
+
+    /// Step the wall clock of a node forward, as an NTP step correction or VM migration would.
+    ///
+    /// Only `SystemTime` is affected. `Instant` remains monotonic.
+    pub fn jump_clock_forward(&self, id: impl ToNodeId, duration: Duration) {
+        let nanos = duration.as_nanos().min(i64::MAX as u128) as i64;
+        self.time.jump_node_clock(id.to_node_id(&self.task), nanos);
+    }
+
+    /// Step the wall clock of a node backward.
+    ///
+    /// Only `SystemTime` is affected. `Instant` remains monotonic.
+    pub fn jump_clock_backward(&self, id: impl ToNodeId, duration: Duration) {
+        let nanos = duration.as_nanos().min(i64::MAX as u128) as i64;
+        self.time.jump_node_clock(id.to_node_id(&self.task), -nanos);
+    }
+
     /// Send a Ctrl+C signal to the node.
     pub fn send_ctrl_c(&self, id: impl ToNodeId) {
         self.task.send_ctrl_c(id);
@@ -278,13 +497,160 @@ impl Handle {
 
     /// Return a handle of the specified node.
     pub fn get_node(&self, id: impl ToNodeId) -> Option<NodeHandle> {
-        self.task.get_node(id).map(|task| NodeHandle { task })
+        self.task.get_node(id).map(|task| NodeHandle {
+            task,
+            handle: self.task.clone(),
+            time: self.time.clone(),
+        })
+    }
+
+    /// Returns the simulator of type `S`.
+    pub(crate) fn simulator<S: plugin::Simulator>(&self) -> Arc<S> {
+        let sims = self.sims.lock();
+        sims[&TypeId::of::<S>()]
+            .clone()
+            .downcast_arc()
+            .ok()
+            .unwrap()
+    }
+
+    /// Create nodes, override links and schedule faults described in the config.
+    fn apply_config(&self) {
+        for node in &self.config.nodes {
+            let mut builder = self.create_node().name(&node.name);
+            if let Some(ip) = node.ip {
+                builder = builder.ip(ip);
+            }
+            if let Some(zone) = &node.zone {
+                builder = builder.label("zone", zone);
+            }
+            for (key, value) in &node.labels {
+                builder = builder.label(key, value);
+            }
+            for (key, value) in &node.env {
+                builder = builder.env(key, value);
+            }
+            builder.build();
+        }
+        let net = self.simulator::<net::NetSim>();
+        for link in &self.config.links {
+            net.set_link_config(link.src.clone(), link.dst.clone(), link.resolve());
+        }
+        if let Some(ntp) = &self.config.time.ntp {
+            self.poll_ntp(ntp.clone());
+        }
+        for fault in &self.config.faults {
+            let handle = self.clone();
+            let kind = fault.kind.clone();
+            self.time
+                .add_timer(fault.at, move || handle.inject_fault(&kind));
+        }
+    }
+
+    /// Synchronize wall clocks of reachable nodes, and schedule the next poll.
+    fn poll_ntp(&self, ntp: time::NtpConfig) {
+        let net = self.simulator::<net::NetSim>();
+        let max = ntp.accuracy.as_nanos().min(i64::MAX as u128) as i64;
+        for node in self.time.skewed_nodes() {
+            if self.task.is_exit(node) || net.is_node_clogged(node) {
+                continue;
+            }
+            let error = self.rand.with(|rng| rng.gen_range(-max..=max));
+            self.time.sync_node_clock(node, error);
+        }
+        let handle = self.clone();
+        self.time
+            .add_timer(ntp.poll_interval, move || handle.poll_ntp(ntp));
+    }
+
+    /// Inject a fault described in the config.
+    fn inject_fault(&self, fault: &FaultKind) {
+        debug!(?fault, "inject fault");
+        let net = self.simulator::<net::NetSim>();
+        let id = |name: &String| name.to_node_id(&self.task);
+        match fault {
+            FaultKind::Kill { node } => self.kill(node),
+            FaultKind::Restart { node } => self.restart(node),
+            FaultKind::Pause { node } => self.pause(node),
+            FaultKind::Resume { node } => self.resume(node),
+            FaultKind::Clog { node } => net.clog_node(id(node)),
+            FaultKind::Unclog { node } => net.unclog_node(id(node)),
+            FaultKind::ClogLink { src, dst } => net.clog_link(id(src), id(dst)),
+            FaultKind::UnclogLink { src, dst } => net.unclog_link(id(src), id(dst)),
+            FaultKind::ClockForward { node, by } => self.jump_clock_forward(node, *by),
+            FaultKind::ClockBackward { node, by } => self.jump_clock_backward(node, *by),
+        }
+    }
+
+    /// End the current phase of the simulation and start a new one.
+    ///
+    /// Phases are reported separately in [`RuntimeMetrics::time_report`].
+    pub fn enter_phase(&self, name: impl Into<String>) {
+        (self.phases.lock()).enter(name.into(), &self.time, &self.task);
     }
 
     /// Returns a view that lets you get information about how the runtime is performing.
     pub fn metrics(&self) -> RuntimeMetrics {
         RuntimeMetrics {
             task: self.task.clone(),
+            time: self.time.clone(),
+            phases: self.phases.clone(),
+        }
+    }
+
+    /// Returns a snapshot of live tasks on all nodes, with their names, spawn sites and states.
+    ///
+    /// This helps to find out why the simulation is stuck:
+    ///
+    /// ```
+    /// # use madsim::runtime::Handle;
+    /// # madsim::runtime::Runtime::new().block_on(async {
+    /// println!("{}", Handle::current().dump());
+    /// # });
+    /// ```
+    pub fn dump(&self) -> task::Dump {
+        self.task.dump()
+    }
+}
+
+/// A scheduling decision made by [`Runtime::step`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// A task was polled.
+    Poll {
+        /// The node of the task.
+        node: NodeId,
+        /// The task ID.
+        task: task::Id,
+        /// The task name.
+        name: Option<String>,
+        /// The location where the task was spawned.
+        location: String,
+    },
+    /// No task was ready, so the time advanced to the next timer event, which may deliver
+    /// messages and wake tasks.
+    Advance {
+        /// The elapsed time after the advance.
+        elapsed: Duration,
+    },
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Poll {
+                node,
+                task,
+                name,
+                location,
+            } => {
+                write!(f, "poll task {task}")?;
+                if let Some(name) = name {
+                    write!(f, " {name:?}")?;
+                }
+                write!(f, " on node {node} spawned at {location}")
+            }
+            Step::Advance { elapsed } => write!(f, "advance to {elapsed:?}"),
         }
     }
 }
@@ -294,10 +660,16 @@ pub struct NodeBuilder<'a> {
     handle: &'a Handle,
     pub(crate) name: Option<String>,
     pub(crate) ip: Option<IpAddr>,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) env: Vec<(String, String)>,
     pub(crate) cores: Option<usize>,
     pub(crate) init: Option<task::InitFn>,
     pub(crate) restart_on_panic: bool,
     pub(crate) restart_on_panic_matching: Vec<String>,
+    pub(crate) grace_period: Duration,
+    pub(crate) cpu_slowdown: f64,
+    pub(crate) memory_limit: Option<usize>,
+    pub(crate) restart_on_oom: bool,
 }
 
 impl<'a> NodeBuilder<'a> {
@@ -306,9 +678,15 @@ impl<'a> NodeBuilder<'a> {
             handle,
             name: None,
             ip: None,
+            labels: vec![],
+            env: vec![],
             cores: None,
             init: None,
             restart_on_panic: false,
+            grace_period: task::DEFAULT_GRACE_PERIOD,
+            cpu_slowdown: 1.0,
+            memory_limit: None,
+            restart_on_oom: false,
             restart_on_panic_matching: vec![],
         }
     }
@@ -328,14 +706,7 @@ impl<'a> NodeBuilder<'a> {
     where
         F: Future + 'static,
     {
-        self.init = Some(Arc::new(move |handle| {
-            let future = new_task();
-            let h = handle.clone();
-            handle.spawn_local(async move {
-                future.await;
-                h.exit();
-            });
-        }));
+        self.init = Some(init_fn(new_task));
         self
     }
 
@@ -353,12 +724,40 @@ impl<'a> NodeBuilder<'a> {
         self
     }
 
+    /// Set how long the node is allowed to shut down after handling a signal sent by
+    /// [`NodeHandle::signal`], before it is killed. The default is 30 seconds.
+    pub fn grace_period(mut self, period: Duration) -> Self {
+        self.grace_period = period;
+        self
+    }
+
     /// Set one IP address of the node.
     pub fn ip(mut self, ip: IpAddr) -> Self {
         self.ip = Some(ip);
         self
     }
 
+    /// Set a label of the node.
+    ///
+    /// Labels can be used to select nodes in [`NetSim::set_link_config`] and
+    /// [`Handle::select_nodes`].
+    ///
+    /// [`NetSim::set_link_config`]: crate::net::NetSim::set_link_config
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set an environment variable of the node.
+    ///
+    /// Inside the node, [`std::env::var`] returns the value, and [`std::env::set_var`] only
+    /// affects the node. Variables not set fall back to the process environment. The environment
+    /// is reset on restart.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
     /// Set the number of CPU cores of the node.
     ///
     /// This will be the return value of [`std::thread::available_parallelism`].
@@ -368,27 +767,99 @@ impl<'a> NodeBuilder<'a> {
         self
     }
 
+    /// Set the CPU slowdown factor of the node. The default is 1.
+    ///
+    /// Polls on the node take the factor times the simulated CPU time of
+    /// [`task::Config::poll_time`], to model slower hardware or an overloaded host. If the poll
+    /// time is not set, polls take [`task::DEFAULT_POLL_TIME`] times the factor instead.
+    pub fn cpu_slowdown(mut self, factor: f64) -> Self {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "CPU slowdown factor must be positive"
+        );
+        self.cpu_slowdown = factor;
+        self
+    }
+
+    /// Set the memory limit of the node in bytes.
+    ///
+    /// The node is killed when the memory accounted by [`crate::memory::alloc`] exceeds the limit.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Automatically restart the node when it runs out of memory.
+    pub fn restart_on_oom(mut self) -> Self {
+        self.restart_on_oom = true;
+        self
+    }
+
     /// Build a node.
     pub fn build(self) -> NodeHandle {
         let task = self.handle.task.create_node(&self);
+        let weights = &self.handle.config.task.node_weights;
+        if let Some(weight) = self.name.as_ref().and_then(|name| weights.get(name)) {
+            self.handle
+                .task
+                .set_schedule_weight(task.node_id(), *weight);
+        }
+        (self.handle.time).init_node(task.node_id(), &self.handle.config.time, &self.handle.rand);
         let sims = self.handle.sims.lock();
         let values = sims.values();
         for sim in values {
             sim.create_node(task.node_id());
-            if let Some(ip) = self.ip {
-                if let Some(net) = sim.downcast_ref::<net::NetSim>() {
+            if let Some(net) = sim.downcast_ref::<net::NetSim>() {
+                if let Some(ip) = self.ip {
                     net.set_ip(task.node_id(), ip)
                 }
+                for (key, value) in &self.labels {
+                    net.set_node_label(task.node_id(), key, value);
+                }
             }
         }
-        NodeHandle { task }
+        NodeHandle {
+            task,
+            handle: self.handle.task.clone(),
+            time: self.handle.time.clone(),
+        }
     }
 }
 
+/// Wraps the initial task of a node, which exits the node when it finishes.
+fn init_fn<F>(new_task: impl Fn() -> F + Send + Sync + 'static) -> task::InitFn
+where
+    F: Future + 'static,
+{
+    Arc::new(move |handle| {
+        let future = new_task();
+        let h = handle.clone();
+        handle.spawn_local(async move {
+            future.await;
+            h.exit();
+        });
+    })
+}
+
+/// How a node terminated. See [`NodeHandle::join`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeExit {
+    /// The initial task of the node finished.
+    Finished,
+    /// A task of the node panicked with the message, and the node is going to restart.
+    Panicked(String),
+    /// The node was killed.
+    Killed,
+    /// The node was killed because it ran out of memory. See [`crate::memory`].
+    OutOfMemory,
+}
+
 /// Handle to a node.
 #[derive(Clone)]
 pub struct NodeHandle {
     task: task::Spawner,
+    handle: task::TaskHandle,
+    time: time::TimeHandle,
 }
 
 impl NodeHandle {
@@ -397,14 +868,136 @@ impl NodeHandle {
         self.task.node_id()
     }
 
-    /// Spawn a future onto the runtime.
+    /// Kill the node, as if it lost power. See [`Handle::kill`].
+    ///
+    /// All in-memory state of the node is lost, while its file system persists except for
+    /// writes that have not been synced. See [`crate::fs`].
+    pub fn kill(&self) {
+        self.handle.kill(self.id());
+    }
+
+    /// Restart the node. See [`Handle::restart`].
+    pub fn restart(&self) {
+        self.handle.restart(self.id());
+    }
+
+    /// Spawn a future onto the node.
+    ///
+    /// This can be called from the test driver at any time, e.g. to inject probes or admin
+    /// commands into a running node. The task runs in the context of the node, with its
+    /// network identity and clock. If the node has been restarted, the task is spawned on the
+    /// new instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has been killed and not restarted.
     #[track_caller]
     pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
     where
         F: Future + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.task.spawn(future)
+        match self.handle.get_node(self.id()) {
+            Some(task) => task.spawn(future),
+            None => self.task.spawn(future),
+        }
+    }
+
+    /// Waits for the current instance of the node to terminate, and returns how it terminated.
+    ///
+    /// A node terminates when its initial task set by [`NodeBuilder::init`] finishes, when one
+    /// of its tasks panics and it restarts on panic, or when it is killed. If the node is
+    /// restarted later, a new call waits for the new instance.
+    pub async fn join(&self) -> NodeExit {
+        let mut exit = self.handle.node_exit(self.id());
+        loop {
+            if let Some(exit) = exit.borrow().clone() {
+                return exit;
+            }
+            if exit.changed().await.is_err() {
+                return NodeExit::Killed;
+            }
+        }
+    }
+
+    /// Returns whether the current instance of the node has terminated.
+    pub fn is_finished(&self) -> bool {
+        self.handle.node_exit(self.id()).borrow().is_some()
+    }
+
+    /// Pauses all tasks of the node, to simulate a stop-the-world stall like a GC pause, a VM
+    /// freeze or `SIGSTOP`.
+    ///
+    /// The rest of the cluster and the simulated time keep running. Timers of the node that
+    /// expire during the pause fire on [`resume`](NodeHandle::resume), and messages sent to the
+    /// node are buffered in its sockets.
+    pub fn pause(&self) {
+        self.handle.pause(self.id());
+    }
+
+    /// Resumes the tasks of the node paused by [`pause`](NodeHandle::pause).
+    pub fn resume(&self) {
+        self.handle.resume(self.id());
+    }
+
+    /// Returns whether the node is paused.
+    pub fn is_paused(&self) -> bool {
+        self.handle.is_paused(self.id())
+    }
+
+    /// Sets the CPU slowdown factor of the node, e.g. to simulate a host getting overloaded.
+    /// See [`NodeBuilder::cpu_slowdown`].
+    pub fn set_cpu_slowdown(&self, factor: f64) {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "CPU slowdown factor must be positive"
+        );
+        self.handle.set_cpu_slowdown(self.id(), factor);
+    }
+
+    /// Sends a signal to the node.
+    ///
+    /// If the node has a handler of the signal, e.g. [`signal::ctrl_c`] for [`Signal::Int`],
+    /// the handler is triggered and the node is killed if it does not terminate within the
+    /// [grace period](NodeBuilder::grace_period). Otherwise the node is killed immediately.
+    ///
+    /// [`signal::ctrl_c`]: crate::signal::ctrl_c
+    pub fn signal(&self, signal: Signal) {
+        let id = self.id();
+        if !self.handle.send_signal(id, signal.kind()) {
+            return;
+        }
+        let grace_period = self.handle.grace_period(id);
+        let exit = self.handle.node_exit(id);
+        let handle = self.handle.clone();
+        self.time.add_timer(grace_period, move || {
+            // the same instance is still running
+            if exit.borrow().is_none() {
+                debug!(node = %id, "grace period expired");
+                handle.kill(id);
+            }
+        });
+    }
+
+    /// Returns a snapshot of live tasks on this node.
+    pub fn dump_tasks(&self) -> Vec<task::TaskDump> {
+        self.handle.dump_node(self.id()).unwrap_or_default()
+    }
+
+    /// Aborts the task with the ID on this node, to simulate a single subsystem crashing.
+    ///
+    /// Returns `false` if no such task is alive. Task IDs can be listed by
+    /// [`dump_tasks`](NodeHandle::dump_tasks).
+    pub fn abort_task(&self, id: task::Id) -> bool {
+        self.handle.abort_tasks(self.id(), |task| task.id == id) != 0
+    }
+
+    /// Aborts all tasks with the name on this node. Returns the number of aborted tasks.
+    ///
+    /// Tasks are named by [`task::Builder::name`].
+    pub fn abort_tasks_by_name(&self, name: &str) -> usize {
+        self.handle
+            .abort_tasks(self.id(), |task| task.name.as_deref() == Some(name))
     }
 }
 
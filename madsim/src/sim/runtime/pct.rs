@@ -0,0 +1,131 @@
+//! The PCT (probabilistic concurrency testing) scheduler.
+//!
+//! It follows "A Randomized Scheduler with Probabilistic Guarantees of Finding Bugs"
+//! (Burckhardt et al., ASPLOS 2010). See [`Scheduler::Pct`].
+//!
+//! [`Scheduler::Pct`]: crate::config::Scheduler::Pct
+
+use crate::task;
+use rand::{seq::index, Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+use std::collections::HashMap;
+
+pub(crate) struct Pct {
+    rng: Xoshiro256PlusPlus,
+    depth: u64,
+    priorities: HashMap<task::Id, u64>,
+    /// The steps at which the priority of the polled task is lowered.
+    change_points: Vec<u64>,
+    /// The number of polls so far.
+    step: u64,
+}
+
+impl Pct {
+    pub fn new(seed: u64, depth: usize, steps: u64) -> Self {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        // distinct steps, at most one for each
+        let steps = steps.max(1) as usize;
+        let change_points = index::sample(&mut rng, steps, depth.saturating_sub(1).min(steps))
+            .into_iter()
+            .map(|i| i as u64 + 1)
+            .collect();
+        Pct {
+            rng,
+            depth: depth as u64,
+            priorities: HashMap::new(),
+            change_points,
+            step: 0,
+        }
+    }
+
+    /// Chooses a task to poll among `len` ready ones. `id` returns the ID of the i-th task.
+    pub fn choose(&mut self, len: usize, id: impl Fn(usize) -> Option<task::Id>) -> usize {
+        self.step += 1;
+        let mut chosen = 0;
+        let mut highest = 0;
+        for i in 0..len {
+            // tasks that have been dropped are removed first
+            let Some(id) = id(i) else {
+                return i;
+            };
+            let depth = self.depth;
+            let rng = &mut self.rng;
+            // initial priorities are no less than `depth`
+            let priority = *(self.priorities)
+                .entry(id)
+                .or_insert_with(|| depth + rng.gen::<u32>() as u64);
+            if priority > highest {
+                chosen = i;
+                highest = priority;
+            }
+        }
+        if let Some(i) = self.change_points.iter().position(|&s| s == self.step) {
+            // the i-th change point lowers the priority to `depth - 1 - i`
+            let id = id(chosen).unwrap();
+            self.priorities.insert(id, self.depth - 1 - i as u64);
+        }
+        chosen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{runtime::Runtime, Config};
+    use std::sync::{Arc, Mutex};
+
+    /// Returns the sequence of (task, step) polled.
+    fn run(seed: u64, config: Config) -> Vec<(usize, usize)> {
+        let runtime = Runtime::with_seed_and_config(seed, config);
+        runtime.block_on(async {
+            let log = Arc::new(Mutex::new(vec![]));
+            let tasks: Vec<_> = (0..5)
+                .map(|i| {
+                    let log = log.clone();
+                    crate::task::spawn(async move {
+                        for step in 0..5 {
+                            log.lock().unwrap().push((i, step));
+                            crate::task::yield_now().await;
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            Arc::try_unwrap(log).unwrap().into_inner().unwrap()
+        })
+    }
+
+    /// Returns true if the steps of each task are contiguous.
+    fn is_sequential(log: &[(usize, usize)]) -> bool {
+        log.chunks(5).all(|c| c.iter().all(|&(i, _)| i == c[0].0))
+    }
+
+    #[test]
+    fn priority() {
+        let pct = |depth| Config {
+            scheduler: crate::config::Scheduler::Pct { depth, steps: 30 },
+            ..Default::default()
+        };
+        // without change points, the task with the highest priority runs to completion
+        assert!(is_sequential(&run(1, pct(1))));
+        assert!(!is_sequential(&run(1, Config::default())));
+        // deterministic
+        assert_eq!(run(2, pct(3)), run(2, pct(3)));
+        // change points preempt tasks in some seeds
+        assert!((0..10).any(|seed| !is_sequential(&run(seed, pct(3)))));
+    }
+
+    #[test]
+    fn distinct_change_points() {
+        for seed in 0..10 {
+            let mut points = super::Pct::new(seed, 10, 10).change_points;
+            points.sort();
+            points.dedup();
+            assert_eq!(points.len(), 9);
+            assert!(points.iter().all(|s| (1..=10).contains(s)));
+            // more change points than steps
+            assert_eq!(super::Pct::new(seed, 10, 3).change_points.len(), 3);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Node registry: labels, queries and events of nodes.
+
+use super::*;
+use crate::net::NodeSelector;
+use std::collections::BTreeMap;
+use tokio::sync::mpsc;
+
+/// An event of the node registry. See [`Handle::subscribe_nodes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeEvent {
+    /// A node was created.
+    Created(NodeId),
+    /// A node was killed.
+    Killed(NodeId),
+    /// A node was restarted.
+    Restarted(NodeId),
+    /// A label of the node was set.
+    LabelChanged {
+        /// The node.
+        id: NodeId,
+        /// The label key.
+        key: String,
+        /// The new label value.
+        value: String,
+    },
+}
+
+impl Handle {
+    /// Returns handles of all nodes in the order of creation, excluding the main node.
+    pub fn nodes(&self) -> Vec<NodeHandle> {
+        (self.task.node_ids().into_iter())
+            .filter_map(|id| self.get_node(id))
+            .collect()
+    }
+
+    /// Returns handles of nodes selected by all of the selectors in the order of creation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{net::NodeSelector, runtime::Runtime};
+    ///
+    /// let runtime = Runtime::new();
+    /// for (zone, role) in [("a", "leader"), ("b", "follower"), ("b", "follower"), ("c", "follower")] {
+    ///     runtime.create_node().label("zone", zone).label("role", role).build();
+    /// }
+    /// let followers_in_b = runtime.handle().select_nodes(&[
+    ///     NodeSelector::label("role", "follower"),
+    ///     NodeSelector::label("zone", "b"),
+    /// ]);
+    /// assert_eq!(followers_in_b.len(), 2);
+    /// ```
+    pub fn select_nodes(&self, selectors: &[NodeSelector]) -> Vec<NodeHandle> {
+        (self.task.node_ids().into_iter())
+            .filter(|&id| {
+                let labels = self.task.labels(id);
+                selectors.iter().all(|s| match s {
+                    NodeSelector::Any => true,
+                    NodeSelector::Id(x) => *x == id,
+                    NodeSelector::Label { key, value } => {
+                        matches!(labels.get(key), Some(v) if crate::net::wildcard_match(value, v))
+                    }
+                })
+            })
+            .filter_map(|id| self.get_node(id))
+            .collect()
+    }
+
+    /// Set a label of the node.
+    ///
+    /// The label is visible to both [`select_nodes`](Handle::select_nodes) and
+    /// [`NetSim::set_link_config`](crate::net::NetSim::set_link_config).
+    pub fn set_node_label(
+        &self,
+        id: impl ToNodeId,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) {
+        let id = id.to_node_id(&self.task);
+        let (key, value) = (key.into(), value.into());
+        self.simulator::<net::NetSim>()
+            .set_node_label(id, key.clone(), value.clone());
+        self.task.set_label(id, key, value);
+    }
+
+    /// Subscribe to events of the node registry.
+    ///
+    /// Events happened after this call are sent to the returned receiver.
+    /// Dropping the receiver cancels the subscription.
+    pub fn subscribe_nodes(&self) -> mpsc::UnboundedReceiver<NodeEvent> {
+        self.task.subscribe_nodes()
+    }
+}
+
+impl NodeHandle {
+    /// Returns the node name.
+    pub fn name(&self) -> Option<String> {
+        self.handle.node_name(self.id())
+    }
+
+    /// Returns all labels of the node.
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        self.handle.labels(self.id())
+    }
+
+    /// Returns the value of a label of the node.
+    pub fn label(&self, key: &str) -> Option<String> {
+        self.handle.labels(self.id()).remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_and_subscribe() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let handle = Handle::current();
+            let mut events = handle.subscribe_nodes();
+            let a = handle
+                .create_node()
+                .name("a")
+                .label("zone", "us-east-1")
+                .label("version", "1.0")
+                .build();
+            let b = handle
+                .create_node()
+                .name("b")
+                .label("zone", "us-west-1")
+                .build();
+            assert_eq!(handle.nodes().len(), 2);
+            assert_eq!(a.name().as_deref(), Some("a"));
+            assert_eq!(a.label("version").as_deref(), Some("1.0"));
+            assert_eq!(b.label("version"), None);
+
+            let selected = handle.select_nodes(&[NodeSelector::label("zone", "us-*")]);
+            assert_eq!(
+                selected.iter().map(|n| n.id()).collect::<Vec<_>>(),
+                [a.id(), b.id()]
+            );
+
+            handle.set_node_label(b.id(), "version", "2.0");
+            let selected = handle.select_nodes(&[NodeSelector::label("version", "2.*")]);
+            assert_eq!(selected.len(), 1);
+            assert_eq!(selected[0].id(), b.id());
+
+            handle.kill(a.id());
+            handle.restart(a.id());
+            assert_eq!(events.recv().await, Some(NodeEvent::Created(a.id())));
+            assert_eq!(events.recv().await, Some(NodeEvent::Created(b.id())));
+            assert_eq!(
+                events.recv().await,
+                Some(NodeEvent::LabelChanged {
+                    id: b.id(),
+                    key: "version".into(),
+                    value: "2.0".into()
+                })
+            );
+            assert_eq!(events.recv().await, Some(NodeEvent::Killed(a.id())));
+            assert_eq!(events.recv().await, Some(NodeEvent::Restarted(a.id())));
+        });
+    }
+}
@@ -0,0 +1,162 @@
+//! Simulated time versus wall-clock time.
+
+use std::{fmt, time::Duration};
+
+use super::*;
+
+/// Tracks the phases of a simulation.
+pub(crate) struct Phases {
+    finished: Vec<PhaseReport>,
+    current: Phase,
+}
+
+/// The start of a phase.
+struct Phase {
+    name: String,
+    sim_start: Duration,
+    wall_start: Duration,
+    polls_start: u64,
+}
+
+impl Phases {
+    pub fn new(time: &time::TimeHandle, task: &task::TaskHandle) -> Self {
+        Phases {
+            finished: vec![],
+            current: Phase::start("main".into(), time, task),
+        }
+    }
+
+    /// Ends the current phase and starts a new one.
+    pub fn enter(&mut self, name: String, time: &time::TimeHandle, task: &task::TaskHandle) {
+        let next = Phase::start(name, time, task);
+        let prev = std::mem::replace(&mut self.current, next);
+        self.finished.push(prev.end(time, task));
+    }
+
+    /// Returns the report including the current phase so far.
+    pub fn report(&self, time: &time::TimeHandle, task: &task::TaskHandle) -> TimeReport {
+        let mut phases = self.finished.clone();
+        phases.push(self.current.end(time, task));
+        TimeReport { phases }
+    }
+}
+
+impl Phase {
+    fn start(name: String, time: &time::TimeHandle, task: &task::TaskHandle) -> Self {
+        Phase {
+            name,
+            sim_start: time.elapsed(),
+            wall_start: time::real_monotonic(),
+            polls_start: task.num_polls(),
+        }
+    }
+
+    fn end(&self, time: &time::TimeHandle, task: &task::TaskHandle) -> PhaseReport {
+        PhaseReport {
+            name: self.name.clone(),
+            sim_time: time.elapsed() - self.sim_start,
+            wall_time: time::real_monotonic() - self.wall_start,
+            polls: task.num_polls() - self.polls_start,
+        }
+    }
+}
+
+/// Report of simulated time versus wall-clock time, per phase.
+///
+/// Phases are started by [`Handle::enter_phase`]. The first phase is named `main`.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeReport {
+    /// All phases in order.
+    pub phases: Vec<PhaseReport>,
+}
+
+/// Simulated time versus wall-clock time of a phase.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseReport {
+    /// The phase name.
+    pub name: String,
+    /// The simulated time elapsed.
+    pub sim_time: Duration,
+    /// The wall-clock time spent.
+    pub wall_time: Duration,
+    /// The number of times tasks were polled.
+    pub polls: u64,
+}
+
+impl PhaseReport {
+    /// Returns the ratio of simulated time to wall-clock time.
+    pub fn speedup(&self) -> f64 {
+        self.sim_time.as_secs_f64() / self.wall_time.as_secs_f64()
+    }
+
+    /// Returns the number of polls per wall-clock second.
+    pub fn polls_per_sec(&self) -> f64 {
+        self.polls as f64 / self.wall_time.as_secs_f64()
+    }
+}
+
+impl TimeReport {
+    /// Returns the sum of all phases.
+    pub fn total(&self) -> PhaseReport {
+        PhaseReport {
+            name: "total".into(),
+            sim_time: self.phases.iter().map(|p| p.sim_time).sum(),
+            wall_time: self.phases.iter().map(|p| p.wall_time).sum(),
+            polls: self.phases.iter().map(|p| p.polls).sum(),
+        }
+    }
+}
+
+impl fmt::Display for TimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>12} {:>12} {:>10} {:>12} {:>12}",
+            "phase", "sim time", "wall time", "speedup", "polls", "polls/s"
+        )?;
+        for p in self.phases.iter().chain(Some(&self.total())) {
+            writeln!(
+                f,
+                "{:<16} {:>12} {:>12} {:>10.1} {:>12} {:>12.0}",
+                p.name,
+                format!("{:.3?}", p.sim_time),
+                format!("{:.3?}", p.wall_time),
+                p.speedup(),
+                p.polls,
+                p.polls_per_sec(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::sleep;
+
+    #[test]
+    fn phases() {
+        let runtime = Runtime::new();
+        let handle = runtime.handle().clone();
+        runtime.block_on(async move {
+            sleep(Duration::from_secs(1)).await;
+            handle.enter_phase("load");
+            for _ in 0..10 {
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+        let report = runtime.handle().metrics().time_report();
+        let names: Vec<_> = report.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["main", "load"]);
+        let (main, load) = (&report.phases[0], &report.phases[1]);
+        assert!(main.sim_time >= Duration::from_secs(1) && main.sim_time < Duration::from_secs(2));
+        assert!(load.sim_time >= Duration::from_secs(10));
+        assert!(load.polls >= 10);
+        let total = report.total();
+        assert_eq!(total.sim_time, main.sim_time + load.sim_time);
+        assert!(report.to_string().contains("load"));
+    }
+}
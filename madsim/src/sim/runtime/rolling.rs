@@ -0,0 +1,256 @@
+//! Rolling restart of nodes.
+
+use super::*;
+use std::{fmt, pin::Pin};
+
+type HealthCheckFn = Box<dyn Fn(NodeHandle) -> Pin<Box<dyn Future<Output = bool>>>>;
+
+/// Restarts a set of nodes in batches, waiting for them to become healthy between batches.
+///
+/// Created by [`Handle::rolling_restart`].
+///
+/// # Example
+///
+/// ```
+/// use madsim::{runtime::Runtime, time::Duration};
+///
+/// let runtime = Runtime::new();
+/// let nodes: Vec<_> = (0..3)
+///     .map(|_| runtime.create_node().init(|| async { /* v1 */ }).build().id())
+///     .collect();
+/// runtime.block_on(async move {
+///     madsim::runtime::Handle::current()
+///         .rolling_restart(nodes)
+///         .upgrade(|| async { /* v2 */ })
+///         .health_check(|node| async move { !node.is_paused() })
+///         .timeout(Duration::from_secs(30))
+///         .run()
+///         .await
+///         .unwrap();
+/// });
+/// ```
+pub struct RollingRestart {
+    handle: Handle,
+    nodes: Vec<NodeId>,
+    batch_size: usize,
+    check_interval: Duration,
+    timeout: Duration,
+    health_check: Option<HealthCheckFn>,
+    upgrade: Option<task::InitFn>,
+}
+
+impl RollingRestart {
+    /// Set the number of nodes restarted at a time. The default is 1.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "batch size must be positive");
+        self.batch_size = size;
+        self
+    }
+
+    /// Set the health check of restarted nodes.
+    ///
+    /// After restarting a batch, the check is called on each node of the batch every
+    /// [`check_interval`](RollingRestart::check_interval) until it returns `true`.
+    /// Without a health check, the next batch is restarted immediately.
+    pub fn health_check<F>(mut self, check: impl Fn(NodeHandle) -> F + 'static) -> Self
+    where
+        F: Future<Output = bool> + 'static,
+    {
+        self.health_check = Some(Box::new(move |node| Box::pin(check(node))));
+        self
+    }
+
+    /// Set the interval between health checks of a node. The default is 1 second.
+    pub fn check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Set how long a restarted node is allowed to become healthy. The default is 60 seconds.
+    ///
+    /// If a node does not pass the health check in time, the rollout is stopped.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Replace the initial task of restarted nodes, to simulate a version upgrade.
+    pub fn upgrade<F>(mut self, new_task: impl Fn() -> F + Send + Sync + 'static) -> Self
+    where
+        F: Future + 'static,
+    {
+        self.upgrade = Some(init_fn(new_task));
+        self
+    }
+
+    /// Run the rollout.
+    ///
+    /// Returns an error if a node does not pass the health check in time. Nodes in later batches
+    /// are not restarted in that case.
+    pub async fn run(self) -> Result<(), UnhealthyNode> {
+        for batch in self.nodes.chunks(self.batch_size) {
+            debug!(?batch, "rolling restart");
+            for &id in batch {
+                if let Some(init) = &self.upgrade {
+                    self.handle.task.set_init(id, init.clone());
+                }
+                self.handle.restart(id);
+            }
+            let Some(check) = &self.health_check else {
+                continue;
+            };
+            for &id in batch {
+                let node = self.handle.get_node(id).expect("node not found");
+                let deadline = time::Instant::now() + self.timeout;
+                loop {
+                    // a check that never completes is bounded by the deadline too
+                    let remaining = deadline.saturating_duration_since(time::Instant::now());
+                    let healthy = time::timeout(remaining, check(node.clone())).await;
+                    if matches!(healthy, Ok(true)) {
+                        break;
+                    }
+                    if time::Instant::now() >= deadline {
+                        return Err(UnhealthyNode { id });
+                    }
+                    time::sleep(self.check_interval).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by [`RollingRestart::run`] if a node does not become healthy in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnhealthyNode {
+    /// The node that failed the health check.
+    pub id: NodeId,
+}
+
+impl fmt::Display for UnhealthyNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {} did not become healthy after restart", self.id)
+    }
+}
+
+impl std::error::Error for UnhealthyNode {}
+
+impl Handle {
+    /// Restart the nodes one at a time, or in batches. See [`RollingRestart`].
+    pub fn rolling_restart(&self, nodes: impl IntoIterator<Item = NodeId>) -> RollingRestart {
+        RollingRestart {
+            handle: self.clone(),
+            nodes: nodes.into_iter().collect(),
+            batch_size: 1,
+            check_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(60),
+            health_check: None,
+            upgrade: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[test]
+    fn upgrade_one_at_a_time() {
+        let runtime = Runtime::new();
+        let version = Arc::new([
+            AtomicUsize::new(1),
+            AtomicUsize::new(1),
+            AtomicUsize::new(1),
+        ]);
+        let nodes: Vec<_> = (0..3)
+            .map(|i| {
+                let version = version.clone();
+                runtime
+                    .create_node()
+                    .init(move || {
+                        let version = version.clone();
+                        async move { version[i].store(1, Ordering::SeqCst) }
+                    })
+                    .build()
+                    .id()
+            })
+            .collect();
+
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let t0 = time::Instant::now();
+            let (version1, nodes1) = (version.clone(), nodes.clone());
+            let (version2, nodes2) = (version.clone(), nodes.clone());
+            handle
+                .rolling_restart(nodes.clone())
+                .upgrade(move || {
+                    let (version, nodes) = (version1.clone(), nodes1.clone());
+                    async move {
+                        let id = crate::context::current_node();
+                        let i = nodes.iter().position(|&x| x == id).unwrap();
+                        // takes 2 seconds to start up
+                        time::sleep(Duration::from_secs(2)).await;
+                        version[i].store(2, Ordering::SeqCst);
+                    }
+                })
+                .health_check(move |node| {
+                    let i = nodes2.iter().position(|&id| id == node.id()).unwrap();
+                    // later nodes are not restarted yet
+                    assert!(version2[i + 1..]
+                        .iter()
+                        .all(|v| v.load(Ordering::SeqCst) == 1));
+                    let healthy = version2[i].load(Ordering::SeqCst) == 2;
+                    async move { healthy }
+                })
+                .run()
+                .await
+                .unwrap();
+            assert!(version.iter().all(|v| v.load(Ordering::SeqCst) == 2));
+            assert!(t0.elapsed() >= Duration::from_secs(6));
+        });
+    }
+
+    #[test]
+    fn stop_on_unhealthy() {
+        let runtime = Runtime::new();
+        let nodes: Vec<_> = (0..3).map(|_| runtime.create_node().build().id()).collect();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let checked = Arc::new(AtomicBool::new(false));
+            let checked1 = checked.clone();
+            let err = handle
+                .rolling_restart(nodes.clone())
+                .batch_size(2)
+                .timeout(Duration::from_secs(10))
+                .health_check(move |_| {
+                    checked1.store(true, Ordering::SeqCst);
+                    async { false }
+                })
+                .run()
+                .await
+                .unwrap_err();
+            assert!(checked.load(Ordering::SeqCst));
+            assert_eq!(err, UnhealthyNode { id: nodes[0] });
+        });
+    }
+
+    #[test]
+    fn hanging_health_check() {
+        let runtime = Runtime::new();
+        let nodes: Vec<_> = (0..2).map(|_| runtime.create_node().build().id()).collect();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let t0 = time::Instant::now();
+            let err = handle
+                .rolling_restart(nodes.clone())
+                .timeout(Duration::from_secs(10))
+                .health_check(|_| std::future::pending())
+                .run()
+                .await
+                .unwrap_err();
+            assert_eq!(err, UnhealthyNode { id: nodes[0] });
+            assert!(t0.elapsed() < Duration::from_secs(11));
+        });
+    }
+}
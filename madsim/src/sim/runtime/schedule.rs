@@ -0,0 +1,214 @@
+//! Recording and replaying schedules.
+//!
+//! A schedule is the sequence of decisions made by the simulator: which task is polled next,
+//! and the latency of each packet on a link. Unlike a seed, a schedule does not depend on how
+//! random numbers are consumed, so it reproduces a run even after unrelated code changes.
+
+use crate::task::{self, NodeId};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, io,
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+/// A decision made by the simulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Decision {
+    /// The task is polled.
+    Poll(task::Id),
+    /// The latency of a packet on the link. `None` if the packet is lost.
+    Link(NodeId, NodeId, Option<Duration>),
+}
+
+/// The scheduling and network decisions of a run.
+///
+/// Record a schedule with [`Runtime::enable_schedule_record`] and replay it with
+/// [`Runtime::replay_schedule`].
+///
+/// The schedule is saved as a text file with one decision per line:
+///
+/// - `p <task>`: poll the task.
+/// - `l <src node> <dst node> <latency in ns>`: deliver a packet on the link with the latency.
+/// - `l <src node> <dst node> -`: drop a packet on the link.
+///
+/// [`Runtime::enable_schedule_record`]: super::Runtime::enable_schedule_record
+/// [`Runtime::replay_schedule`]: super::Runtime::replay_schedule
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    decisions: Vec<Decision>,
+}
+
+impl Schedule {
+    pub(crate) fn push(&mut self, decision: Decision) {
+        self.decisions.push(decision);
+    }
+
+    /// Returns the number of decisions.
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Returns the first `len` decisions.
+    pub(crate) fn prefix(&self, len: usize) -> Schedule {
+        Schedule {
+            decisions: self.decisions[..len.min(self.len())].to_vec(),
+        }
+    }
+
+    /// Returns true if there is no decision.
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+
+    /// Writes the schedule to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Reads a schedule from a file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for decision in &self.decisions {
+            match decision {
+                Decision::Poll(id) => writeln!(f, "p {}", id.as_u64())?,
+                Decision::Link(src, dst, Some(latency)) => {
+                    writeln!(f, "l {src} {dst} {}", latency.as_nanos())?
+                }
+                Decision::Link(src, dst, None) => writeln!(f, "l {src} {dst} -")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut decisions = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let invalid = || format!("invalid schedule at line {}: {line:?}", i + 1);
+            let int = |s: Option<&str>| s.and_then(|s| s.parse::<u64>().ok()).ok_or_else(invalid);
+            let mut tokens = line.split_whitespace();
+            let decision = match tokens.next() {
+                None => continue,
+                Some("p") => Decision::Poll(task::Id::from_u64(int(tokens.next())?)),
+                Some("l") => {
+                    let src = NodeId::from_u64(int(tokens.next())?);
+                    let dst = NodeId::from_u64(int(tokens.next())?);
+                    let latency = match tokens.next() {
+                        Some("-") => None,
+                        s => Some(Duration::from_nanos(int(s)?)),
+                    };
+                    Decision::Link(src, dst, latency)
+                }
+                Some(_) => return Err(invalid()),
+            };
+            if tokens.next().is_some() {
+                return Err(invalid());
+            }
+            decisions.push(decision);
+        }
+        Ok(Schedule { decisions })
+    }
+}
+
+/// The state of replaying a schedule.
+///
+/// Decisions of each kind are replayed in order. Decisions of different links are independent.
+pub(crate) struct Replay {
+    polls: VecDeque<task::Id>,
+    links: HashMap<(NodeId, NodeId), VecDeque<Option<Duration>>>,
+}
+
+impl Replay {
+    pub fn new(schedule: Schedule) -> Self {
+        let mut polls = VecDeque::new();
+        let mut links = HashMap::<_, VecDeque<_>>::new();
+        for decision in schedule.decisions {
+            match decision {
+                Decision::Poll(id) => polls.push_back(id),
+                Decision::Link(src, dst, latency) => {
+                    links.entry((src, dst)).or_default().push_back(latency)
+                }
+            }
+        }
+        Replay { polls, links }
+    }
+
+    /// Returns the next task to poll.
+    pub fn next_poll(&mut self) -> Option<task::Id> {
+        self.polls.pop_front()
+    }
+
+    /// Returns the latency of the next packet on the link.
+    pub fn next_link(&mut self, src: NodeId, dst: NodeId) -> Option<Option<Duration>> {
+        self.links.get_mut(&(src, dst))?.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::sync::{Arc, Mutex};
+
+    /// Returns the order in which tasks finish, and the schedule.
+    fn run(seed: u64, replay: Option<Schedule>) -> (Vec<usize>, Schedule) {
+        let runtime = Runtime::with_seed_and_config(seed, crate::Config::default());
+        runtime.enable_schedule_record();
+        if let Some(schedule) = replay {
+            runtime.replay_schedule(schedule);
+        }
+        let order = runtime.block_on(async {
+            let order = Arc::new(Mutex::new(vec![]));
+            let tasks: Vec<_> = (0..10)
+                .map(|i| {
+                    let order = order.clone();
+                    crate::task::spawn(async move {
+                        for _ in 0..3 {
+                            crate::task::yield_now().await;
+                        }
+                        order.lock().unwrap().push(i);
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            Arc::try_unwrap(order).unwrap().into_inner().unwrap()
+        });
+        (order, runtime.take_schedule().unwrap())
+    }
+
+    #[test]
+    fn replay() {
+        let (order1, schedule) = run(1, None);
+        let (order2, _) = run(2, None);
+        assert_ne!(order1, order2);
+        // replaying with another seed gives the same order
+        let schedule: Schedule = schedule.to_string().parse().unwrap();
+        let (order3, _) = run(2, Some(schedule));
+        assert_eq!(order1, order3);
+    }
+
+    #[test]
+    fn parse() {
+        let s = "p 1\nl 1 2 1000\nl 2 1 -\n";
+        let schedule: Schedule = s.parse().unwrap();
+        assert_eq!(schedule.len(), 3);
+        assert_eq!(schedule.to_string(), s);
+        assert!("x 1".parse::<Schedule>().is_err());
+        assert!("p 1 2".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Snapshot and restore of simulations.
+
+use super::*;
+
+/// A checkpoint of a simulation, taken by [`Handle::snapshot`].
+///
+/// Futures can not be copied, so the state is restored by re-executing the run with the same
+/// seed and config up to the snapshot. As the simulation is deterministic, tasks, network,
+/// clocks and file systems all end up in the same state. See [`Runtime::restore`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The random seed of the run.
+    pub seed: u64,
+    /// The config of the run.
+    pub config: Config,
+    /// The number of task polls before the snapshot, including the current one.
+    pub steps: u64,
+    /// The simulated time when the snapshot was taken.
+    pub elapsed: Duration,
+}
+
+impl Handle {
+    /// Takes a snapshot of the simulation after the current poll.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            seed: self.rand.seed(),
+            config: self.config.clone(),
+            steps: self.task.num_polls(),
+            elapsed: self.time.elapsed(),
+        }
+    }
+}
+
+impl Runtime {
+    /// Creates a runtime that restores the snapshot.
+    ///
+    /// The future passed to [`block_on`](Runtime::block_on) must be the one that took the
+    /// snapshot. It is re-executed up to the snapshot, where `inject` is called, for example to
+    /// inject faults, and the random streams are reseeded with `salt`. So different `inject` or
+    /// `salt` explore different futures from the same state.
+    ///
+    /// Panics if the re-executed run does not reach the same state.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::{Handle, Runtime, Snapshot};
+    /// use std::{sync::Mutex, time::Duration};
+    ///
+    /// static SNAPSHOT: Mutex<Option<Snapshot>> = Mutex::new(None);
+    ///
+    /// async fn scenario() {
+    ///     let handle = Handle::current();
+    ///     handle.create_node().name("server").build();
+    ///     madsim::time::sleep(Duration::from_secs(1)).await;
+    ///     // the critical moment
+    ///     SNAPSHOT.lock().unwrap().get_or_insert_with(|| handle.snapshot());
+    ///     madsim::time::sleep(Duration::from_secs(1)).await;
+    /// }
+    ///
+    /// Runtime::new().block_on(scenario());
+    /// let snapshot = SNAPSHOT.lock().unwrap().clone().unwrap();
+    /// for salt in 0..3 {
+    ///     let runtime = Runtime::restore(&snapshot, salt, |handle| handle.kill("server"));
+    ///     runtime.block_on(scenario());
+    /// }
+    /// ```
+    pub fn restore(
+        snapshot: &Snapshot,
+        salt: u64,
+        inject: impl FnOnce(&Handle) + Send + 'static,
+    ) -> Runtime {
+        let mut rt = Runtime::with_seed_and_config(snapshot.seed, snapshot.config.clone());
+        let handle = rt.handle.clone();
+        let elapsed = snapshot.elapsed;
+        rt.task.set_step_hook(snapshot.steps, move || {
+            assert_eq!(
+                handle.time.elapsed(),
+                elapsed,
+                "failed to restore the snapshot: the run diverged"
+            );
+            debug!(?elapsed, salt, "restored snapshot");
+            inject(&handle);
+            handle.rand.fork_now(salt);
+        });
+        rt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static SNAPSHOT: Mutex<Option<Snapshot>> = Mutex::new(None);
+
+    async fn scenario() -> u64 {
+        let handle = Handle::current();
+        let node = handle.create_node().name("worker").build();
+        let count = Arc::new(AtomicU64::new(0));
+        let count1 = count.clone();
+        node.spawn(async move {
+            loop {
+                time::sleep(Duration::from_millis(100)).await;
+                count1.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        time::sleep(Duration::from_millis(1050)).await;
+        SNAPSHOT.lock().get_or_insert_with(|| handle.snapshot());
+        time::sleep(Duration::from_secs(1)).await;
+        count.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn restore() {
+        let full = Runtime::new().block_on(scenario());
+        assert_eq!(full, 20);
+        let snapshot = SNAPSHOT.lock().clone().unwrap();
+
+        let runtime = Runtime::restore(&snapshot, 0, |_| {});
+        assert_eq!(runtime.block_on(scenario()), 20);
+
+        let runtime = Runtime::restore(&snapshot, 1, |handle| handle.kill("worker"));
+        assert_eq!(runtime.block_on(scenario()), 10);
+    }
+}
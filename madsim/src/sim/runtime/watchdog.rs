@@ -0,0 +1,109 @@
+//! Wall-clock watchdog.
+
+use super::*;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Condvar, Mutex as StdMutex,
+};
+
+/// A thread that dumps the simulation state if it makes no progress in real time.
+pub(super) struct Watchdog {
+    stop: Arc<(StdMutex<bool>, Condvar)>,
+    thread: Option<std::thread::JoinHandle<()>>,
+    /// The number of times the watchdog has fired.
+    fired: Arc<AtomicU64>,
+}
+
+impl Watchdog {
+    /// Starts a watchdog on the runtime.
+    pub fn start(config: config::Watchdog, task: task::TaskHandle, time: time::TimeHandle) -> Self {
+        let stop = Arc::new((StdMutex::new(false), Condvar::new()));
+        let stop1 = stop.clone();
+        let fired = Arc::new(AtomicU64::new(0));
+        let fired1 = fired.clone();
+        let thread = std::thread::Builder::new()
+            .name("madsim-watchdog".into())
+            .spawn(move || {
+                let (lock, cvar) = &*stop1;
+                let mut stopped = lock.lock().unwrap();
+                let mut last_polls = task.num_polls();
+                loop {
+                    stopped = (cvar.wait_timeout_while(stopped, config.timeout, |s| !*s))
+                        .unwrap()
+                        .0;
+                    if *stopped {
+                        return;
+                    }
+                    let polls = task.num_polls();
+                    if polls != last_polls {
+                        last_polls = polls;
+                        continue;
+                    }
+                    fired1.fetch_add(1, Ordering::Relaxed);
+                    eprintln!(
+                        "madsim watchdog: no progress in {:?} of real time\n\
+                         elapsed: {:?}, polls: {polls}, pending timers: {}\n{}",
+                        config.timeout,
+                        time.elapsed(),
+                        time.num_timers(),
+                        task.dump(),
+                    );
+                    if config.abort {
+                        std::process::abort();
+                    }
+                }
+            })
+            .expect("failed to spawn watchdog thread");
+        Watchdog {
+            stop,
+            thread: Some(thread),
+            fired,
+        }
+    }
+
+    /// Returns the number of times the watchdog has fired.
+    #[cfg(test)]
+    fn fired(&self) -> u64 {
+        self.fired.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.stop;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_stalled() {
+        let runtime = Runtime::new();
+        let config = config::Watchdog {
+            timeout: Duration::from_millis(50),
+            abort: false,
+        };
+        let (task, time) = (runtime.handle.task.clone(), runtime.handle.time.clone());
+        let watchdog = Watchdog::start(config, task, time);
+        runtime.block_on(async {
+            // progress is made while the task yields
+            for _ in 0..10 {
+                crate::task::yield_now().await;
+            }
+            // spin in real time without yielding
+            let t0 = time::real_monotonic();
+            while time::real_monotonic() - t0 < Duration::from_millis(300) {
+                std::hint::spin_loop();
+            }
+        });
+        // the process is not aborted
+        assert!(watchdog.fired() >= 1);
+    }
+}
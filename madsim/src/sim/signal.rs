@@ -1,14 +1,129 @@
 //! Asynchronous signal handling.
+//!
+//! Signals are sent to nodes by [`NodeHandle::signal`].
+//!
+//! [`NodeHandle::signal`]: crate::runtime::NodeHandle::signal
+
+use self::unix::SignalKind;
 
 /// Completes when a "ctrl-c" notification is sent to the process.
 pub async fn ctrl_c() -> std::io::Result<()> {
-    let mut rx = crate::context::current_task().node.ctrl_c();
+    let mut rx = crate::context::current_task()
+        .node
+        .signal(SignalKind::interrupt());
     _ = rx.changed().await;
     Ok(())
 }
 
+/// A signal that can be sent to a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Signal {
+    /// `SIGINT`, sent by ctrl-c.
+    Int,
+    /// `SIGTERM`, a request to shut down gracefully.
+    Term,
+    /// `SIGHUP`, usually a request to reload the configuration.
+    Hup,
+    /// `SIGQUIT`.
+    Quit,
+}
+
+impl Signal {
+    /// Returns the kind of the signal.
+    pub(crate) fn kind(self) -> SignalKind {
+        match self {
+            Signal::Int => SignalKind::interrupt(),
+            Signal::Term => SignalKind::terminate(),
+            Signal::Hup => SignalKind::hangup(),
+            Signal::Quit => SignalKind::quit(),
+        }
+    }
+}
+
+/// Unix specific signal handling, mirroring `tokio::signal::unix`.
+pub mod unix {
+    use std::io;
+    use tokio::sync::watch;
+
+    /// Represents the specific kind of signal to listen for.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct SignalKind(libc::c_int);
+
+    impl SignalKind {
+        /// Allows for listening to any valid OS signal.
+        pub const fn from_raw(signum: libc::c_int) -> Self {
+            SignalKind(signum)
+        }
+
+        /// Get the signal's numeric value.
+        pub const fn as_raw_value(&self) -> libc::c_int {
+            self.0
+        }
+
+        /// Represents the `SIGHUP` signal.
+        pub const fn hangup() -> Self {
+            SignalKind(libc::SIGHUP)
+        }
+
+        /// Represents the `SIGINT` signal.
+        pub const fn interrupt() -> Self {
+            SignalKind(libc::SIGINT)
+        }
+
+        /// Represents the `SIGQUIT` signal.
+        pub const fn quit() -> Self {
+            SignalKind(libc::SIGQUIT)
+        }
+
+        /// Represents the `SIGTERM` signal.
+        pub const fn terminate() -> Self {
+            SignalKind(libc::SIGTERM)
+        }
+
+        /// Represents the `SIGUSR1` signal.
+        pub const fn user_defined1() -> Self {
+            SignalKind(libc::SIGUSR1)
+        }
+
+        /// Represents the `SIGUSR2` signal.
+        pub const fn user_defined2() -> Self {
+            SignalKind(libc::SIGUSR2)
+        }
+    }
+
+    /// An listener for receiving a particular type of OS signal.
+    #[derive(Debug)]
+    pub struct Signal {
+        rx: watch::Receiver<()>,
+    }
+
+    impl Signal {
+        /// Receives the next signal notification event.
+        ///
+        /// `None` is returned if no more events can be received.
+        pub async fn recv(&mut self) -> Option<()> {
+            self.rx.changed().await.ok()
+        }
+    }
+
+    /// Creates a new listener which will receive notifications when the current node receives
+    /// the specified signal.
+    ///
+    /// Once a listener is created, the signal no longer kills the node.
+    pub fn signal(kind: SignalKind) -> io::Result<Signal> {
+        let rx = crate::context::current_task().node.signal(kind);
+        Ok(Signal { rx })
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{
+        unix::{signal, SignalKind},
+        Signal,
+    };
+    use crate::runtime::NodeExit;
     use crate::{
         runtime::{Handle, Runtime},
         time,
@@ -67,4 +182,56 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn graceful_shutdown() {
+        let runtime = Runtime::new();
+        let drained = Arc::new(AtomicBool::new(false));
+        let drained1 = drained.clone();
+        let node = runtime
+            .create_node()
+            .init(move || {
+                let drained = drained1.clone();
+                async move {
+                    let mut term = signal(SignalKind::terminate()).unwrap();
+                    term.recv().await;
+                    // drain
+                    time::sleep(Duration::from_secs(1)).await;
+                    drained.store(true, Ordering::Relaxed);
+                }
+            })
+            .build();
+
+        runtime.block_on(async move {
+            time::sleep(Duration::from_secs(1)).await;
+            let t0 = time::Instant::now();
+            node.signal(Signal::Term);
+            assert_eq!(node.join().await, NodeExit::Finished);
+            assert_eq!(t0.elapsed(), Duration::from_secs(1));
+            assert!(drained.load(Ordering::Relaxed));
+        });
+    }
+
+    #[test]
+    fn grace_period() {
+        let runtime = Runtime::new();
+        let node = runtime
+            .create_node()
+            .grace_period(Duration::from_secs(5))
+            .init(|| async {
+                let mut term = signal(SignalKind::terminate()).unwrap();
+                term.recv().await;
+                // stuck in draining
+                pending::<()>().await;
+            })
+            .build();
+
+        runtime.block_on(async move {
+            time::sleep(Duration::from_secs(1)).await;
+            let t0 = time::Instant::now();
+            node.signal(Signal::Term);
+            assert_eq!(node.join().await, NodeExit::Killed);
+            assert_eq!(t0.elapsed(), Duration::from_secs(5));
+        });
+    }
 }
@@ -0,0 +1,178 @@
+//! Blocking operations.
+
+use super::{allow_blocking, JoinHandle, Spawner};
+use crate::{rand::Rng, runtime::Handle, time};
+use spin::Mutex;
+use std::{
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// Runs the provided closure on a thread where blocking is acceptable.
+///
+/// The closure runs on a real thread pool, concurrently with the simulation. The returned task
+/// completes after a simulated duration drawn from
+/// [`Config::blocking_latency`](super::Config::blocking_latency), when it waits for the closure
+/// to finish in real time. So the completion is deterministic no matter how long the closure
+/// actually takes.
+///
+/// The closure runs outside the simulation. It must not access simulated resources, and its own
+/// randomness and clock are not deterministic.
+#[track_caller]
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let delay = blocking_delay(&Handle::current());
+    let (tx, rx) = mpsc::sync_channel(1);
+    execute(Box::new(move || {
+        _ = tx.send(catch_unwind(AssertUnwindSafe(f)));
+    }));
+    Spawner::current().spawn(async move {
+        time::sleep(delay).await;
+        match allow_blocking(|| rx.recv()).expect("blocking thread exited") {
+            Ok(value) => value,
+            Err(payload) => resume_unwind(payload),
+        }
+    })
+}
+
+/// Runs the provided blocking function on the current thread.
+///
+/// The function runs to completion as one atomic step of the simulation: no other task can run
+/// in between. Then the simulated time advances by a duration drawn from
+/// [`Config::blocking_latency`](super::Config::blocking_latency). Timers expired meanwhile fire
+/// once the current poll returns to the scheduler.
+pub fn block_in_place<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let handle = Handle::current();
+    let delay = blocking_delay(&handle);
+    let value = allow_blocking(f);
+    handle.time.advance_clock(delay);
+    value
+}
+
+/// Draws the simulated duration of a blocking operation.
+fn blocking_delay(handle: &Handle) -> Duration {
+    let latency = handle.config.task.blocking_latency.clone();
+    handle.rand.with(|rng| rng.gen_range(latency))
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Idle threads in the pool.
+static IDLE: Mutex<Vec<mpsc::Sender<Job>>> = Mutex::new(Vec::new());
+
+/// How long an idle thread waits for a new job before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// Runs the job on an idle thread, or a new thread if none is idle.
+fn execute(mut job: Job) {
+    while let Some(worker) = IDLE.lock().pop() {
+        match worker.send(job) {
+            Ok(()) => return,
+            // the thread has exited
+            Err(mpsc::SendError(j)) => job = j,
+        }
+    }
+    // system threads can not be spawned from a task
+    crate::context::exit_task(|| {
+        thread::Builder::new()
+            .name("madsim-blocking".into())
+            .spawn(move || {
+                let (tx, rx) = mpsc::channel();
+                loop {
+                    job();
+                    IDLE.lock().push(tx.clone());
+                    match rx.recv_timeout(KEEP_ALIVE) {
+                        Ok(j) => job = j,
+                        Err(_) => return,
+                    }
+                }
+            })
+            .expect("failed to spawn blocking thread")
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    };
+
+    #[test]
+    fn spawn_blocking_deterministic() {
+        Runtime::check_determinism(0, crate::Config::default(), || async {
+            let order = Arc::new(StdMutex::new(vec![]));
+            let mut tasks = vec![];
+            for i in 0..10u64 {
+                let order = order.clone();
+                let task = spawn_blocking(move || {
+                    // real time must not affect the result
+                    thread::sleep(Duration::from_millis(10 - i));
+                    i
+                });
+                tasks.push(crate::task::spawn(async move {
+                    let i = task.await.unwrap();
+                    order.lock().unwrap().push(i);
+                }));
+            }
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(order.lock().unwrap().len(), 10);
+        });
+    }
+
+    #[test]
+    fn block_in_place_advances_time() {
+        let config = crate::Config {
+            task: crate::task::Config {
+                blocking_latency: Duration::from_secs(1)..Duration::from_secs(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(0, config);
+        runtime.block_on(async {
+            let fired = Arc::new(AtomicBool::new(false));
+            let fired1 = fired.clone();
+            (Handle::current().time).add_timer(Duration::from_millis(100), move || {
+                fired1.store(true, Ordering::Relaxed)
+            });
+            let t0 = time::Instant::now();
+            let value = block_in_place(|| {
+                thread::sleep(Duration::from_millis(10));
+                42
+            });
+            assert_eq!(value, 42);
+            let elapsed = t0.elapsed();
+            assert!(elapsed >= Duration::from_secs(1), "{elapsed:?}");
+            assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+            // the expired timer fires after the poll, not within it
+            assert!(!fired.load(Ordering::Relaxed));
+            crate::task::yield_now().await;
+            assert!(fired.load(Ordering::Relaxed));
+        });
+    }
+
+    #[test]
+    fn spawn_blocking_panic() {
+        let runtime = Runtime::new();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(async {
+                spawn_blocking(|| panic!("blocking panic")).await.unwrap();
+            })
+        }));
+        let err = result.unwrap_err();
+        assert_eq!(panic_message::panic_message(&err), "blocking panic");
+    }
+}
@@ -0,0 +1,80 @@
+//! Cooperative scheduling budget.
+//!
+//! Like tokio, each task has a budget of resource operations per poll. Once the budget is
+//! exhausted, resource operations return `Pending` and the task is rescheduled, so that a task
+//! looping over ready resources can not monopolize its node.
+
+use super::*;
+use futures_util::future::poll_fn;
+
+/// The remaining budget of a task that has unlimited budget.
+pub(super) const UNLIMITED: u32 = u32::MAX;
+
+/// Consumes a unit of budget and yields if the budget of the current task is exhausted.
+///
+/// The budget is reset on every poll to [`Config::budget`].
+pub async fn consume_budget() {
+    poll_fn(poll_proceed).await
+}
+
+/// Consumes a unit of budget, or wakes the task and returns `Pending` if it is exhausted.
+fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    let Some(task) = crate::context::try_current_task() else {
+        return Poll::Ready(());
+    };
+    match task.budget.load(Ordering::Relaxed) {
+        UNLIMITED => Poll::Ready(()),
+        0 => {
+            trace!(task = %task.id, "budget exhausted");
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        n => {
+            task.budget.store(n - 1, Ordering::Relaxed);
+            Poll::Ready(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn yield_on_exhausted() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let flag = Arc::new(AtomicBool::new(false));
+            let flag1 = flag.clone();
+            spawn(async move { flag1.store(true, Ordering::SeqCst) });
+            for _ in 0..Config::default().budget {
+                consume_budget().await;
+            }
+            assert!(!flag.load(Ordering::SeqCst));
+            consume_budget().await;
+            assert!(flag.load(Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn unlimited() {
+        let config = crate::Config {
+            task: Config {
+                budget: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async {
+            let flag = Arc::new(AtomicBool::new(false));
+            let flag1 = flag.clone();
+            spawn(async move { flag1.store(true, Ordering::SeqCst) });
+            for _ in 0..10_000 {
+                consume_budget().await;
+            }
+            assert!(!flag.load(Ordering::SeqCst));
+        });
+    }
+}
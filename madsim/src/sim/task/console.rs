@@ -0,0 +1,119 @@
+//! Instrumentation for [tokio-console].
+//!
+//! When [`Config::console`] is enabled, tasks emit the same tracing spans and events as tokio
+//! built with `tokio_unstable`, so `console-subscriber` can record them. Spans are entered on
+//! the simulation thread, where `Instant::now` returns the simulated time, so busy and idle
+//! times are measured in simulated time. Task spans carry the ID and name of their node.
+//!
+//! [tokio-console]: https://github.com/tokio-rs/console
+
+use super::*;
+
+/// Creates the span of a task, or a disabled span if the console is not enabled.
+pub(super) fn task_span(
+    node: &NodeInfo,
+    id: Id,
+    name: Option<&str>,
+    location: StaticLocation,
+) -> Span {
+    if !node.console.load(Ordering::Relaxed) {
+        return Span::none();
+    }
+    tracing::trace_span!(
+        target: "tokio::task",
+        parent: None,
+        "runtime.spawn",
+        kind = "task",
+        task.name = name.unwrap_or_default(),
+        task.id = id.0,
+        loc.file = location.file(),
+        loc.line = location.line(),
+        loc.col = location.column(),
+        node.id = node.id.0,
+        node.name = node.name.as_deref().unwrap_or_default(),
+    )
+}
+
+/// Records that the task is woken.
+pub(super) fn wake(task: &TaskInfo) {
+    if let Some(id) = task.console.id() {
+        tracing::trace!(
+            target: "tokio::task::waker",
+            op = "waker.wake",
+            task.id = id.into_u64(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use std::sync::Mutex as StdMutex;
+    use tracing::{
+        span::{Attributes, Id as SpanId},
+        subscriber::Interest,
+        Event, Metadata, Subscriber,
+    };
+
+    /// Collects the names of spans and events with a `tokio::task` target.
+    #[derive(Default)]
+    struct Recorder {
+        records: StdMutex<Vec<String>>,
+        next_id: AtomicU64,
+    }
+
+    impl Subscriber for Recorder {
+        fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+            Interest::sometimes()
+        }
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target().starts_with("tokio::task")
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> SpanId {
+            self.records
+                .lock()
+                .unwrap()
+                .push(span.metadata().name().into());
+            SpanId::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+        fn record(&self, _: &SpanId, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &SpanId, _: &SpanId) {}
+        fn event(&self, event: &Event<'_>) {
+            (self.records.lock().unwrap()).push(event.metadata().target().into());
+        }
+        fn enter(&self, _: &SpanId) {}
+        fn exit(&self, _: &SpanId) {}
+    }
+
+    fn run(console: bool) -> Vec<String> {
+        let recorder = Arc::new(Recorder::default());
+        let config = crate::Config {
+            task: Config {
+                console,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        tracing::subscriber::with_default(recorder.clone(), || {
+            runtime.block_on(async {
+                spawn(async { yield_now().await }).await.unwrap();
+            })
+        });
+        let records = recorder.records.lock().unwrap();
+        records.clone()
+    }
+
+    #[test]
+    fn spawn_and_wake() {
+        let records = run(true);
+        assert_eq!(records.iter().filter(|r| *r == "runtime.spawn").count(), 2);
+        assert!(records.iter().any(|r| r == "tokio::task::waker"));
+    }
+
+    #[test]
+    fn disabled() {
+        assert!(run(false).is_empty());
+    }
+}
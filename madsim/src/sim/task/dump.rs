@@ -0,0 +1,228 @@
+use super::*;
+use std::net::SocketAddr;
+
+/// A snapshot of a live task.
+#[derive(Debug, Clone)]
+pub struct TaskDump {
+    /// The task ID.
+    pub id: Id,
+    /// The task name.
+    pub name: Option<String>,
+    /// The location where the task was spawned.
+    pub location: String,
+    /// The state of the task.
+    pub state: TaskState,
+    /// The simulated CPU time consumed by the task. See [`NodeMetrics::cpu_time`].
+    ///
+    /// [`NodeMetrics::cpu_time`]: crate::runtime::NodeMetrics::cpu_time
+    pub cpu_time: Duration,
+}
+
+/// The state of a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskState {
+    /// The task is ready to be polled.
+    Ready,
+    /// The task is ready, but its node is paused.
+    Paused,
+    /// The task is waiting to be woken.
+    ///
+    /// Contains descriptions of what the task is waiting for, e.g. "timer at 1.5s".
+    /// It is empty if the task is waiting for something not tracked by the simulator,
+    /// such as a channel or a lock.
+    Pending(Vec<String>),
+}
+
+impl fmt::Display for TaskDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}", self.id)?;
+        if let Some(name) = &self.name {
+            write!(f, " {name:?}")?;
+        }
+        write!(f, " spawned at {}: ", self.location)?;
+        match &self.state {
+            TaskState::Ready => write!(f, "ready"),
+            TaskState::Paused => write!(f, "paused"),
+            TaskState::Pending(waits) if waits.is_empty() => {
+                write!(f, "pending (channel, lock or other)")
+            }
+            TaskState::Pending(waits) => write!(f, "pending on {}", waits.join(", ")),
+        }
+    }
+}
+
+/// A snapshot of the live tasks in the runtime.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    /// Nodes in order of ID.
+    pub nodes: Vec<NodeDump>,
+}
+
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{node}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A snapshot of the live tasks on a node.
+#[derive(Debug, Clone)]
+pub struct NodeDump {
+    /// The node ID.
+    pub id: NodeId,
+    /// The node name.
+    pub name: Option<String>,
+    /// Live tasks on the node, in order of spawning.
+    pub tasks: Vec<TaskDump>,
+}
+
+impl fmt::Display for NodeDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {}", self.id)?;
+        if let Some(name) = &self.name {
+            write!(f, " {name:?}")?;
+        }
+        writeln!(f, ": {} tasks", self.tasks.len())?;
+        for task in &self.tasks {
+            writeln!(f, "  {task}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a pending task is waiting for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Waiting {
+    /// A timer firing at the given time since the start of simulation.
+    Timer(Duration),
+    /// A message with the tag on the socket.
+    Recv { addr: SocketAddr, tag: u64 },
+    /// Data on a connection.
+    Connection,
+}
+
+impl fmt::Display for Waiting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Waiting::Timer(at) => write!(f, "timer at {at:?}"),
+            Waiting::Recv { addr, tag } => write!(f, "net recv on {addr} tag={tag}"),
+            Waiting::Connection => write!(f, "net connection"),
+        }
+    }
+}
+
+/// Marks the current task as waiting for something until dropped.
+pub(crate) struct WaitGuard {
+    task: Weak<TaskInfo>,
+    waiting: Waiting,
+}
+
+impl WaitGuard {
+    pub(crate) fn new(waiting: Waiting) -> Self {
+        let task = crate::context::try_current_task();
+        if let Some(task) = &task {
+            task.waits.lock().push(waiting.clone());
+        }
+        WaitGuard {
+            task: task.as_ref().map(Arc::downgrade).unwrap_or_default(),
+            waiting,
+        }
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.upgrade() {
+            let mut waits = task.waits.lock();
+            if let Some(i) = waits.iter().position(|w| *w == self.waiting) {
+                waits.remove(i);
+            }
+        }
+    }
+}
+
+impl NodeInfo {
+    pub(super) fn dump(&self) -> NodeDump {
+        let paused = self.paused.load(Ordering::Relaxed);
+        let mut tasks = self.tasks.lock();
+        tasks.retain(|weak| weak.strong_count() != 0);
+        let tasks = tasks
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|task| {
+                let state = if !task.ready.load(Ordering::Relaxed) {
+                    TaskState::Pending(task.waits.lock().iter().map(|w| w.to_string()).collect())
+                } else if paused {
+                    TaskState::Paused
+                } else {
+                    TaskState::Ready
+                };
+                TaskDump {
+                    id: task.id,
+                    name: task.name.clone(),
+                    location: task.location.to_string(),
+                    state,
+                    cpu_time: task.cpu_time(),
+                }
+            })
+            .collect();
+        NodeDump {
+            id: self.id,
+            name: self.name.clone(),
+            tasks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::Endpoint,
+        runtime::{Handle, Runtime},
+        time,
+    };
+
+    #[test]
+    fn dump() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let handle = Handle::current();
+            let node = handle
+                .create_node()
+                .name("server")
+                .ip([10, 0, 0, 1].into())
+                .build();
+            node.spawn(async {
+                let ep = Endpoint::bind("10.0.0.1:1").await.unwrap();
+                ep.recv_from(7, &mut []).await.unwrap();
+            });
+            node.spawn(async { time::sleep(Duration::from_secs(5)).await });
+            node.spawn(async {
+                Builder::new()
+                    .name("waiter")
+                    .spawn(std::future::pending::<()>());
+            });
+            time::sleep(Duration::from_secs(1)).await;
+
+            let tasks = node.dump_tasks();
+            assert_eq!(tasks.len(), 3);
+            assert_eq!(
+                tasks[0].state,
+                TaskState::Pending(vec!["net recv on 10.0.0.1:1 tag=7".into()])
+            );
+            let TaskState::Pending(waits) = &tasks[1].state else {
+                panic!("unexpected state: {:?}", tasks[1].state);
+            };
+            assert!(waits[0].starts_with("timer at 5."), "{waits:?}");
+            assert_eq!(tasks[2].name.as_deref(), Some("waiter"));
+            assert_eq!(tasks[2].state, TaskState::Pending(vec![]));
+
+            let dump = handle.dump().to_string();
+            assert!(dump.contains("node 1 \"server\": 3 tasks"), "{dump}");
+            assert!(dump.contains("\"waiter\" spawned at"), "{dump}");
+        });
+    }
+}
@@ -0,0 +1,173 @@
+//! Per-node environment variables.
+//!
+//! Inside a node, `getenv`, `setenv` and `unsetenv` operate on the environment of the node,
+//! which is initialized by [`NodeBuilder::env`] on top of the process environment. So
+//! [`std::env::var`] and [`std::env::set_var`] are isolated between nodes. Iterating over the
+//! environment by [`std::env::vars`] is not intercepted and returns the process environment.
+//!
+//! The environment is reset when the node restarts.
+
+use super::*;
+use std::ffi::{CStr, CString};
+
+/// The environment of a node. `None` means the variable is removed.
+pub(super) type Env = HashMap<CString, Option<CString>>;
+
+/// Builds the environment of a node from key-value pairs.
+pub(crate) fn from_pairs(pairs: &[(String, String)]) -> Env {
+    let cstring = |s: &str| CString::new(s).expect("environment variable contains NUL");
+    (pairs.iter())
+        .map(|(k, v)| (cstring(k), Some(cstring(v))))
+        .collect()
+}
+
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn getenv(name: *const libc::c_char) -> *mut libc::c_char {
+    if let Some(info) = crate::context::try_current_task() {
+        let env = info.node.env.lock();
+        if let Some(value) = env.get(CStr::from_ptr(name)) {
+            // the value lives as long as the node or until it is modified
+            return value
+                .as_ref()
+                .map_or(std::ptr::null_mut(), |v| v.as_ptr() as _);
+        }
+    }
+    real_getenv(name)
+}
+
+/// Calls `getenv` of the process environment.
+unsafe fn real_getenv(name: *const libc::c_char) -> *mut libc::c_char {
+    lazy_static::lazy_static! {
+        static ref GETENV: unsafe extern "C" fn(name: *const libc::c_char) -> *mut libc::c_char = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"getenv\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    GETENV(name)
+}
+
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn setenv(
+    name: *const libc::c_char,
+    value: *const libc::c_char,
+    overwrite: libc::c_int,
+) -> libc::c_int {
+    if let Some(info) = crate::context::try_current_task() {
+        let key = CStr::from_ptr(name).to_owned();
+        let value = CStr::from_ptr(value).to_owned();
+        let mut env = info.node.env.lock();
+        let exists = match env.get(&key) {
+            Some(v) => v.is_some(),
+            // inherited from the process environment
+            None => !real_getenv(name).is_null(),
+        };
+        if overwrite != 0 || !exists {
+            env.insert(key, Some(value));
+        }
+        return 0;
+    }
+    lazy_static::lazy_static! {
+        static ref SETENV: unsafe extern "C" fn(
+            name: *const libc::c_char,
+            value: *const libc::c_char,
+            overwrite: libc::c_int,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"setenv\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    SETENV(name, value, overwrite)
+}
+
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn unsetenv(name: *const libc::c_char) -> libc::c_int {
+    if let Some(info) = crate::context::try_current_task() {
+        let name = CStr::from_ptr(name).to_owned();
+        info.node.env.lock().insert(name, None);
+        return 0;
+    }
+    lazy_static::lazy_static! {
+        static ref UNSETENV: unsafe extern "C" fn(name: *const libc::c_char) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"unsetenv\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    UNSETENV(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::{Handle, Runtime};
+
+    #[test]
+    fn isolated_between_nodes() {
+        let runtime = Runtime::new();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let node1 = handle.create_node().env("ROLE", "leader").build();
+            let node2 = handle.create_node().env("ROLE", "follower").build();
+            let node3 = handle.create_node().build();
+            let get = || async { std::env::var("ROLE").ok() };
+            assert_eq!(node1.spawn(get()).await.unwrap().as_deref(), Some("leader"));
+            assert_eq!(
+                node2.spawn(get()).await.unwrap().as_deref(),
+                Some("follower")
+            );
+            assert_eq!(node3.spawn(get()).await.unwrap(), None);
+
+            node1
+                .spawn(async {
+                    std::env::set_var("ROLE", "candidate");
+                    std::env::set_var("TERM_ID", "1");
+                })
+                .await
+                .unwrap();
+            assert_eq!(
+                node1.spawn(get()).await.unwrap().as_deref(),
+                Some("candidate")
+            );
+            assert_eq!(
+                node2.spawn(get()).await.unwrap().as_deref(),
+                Some("follower")
+            );
+            assert!(std::env::var("TERM_ID").is_err());
+
+            // reset on restart
+            handle.restart(node1.id());
+            assert_eq!(node1.spawn(get()).await.unwrap().as_deref(), Some("leader"));
+        });
+    }
+
+    #[test]
+    fn setenv_no_overwrite() {
+        std::env::set_var("MADSIM_ENV_TEST_INHERITED", "process");
+        let runtime = Runtime::new();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let node = handle.create_node().env("ROLE", "leader").build();
+            node.spawn(async {
+                let set = |name: &[u8], value: &[u8]| unsafe {
+                    libc::setenv(name.as_ptr() as _, value.as_ptr() as _, 0)
+                };
+                set(b"ROLE\0", b"follower\0");
+                set(b"MADSIM_ENV_TEST_INHERITED\0", b"node\0");
+                set(b"MADSIM_ENV_TEST_NEW\0", b"node\0");
+                assert_eq!(std::env::var("ROLE").unwrap(), "leader");
+                assert_eq!(
+                    std::env::var("MADSIM_ENV_TEST_INHERITED").unwrap(),
+                    "process"
+                );
+                assert_eq!(std::env::var("MADSIM_ENV_TEST_NEW").unwrap(), "node");
+            })
+            .await
+            .unwrap();
+        });
+        assert!(std::env::var("MADSIM_ENV_TEST_NEW").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use super::*;
+use futures_util::future::poll_fn;
+use std::collections::VecDeque;
+
+/// A collection of tasks spawned on the runtime.
+///
+/// Tasks are returned by [`join_next`](JoinSet::join_next) in the order they complete, which
+/// is decided by the simulation scheduler and thus deterministic.
+///
+/// When the `JoinSet` is dropped, all tasks in it are aborted.
+pub struct JoinSet<T> {
+    tasks: BTreeMap<u64, JoinHandle<T>>,
+    shared: Arc<Mutex<Shared>>,
+    next_key: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    /// Keys of the tasks that have completed or been cancelled, in order.
+    done: VecDeque<u64>,
+    /// The waker of `join_next`.
+    waker: Option<Waker>,
+}
+
+/// Notifies the `JoinSet` when the task's future is dropped.
+struct DoneGuard {
+    key: u64,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Drop for DoneGuard {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.done.push_back(self.key);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> JoinSet<T> {
+    /// Create a new `JoinSet`.
+    pub fn new() -> Self {
+        JoinSet {
+            tasks: BTreeMap::new(),
+            shared: Default::default(),
+            next_key: 0,
+        }
+    }
+
+    /// Returns the number of tasks currently in the `JoinSet`.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns whether the `JoinSet` is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Returns a guard which notifies the set when the new task is done.
+    fn guard(&mut self) -> DoneGuard {
+        let key = self.next_key;
+        self.next_key += 1;
+        DoneGuard {
+            key,
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn insert(&mut self, key: u64, handle: JoinHandle<T>) -> AbortHandle {
+        let abort = handle.abort_handle();
+        self.tasks.insert(key, handle);
+        abort
+    }
+}
+
+impl<T: 'static> JoinSet<T> {
+    /// Spawn the provided task on the `JoinSet`, returning an [`AbortHandle`] that can be used
+    /// to remotely cancel the task.
+    #[track_caller]
+    pub fn spawn<F>(&mut self, task: F) -> AbortHandle
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send,
+    {
+        let guard = self.guard();
+        let key = guard.key;
+        let handle = spawn(async move {
+            let _guard = guard;
+            task.await
+        });
+        self.insert(key, handle)
+    }
+
+    /// Spawn the provided `!Send` task on the `JoinSet`, returning an [`AbortHandle`] that can
+    /// be used to remotely cancel the task.
+    #[track_caller]
+    pub fn spawn_local<F>(&mut self, task: F) -> AbortHandle
+    where
+        F: Future<Output = T> + 'static,
+    {
+        let guard = self.guard();
+        let key = guard.key;
+        let handle = spawn_local(async move {
+            let _guard = guard;
+            task.await
+        });
+        self.insert(key, handle)
+    }
+
+    /// Waits until one of the tasks in the set completes and returns its output.
+    ///
+    /// Returns `None` if the set is empty.
+    pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    /// Polls for one of the tasks in the set to complete.
+    pub fn poll_join_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<T, JoinError>>> {
+        if self.tasks.is_empty() {
+            return Poll::Ready(None);
+        }
+        loop {
+            let key = self.shared.lock().done.pop_front();
+            let Some(key) = key else {
+                self.shared.lock().waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            let Some(mut handle) = self.tasks.remove(&key) else {
+                // detached task
+                continue;
+            };
+            match Pin::new(&mut handle).poll(cx) {
+                Poll::Ready(output) => return Poll::Ready(Some(output)),
+                // the output has not been stored yet
+                Poll::Pending => {
+                    self.shared.lock().done.push_front(key);
+                    self.tasks.insert(key, handle);
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    /// Aborts all tasks and waits for them to finish shutting down.
+    pub async fn shutdown(&mut self) {
+        self.abort_all();
+        while self.join_next().await.is_some() {}
+    }
+}
+
+impl<T> JoinSet<T> {
+    /// Aborts all tasks on this `JoinSet`.
+    ///
+    /// This does not remove the tasks from the `JoinSet`.
+    pub fn abort_all(&mut self) {
+        for handle in self.tasks.values() {
+            handle.abort();
+        }
+    }
+
+    /// Removes all tasks from this `JoinSet` without aborting them.
+    pub fn detach_all(&mut self) {
+        self.tasks.clear();
+    }
+}
+
+impl<T> Default for JoinSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for JoinSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinSet").field("len", &self.len()).finish()
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    fn drop(&mut self) {
+        self.abort_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, time};
+
+    #[test]
+    fn join_next_in_completion_order() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let mut set = JoinSet::new();
+            for i in [3, 1, 2] {
+                set.spawn(async move {
+                    time::sleep(Duration::from_secs(i)).await;
+                    i
+                });
+            }
+            let mut outputs = vec![];
+            while let Some(output) = set.join_next().await {
+                outputs.push(output.unwrap());
+            }
+            assert_eq!(outputs, [1, 2, 3]);
+            assert!(set.join_next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn abort_all() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let mut set = JoinSet::new();
+            for i in 0..3 {
+                set.spawn(async move {
+                    time::sleep(Duration::from_secs(10)).await;
+                    i
+                });
+            }
+            set.spawn(async { 3 });
+            time::sleep(Duration::from_secs(1)).await;
+            set.abort_all();
+            assert_eq!(set.join_next().await.unwrap().unwrap(), 3);
+            for _ in 0..3 {
+                assert!(set.join_next().await.unwrap().unwrap_err().is_cancelled());
+            }
+            assert!(set.is_empty());
+        });
+    }
+}
@@ -0,0 +1,167 @@
+//! Detection of blocking calls inside simulated tasks.
+//!
+//! All tasks run on one thread. A task that blocks the thread either deadlocks the simulation
+//! or makes it depend on real time, which silently breaks determinism. These calls are
+//! intercepted and reported with the call stack.
+
+use std::{backtrace::Backtrace, cell::Cell, time::Duration};
+
+thread_local! {
+    /// Whether blocking is allowed, e.g. inside `block_in_place`.
+    static ALLOW_BLOCKING: Cell<bool> = Cell::new(false);
+    /// The diagnostic of a blocking call in the current poll.
+    static REPORT: Cell<Option<String>> = Cell::new(None);
+}
+
+/// Runs the function with blocking the simulation thread allowed.
+pub(crate) fn allow_blocking<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            ALLOW_BLOCKING.with(|allow| allow.set(self.0));
+        }
+    }
+    let _reset = Reset(ALLOW_BLOCKING.with(|allow| allow.replace(true)));
+    f()
+}
+
+/// Returns whether the call blocks a simulated task.
+fn is_misuse() -> bool {
+    crate::context::try_current_task().is_some() && !ALLOW_BLOCKING.with(|allow| allow.get())
+}
+
+/// Formats the diagnostic of a blocking call.
+fn diagnostic(call: &str, hint: &str) -> String {
+    format!(
+        "`{call}` blocks the simulation thread inside a simulated task\n\
+         note: {hint}\n\
+         note: the call was made at:\n{}",
+        Backtrace::force_capture()
+    )
+}
+
+/// Takes the diagnostic of a blocking call made in the current poll.
+pub(super) fn take_report() -> Option<String> {
+    REPORT.with(|report| report.take())
+}
+
+/// The real time a simulated task can wait on a futex before it is considered blocked.
+const FUTEX_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Handles `futex` system calls invoked by `syscall` inside a simulated task.
+///
+/// Waiting on a futex parks the thread until another thread wakes it, as
+/// `futures::executor::block_on` and blocking channels do. Brief waits on locks shared with
+/// real threads are tolerated, but if an unbounded wait does not return in [`FUTEX_TIMEOUT`],
+/// the process is aborted, since the simulation can not make progress.
+///
+/// Returns `None` if the system call is not an unbounded futex wait inside a simulated task.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn futex_syscall(
+    num: libc::c_long,
+    args: [libc::c_long; 6],
+) -> Option<libc::c_long> {
+    let [uaddr, op, val, timeout, uaddr2, val3] = args;
+    if num != libc::SYS_futex || timeout != 0 || !is_misuse() {
+        return None;
+    }
+    let timeout = match op as libc::c_int & !libc::FUTEX_PRIVATE_FLAG {
+        // relative timeout
+        libc::FUTEX_WAIT => FUTEX_TIMEOUT,
+        // absolute timeout on the monotonic clock
+        libc::FUTEX_WAIT_BITSET => crate::time::real_monotonic() + FUTEX_TIMEOUT,
+        _ => return None,
+    };
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs() as _,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+    // NOTE: this reenters our `syscall` with a timeout, which is passed through.
+    let ret = libc::syscall(
+        num,
+        uaddr,
+        op,
+        val,
+        &timeout as *const _ as libc::c_long,
+        uaddr2,
+        val3,
+    );
+    if ret == -1 && *libc::__errno_location() == libc::ETIMEDOUT {
+        eprintln!(
+            "{}",
+            diagnostic(
+                "futex wait",
+                "the thread is parked, e.g. by `futures::executor::block_on` or a blocking \
+                 channel. use async primitives or `madsim::task::spawn_blocking` instead."
+            )
+        );
+        std::process::abort();
+    }
+    Some(ret)
+}
+
+/// Override the libc `nanosleep` function. For `std::thread::sleep`.
+///
+/// Inside a simulated task the call returns immediately, and the task panics after the poll.
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn nanosleep(
+    req: *const libc::timespec,
+    rem: *mut libc::timespec,
+) -> libc::c_int {
+    if is_misuse() {
+        let report = diagnostic("std::thread::sleep", "use `madsim::time::sleep` instead.");
+        REPORT.with(|r| r.set(Some(report)));
+        return 0;
+    }
+    lazy_static::lazy_static! {
+        static ref NANOSLEEP: unsafe extern "C" fn(
+            req: *const libc::timespec,
+            rem: *mut libc::timespec,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"nanosleep\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    NANOSLEEP(req, rem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn thread_sleep() {
+        let runtime = Runtime::new();
+        let err = std::panic::catch_unwind(move || {
+            runtime.block_on(async {
+                std::thread::sleep(Duration::from_secs(1));
+            })
+        })
+        .unwrap_err();
+        let msg = panic_message::panic_message(&err);
+        assert!(
+            msg.starts_with("`std::thread::sleep` blocks the simulation thread"),
+            "{msg}"
+        );
+        assert!(msg.contains("misuse.rs"), "{msg}");
+    }
+
+    #[test]
+    fn nested_block_on() {
+        let runtime = Runtime::new();
+        let err = std::panic::catch_unwind(move || {
+            runtime.block_on(async {
+                Runtime::new().block_on(async {});
+            })
+        })
+        .unwrap_err();
+        let msg = panic_message::panic_message(&err);
+        assert!(
+            msg.contains("`Runtime::block_on` is called inside"),
+            "{msg}"
+        );
+    }
+}
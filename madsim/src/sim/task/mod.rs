@@ -2,29 +2,32 @@
 
 use super::{
     rand::GlobalRng,
-    runtime::{NodeBuilder, Simulators},
+    runtime::{NodeBuilder, NodeEvent, NodeExit, NodeMetrics, Simulators, Step},
+    signal::unix::SignalKind,
     time::{TimeHandle, TimeRuntime},
     utils::mpsc,
 };
 use futures_util::FutureExt;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use spin::Mutex;
 use std::{
     collections::{BTreeMap, HashMap},
     fmt,
     future::Future,
+    hash::{Hash, Hasher},
     io,
-    ops::Deref,
+    ops::{Deref, Range},
     panic::Location,
     pin::Pin,
     sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc, Weak,
     },
     task::{Context, Poll, Waker},
     time::{Duration, Instant},
 };
-use tokio::sync::watch;
+use tokio::sync::{mpsc::UnboundedReceiver, mpsc::UnboundedSender, watch};
 use tracing::{debug, error, error_span, trace, Span};
 
 pub use tokio::task::yield_now;
@@ -34,11 +37,151 @@ type Runnable = async_task::Runnable<Weak<TaskInfo>>;
 #[doc(hidden)]
 pub type FallibleTask<T> = async_task::FallibleTask<T, Weak<TaskInfo>>;
 
+mod blocking;
+mod budget;
 mod builder;
+mod console;
+mod dump;
+mod env;
 mod join;
+mod join_set;
+mod misuse;
+mod stats;
 
+pub use self::blocking::*;
+pub use self::budget::consume_budget;
 pub use self::builder::*;
+pub use self::dump::{Dump, NodeDump, TaskDump, TaskState};
+pub(crate) use self::dump::{WaitGuard, Waiting};
 pub use self::join::*;
+pub use self::join_set::JoinSet;
+pub(crate) use self::misuse::allow_blocking;
+#[cfg(target_os = "linux")]
+pub(crate) use self::misuse::futex_syscall;
+
+/// Task configurations.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Config {
+    /// The probability of waking a task spuriously after it returns `Pending`.
+    ///
+    /// The task is woken after a random delay of up to 1ms, without its event being ready.
+    /// Correct futures must tolerate spurious wakeups.
+    #[serde(default)]
+    pub spurious_wakeup_rate: f64,
+    /// Scheduling weights of nodes by name. See [`Handle::set_schedule_weight`].
+    ///
+    /// [`Handle::set_schedule_weight`]: crate::runtime::Handle::set_schedule_weight
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_weights: BTreeMap<String, f64>,
+    /// Scheduling weights of tasks by name. See [`Handle::set_schedule_weight`].
+    ///
+    /// [`Handle::set_schedule_weight`]: crate::runtime::Handle::set_schedule_weight
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub task_weights: BTreeMap<String, f64>,
+    /// The range of simulated durations of blocking operations. See [`spawn_blocking`].
+    #[serde(default = "default_blocking_latency")]
+    pub blocking_latency: Range<Duration>,
+    /// The number of resource operations a task can perform in one poll before it is forced to
+    /// yield. See [`consume_budget`]. 0 means unlimited.
+    #[serde(default = "default_budget")]
+    pub budget: u32,
+    /// The maximum number of task polls. Exceeding it fails the test with scheduler statistics.
+    #[serde(default)]
+    pub max_steps: Option<u64>,
+    /// The maximum simulated time. Exceeding it fails the test with scheduler statistics.
+    #[serde(default)]
+    pub max_time: Option<Duration>,
+    /// The range of simulated CPU time of each poll. Disabled if `None`.
+    ///
+    /// If set, each node has as many worker threads as its cores. A poll occupies a worker for
+    /// the CPU time, and the ready tasks of a node wait while all its workers are busy. Polls on
+    /// different workers overlap in simulated time, so tasks of the same node run in parallel
+    /// relative to tasks of other nodes.
+    ///
+    /// If not set, polls on nodes with a [CPU slowdown] still take [`DEFAULT_POLL_TIME`] times
+    /// the factor, so that the slowdown takes effect.
+    ///
+    /// [CPU slowdown]: crate::runtime::NodeBuilder::cpu_slowdown
+    #[serde(default)]
+    pub poll_time: Option<Range<Duration>>,
+    /// Emits instrumentation for tokio-console.
+    ///
+    /// Tasks are recorded as `runtime.spawn` spans with simulated timestamps, along with their
+    /// wakeups. Install `console-subscriber` as the global subscriber to observe them.
+    #[serde(default)]
+    pub console: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            spurious_wakeup_rate: 0.0,
+            node_weights: BTreeMap::new(),
+            task_weights: BTreeMap::new(),
+            blocking_latency: default_blocking_latency(),
+            budget: default_budget(),
+            max_steps: None,
+            max_time: None,
+            poll_time: None,
+            console: false,
+        }
+    }
+}
+
+/// The default grace period of nodes. See [`NodeBuilder::grace_period`].
+pub(crate) const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// The CPU time of polls on slowed down nodes if [`Config::poll_time`] is not set.
+pub const DEFAULT_POLL_TIME: Range<Duration> = Duration::from_micros(10)..Duration::from_micros(20);
+
+const fn default_budget() -> u32 {
+    128
+}
+
+const fn default_blocking_latency() -> Range<Duration> {
+    Duration::from_micros(100)..Duration::from_millis(1)
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for Config {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.spurious_wakeup_rate.to_bits().hash(state);
+        for (name, weight) in self.node_weights.iter().chain(&self.task_weights) {
+            name.hash(state);
+            weight.to_bits().hash(state);
+        }
+        self.blocking_latency.hash(state);
+        self.budget.hash(state);
+        self.max_steps.hash(state);
+        self.max_time.hash(state);
+        self.poll_time.hash(state);
+        self.console.hash(state);
+    }
+}
+
+/// Scheduling weights of nodes and tasks. The default weight is 1.
+#[derive(Debug, Default)]
+struct Weights {
+    nodes: HashMap<NodeId, f64>,
+    tasks: BTreeMap<String, f64>,
+}
+
+impl Weights {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.tasks.is_empty()
+    }
+
+    /// Returns the weight of the task, which is the product of the node and task weights.
+    fn of(&self, info: &TaskInfo) -> f64 {
+        let node = self.nodes.get(&info.node.id).copied().unwrap_or(1.0);
+        let task = (info.name.as_ref())
+            .and_then(|name| self.tasks.get(name))
+            .copied()
+            .unwrap_or(1.0);
+        node * task
+    }
+}
 
 pub(crate) struct Executor {
     queue: mpsc::Receiver<Runnable>,
@@ -46,11 +189,19 @@ pub(crate) struct Executor {
     rand: GlobalRng,
     time: TimeRuntime,
     time_limit: Option<Duration>,
+    spurious_wakeup_rate: f64,
+    budget: u32,
+    max_steps: Option<u64>,
+    poll_time: Option<Range<Duration>>,
+    /// The function called after the given step.
+    step_hook: Mutex<Option<(u64, StepHook)>>,
 }
 
+type StepHook = Box<dyn FnOnce() + Send>;
+
 /// A unique identifier for a node.
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
 pub struct NodeId(u64);
 
 impl fmt::Display for NodeId {
@@ -63,6 +214,14 @@ impl NodeId {
     pub(crate) const fn zero() -> Self {
         NodeId(0)
     }
+
+    pub(crate) const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub(crate) const fn from_u64(id: u64) -> Self {
+        NodeId(id)
+    }
 }
 
 // The lifetime of `TaskInfo` equals to the future.
@@ -82,6 +241,58 @@ pub struct TaskInfo {
     waker: Waker,
     /// A flag indicating that the task has been cancelled.
     cancelled: AtomicBool,
+    /// The correlation ID of messages sent by this task.
+    correlation_id: Mutex<Option<u64>>,
+    /// A flag indicating that the task is in the ready queue.
+    ready: AtomicBool,
+    /// What the task is waiting for.
+    waits: Mutex<Vec<Waiting>>,
+    /// The remaining budget in the current poll.
+    budget: AtomicU32,
+    /// The span for tokio-console, disabled if the console is not enabled.
+    console: Span,
+    /// The simulated CPU time consumed by polls in nanoseconds.
+    cpu_time: AtomicU64,
+}
+
+impl TaskInfo {
+    /// Returns the simulated CPU time consumed by this task.
+    pub(crate) fn cpu_time(&self) -> Duration {
+        Duration::from_nanos(self.cpu_time.load(Ordering::Relaxed))
+    }
+
+    /// Accounts the CPU time of a poll to this task and its node.
+    fn add_cpu_time(&self, time: Duration) {
+        let nanos = time.as_nanos() as u64;
+        self.cpu_time.fetch_add(nanos, Ordering::Relaxed);
+        (self.node.counters.cpu_time).fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Returns the correlation ID of this task.
+    pub(crate) fn correlation_id(&self) -> Option<u64> {
+        *self.correlation_id.lock()
+    }
+
+    /// Sets the correlation ID of this task and records it in the span.
+    pub(crate) fn set_correlation_id(&self, id: Option<u64>) {
+        *self.correlation_id.lock() = id;
+        if let Some(id) = id {
+            self.span.record("correlation_id", id);
+        }
+    }
+}
+
+/// Counters of a node for metrics.
+#[derive(Debug, Default)]
+struct NodeCounters {
+    /// The number of tasks spawned.
+    spawned: AtomicU64,
+    /// The number of times tasks have been polled.
+    polls: AtomicU64,
+    /// The number of times tasks have been scheduled.
+    wakeups: AtomicU64,
+    /// The simulated CPU time consumed by polls in nanoseconds.
+    cpu_time: AtomicU64,
 }
 
 pub(crate) struct NodeInfo {
@@ -90,10 +301,26 @@ pub(crate) struct NodeInfo {
     name: Option<String>,
     /// The number of CPU cores.
     cores: usize,
+    /// The elapsed time when each worker thread becomes free, if polls take time.
+    workers: Mutex<Vec<Duration>>,
+    /// Counters of the node, kept across restarts.
+    counters: Arc<NodeCounters>,
+    /// The factor of the poll time on this node, kept across restarts.
+    cpu_slowdown: Arc<Mutex<f64>>,
     /// Whether to restart the node on panic.
     restart_on_panic: bool,
     /// The list of panic messages that will cause the node to restart.
     restart_on_panic_matching: Vec<String>,
+    /// How long the node is allowed to shut down after a signal is handled.
+    grace_period: Duration,
+    /// The memory usage of this incarnation in bytes.
+    memory: Arc<AtomicUsize>,
+    /// The memory limit in bytes.
+    memory_limit: Option<usize>,
+    /// Whether to restart the node when it runs out of memory.
+    restart_on_oom: bool,
+    /// Environment variables of this incarnation.
+    env: Mutex<env::Env>,
     /// The span of this node.
     span: Span,
 
@@ -103,34 +330,59 @@ pub(crate) struct NodeInfo {
     killed: AtomicBool,
     /// All tasks spawned in this node.
     tasks: Mutex<Vec<Weak<TaskInfo>>>,
-    /// Sender of the "ctrl-c" signal.
+    /// Senders of signals by kind.
     ///
-    /// This value is `None` at the beginning, meaning that `signal::ctrl_c` has never been called,
-    /// and sending "ctrl-c" will cause the node being killed. Once `signal::ctrl_c` is called,
-    /// this will be set to `Some`, and sending "ctrl-c" will no longer kill the node.
-    ctrl_c: Mutex<Option<watch::Sender<()>>>,
+    /// A kind is absent at the beginning, meaning that no handler has been installed, and
+    /// sending the signal will cause the node being killed. Once a handler is installed, e.g. by
+    /// `signal::ctrl_c`, sending the signal will no longer kill the node.
+    signals: Mutex<HashMap<SignalKind, watch::Sender<()>>>,
+    /// The ID of the next task, shared by all nodes of the runtime.
+    next_task_id: Arc<AtomicU64>,
+    /// Whether to emit instrumentation for tokio-console, shared by all nodes of the runtime.
+    console: Arc<AtomicBool>,
+    /// How the node terminated. `None` if it is running.
+    exit: watch::Sender<Option<NodeExit>>,
 }
 
 impl NodeInfo {
     #[track_caller]
     fn new_task(self: &Arc<Self>, name: Option<&str>) -> Arc<TaskInfo> {
-        let id = Id::new();
+        let id = Id(self.next_task_id.fetch_add(1, Ordering::Relaxed));
+        self.counters.spawned.fetch_add(1, Ordering::Relaxed);
         let name = name.map(|s| s.to_string());
+        // inherit the correlation ID from the parent task
+        let correlation_id = crate::context::try_current_task().and_then(|t| t.correlation_id());
+        let span = error_span!(
+            parent: &self.span,
+            "task",
+            %id,
+            name,
+            correlation_id = tracing::field::Empty
+        );
+        let location = Location::caller();
         let task = Arc::new(TaskInfo {
-            span: error_span!(parent: &self.span, "task", %id, name),
+            console: console::task_span(self, id, name.as_deref(), location),
+            span,
             id,
             name,
             node: self.clone(),
-            location: Location::caller(),
+            location,
             spawn_time: Instant::now(),
             waker: futures_util::task::noop_waker(), // updated later
             cancelled: AtomicBool::new(false),
+            correlation_id: Mutex::new(None),
+            ready: AtomicBool::new(false),
+            waits: Mutex::new(vec![]),
+            budget: AtomicU32::new(budget::UNLIMITED),
+            cpu_time: AtomicU64::new(0),
         });
+        task.set_correlation_id(correlation_id);
         self.tasks.lock().push(Arc::downgrade(&task));
         task
     }
 
     fn kill(&self) {
+        self.set_exit(NodeExit::Killed);
         self.killed.store(true, Ordering::Relaxed);
         for task in self.tasks.lock().drain(..) {
             if let Some(task) = task.upgrade() {
@@ -139,6 +391,31 @@ impl NodeInfo {
         }
     }
 
+    /// Aborts the live tasks matching the predicate. Returns the number of aborted tasks.
+    fn abort_tasks(&self, pred: impl Fn(&TaskInfo) -> bool) -> usize {
+        let tasks = self.tasks.lock();
+        let mut count = 0;
+        for task in tasks.iter().filter_map(Weak::upgrade) {
+            if pred(&task) && !task.cancelled.swap(true, Ordering::Relaxed) {
+                debug!(task = %task.id, name = task.name, "abort");
+                task.waker.wake_by_ref();
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Records how the node terminated, unless it has been recorded.
+    fn set_exit(&self, exit: NodeExit) {
+        self.exit.send_if_modified(|old| {
+            if old.is_some() {
+                return false;
+            }
+            *old = Some(exit);
+            true
+        });
+    }
+
     fn num_tasks(&self) -> usize {
         let mut tasks = self.tasks.lock();
         tasks.retain(|weak| weak.strong_count() != 0);
@@ -159,16 +436,42 @@ impl NodeInfo {
         map
     }
 
+    fn metrics(&self) -> NodeMetrics {
+        let mut tasks = self.tasks.lock();
+        tasks.retain(|weak| weak.strong_count() != 0);
+        let ready = (tasks.iter().filter_map(Weak::upgrade))
+            .filter(|task| task.ready.load(Ordering::Relaxed))
+            .count();
+        NodeMetrics {
+            num_alive_tasks: tasks.len(),
+            num_spawned_tasks: self.counters.spawned.load(Ordering::Relaxed),
+            num_polls: self.counters.polls.load(Ordering::Relaxed),
+            num_wakeups: self.counters.wakeups.load(Ordering::Relaxed),
+            ready_queue_depth: ready,
+            memory_usage: self.memory.load(Ordering::Relaxed),
+            cpu_time: Duration::from_nanos(self.counters.cpu_time.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn memory_usage(&self) -> &Arc<AtomicUsize> {
+        &self.memory
+    }
+
+    pub(crate) fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
     pub(crate) fn is_killed(&self) -> bool {
         self.killed.load(Ordering::Relaxed)
     }
 
-    /// Get a receiver of "ctrl-c" signal.
-    pub(crate) fn ctrl_c(&self) -> watch::Receiver<()> {
-        self.ctrl_c
+    /// Get a receiver of the signal.
+    pub(crate) fn signal(&self, kind: SignalKind) -> watch::Receiver<()> {
+        self.signals
             .lock()
-            .get_or_insert_with(|| {
-                debug!("ctrl-c signal handler installed");
+            .entry(kind)
+            .or_insert_with(|| {
+                debug!(?kind, "signal handler installed");
                 watch::channel(()).0
             })
             .subscribe()
@@ -178,29 +481,53 @@ impl NodeInfo {
 impl Executor {
     pub fn new(rand: GlobalRng, sims: Arc<Simulators>) -> Self {
         let (sender, queue) = mpsc::channel();
+        let next_task_id = Arc::new(AtomicU64::new(0));
+        let console = Arc::new(AtomicBool::new(false));
         Executor {
             queue,
             handle: TaskHandle {
                 nodes: Arc::new(Mutex::new(HashMap::new())),
                 sender,
                 next_node_id: Arc::new(AtomicU64::new(1)),
+                next_task_id: next_task_id.clone(),
                 main_info: Arc::new(NodeInfo {
                     id: NodeId::zero(),
                     name: Some("main".into()),
                     cores: 1,
+                    workers: Mutex::new(vec![Duration::ZERO]),
+                    counters: Default::default(),
+                    cpu_slowdown: Arc::new(Mutex::new(1.0)),
+                    console: console.clone(),
                     restart_on_panic: false,
                     restart_on_panic_matching: vec![],
+                    grace_period: DEFAULT_GRACE_PERIOD,
+                    memory: Default::default(),
+                    memory_limit: None,
+                    env: Default::default(),
+                    restart_on_oom: false,
                     span: error_span!("node", id = %NodeId::zero(), name = "main"),
                     paused: AtomicBool::new(false),
                     killed: AtomicBool::new(false),
                     tasks: Mutex::new(vec![]),
-                    ctrl_c: Mutex::new(None),
+                    signals: Mutex::new(HashMap::new()),
+                    exit: watch::channel(None).0,
+                    next_task_id,
                 }),
+                console,
+                subscribers: Default::default(),
                 sims,
+                polls: Arc::new(AtomicU64::new(0)),
+                weights: Default::default(),
+                stats: Default::default(),
             },
             time: TimeRuntime::new(&rand),
             rand,
             time_limit: None,
+            spurious_wakeup_rate: 0.0,
+            budget: default_budget(),
+            max_steps: None,
+            poll_time: None,
+            step_hook: Mutex::new(None),
         }
     }
 
@@ -214,6 +541,56 @@ impl Executor {
 
     pub fn set_time_limit(&mut self, limit: Duration) {
         self.time_limit = Some(limit);
+        self.enable_stats();
+    }
+
+    pub fn set_max_steps(&mut self, steps: u64) {
+        self.max_steps = Some(steps);
+        self.enable_stats();
+    }
+
+    pub fn set_poll_time(&mut self, poll_time: Option<Range<Duration>>) {
+        self.poll_time = poll_time;
+    }
+
+    /// Calls the function after the given number of polls.
+    pub fn set_step_hook(&mut self, step: u64, hook: impl FnOnce() + Send + 'static) {
+        *self.step_hook.lock() = Some((step, Box::new(hook)));
+    }
+
+    fn enable_stats(&self) {
+        self.stats.lock().get_or_insert_with(Default::default);
+    }
+
+    /// Panics with scheduler statistics if the step or time limit is exceeded.
+    fn check_limits(&self) {
+        if self.max_steps.is_none() && self.time_limit.is_none() {
+            return;
+        }
+        let steps = self.polls.load(Ordering::Relaxed);
+        let elapsed = self.time.handle().elapsed();
+        let msg = match (self.max_steps, self.time_limit) {
+            (Some(max), _) if steps > max => format!("step limit exceeded: {max} steps"),
+            (_, Some(limit)) if elapsed >= limit => format!("time limit exceeded: {limit:?}"),
+            _ => return,
+        };
+        let stats = self.stats.lock();
+        panic!(
+            "{msg}\nsteps: {steps}, elapsed: {elapsed:?}\n{}",
+            stats.as_ref().unwrap()
+        );
+    }
+
+    pub fn set_spurious_wakeup_rate(&mut self, rate: f64) {
+        self.spurious_wakeup_rate = rate;
+    }
+
+    pub fn set_budget(&mut self, budget: u32) {
+        self.budget = budget;
+    }
+
+    pub fn set_console(&mut self, enabled: bool) {
+        self.console.store(enabled, Ordering::Relaxed);
     }
 
     #[track_caller]
@@ -229,7 +606,7 @@ impl Executor {
                         let _info = info; // drop the info when the future is dropped
                         future.await
                     },
-                    move |runnable| _ = sender.send(runnable),
+                    move |runnable| schedule(&sender, runnable),
                 )
         };
         runnable.schedule();
@@ -239,71 +616,223 @@ impl Executor {
             if task.is_finished() {
                 return task.now_or_never().unwrap();
             }
-            let going = self.time.advance_to_next_event();
-            assert!(going, "no events, all tasks will block forever");
-            if let Some(limit) = self.time_limit {
-                assert!(
-                    self.time.handle().elapsed() < limit,
-                    "time limit exceeded: {limit:?}"
-                )
+            if !self.time.advance_to_next_event() {
+                // no ready tasks, no timers and thus no messages in flight
+                panic!(
+                    "deadlock detected: all tasks are blocked and no timers are pending\n{}",
+                    self.dump()
+                );
             }
+            self.check_limits();
         }
     }
 
     /// Drain all tasks from ready queue and run them.
     fn run_all_ready(&self) {
-        while let Ok(runnable) = self.queue.try_recv_random(&self.rand) {
-            let Some(info) = runnable.metadata().upgrade() else {
-                // future has been dropped
-                continue;
-            };
-            if info.cancelled.load(Ordering::Relaxed) || info.node.killed.load(Ordering::Relaxed) {
-                // cancelled task or killed node: drop the future
-                continue;
-            } else if info.node.paused.load(Ordering::Relaxed) {
-                // paused task: push to waiting list
-                (self.nodes.lock().get_mut(&info.node.id).unwrap().paused).push(runnable);
-                continue;
+        while self.run_next().is_some() {}
+    }
+
+    /// Runs a single scheduling decision. Returns `None` if no task is ready and no timer is
+    /// pending.
+    pub fn step(&self) -> Option<Step> {
+        while let Some(polled) = self.run_next() {
+            if let Some(info) = polled {
+                return Some(Step::Poll {
+                    node: info.node.id,
+                    task: info.id,
+                    name: info.name.clone(),
+                    location: info.location.to_string(),
+                });
             }
-            // run the task
-            let res = {
-                let _guard = crate::context::enter_task(info.clone());
-                std::panic::catch_unwind(move || runnable.run())
-            };
-            if let Err(e) = res {
-                eprintln!(
-                    "context: node={} {:?}, task={} (spawned at {})",
-                    info.node.id,
-                    info.node.name.as_ref().map_or("<unnamed>", |s| s),
-                    info.id,
-                    info.location
+        }
+        if self.time.advance_to_next_event() {
+            self.check_limits();
+            let elapsed = self.time.handle().elapsed();
+            return Some(Step::Advance { elapsed });
+        }
+        None
+    }
+
+    /// Takes a task from the ready queue and runs it.
+    ///
+    /// Returns `None` if no task is ready, or `Some(None)` if the task is not polled.
+    fn run_next(&self) -> Option<Option<Arc<TaskInfo>>> {
+        let task = |runnable: &Runnable| {
+            (runnable.metadata().upgrade()).map(|info| (info.id, info.node.id))
+        };
+        // returns the weights of ready tasks, or `None` if no weight is set
+        let weights = |queue: &[Runnable]| {
+            let weights = self.weights.lock();
+            if weights.is_empty() {
+                return None;
+            }
+            let weight = |r: &Runnable| r.metadata().upgrade().map_or(1.0, |i| weights.of(&i));
+            Some(queue.iter().map(weight).collect::<Vec<_>>())
+        };
+        let runnable = (self.queue)
+            .try_recv_by(|queue| {
+                let task = |i| task(&queue[i]);
+                self.rand.choose_task(queue.len(), task, weights(queue))
+            })
+            .ok()?;
+        let Some(info) = runnable.metadata().upgrade() else {
+            // future has been dropped
+            return Some(None);
+        };
+        if info.cancelled.load(Ordering::Relaxed) || info.node.killed.load(Ordering::Relaxed) {
+            // cancelled task or killed node: drop the future
+            return Some(None);
+        } else if info.node.paused.load(Ordering::Relaxed) {
+            // paused task: push to waiting list
+            (self.nodes.lock().get_mut(&info.node.id).unwrap().paused).push(runnable);
+            return Some(None);
+        }
+        let weight = {
+            let weights = self.weights.lock();
+            (!weights.is_empty()).then(|| weights.of(&info))
+        };
+        if let Some(weight) = weight.filter(|&w| w < 1.0) {
+            if self.rand.with(|rng| rng.gen_bool(1.0 - weight.max(0.0))) {
+                // starved task: defer the poll
+                let delay = Duration::from_nanos(self.rand.with(|rng| rng.gen_range(0..1_000_000)));
+                trace!(task = %info.id, ?delay, "deferred by weight");
+                self.time
+                    .handle()
+                    .add_timer(delay, move || runnable.schedule());
+                return Some(None);
+            }
+        }
+        let mut cpu_time = Duration::ZERO;
+        let slowdown = *info.node.cpu_slowdown.lock();
+        let poll_time = match &self.poll_time {
+            Some(poll_time) => Some(poll_time.clone()),
+            None if slowdown != 1.0 => Some(DEFAULT_POLL_TIME),
+            None => None,
+        };
+        if let Some(poll_time) = poll_time {
+            let now = self.time.handle().elapsed();
+            let mut workers = info.node.workers.lock();
+            let (i, free_at) = (workers.iter().copied().enumerate())
+                .min_by_key(|(_, t)| *t)
+                .unwrap();
+            if free_at > now {
+                // all workers are busy: wait for the first free one
+                drop(workers);
+                trace!(task = %info.id, "all workers busy");
+                self.time
+                    .handle()
+                    .add_timer(free_at - now, move || runnable.schedule());
+                return Some(None);
+            }
+            let time = self.rand.with(|rng| rng.gen_range(poll_time));
+            cpu_time = time.mul_f64(slowdown);
+            workers[i] = now + cpu_time;
+        }
+        // run the task
+        let step = self.polls.fetch_add(1, Ordering::Relaxed) + 1;
+        info.node.counters.polls.fetch_add(1, Ordering::Relaxed);
+        let waker = runnable.waker();
+        info.ready.store(false, Ordering::Relaxed);
+        let budget = match self.budget {
+            0 => budget::UNLIMITED,
+            n => n,
+        };
+        info.budget.store(budget, Ordering::Relaxed);
+        if let Some(stats) = self.stats.lock().as_mut() {
+            stats.poll(&info);
+        }
+        let res = {
+            let _guard = crate::context::enter_task(info.clone());
+            let _console = info.console.enter();
+            (self.rand).trace(crate::trace::EventKind::Poll(info.location));
+            std::panic::catch_unwind(move || runnable.run())
+        };
+        if let Some(report) = misuse::take_report() {
+            panic!("{report}");
+        }
+        if let Some(report) = crate::fs::take_real_fs_report() {
+            panic!("{report}");
+        }
+        let oom = matches!(&res, Err(e) if e.is::<crate::memory::OutOfMemory>());
+        if oom {
+            info.node.set_exit(NodeExit::OutOfMemory);
+            if info.node.restart_on_oom {
+                self.kill_and_restart(&info.node, "out of memory");
+            } else {
+                error!(
+                    "node {} {:?} killed: out of memory",
+                    info.node.id, info.node.name
                 );
-                let error_msg = panic_message::panic_message(&e);
-                if info.node.restart_on_panic
-                    || (info.node.restart_on_panic_matching.iter()).any(|s| error_msg.contains(s))
-                {
-                    let node_id = info.node.id;
-                    let delay = self
-                        .rand
-                        .with(|rng| rng.gen_range(Duration::from_secs(1)..Duration::from_secs(10)));
-                    error!(
-                        "task panicked, restarting node {} {:?} after {:?}",
-                        node_id, info.node.name, delay
-                    );
-                    self.kill(node_id);
-                    let h = self.handle.clone();
-                    self.time
-                        .handle()
-                        .add_timer(delay, move || h.restart(node_id));
-                } else {
-                    std::panic::resume_unwind(e);
-                }
+                self.kill(info.node.id);
+            }
+        } else if let Err(e) = res {
+            eprintln!(
+                "context: node={} {:?}, task={} (spawned at {})",
+                info.node.id,
+                info.node.name.as_ref().map_or("<unnamed>", |s| s),
+                info.id,
+                info.location
+            );
+            let error_msg = panic_message::panic_message(&e);
+            if info.node.restart_on_panic
+                || (info.node.restart_on_panic_matching.iter()).any(|s| error_msg.contains(s))
+            {
+                info.node
+                    .set_exit(NodeExit::Panicked(error_msg.to_string()));
+                self.kill_and_restart(&info.node, "task panicked");
+            } else {
+                std::panic::resume_unwind(e);
             }
+        } else if self.spurious_wakeup_rate > 0.0
+            && self
+                .rand
+                .with(|rng| rng.gen_bool(self.spurious_wakeup_rate))
+        {
+            // waking a finished task has no effect
+            let delay = Duration::from_nanos(self.rand.with(|rng| rng.gen_range(0..1_000_000)));
+            trace!(task = %info.id, ?delay, "spurious wakeup");
+            self.time.handle().add_timer(delay, move || waker.wake());
+        }
 
-            // advance time: 50-100ns
-            let dur = Duration::from_nanos(self.rand.with(|rng| rng.gen_range(50..100)));
-            self.time.handle().advance(dur);
+        let hook = {
+            let mut step_hook = self.step_hook.lock();
+            match step_hook.take() {
+                Some((at, hook)) if at == step => Some(hook),
+                other => {
+                    *step_hook = other;
+                    None
+                }
+            }
+        };
+        if let Some(hook) = hook {
+            hook();
         }
+
+        // advance time: 50-100ns, unless time is paused
+        // timers expired during the poll, e.g. by `block_in_place`, fire here either way
+        let dur = Duration::from_nanos(self.rand.with(|rng| rng.gen_range(50..100)));
+        let paused = self.time.handle().is_paused();
+        (self.time.handle()).advance(if paused { Duration::ZERO } else { dur });
+        info.add_cpu_time(cpu_time + dur);
+        self.check_limits();
+        Some(Some(info))
+    }
+
+    /// Kills the node and restarts it after a random delay.
+    fn kill_and_restart(&self, node: &NodeInfo, reason: &str) {
+        let node_id = node.id;
+        let delay = self
+            .rand
+            .with(|rng| rng.gen_range(Duration::from_secs(1)..Duration::from_secs(10)));
+        error!(
+            "{reason}, restarting node {} {:?} after {:?}",
+            node_id, node.name, delay
+        );
+        self.kill(node_id);
+        let h = self.handle.clone();
+        self.time
+            .handle()
+            .add_timer(delay, move || h.restart(node_id));
     }
 }
 
@@ -321,9 +850,19 @@ pub struct TaskHandle {
     sender: mpsc::Sender<Runnable>,
     nodes: Arc<Mutex<HashMap<NodeId, Node>>>,
     next_node_id: Arc<AtomicU64>,
+    next_task_id: Arc<AtomicU64>,
     /// Info of the main node.
     main_info: Arc<NodeInfo>,
     sims: Arc<Simulators>,
+    /// The number of times tasks have been polled.
+    polls: Arc<AtomicU64>,
+    weights: Arc<Mutex<Weights>>,
+    /// Scheduler statistics. `None` if not collected.
+    stats: Arc<Mutex<Option<stats::Stats>>>,
+    /// Whether to emit instrumentation for tokio-console.
+    console: Arc<AtomicBool>,
+    /// Subscribers of node events.
+    subscribers: Arc<Mutex<Vec<UnboundedSender<NodeEvent>>>>,
 }
 
 struct Node {
@@ -331,6 +870,10 @@ struct Node {
     paused: Vec<Runnable>,
     /// A function to spawn the initial task.
     init: Option<InitFn>,
+    /// The initial environment variables.
+    env: env::Env,
+    /// Labels of the node.
+    labels: BTreeMap<String, String>,
 }
 
 pub(crate) type InitFn = Arc<dyn Fn(&Spawner) + Send + Sync>;
@@ -352,6 +895,23 @@ impl TaskHandle {
         for sim in self.sims.lock().values() {
             sim.reset_node(id);
         }
+        self.emit(NodeEvent::Killed(id));
+    }
+
+    /// Sets the scheduling weight of the node.
+    pub fn set_schedule_weight(&self, id: impl ToNodeId, weight: f64) {
+        let id = id.to_node_id(self);
+        let mut weights = self.weights.lock();
+        if weight == 1.0 {
+            weights.nodes.remove(&id);
+        } else {
+            weights.nodes.insert(id, weight);
+        }
+    }
+
+    /// Sets the scheduling weights of tasks by name.
+    pub(crate) fn set_task_weights(&self, tasks: &BTreeMap<String, f64>) {
+        self.weights.lock().tasks = tasks.clone();
     }
 
     /// Kill all tasks of the node and restart the initial task.
@@ -364,13 +924,24 @@ impl TaskHandle {
             id,
             name: node.info.name.clone(),
             cores: node.info.cores,
+            workers: Mutex::new(vec![Duration::ZERO; node.info.cores]),
+            counters: node.info.counters.clone(),
+            cpu_slowdown: node.info.cpu_slowdown.clone(),
+            console: self.console.clone(),
             restart_on_panic: node.info.restart_on_panic,
             restart_on_panic_matching: node.info.restart_on_panic_matching.clone(),
+            grace_period: node.info.grace_period,
+            memory: Default::default(),
+            memory_limit: node.info.memory_limit,
+            env: Mutex::new(node.env.clone()),
+            restart_on_oom: node.info.restart_on_oom,
             paused: AtomicBool::new(false),
             killed: AtomicBool::new(false),
             span: error_span!(parent: None, "node", %id, name = &node.info.name),
             tasks: Mutex::new(vec![]),
-            ctrl_c: Mutex::new(None),
+            signals: Mutex::new(HashMap::new()),
+            exit: watch::channel(None).0,
+            next_task_id: self.next_task_id.clone(),
         });
         let old_info = std::mem::replace(&mut node.info, new_info);
         node.paused.clear();
@@ -382,6 +953,14 @@ impl TaskHandle {
                 info: node.info.clone(),
             });
         }
+        drop(nodes);
+        self.emit(NodeEvent::Restarted(id));
+    }
+
+    /// Replaces the initial task of the node, which takes effect on the next restart.
+    pub(crate) fn set_init(&self, id: NodeId, init: InitFn) {
+        let mut nodes = self.nodes.lock();
+        nodes.get_mut(&id).expect("node not found").init = Some(init);
     }
 
     /// Pause all tasks of the node.
@@ -403,25 +982,63 @@ impl TaskHandle {
 
         // take paused tasks from waiting list and push them to ready queue
         for runnable in node.paused.drain(..) {
-            self.sender.send(runnable).unwrap();
+            schedule(&self.sender, runnable);
         }
     }
 
+    /// Sets the CPU slowdown factor of the node.
+    pub fn set_cpu_slowdown(&self, id: NodeId, factor: f64) {
+        let nodes = self.nodes.lock();
+        let node = nodes.get(&id).expect("node not found");
+        *node.info.cpu_slowdown.lock() = factor;
+    }
+
+    /// Returns whether the node is paused.
+    pub fn is_paused(&self, id: NodeId) -> bool {
+        let nodes = self.nodes.lock();
+        let node = nodes.get(&id).expect("node not found");
+        node.info.paused.load(Ordering::Relaxed)
+    }
+
     /// Send a "ctrl-c" signal to the node.
     pub fn send_ctrl_c(&self, id: impl ToNodeId) {
-        debug!(node = %id, "send ctrl-c");
+        self.send_signal(id, SignalKind::interrupt());
+    }
+
+    /// Send a signal to the node.
+    ///
+    /// Returns `true` if the signal is handled, or `false` if the node is killed.
+    pub fn send_signal(&self, id: impl ToNodeId, kind: SignalKind) -> bool {
+        debug!(node = %id, ?kind, "send signal");
         let id = id.to_node_id(self);
         let mut nodes = self.nodes.lock();
         let node = nodes.get_mut(&id).expect("node not found");
-        if let Some(tx) = &*node.info.ctrl_c.lock() {
+        if let Some(tx) = node.info.signals.lock().get(&kind) {
             // may return error if no receiver
             _ = tx.send(());
-            return;
+            return true;
         }
         drop(nodes);
-        // "ctrl-c" has never been called. kill node
-        debug!(node = %id, "killed by ctrl-c");
+        // no handler has been installed. kill node
+        debug!(node = %id, ?kind, "killed by signal");
         self.kill_id(id);
+        false
+    }
+
+    /// Returns the grace period of the node.
+    pub fn grace_period(&self, id: NodeId) -> Duration {
+        let nodes = self.nodes.lock();
+        nodes.get(&id).expect("node not found").info.grace_period
+    }
+
+    /// Returns a receiver of how the current instance of the node terminates.
+    pub fn node_exit(&self, id: NodeId) -> watch::Receiver<Option<NodeExit>> {
+        if id == self.main_info.id {
+            return self.main_info.exit.subscribe();
+        }
+        let nodes = self.nodes.lock();
+        let node = nodes.get(&id).expect("node not found");
+        node.info.exit.subscribe()
     }
 
     /// Returns whether the node is killed or exited.
@@ -437,17 +1054,29 @@ impl TaskHandle {
         let id = NodeId(self.next_node_id.fetch_add(1, Ordering::Relaxed));
         let name = &builder.name;
         debug!(node = %id, name, "create");
+        let env = env::from_pairs(&builder.env);
         let info = Arc::new(NodeInfo {
             span: error_span!(parent: None, "node", %id, name),
             id,
             name: builder.name.clone(),
             cores: builder.cores.unwrap_or(1),
+            workers: Mutex::new(vec![Duration::ZERO; builder.cores.unwrap_or(1)]),
+            counters: Default::default(),
+            cpu_slowdown: Arc::new(Mutex::new(builder.cpu_slowdown)),
+            console: self.console.clone(),
             restart_on_panic: builder.restart_on_panic,
             restart_on_panic_matching: builder.restart_on_panic_matching.clone(),
+            grace_period: builder.grace_period,
+            memory: Default::default(),
+            memory_limit: builder.memory_limit,
+            env: Mutex::new(env.clone()),
+            restart_on_oom: builder.restart_on_oom,
             paused: AtomicBool::new(false),
             killed: AtomicBool::new(false),
             tasks: Mutex::new(vec![]),
-            ctrl_c: Mutex::new(None),
+            signals: Mutex::new(HashMap::new()),
+            exit: watch::channel(None).0,
+            next_task_id: self.next_task_id.clone(),
         });
         let handle = Spawner {
             sender: self.sender.clone(),
@@ -460,11 +1089,59 @@ impl TaskHandle {
             info,
             paused: vec![],
             init: builder.init.clone(),
+            env,
+            labels: builder.labels.iter().cloned().collect(),
         };
         self.nodes.lock().insert(id, node);
+        self.emit(NodeEvent::Created(id));
         handle
     }
 
+    /// Returns IDs of all nodes except the main node in order.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<_> = self.nodes.lock().keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the name of the node.
+    pub fn node_name(&self, id: NodeId) -> Option<String> {
+        if id == self.main_info.id {
+            return self.main_info.name.clone();
+        }
+        let nodes = self.nodes.lock();
+        nodes.get(&id).expect("node not found").info.name.clone()
+    }
+
+    /// Returns labels of the node.
+    pub fn labels(&self, id: NodeId) -> BTreeMap<String, String> {
+        let nodes = self.nodes.lock();
+        nodes
+            .get(&id)
+            .map_or_else(BTreeMap::new, |node| node.labels.clone())
+    }
+
+    /// Sets a label of the node.
+    pub fn set_label(&self, id: NodeId, key: String, value: String) {
+        let mut nodes = self.nodes.lock();
+        let node = nodes.get_mut(&id).expect("node not found");
+        node.labels.insert(key.clone(), value.clone());
+        drop(nodes);
+        self.emit(NodeEvent::LabelChanged { id, key, value });
+    }
+
+    /// Subscribes to node events.
+    pub fn subscribe_nodes(&self) -> UnboundedReceiver<NodeEvent> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Sends an event to all subscribers.
+    fn emit(&self, event: NodeEvent) {
+        (self.subscribers.lock()).retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
     /// Get the node handle.
     pub fn get_node(&self, id: impl ToNodeId) -> Option<Spawner> {
         let id = id.to_node_id(self);
@@ -482,6 +1159,42 @@ impl TaskHandle {
         self.nodes.lock().len()
     }
 
+    pub fn num_polls(&self) -> u64 {
+        self.polls.load(Ordering::Relaxed)
+    }
+
+    pub fn num_spawned_tasks(&self) -> u64 {
+        self.next_task_id.load(Ordering::Relaxed)
+    }
+
+    pub fn num_wakeups(&self) -> u64 {
+        let nodes = self.nodes.lock();
+        let infos = std::iter::once(&self.main_info).chain(nodes.values().map(|node| &node.info));
+        infos
+            .map(|info| info.counters.wakeups.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn ready_queue_depth(&self) -> usize {
+        self.sender.len()
+    }
+
+    pub fn node_metrics(&self, id: impl ToNodeId) -> Option<NodeMetrics> {
+        let id = id.to_node_id(self);
+        if id == self.main_info.id {
+            return Some(self.main_info.metrics());
+        }
+        let nodes = self.nodes.lock();
+        nodes.get(&id).map(|node| node.info.metrics())
+    }
+
+    /// Records a timer registered by the task.
+    pub(crate) fn record_timer(&self, info: &TaskInfo) {
+        if let Some(stats) = self.stats.lock().as_mut() {
+            stats.timer(info);
+        }
+    }
+
     pub fn num_tasks(&self) -> usize {
         self.nodes
             .lock()
@@ -506,6 +1219,37 @@ impl TaskHandle {
             .collect()
     }
 
+    /// Returns a snapshot of live tasks on all nodes.
+    pub fn dump(&self) -> Dump {
+        let mut nodes = vec![self.main_info.dump()];
+        let mut others: Vec<_> = (self.nodes.lock().values())
+            .map(|node| node.info.dump())
+            .collect();
+        others.sort_by_key(|node| node.id);
+        nodes.extend(others);
+        Dump { nodes }
+    }
+
+    /// Returns a snapshot of live tasks on the node, or `None` if the node does not exist.
+    pub fn dump_node(&self, id: impl ToNodeId) -> Option<Vec<TaskDump>> {
+        let id = id.to_node_id(self);
+        if id == self.main_info.id {
+            return Some(self.main_info.dump().tasks);
+        }
+        let nodes = self.nodes.lock();
+        nodes.get(&id).map(|node| node.info.dump().tasks)
+    }
+
+    /// Aborts the live tasks on the node matching the predicate. Returns the number of aborted
+    /// tasks, which is 0 if the node does not exist.
+    pub fn abort_tasks(&self, id: NodeId, pred: impl Fn(&TaskInfo) -> bool) -> usize {
+        if id == self.main_info.id {
+            return self.main_info.abort_tasks(pred);
+        }
+        let nodes = self.nodes.lock();
+        nodes.get(&id).map_or(0, |node| node.info.abort_tasks(pred))
+    }
+
     pub fn num_tasks_by_node_by_spawn(&self) -> String {
         let map = self
             .nodes
@@ -628,7 +1372,7 @@ impl Spawner {
                     let _info = info1; // drop the info when the future is dropped
                     future.await
                 },
-                move |runnable| _ = sender.send(runnable),
+                move |runnable| schedule(&sender, runnable),
             );
         // SAFETY: info can not be accessed by others.
         unsafe { &mut *Arc::as_ptr(&info).cast_mut() }.waker = runnable.waker();
@@ -641,10 +1385,21 @@ impl Spawner {
     pub(crate) fn exit(&self) {
         debug!(node = %self.info.id, "exit");
         // FIXME: clear paused tasks
+        self.info.set_exit(NodeExit::Finished);
         self.info.kill();
     }
 }
 
+/// Pushes the runnable into the ready queue.
+fn schedule(sender: &mpsc::Sender<Runnable>, runnable: Runnable) {
+    if let Some(info) = runnable.metadata().upgrade() {
+        info.ready.store(true, Ordering::Relaxed);
+        info.node.counters.wakeups.fetch_add(1, Ordering::Relaxed);
+        console::wake(&info);
+    }
+    _ = sender.send(runnable);
+}
+
 /// Spawns a new asynchronous task, returning a [`JoinHandle`] for it.
 #[track_caller]
 pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
@@ -665,27 +1420,19 @@ where
     Spawner::current().spawn_local(future)
 }
 
-/// Runs the provided closure on a thread where blocking is acceptable.
-#[deprecated(
-    since = "0.3.0",
-    note = "blocking function is not allowed in simulation"
-)]
-pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
-where
-    F: FnOnce() -> R + Send + 'static,
-    R: Send + 'static,
-{
-    Spawner::current().spawn(async move { f() })
-}
-
-/// An opaque ID that uniquely identifies a task relative to all other currently running tasks.
+/// An opaque ID that uniquely identifies a task in the runtime.
+///
+/// IDs are assigned in order of spawning, so they are the same across runs with the same seed.
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 pub struct Id(u64);
 
 impl Id {
-    fn new() -> Self {
-        static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(0);
-        Id(NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed))
+    pub(crate) const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub(crate) const fn from_u64(id: u64) -> Self {
+        Id(id)
     }
 }
 
@@ -775,7 +1522,7 @@ mod tests {
         runtime::{Handle, Runtime},
         time,
     };
-    use std::{collections::HashSet, sync::atomic::AtomicUsize, time::Duration};
+    use std::{collections::HashSet, time::Duration};
 
     #[test]
     fn spawn_in_block_on() {
@@ -866,6 +1613,130 @@ mod tests {
         });
     }
 
+    #[test]
+    fn join_node() {
+        let runtime = Runtime::new();
+        let node = runtime
+            .create_node()
+            .init(|| async { time::sleep(Duration::from_secs(1)).await })
+            .build();
+
+        runtime.block_on(async move {
+            let t0 = time::Instant::now();
+            assert!(!node.is_finished());
+            assert_eq!(node.join().await, NodeExit::Finished);
+            assert_eq!(t0.elapsed(), Duration::from_secs(1));
+            assert!(node.is_finished());
+
+            Handle::current().restart(node.id());
+            assert!(!node.is_finished());
+            let h = Handle::current();
+            let id = node.id();
+            spawn(async move {
+                time::sleep(Duration::from_millis(100)).await;
+                h.kill(id);
+            });
+            assert_eq!(node.join().await, NodeExit::Killed);
+        });
+    }
+
+    #[test]
+    fn abort_task_by_name() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let counts = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        for (i, name) in ["server", "compaction"].into_iter().enumerate() {
+            let counts = counts.clone();
+            node.spawn(async move {
+                Builder::new().name(name).spawn(async move {
+                    loop {
+                        time::sleep(Duration::from_secs(1)).await;
+                        counts[i].fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            });
+        }
+
+        runtime.block_on(async move {
+            time::sleep(Duration::from_millis(1500)).await;
+            assert_eq!(node.abort_tasks_by_name("compaction"), 1);
+            // the aborted task is dropped when it is scheduled
+            time::sleep(Duration::from_millis(1)).await;
+            let tasks = node.dump_tasks();
+            assert_eq!(tasks.len(), 1);
+            assert_eq!(tasks[0].name.as_deref(), Some("server"));
+
+            time::sleep(Duration::from_secs(2)).await;
+            assert_eq!(counts[0].load(Ordering::SeqCst), 3);
+            assert_eq!(counts[1].load(Ordering::SeqCst), 1);
+
+            assert!(node.abort_task(tasks[0].id));
+            assert!(!node.abort_task(tasks[0].id));
+
+            let handle = crate::runtime::Handle::current().task;
+            assert!(handle.dump_node(NodeId(100)).is_none());
+            assert_eq!(handle.abort_tasks(NodeId(100), |_| true), 0);
+        });
+    }
+
+    #[test]
+    fn pause_node() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let expired = Arc::new(AtomicBool::new(false));
+        let expired1 = expired.clone();
+
+        runtime.block_on(async move {
+            // the holder renews a lease of 3s every second
+            let lease = Arc::new(Mutex::new(time::Instant::now()));
+            let lease1 = lease.clone();
+            node.spawn(async move {
+                loop {
+                    if lease1.lock().elapsed() > Duration::from_secs(3) {
+                        // the holder finds its lease expired only after resume
+                        expired1.store(true, Ordering::Relaxed);
+                    }
+                    *lease1.lock() = time::Instant::now();
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+            });
+            time::sleep(Duration::from_millis(1500)).await;
+
+            // a stop-the-world pause longer than the lease
+            node.pause();
+            assert!(node.is_paused());
+            time::sleep(Duration::from_secs(5)).await;
+            assert!(lease.lock().elapsed() > Duration::from_secs(3));
+            assert!(!expired.load(Ordering::Relaxed));
+
+            node.resume();
+            time::sleep(Duration::from_millis(1)).await;
+            assert!(expired.load(Ordering::Relaxed));
+        });
+    }
+
+    #[test]
+    fn spawn_on_restarted_node() {
+        let runtime = Runtime::new();
+        let node = runtime
+            .create_node()
+            .ip([10, 0, 0, 1].into())
+            .init(|| async {})
+            .build();
+        let id = node.id();
+
+        runtime.block_on(async move {
+            Handle::current().restart(id);
+            // the task is spawned on the new instance
+            let task = node.spawn(async move {
+                // binding the IP fails on other nodes
+                crate::net::Endpoint::bind("10.0.0.1:0").await.unwrap();
+                crate::context::current_node()
+            });
+            assert_eq!(task.await.unwrap(), id);
+        });
+    }
+
     #[test]
     fn restart_on_panic() {
         let runtime = Runtime::new();
@@ -1069,4 +1940,271 @@ mod tests {
             assert_eq!(flag.load(Ordering::Relaxed), 4);
         });
     }
+
+    #[test]
+    fn spurious_wakeup() {
+        /// A future that assumes it is only woken when the flag is set.
+        struct WaitFlag {
+            flag: Arc<AtomicBool>,
+            waker: Arc<Mutex<Option<Waker>>>,
+            polled: bool,
+        }
+
+        impl Future for WaitFlag {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if self.polled {
+                    assert!(self.flag.load(Ordering::SeqCst), "spurious wakeup");
+                    return Poll::Ready(());
+                }
+                self.polled = true;
+                *self.waker.lock() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+
+        fn run(rate: f64) {
+            let config = crate::Config {
+                task: Config {
+                    spurious_wakeup_rate: rate,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let runtime = Runtime::with_seed_and_config(1, config);
+            runtime.block_on(async {
+                let flag = Arc::new(AtomicBool::new(false));
+                let waker = Arc::new(Mutex::new(None::<Waker>));
+                let (flag1, waker1) = (flag.clone(), waker.clone());
+                spawn(async move {
+                    time::sleep(Duration::from_secs(1)).await;
+                    flag1.store(true, Ordering::SeqCst);
+                    waker1.lock().take().unwrap().wake();
+                });
+                WaitFlag {
+                    flag,
+                    waker,
+                    polled: false,
+                }
+                .await;
+            });
+        }
+
+        run(0.0);
+        let err = std::panic::catch_unwind(|| run(1.0)).unwrap_err();
+        assert_eq!(panic_message::panic_message(&err), "spurious wakeup");
+    }
+
+    #[test]
+    #[should_panic(expected = "deadlock detected")]
+    fn deadlock() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let (tx1, rx1) = tokio::sync::oneshot::channel::<()>();
+            let (tx2, rx2) = tokio::sync::oneshot::channel::<()>();
+            let t1 = Builder::new().name("t1").spawn(async move {
+                rx2.await.unwrap();
+                tx1.send(()).unwrap();
+            });
+            let t2 = Builder::new().name("t2").spawn(async move {
+                rx1.await.unwrap();
+                tx2.send(()).unwrap();
+            });
+            t1.await.unwrap();
+            t2.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn parallel_workers() {
+        fn run(cores: usize) -> Duration {
+            let config = crate::Config {
+                task: Config {
+                    poll_time: Some(Duration::from_millis(1)..Duration::from_micros(1001)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let runtime = Runtime::with_seed_and_config(1, config);
+            runtime.block_on(async move {
+                let node = Handle::current().create_node().cores(cores).build();
+                let t0 = time::Instant::now();
+                let tasks: Vec<_> = (0..4)
+                    .map(|_| {
+                        node.spawn(async {
+                            for _ in 0..10 {
+                                yield_now().await;
+                            }
+                        })
+                    })
+                    .collect();
+                for task in tasks {
+                    task.await.unwrap();
+                }
+                t0.elapsed()
+            })
+        }
+        // 4 tasks x 11 polls
+        let serial = run(1);
+        assert!(serial >= Duration::from_millis(44), "{serial:?}");
+        let parallel = run(4);
+        assert!(parallel < Duration::from_millis(20), "{parallel:?}");
+    }
+
+    #[test]
+    fn cpu_slowdown() {
+        let config = crate::Config {
+            task: Config {
+                poll_time: Some(Duration::from_millis(1)..Duration::from_micros(1001)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let fast = handle.create_node().build();
+            let slow = handle.create_node().cpu_slowdown(10.0).build();
+            let t0 = time::Instant::now();
+            let spin = || async move {
+                for _ in 0..9 {
+                    yield_now().await;
+                }
+                t0.elapsed()
+            };
+            let fast = fast.spawn(spin()).await.unwrap();
+            let slow = slow.spawn(spin()).await.unwrap();
+            // 10 polls
+            assert!(fast < Duration::from_millis(11), "{fast:?}");
+            assert!(slow >= Duration::from_millis(100), "{slow:?}");
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "time limit exceeded")]
+    fn step_time_limit() {
+        let mut runtime = Runtime::new();
+        runtime.set_time_limit(Duration::from_secs(1));
+        let node = runtime.create_node().build();
+        node.spawn(time::sleep(Duration::from_secs(2)));
+        while runtime.step().is_some() {}
+    }
+
+    #[test]
+    fn cpu_slowdown_default_poll_time() {
+        let runtime = Runtime::new();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let fast = handle.create_node().build();
+            let slow = handle.create_node().cpu_slowdown(10.0).build();
+            let spin = || async move {
+                let t0 = time::Instant::now();
+                for _ in 0..9 {
+                    yield_now().await;
+                }
+                t0.elapsed()
+            };
+            let fast = fast.spawn(spin()).await.unwrap();
+            let slow = slow.spawn(spin()).await.unwrap();
+            // 10 polls of at least 10us each, times 10
+            assert!(fast < Duration::from_micros(10), "{fast:?}");
+            assert!(slow >= Duration::from_micros(900), "{slow:?}");
+        });
+    }
+
+    #[test]
+    fn cpu_time() {
+        let config = crate::Config {
+            task: Config {
+                poll_time: Some(Duration::from_millis(1)..Duration::from_micros(1001)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let leader = handle.create_node().build();
+            let follower = handle.create_node().build();
+            let spin = |n| async move {
+                for _ in 0..n {
+                    yield_now().await;
+                }
+            };
+            leader.spawn(spin(99)).await.unwrap();
+            follower.spawn(spin(9)).await.unwrap();
+            let metrics = handle.metrics();
+            let leader = metrics.node(leader.id()).unwrap().cpu_time;
+            let follower = metrics.node(follower.id()).unwrap().cpu_time;
+            assert!(leader >= Duration::from_millis(100), "{leader:?}");
+            assert!(follower >= Duration::from_millis(10), "{follower:?}");
+            assert!(follower < Duration::from_millis(11), "{follower:?}");
+        });
+    }
+
+    #[test]
+    fn schedule_weight() {
+        let runtime = Runtime::new();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let counts = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+            for i in 0..2 {
+                let node = handle.create_node().name(format!("node{i}")).build();
+                let counts = counts.clone();
+                node.spawn(async move {
+                    loop {
+                        counts[i].fetch_add(1, Ordering::SeqCst);
+                        time::sleep(Duration::from_millis(1)).await;
+                    }
+                });
+            }
+            handle.set_schedule_weight("node1", 0.05);
+            time::sleep(Duration::from_secs(1)).await;
+            let a = counts[0].load(Ordering::SeqCst);
+            let b = counts[1].load(Ordering::SeqCst);
+            assert!(b * 4 < a, "node0: {a}, node1: {b}");
+
+            // weight 0 starves the node
+            handle.set_schedule_weight("node1", 0.0);
+            time::sleep(Duration::from_millis(10)).await;
+            let b = counts[1].load(Ordering::SeqCst);
+            time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(counts[1].load(Ordering::SeqCst), b);
+        });
+    }
+
+    #[test]
+    fn node_metrics() {
+        let runtime = Runtime::new();
+        let metrics = runtime.handle().metrics();
+        runtime.block_on(async move {
+            let handle = Handle::current();
+            let node = handle.create_node().name("node").build();
+            node.spawn(async {});
+            node.spawn(async {
+                for _ in 0..10 {
+                    time::sleep(Duration::from_millis(1)).await;
+                }
+                std::future::pending::<()>().await;
+            });
+            time::sleep(Duration::from_secs(1)).await;
+
+            let m = metrics.node("node").unwrap();
+            assert_eq!(m.num_alive_tasks, 1);
+            assert_eq!(m.num_spawned_tasks, 2);
+            assert_eq!(m.num_wakeups, 12);
+            assert_eq!(m.num_polls, 12);
+            assert_eq!(m.ready_queue_depth, 0);
+            assert_eq!(metrics.num_spawned_tasks(), 3);
+            assert_eq!(metrics.ready_queue_depth(), 0);
+
+            // counters are kept across restarts
+            handle.restart("node");
+            let m = metrics.node("node").unwrap();
+            assert_eq!(m.num_alive_tasks, 0);
+            assert_eq!(m.num_spawned_tasks, 2);
+            assert_eq!(m.num_polls, 12);
+        });
+    }
 }
@@ -0,0 +1,128 @@
+use super::*;
+
+/// The number of entries shown in each ranking of the report.
+const TOP: usize = 10;
+
+/// Scheduler statistics to diagnose livelocks.
+///
+/// They are collected only when a step or time limit is set.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    tasks: HashMap<Id, TaskStats>,
+}
+
+#[derive(Debug)]
+struct TaskStats {
+    name: Option<String>,
+    location: StaticLocation,
+    /// The number of times the task has been polled.
+    polls: u64,
+    /// The number of timers registered by the task.
+    timers: u64,
+}
+
+impl Stats {
+    fn entry(&mut self, info: &TaskInfo) -> &mut TaskStats {
+        self.tasks.entry(info.id).or_insert_with(|| TaskStats {
+            name: info.name.clone(),
+            location: info.location,
+            polls: 0,
+            timers: 0,
+        })
+    }
+
+    /// Records a poll of the task.
+    pub fn poll(&mut self, info: &TaskInfo) {
+        self.entry(info).polls += 1;
+    }
+
+    /// Records a timer registered by the task.
+    pub fn timer(&mut self, info: &TaskInfo) {
+        self.entry(info).timers += 1;
+    }
+
+    /// Returns the top tasks by the given counter, ties broken by ID.
+    fn top(&self, count: impl Fn(&TaskStats) -> u64) -> Vec<(Id, &TaskStats)> {
+        let mut tasks: Vec<_> = (self.tasks.iter())
+            .filter(|(_, s)| count(s) != 0)
+            .map(|(id, s)| (*id, s))
+            .collect();
+        tasks.sort_by_key(|(id, s)| (std::cmp::Reverse(count(s)), id.0));
+        tasks.truncate(TOP);
+        tasks
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut section = |title: &str, unit: &str, count: fn(&TaskStats) -> u64| {
+            writeln!(f, "{title}:")?;
+            for (id, s) in self.top(count) {
+                write!(f, "  {:>10} {unit}  task {id}", count(s))?;
+                if let Some(name) = &s.name {
+                    write!(f, " {name:?}")?;
+                }
+                writeln!(f, " spawned at {}", s.location)?;
+            }
+            Ok(())
+        };
+        section("hottest tasks", "polls", |s| s.polls)?;
+        section("busiest timers", "timers", |s| s.timers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, time};
+
+    fn config(max_steps: Option<u64>, max_time: Option<Duration>) -> crate::Config {
+        crate::Config {
+            task: Config {
+                max_steps,
+                max_time,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn max_steps() {
+        let runtime = Runtime::with_seed_and_config(1, config(Some(1000), None));
+        let err = std::panic::catch_unwind(move || {
+            runtime.block_on(async {
+                Builder::new().name("spinner").spawn(async {
+                    loop {
+                        yield_now().await;
+                    }
+                });
+                time::sleep(Duration::from_secs(1)).await;
+            })
+        })
+        .unwrap_err();
+        let msg = panic_message::panic_message(&err);
+        assert!(msg.starts_with("step limit exceeded: 1000 steps"), "{msg}");
+        assert!(msg.contains("polls  task 1 \"spinner\""), "{msg}");
+    }
+
+    #[test]
+    fn max_time() {
+        let max_time = Duration::from_secs(10);
+        let runtime = Runtime::with_seed_and_config(1, config(None, Some(max_time)));
+        let err = std::panic::catch_unwind(move || {
+            runtime.block_on(async {
+                Builder::new().name("retry").spawn(async {
+                    loop {
+                        time::sleep(Duration::from_millis(100)).await;
+                    }
+                });
+                std::future::pending::<()>().await;
+            })
+        })
+        .unwrap_err();
+        let msg = panic_message::panic_message(&err);
+        assert!(msg.starts_with("time limit exceeded: 10s"), "{msg}");
+        assert!(msg.contains("timers  task 1 \"retry\""), "{msg}");
+    }
+}
@@ -187,3 +187,107 @@ impl Interval {
         self.period
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Runtime;
+    use crate::time::sleep;
+    use std::sync::{Arc, Mutex};
+
+    /// Ticks an interval of 10ms on a node that is paused from 5ms to 35ms.
+    /// Returns the ticks in milliseconds.
+    fn ticks_with_pause(behavior: MissedTickBehavior) -> Vec<u128> {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let handle = runtime.handle().clone();
+        let ticks = Arc::new(Mutex::new(vec![]));
+        let ticks0 = ticks.clone();
+        runtime.block_on(async move {
+            node.spawn(async move {
+                let start = Instant::now();
+                let mut interval = interval_at(start, Duration::from_millis(10));
+                interval.set_missed_tick_behavior(behavior);
+                loop {
+                    let t = interval.tick().await;
+                    ticks0.lock().unwrap().push((t - start).as_millis());
+                }
+            });
+            sleep(Duration::from_millis(5)).await;
+            handle.pause(node.id());
+            sleep(Duration::from_millis(30)).await;
+            handle.resume(node.id());
+            sleep(Duration::from_millis(28)).await;
+        });
+        let ticks = ticks.lock().unwrap();
+        ticks.clone()
+    }
+
+    #[test]
+    fn missed_tick_behavior() {
+        assert_eq!(
+            ticks_with_pause(MissedTickBehavior::Burst),
+            [0, 10, 20, 30, 40, 50, 60]
+        );
+        assert_eq!(ticks_with_pause(MissedTickBehavior::Delay), [0, 10, 45, 55]);
+        assert_eq!(
+            ticks_with_pause(MissedTickBehavior::Skip),
+            [0, 10, 40, 50, 60]
+        );
+    }
+
+    /// Ticks an interval of 10ms on a node slowed down so that each iteration of the loop takes
+    /// about 20ms. Returns the ticks since the start.
+    fn ticks_with_slowdown(behavior: MissedTickBehavior) -> Vec<Duration> {
+        let config = crate::Config {
+            task: crate::task::Config {
+                poll_time: Some(Duration::from_millis(1)..Duration::from_micros(1001)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let node = runtime.create_node().cpu_slowdown(5.0).build();
+        let ticks = Arc::new(Mutex::new(vec![]));
+        let ticks0 = ticks.clone();
+        runtime.block_on(async move {
+            node.spawn(async move {
+                let start = Instant::now();
+                let mut interval = interval_at(start, Duration::from_millis(10));
+                interval.set_missed_tick_behavior(behavior);
+                loop {
+                    let t = interval.tick().await;
+                    ticks0.lock().unwrap().push(t - start);
+                    // 4 polls of 5ms each
+                    for _ in 0..3 {
+                        crate::task::yield_now().await;
+                    }
+                }
+            });
+            sleep(Duration::from_millis(200)).await;
+        });
+        let ticks = ticks.lock().unwrap();
+        ticks.clone()
+    }
+
+    #[test]
+    fn missed_tick_behavior_cpu_slowdown() {
+        let period = Duration::from_millis(10);
+        let on_period = |t: &Duration| t.as_nanos() % period.as_nanos() == 0;
+
+        // every tick is returned, though late
+        let ticks = ticks_with_slowdown(MissedTickBehavior::Burst);
+        assert!(ticks.len() > 5, "{ticks:?}");
+        assert!((ticks.iter().enumerate()).all(|(i, t)| *t == period * i as u32));
+
+        // ticks are a period after the late ones
+        let ticks = ticks_with_slowdown(MissedTickBehavior::Delay);
+        assert!(ticks.windows(2).all(|w| w[1] - w[0] >= period), "{ticks:?}");
+        assert!(!ticks.iter().all(on_period), "{ticks:?}");
+
+        // missed ticks are skipped
+        let ticks = ticks_with_slowdown(MissedTickBehavior::Skip);
+        assert!(ticks.iter().all(on_period), "{ticks:?}");
+        assert!(ticks.windows(2).any(|w| w[1] - w[0] > period), "{ticks:?}");
+    }
+}
@@ -2,21 +2,118 @@
 //!
 //!
 
-use crate::rand::{GlobalRng, Rng};
+use crate::{
+    rand::{GlobalRng, Rng},
+    task::NodeId,
+};
 use futures_util::{select_biased, FutureExt};
-use naive_timer::Timer;
+use serde::{Deserialize, Serialize};
 use spin::Mutex;
 #[doc(no_inline)]
 pub use std::time::{Duration, Instant};
-use std::{future::Future, sync::Arc, time::SystemTime};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    task::Waker,
+    time::SystemTime,
+};
 
 pub mod error;
 mod interval;
 mod sleep;
 mod system_time;
+mod timer;
+mod wall_clock;
 
 pub use self::interval::{interval, interval_at, Interval, MissedTickBehavior};
 pub use self::sleep::{sleep, sleep_until, Sleep};
+#[cfg(target_os = "linux")]
+pub(crate) use self::system_time::clock_syscall;
+pub(crate) use self::system_time::real_monotonic;
+use self::timer::{Callback, Timer, TimerId};
+use self::wall_clock::WallClock;
+
+/// Time configurations.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Config {
+    /// The maximum skew of node wall clocks.
+    ///
+    /// The wall clock of each node is offset from the simulation time by a random amount
+    /// in `[-clock_skew, clock_skew]`.
+    #[serde(default)]
+    pub clock_skew: Duration,
+    /// The maximum drift rate of node wall clocks in parts per million.
+    ///
+    /// The wall clock of each node runs faster or slower than the simulation time by a random
+    /// rate in `[-clock_drift, clock_drift]` ppm.
+    #[serde(default)]
+    pub clock_drift: f64,
+    /// The simulated NTP service. Disabled if `None`.
+    #[serde(default)]
+    pub ntp: Option<NtpConfig>,
+    /// The `SystemTime` when the simulation starts.
+    ///
+    /// By default it is a random time in 2022.
+    #[serde(default)]
+    pub epoch: Option<SystemTime>,
+    /// The resolution of `Instant::now` and `SystemTime::now`.
+    ///
+    /// If set, the time read by the application is rounded down to a multiple of it,
+    /// as on systems with coarse clocks. Timers are not affected.
+    #[serde(default)]
+    pub clock_resolution: Option<Duration>,
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for Config {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.clock_skew.hash(state);
+        self.clock_drift.to_bits().hash(state);
+        self.ntp.hash(state);
+        self.epoch.hash(state);
+        self.clock_resolution.hash(state);
+    }
+}
+
+/// Configuration of the simulated NTP service.
+///
+/// Every `poll_interval`, each node synchronizes its wall clock to the simulation time with
+/// a random error in `[-accuracy, accuracy]`. The drift rate of the clock is unchanged, so the
+/// clock drifts away again until the next synchronization. Nodes that are disconnected from
+/// the network can not reach the NTP server and keep drifting.
+///
+/// Note that the NTP service keeps timers running, so a simulation that is stuck will run
+/// until the time limit instead of being reported as a deadlock.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct NtpConfig {
+    /// The interval between synchronizations.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: Duration,
+    /// The maximum error of a synchronized clock.
+    #[serde(default = "default_accuracy")]
+    pub accuracy: Duration,
+}
+
+impl Default for NtpConfig {
+    fn default() -> Self {
+        NtpConfig {
+            poll_interval: default_poll_interval(),
+            accuracy: default_accuracy(),
+        }
+    }
+}
+
+const fn default_poll_interval() -> Duration {
+    Duration::from_secs(64)
+}
+
+const fn default_accuracy() -> Duration {
+    Duration::from_millis(1)
+}
 
 pub(crate) struct TimeRuntime {
     handle: TimeHandle,
@@ -33,6 +130,7 @@ impl TimeRuntime {
         let handle = TimeHandle {
             timer: Arc::new(Mutex::new(Timer::default())),
             clock: Arc::new(Clock::new(base_time)),
+            wall_clocks: Default::default(),
         };
         TimeRuntime { handle }
     }
@@ -51,8 +149,10 @@ impl TimeRuntime {
             //       t0 + (t1 - t0) < t1 !!
             // we should add eps to make sure 'now >= deadline' and avoid deadlock
             time += Duration::from_nanos(50);
-            timer.expire(time);
+            let expired = timer.expire(time);
             self.handle.clock.set_elapsed(time);
+            drop(timer);
+            expired.into_iter().for_each(Callback::call);
             true
         } else {
             false
@@ -71,6 +171,7 @@ impl TimeRuntime {
 pub struct TimeHandle {
     timer: Arc<Mutex<Timer>>,
     clock: Arc<Clock>,
+    wall_clocks: Arc<Mutex<BTreeMap<NodeId, WallClock>>>,
 }
 
 impl TimeHandle {
@@ -94,15 +195,134 @@ impl TimeHandle {
         self.clock.now_time()
     }
 
+    /// Sets the `SystemTime` when the simulation starts.
+    pub(crate) fn set_epoch(&self, epoch: SystemTime) {
+        self.clock.set_base_time(epoch);
+    }
+
+    /// Sets the resolution of time read by the application.
+    pub(crate) fn set_clock_resolution(&self, resolution: Duration) {
+        self.clock.set_resolution(resolution);
+    }
+
+    /// Rounds a time read by the application down to a multiple of the clock resolution.
+    pub(crate) fn truncate(&self, time: Duration) -> Duration {
+        self.clock.truncate(time)
+    }
+
+    /// Returns the wall clock time of a node.
+    ///
+    /// This is what `SystemTime::now` returns on the node.
+    pub fn node_time(&self, node: NodeId) -> SystemTime {
+        let now = self.clock.now_time();
+        match self.wall_clocks.lock().get(&node) {
+            Some(clock) => clock.time(now),
+            None => now,
+        }
+    }
+
+    /// Sets the wall clock of a node to `time`.
+    ///
+    /// The clock keeps running from `time` at the rate of simulation time.
+    pub fn set_node_time(&self, node: NodeId, time: SystemTime) {
+        let now = self.clock.now_time();
+        let mut clocks = self.wall_clocks.lock();
+        let clock = clocks
+            .entry(node)
+            .or_insert_with(|| WallClock::new(now, 0, 0.0));
+        clock.set(now, time);
+    }
+
+    /// Sets the drift rate of a node's wall clock in parts per million.
+    ///
+    /// A positive rate makes the clock run fast. For example, with a rate of `200.0`,
+    /// the clock gains 200µs every second of simulation time.
+    pub fn set_node_drift(&self, node: NodeId, ppm: f64) {
+        assert!(ppm.abs() < 1e6, "drift rate must be in (-1e6, 1e6) ppm");
+        let now = self.clock.now_time();
+        let mut clocks = self.wall_clocks.lock();
+        let clock = clocks
+            .entry(node)
+            .or_insert_with(|| WallClock::new(now, 0, 0.0));
+        clock.set_drift(now, ppm);
+    }
+
+    /// Steps the wall clock of a node by `nanos` nanoseconds. Negative values step it backward.
+    pub(crate) fn jump_node_clock(&self, node: NodeId, nanos: i64) {
+        let now = self.clock.now_time();
+        let mut clocks = self.wall_clocks.lock();
+        let clock = clocks
+            .entry(node)
+            .or_insert_with(|| WallClock::new(now, 0, 0.0));
+        clock.jump(nanos);
+    }
+
+    /// Returns the nodes whose wall clock is not the simulation time.
+    pub(crate) fn skewed_nodes(&self) -> Vec<NodeId> {
+        self.wall_clocks.lock().keys().copied().collect()
+    }
+
+    /// Synchronizes the wall clock of a node with an error of `nanos` nanoseconds.
+    pub(crate) fn sync_node_clock(&self, node: NodeId, nanos: i64) {
+        let now = self.clock.now_time();
+        if let Some(clock) = self.wall_clocks.lock().get_mut(&node) {
+            clock.sync(now, nanos);
+        }
+    }
+
+    /// Returns the wall clock time of the current node.
+    pub(crate) fn local_time(&self) -> SystemTime {
+        match crate::context::try_current_task() {
+            Some(task) => self.node_time(task.node.id),
+            None => self.now_time(),
+        }
+    }
+
+    /// Initializes the wall clock of a new node with a random skew and drift rate.
+    pub(crate) fn init_node(&self, node: NodeId, config: &Config, rand: &GlobalRng) {
+        let mut offset = 0;
+        let mut drift = 0.0;
+        if !config.clock_skew.is_zero() {
+            let max = config.clock_skew.as_nanos().min(i64::MAX as u128) as i64;
+            offset = rand.with(|rng| rng.gen_range(-max..=max));
+        }
+        if config.clock_drift != 0.0 {
+            let max = config.clock_drift.abs();
+            drift = rand.with(|rng| rng.gen_range(-max..=max));
+        }
+        if offset != 0 || drift != 0.0 {
+            let clock = WallClock::new(self.clock.now_time(), offset, drift);
+            self.wall_clocks.lock().insert(node, clock);
+        }
+    }
+
     /// Returns the amount of time elapsed since this handle was created.
     pub fn elapsed(&self) -> Duration {
         self.clock.elapsed()
     }
 
+    /// Returns whether time is paused by [`pause`].
+    pub(crate) fn is_paused(&self) -> bool {
+        self.clock.inner.lock().paused
+    }
+
+    /// Returns the number of pending timers.
+    pub(crate) fn num_timers(&self) -> usize {
+        self.timer.lock().len()
+    }
+
     /// Advances time.
     pub fn advance(&self, duration: Duration) {
         let time = self.clock.advance(duration);
-        self.timer.lock().expire(time);
+        let expired = self.timer.lock().expire(time);
+        expired.into_iter().for_each(Callback::call);
+    }
+
+    /// Advances the clock without firing timers.
+    ///
+    /// Used within a poll. The expired timers fire after the poll, when the executor advances time.
+    pub(crate) fn advance_clock(&self, duration: Duration) {
+        self.clock.advance(duration);
     }
 
     /// Waits until `duration` has elapsed.
@@ -115,6 +335,8 @@ impl TimeHandle {
         Sleep {
             handle: self.clone(),
             deadline,
+            timer: None,
+            wait: None,
         }
     }
 
@@ -140,7 +362,15 @@ impl TimeHandle {
         callback: impl FnOnce() + Send + Sync + 'static,
     ) {
         let mut timer = self.timer.lock();
-        timer.add(deadline - self.clock.base_instant(), |_| callback());
+        let callback = Callback::Call(Box::new(callback));
+        timer.add(deadline - self.clock.base_instant(), callback);
+    }
+
+    /// Adds a timer waking `waker` at `deadline`.
+    fn add_waker_at(&self, deadline: Instant, waker: &Waker) -> TimerId {
+        let mut timer = self.timer.lock();
+        let callback = Callback::Wake(waker.clone());
+        timer.add(deadline - self.clock.base_instant(), callback)
     }
 
     pub(crate) fn add_timer(&self, dur: Duration, callback: impl FnOnce() + Send + Sync + 'static) {
@@ -157,14 +387,50 @@ pub fn timeout<T: Future>(
     handle.timeout(duration, future)
 }
 
+/// Pauses time.
+///
+/// This function is provided for compatibility with `tokio::time::pause`.
+/// While paused, polling tasks no longer advances time, so `Instant::now()` only changes
+/// by [`advance`], or when there is no runnable task and time advances to the next timer,
+/// which is how tokio behaves with a paused clock.
+///
+/// # Panics
+///
+/// Panics if time is already paused.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub fn pause() {
+    let handle = TimeHandle::current();
+    let mut inner = handle.clock.inner.lock();
+    assert!(!inner.paused, "time is already frozen");
+    inner.paused = true;
+}
+
+/// Resumes time.
+///
+/// This function is provided for compatibility with `tokio::time::resume`.
+/// Polling tasks advances time again. See [`pause`].
+///
+/// # Panics
+///
+/// Panics if time is not paused.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub fn resume() {
+    let handle = TimeHandle::current();
+    let mut inner = handle.clock.inner.lock();
+    assert!(inner.paused, "time is not frozen");
+    inner.paused = false;
+}
+
 /// Advances time.
 ///
-/// Increments the saved `Instant::now()` value by `duration`.
+/// Increments the saved `Instant::now()` value by `duration`, fires the expired timers,
+/// and yields so that the woken tasks can run, like `tokio::time::advance`.
 /// Subsequent calls to `Instant::now()` will return the result of the increment.
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
-pub fn advance(duration: Duration) {
+pub async fn advance(duration: Duration) {
     let handle = TimeHandle::current();
     handle.advance(duration);
+    crate::task::yield_now().await;
 }
 
 struct Clock {
@@ -178,6 +444,10 @@ struct ClockInner {
     base_instant: std::time::Instant,
     /// The amount of mock time which has elapsed.
     advance: Duration,
+    /// Whether time is paused by [`pause`].
+    paused: bool,
+    /// The resolution of time read by the application.
+    resolution: Option<Duration>,
 }
 
 impl Clock {
@@ -186,12 +456,36 @@ impl Clock {
             base_time,
             base_instant: unsafe { std::mem::zeroed() },
             advance: Duration::default(),
+            paused: false,
+            resolution: None,
         };
         Clock {
             inner: Mutex::new(clock),
         }
     }
 
+    fn set_base_time(&self, time: SystemTime) {
+        let mut inner = self.inner.lock();
+        inner.base_time = time;
+    }
+
+    fn set_resolution(&self, resolution: Duration) {
+        let mut inner = self.inner.lock();
+        inner.resolution = Some(resolution);
+    }
+
+    /// Rounds `time` down to a multiple of the resolution.
+    fn truncate(&self, time: Duration) -> Duration {
+        let inner = self.inner.lock();
+        match inner.resolution {
+            Some(res) => {
+                let res = res.as_nanos();
+                Duration::from_nanos((time.as_nanos() / res * res) as u64)
+            }
+            None => time,
+        }
+    }
+
     fn set_elapsed(&self, time: Duration) {
         let mut inner = self.inner.lock();
         inner.advance = time;
@@ -259,8 +553,199 @@ mod tests {
         let runtime = Runtime::new();
         runtime.block_on(async {
             let t0 = Instant::now();
-            advance(Duration::from_secs(1));
+            advance(Duration::from_secs(1)).await;
+            assert!(t0.elapsed() >= Duration::from_secs(1));
+        });
+    }
+
+    #[test]
+    fn timer_callback_adds_timer() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let handle = TimeHandle::current();
+            let handle0 = handle.clone();
+            handle.add_timer(Duration::from_secs(1), move || {
+                handle0.add_timer(Duration::from_secs(1), move || tx.send(()).unwrap());
+            });
+            let t0 = Instant::now();
+            rx.await.unwrap();
+            assert!(t0.elapsed() >= Duration::from_secs(2));
+        });
+    }
+
+    #[test]
+    fn pause_resume() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            pause();
+            let t0 = Instant::now();
+            let deadline = t0 + Duration::from_secs(1);
+            let sleep = crate::task::spawn(sleep_until(deadline));
+            advance(Duration::from_secs(1)).await;
             assert!(t0.elapsed() >= Duration::from_secs(1));
+            sleep.await.unwrap();
+            // the sleep is woken by `advance` rather than auto-advance
+            assert!(Instant::now() < deadline + Duration::from_millis(1));
+            resume();
+        });
+    }
+
+    #[test]
+    fn pause_freezes_polls() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            pause();
+            let t0 = Instant::now();
+            for _ in 0..10 {
+                crate::task::yield_now().await;
+            }
+            assert_eq!(Instant::now(), t0);
+            resume();
+            crate::task::yield_now().await;
+            assert!(Instant::now() > t0);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "time is not frozen")]
+    fn resume_unpaused() {
+        let runtime = Runtime::new();
+        runtime.block_on(async { resume() });
+    }
+
+    #[test]
+    fn epoch() {
+        // one second before the year 2038 problem
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(i32::MAX as u64 - 1);
+        let config = crate::Config::builder().epoch(epoch).build().unwrap();
+        let runtime = Runtime::with_seed_and_config(1, config);
+        runtime.block_on(async move {
+            let t0 = SystemTime::now();
+            assert!(t0.duration_since(epoch).unwrap() < Duration::from_millis(1));
+            sleep(Duration::from_secs(2)).await;
+            let secs = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            assert!(secs > i32::MAX as u64);
+        });
+    }
+
+    #[test]
+    fn clock_skew() {
+        let config = crate::Config {
+            time: Config {
+                clock_skew: Duration::from_secs(10),
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let node1 = runtime.create_node().build();
+        let node2 = runtime.create_node().build();
+        let handle = runtime.handle().time.clone();
+        let (id1, id2) = (node1.id(), node2.id());
+
+        let skew = |id| {
+            let (a, b) = (handle.node_time(id), handle.now_time());
+            a.duration_since(b)
+                .or_else(|_| b.duration_since(a))
+                .unwrap()
+        };
+        assert_ne!(handle.node_time(id1), handle.node_time(id2));
+        assert!(skew(id1) <= Duration::from_secs(10));
+        assert!(skew(id2) <= Duration::from_secs(10));
+
+        let t = handle.now_time() - Duration::from_secs(100);
+        handle.set_node_time(id1, t);
+        let f = node1.spawn(async move {
+            let t0 = SystemTime::now();
+            assert_eq!(t0, t);
+            sleep(Duration::from_secs(1)).await;
+            assert_eq!(SystemTime::now().duration_since(t0).unwrap().as_secs(), 1);
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn clock_drift() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let handle = runtime.handle().time.clone();
+        handle.set_node_drift(node.id(), 200.0);
+
+        let f = node.spawn(async move {
+            let t0 = SystemTime::now();
+            let i0 = Instant::now();
+            sleep(Duration::from_secs(1000)).await;
+            let wall = SystemTime::now().duration_since(t0).unwrap();
+            let mono = i0.elapsed();
+            // 200ppm over 1000s
+            let gain = wall - mono;
+            assert!(gain > Duration::from_millis(199) && gain < Duration::from_millis(201));
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn clock_jump() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().name("node").build();
+        let handle = runtime.handle().clone();
+
+        let f = node.spawn(async move {
+            let t0 = SystemTime::now();
+            let i0 = Instant::now();
+            handle.jump_clock_backward("node", Duration::from_secs(60));
+            let t1 = SystemTime::now();
+            assert_eq!(t0.duration_since(t1).unwrap(), Duration::from_secs(60));
+            assert!(Instant::now() >= i0);
+
+            handle.jump_clock_forward("node", Duration::from_secs(3600));
+            let t2 = SystemTime::now();
+            assert_eq!(t2.duration_since(t0).unwrap(), Duration::from_secs(3540));
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn ntp() {
+        let config = crate::Config {
+            time: Config {
+                clock_drift: 500.0,
+                ntp: Some(NtpConfig {
+                    poll_interval: Duration::from_secs(10),
+                    accuracy: Duration::from_millis(1),
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let node1 = runtime.create_node().build();
+        let node2 = runtime.create_node().build();
+        let handle = runtime.handle().time.clone();
+        let (id1, id2) = (node1.id(), node2.id());
+        let net = runtime.handle().simulator::<crate::net::NetSim>();
+        net.clog_node(id2);
+        handle.set_node_time(id1, handle.now_time() + Duration::from_secs(5));
+        handle.set_node_time(id2, handle.now_time() + Duration::from_secs(5));
+
+        let skew = move |id| {
+            let (a, b) = (handle.node_time(id), handle.now_time());
+            a.duration_since(b)
+                .or_else(|_| b.duration_since(a))
+                .unwrap()
+        };
+        runtime.block_on(async move {
+            sleep(Duration::from_secs(15)).await;
+            // node 1 is synchronized, node 2 can not reach NTP
+            assert!(skew(id1) <= Duration::from_millis(10));
+            assert!(skew(id2) >= Duration::from_secs(4));
+
+            net.unclog_node(id2);
+            sleep(Duration::from_secs(10)).await;
+            assert!(skew(id2) <= Duration::from_millis(10));
         });
     }
 }
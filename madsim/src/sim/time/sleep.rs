@@ -1,4 +1,5 @@
 use super::*;
+use crate::task::{WaitGuard, Waiting};
 use std::{fmt, future::Future, pin::Pin, task::Poll};
 
 /// Waits until `duration` has elapsed.
@@ -20,6 +21,10 @@ pub fn sleep_until(deadline: Instant) -> Sleep {
 pub struct Sleep {
     pub(super) handle: TimeHandle,
     pub(super) deadline: Instant,
+    /// The timer registered on the first poll. It is cancelled on reset and drop.
+    pub(super) timer: Option<TimerId>,
+    /// Marks the task as waiting for the timer.
+    pub(super) wait: Option<WaitGuard>,
 }
 
 impl Sleep {
@@ -38,22 +43,48 @@ impl Sleep {
     /// Resets the `Sleep` instance to a new deadline.
     pub fn reset(mut self: Pin<&mut Self>, deadline: Instant) {
         self.deadline = deadline;
+        self.cancel();
+    }
+
+    fn cancel(&mut self) {
+        if let Some(id) = self.timer.take() {
+            // drop the waker outside the lock
+            let _callback = self.handle.timer.lock().remove(id);
+        }
+        self.wait = None;
     }
 }
 
 impl Future for Sleep {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         if self.is_elapsed() {
+            self.cancel();
             return Poll::Ready(());
         }
-        let waker = cx.waker().clone();
-        self.handle.add_timer_at(self.deadline, || waker.wake());
+        if let Some(id) = self.timer {
+            let old = self.handle.timer.lock().set_waker(id, cx.waker());
+            if old.is_some() {
+                return Poll::Pending;
+            }
+        }
+        self.timer = Some(self.handle.add_waker_at(self.deadline, cx.waker()));
+        if let Some(task) = crate::context::try_current_task() {
+            crate::context::try_current(|h| h.task.record_timer(&task));
+        }
+        let at = self.handle.clock.elapsed() + (self.deadline - self.handle.clock.now_instant());
+        self.wait = Some(WaitGuard::new(Waiting::Timer(at)));
         Poll::Pending
     }
 }
 
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
 impl fmt::Debug for Sleep {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Sleep")
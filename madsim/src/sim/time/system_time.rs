@@ -1,4 +1,92 @@
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+
+lazy_static::lazy_static! {
+    static ref CLOCK_GETTIME: unsafe extern "C" fn(
+        clockid: libc::clockid_t,
+        tp: *mut libc::timespec,
+    ) -> libc::c_int = unsafe {
+        let ptr = libc::dlsym(libc::RTLD_NEXT, b"clock_gettime\0".as_ptr() as _);
+        assert!(!ptr.is_null());
+        std::mem::transmute(ptr)
+    };
+}
+
+/// Returns the real monotonic time, bypassing the simulation.
+pub(crate) fn real_monotonic() -> Duration {
+    let mut tp = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let ret = unsafe { CLOCK_GETTIME(libc::CLOCK_MONOTONIC, &mut tp) };
+    assert_eq!(ret, 0, "failed to get monotonic time");
+    Duration::new(tp.tv_sec as u64, tp.tv_nsec as u32)
+}
+
+/// Warns that the clock is read by a path bypassing the libc functions, once per call stack.
+#[cfg(target_os = "linux")]
+fn warn_bypass(source: &str) {
+    use std::{
+        collections::{hash_map::DefaultHasher, HashSet},
+        hash::{Hash, Hasher},
+    };
+
+    lazy_static::lazy_static! {
+        static ref WARNED: spin::Mutex<HashSet<u64>> = Default::default();
+    }
+    let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+    let mut hasher = DefaultHasher::new();
+    backtrace.hash(&mut hasher);
+    if WARNED.lock().insert(hasher.finish()) {
+        tracing::warn!(
+            "`{source}` bypasses the simulated clock and is redirected to it. \
+             Reading real time breaks determinism.\n{backtrace}"
+        );
+    }
+}
+
+/// Returns the simulated wall clock time as seconds and nanoseconds since the Unix epoch.
+///
+/// Like `timespec`, times before the epoch have negative seconds and non-negative nanoseconds.
+fn since_epoch(time: &super::TimeHandle) -> (i64, u32) {
+    match time.local_time().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(dur) => {
+            let dur = time.truncate(dur);
+            (dur.as_secs() as i64, dur.subsec_nanos())
+        }
+        Err(e) => {
+            let dur = time.truncate(e.duration());
+            match dur.subsec_nanos() {
+                0 => (-(dur.as_secs() as i64), 0),
+                nanos => (-(dur.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// Handles clock system calls invoked by `syscall` inside a madsim context.
+///
+/// Returns `None` if the system call is not a clock one, or if not inside a madsim context.
+#[cfg(target_os = "linux")]
+pub(crate) unsafe fn clock_syscall(
+    num: libc::c_long,
+    a1: libc::c_long,
+    a2: libc::c_long,
+) -> Option<libc::c_long> {
+    if super::TimeHandle::try_current().is_none() {
+        return None;
+    }
+    match num {
+        libc::SYS_clock_gettime => {
+            warn_bypass("SYS_clock_gettime");
+            Some(clock_gettime(a1 as _, a2 as _) as _)
+        }
+        libc::SYS_gettimeofday => {
+            warn_bypass("SYS_gettimeofday");
+            Some(gettimeofday(a1 as _, a2 as _) as _)
+        }
+        _ => None,
+    }
+}
 
 /// Override the libc `gettimeofday` function. For `SystemTime` on macOS.
 #[no_mangle]
@@ -12,13 +100,10 @@ unsafe extern "C" fn gettimeofday(tp: *mut libc::timeval, tz: *mut libc::c_void)
     }
     if let Some(time) = super::TimeHandle::try_current() {
         // inside a madsim context, use the simulated time.
-        let dur = time
-            .now_time()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
+        let (secs, nanos) = since_epoch(&time);
         tp.write(libc::timeval {
-            tv_sec: dur.as_secs() as _,
-            tv_usec: dur.subsec_micros() as _,
+            tv_sec: secs as _,
+            tv_usec: (nanos / 1000) as _,
         });
         0
     } else {
@@ -46,14 +131,13 @@ unsafe extern "C" fn clock_gettime(
 ) -> libc::c_int {
     if let Some(time) = super::TimeHandle::try_current() {
         // inside a madsim context, use the simulated time.
-        let system_time_duration = || {
-            time.now_time()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
+        let system_time_duration = || since_epoch(&time);
+        let instant_duration = || {
+            let dur = time.truncate(time.now_instant().duration_since(std::mem::zeroed()));
+            (dur.as_secs() as i64, dur.subsec_nanos())
         };
-        let instant_duration = || time.now_instant().duration_since(std::mem::zeroed());
 
-        let dur = match clockid {
+        let (secs, nanos) = match clockid {
             // used by SystemTime
             libc::CLOCK_REALTIME => system_time_duration(),
             #[cfg(target_os = "linux")]
@@ -67,21 +151,11 @@ unsafe extern "C" fn clock_gettime(
             _ => panic!("unsupported clockid: {clockid}"),
         };
         tp.write(libc::timespec {
-            tv_sec: dur.as_secs() as _,
-            tv_nsec: dur.subsec_nanos() as _,
+            tv_sec: secs as _,
+            tv_nsec: nanos as _,
         });
         0
     } else {
-        lazy_static::lazy_static! {
-            static ref CLOCK_GETTIME: unsafe extern "C" fn(
-                clockid: libc::clockid_t,
-                tp: *mut libc::timespec,
-            ) -> libc::c_int = unsafe {
-                let ptr = libc::dlsym(libc::RTLD_NEXT, b"clock_gettime\0".as_ptr() as _);
-                assert!(!ptr.is_null());
-                std::mem::transmute(ptr)
-            };
-        }
         CLOCK_GETTIME(clockid, tp)
     }
 }
@@ -95,7 +169,8 @@ unsafe extern "C" fn clock_gettime(
 extern "C" fn mach_absolute_time() -> u64 {
     if let Some(time) = super::TimeHandle::try_current() {
         // inside a madsim context, use the simulated time.
-        let instant = time.now_instant();
+        let base: std::time::Instant = unsafe { std::mem::zeroed() };
+        let instant = base + time.truncate(time.now_instant() - base);
         unsafe { std::mem::transmute(instant) }
     } else {
         lazy_static::lazy_static! {
@@ -115,6 +190,49 @@ mod tests {
     use std::collections::BTreeSet;
     use std::time::{Duration, Instant, SystemTime};
 
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn clock_syscall() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            crate::time::sleep(Duration::from_secs(10)).await;
+            let mut tp = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let ret =
+                unsafe { libc::syscall(libc::SYS_clock_gettime, libc::CLOCK_MONOTONIC, &mut tp) };
+            assert_eq!(ret, 0);
+            let now = Instant::now().duration_since(unsafe { std::mem::zeroed() });
+            assert_eq!(Duration::new(tp.tv_sec as u64, tp.tv_nsec as u32), now);
+        });
+    }
+
+    #[test]
+    fn before_unix_epoch() {
+        let epoch = SystemTime::UNIX_EPOCH - Duration::from_millis(1500);
+        let config = crate::Config {
+            time: crate::time::Config {
+                epoch: Some(epoch),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(0, config);
+        runtime.block_on(async {
+            let mut tv = libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            };
+            assert_eq!(
+                unsafe { libc::gettimeofday(&mut tv, std::ptr::null_mut()) },
+                0
+            );
+            assert_eq!((tv.tv_sec, tv.tv_usec), (-2, 500_000));
+            assert!(SystemTime::now() < SystemTime::UNIX_EPOCH);
+        });
+    }
+
     #[test]
     fn deterministic_std_system_time() {
         let _real_now = SystemTime::now();
@@ -133,6 +251,32 @@ mod tests {
         assert_eq!(times.len(), 3);
     }
 
+    #[test]
+    fn clock_resolution() {
+        let config = crate::Config {
+            time: crate::time::Config {
+                clock_resolution: Some(Duration::from_millis(10)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(0, config);
+        runtime.block_on(async {
+            let t0 = Instant::now();
+            let s0 = SystemTime::now();
+            crate::time::sleep(Duration::from_millis(3)).await;
+            assert_eq!(Instant::now(), t0);
+            assert_eq!(SystemTime::now(), s0);
+            crate::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(t0.elapsed(), Duration::from_millis(10));
+            let nanos = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            assert_eq!(nanos % 10_000_000, 0);
+        });
+    }
+
     #[test]
     fn deterministic_std_instant() {
         let mut times = BTreeSet::new();
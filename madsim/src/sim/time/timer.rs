@@ -0,0 +1,391 @@
+//! A hierarchical timing wheel.
+//!
+//! The wheel has 11 levels of 64 slots. Each slot at level `n` covers `64^n` nanoseconds,
+//! so the wheel covers the whole `u64` range. A timer is put into the level determined by
+//! the highest bit in which its deadline differs from the current time. As time goes by,
+//! timers are cascaded down to lower levels, and fire when they reach level 0.
+//!
+//! Inserting and cancelling a timer is O(1). Each timer is cascaded at most once per level.
+//!
+//! Timers with the same deadline fire in the order they were added.
+
+use std::{task::Waker, time::Duration};
+
+const LEVEL_BITS: u32 = 6;
+const SLOTS: usize = 1 << LEVEL_BITS;
+const NUM_LEVELS: usize = (64 + LEVEL_BITS as usize - 1) / LEVEL_BITS as usize;
+
+/// The action of a timer when it fires.
+pub(super) enum Callback {
+    Wake(Waker),
+    Call(Box<dyn FnOnce() + Send + Sync>),
+}
+
+impl Callback {
+    pub fn call(self) {
+        match self {
+            Callback::Wake(waker) => waker.wake(),
+            Callback::Call(f) => f(),
+        }
+    }
+}
+
+/// A handle to a timer added to [`Timer`], used to cancel it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct TimerId {
+    index: usize,
+    generation: u64,
+}
+
+/// A hierarchical timing wheel.
+pub(super) struct Timer {
+    /// The current time of the wheel in nanoseconds.
+    elapsed: u64,
+    levels: [Level; NUM_LEVELS],
+    /// Timers whose deadline had passed when they were added.
+    ready: List,
+    entries: Vec<Slot>,
+    /// Indexes of vacant slots in `entries`.
+    free: Vec<usize>,
+    len: usize,
+}
+
+struct Level {
+    /// Bitmap of non-empty slots.
+    occupied: u64,
+    slots: [List; SLOTS],
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Level {
+            occupied: 0,
+            slots: [List::default(); SLOTS],
+        }
+    }
+}
+
+/// A doubly linked list of entries.
+#[derive(Default, Clone, Copy)]
+struct List {
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+struct Slot {
+    generation: u64,
+    entry: Option<Entry>,
+}
+
+struct Entry {
+    deadline: u64,
+    callback: Callback,
+    /// The list containing this entry. `None` if it is in the ready list.
+    location: Option<(usize, usize)>,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer {
+            elapsed: 0,
+            levels: Default::default(),
+            ready: List::default(),
+            entries: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+}
+
+impl Timer {
+    /// Returns the number of pending timers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Adds a timer firing at `deadline`.
+    pub fn add(&mut self, deadline: Duration, callback: Callback) -> TimerId {
+        let deadline = deadline.as_nanos().min(u64::MAX as u128) as u64;
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.entries.push(Slot {
+                    generation: 0,
+                    entry: None,
+                });
+                self.entries.len() - 1
+            }
+        };
+        let slot = &mut self.entries[index];
+        slot.entry = Some(Entry {
+            deadline,
+            callback,
+            location: None,
+            prev: None,
+            next: None,
+        });
+        let id = TimerId {
+            index,
+            generation: slot.generation,
+        };
+        self.len += 1;
+        self.link(index);
+        id
+    }
+
+    /// Replaces the waker of a timer.
+    ///
+    /// Returns the old callback, or `None` if the timer has fired or been cancelled.
+    /// The old callback should be dropped after releasing the lock of the timer.
+    pub fn set_waker(&mut self, id: TimerId, waker: &Waker) -> Option<Callback> {
+        let entry = self.entry_mut(id)?;
+        Some(std::mem::replace(
+            &mut entry.callback,
+            Callback::Wake(waker.clone()),
+        ))
+    }
+
+    /// Cancels a timer.
+    ///
+    /// Returns the callback, or `None` if the timer has fired or been cancelled.
+    /// The callback should be dropped after releasing the lock of the timer.
+    pub fn remove(&mut self, id: TimerId) -> Option<Callback> {
+        self.entry_mut(id)?;
+        self.unlink(id.index);
+        Some(self.release(id.index))
+    }
+
+    /// Returns the deadline of the earliest timer.
+    pub fn next(&self) -> Option<Duration> {
+        if self.ready.head.is_some() {
+            return Some(Duration::from_nanos(self.elapsed));
+        }
+        let (level, slot, start) = self.next_slot()?;
+        if level == 0 {
+            return Some(Duration::from_nanos(start));
+        }
+        // timers in a slot of higher levels are unordered
+        let mut min = u64::MAX;
+        let mut cur = self.levels[level].slots[slot].head;
+        while let Some(index) = cur {
+            let entry = self.entries[index].entry.as_ref().unwrap();
+            min = min.min(entry.deadline);
+            cur = entry.next;
+        }
+        Some(Duration::from_nanos(min))
+    }
+
+    /// Advances the wheel to `now`. Returns callbacks of the expired timers in order of deadline.
+    ///
+    /// The callbacks should be called after releasing the lock of the timer,
+    /// so that they can add new timers.
+    pub fn expire(&mut self, now: Duration) -> Vec<Callback> {
+        let now = now.as_nanos().min(u64::MAX as u128) as u64;
+        let mut expired = vec![];
+        let ready = std::mem::take(&mut self.ready);
+        self.drain(ready, |this, index| {
+            this.release_into(index, &mut expired);
+        });
+        while let Some((level, slot, start)) = self.next_slot() {
+            if start > now {
+                break;
+            }
+            self.elapsed = self.elapsed.max(start);
+            let list = std::mem::take(&mut self.levels[level].slots[slot]);
+            self.levels[level].occupied &= !(1 << slot);
+            if level == 0 {
+                self.drain(list, |this, index| this.release_into(index, &mut expired));
+            } else {
+                // cascade down to lower levels
+                self.drain(list, |this, index| this.link(index));
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+        expired
+    }
+
+    /// Returns the level, index and start time of the earliest non-empty slot.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        for (level, l) in self.levels.iter().enumerate() {
+            let shift = level as u32 * LEVEL_BITS;
+            let current = ((self.elapsed >> shift) as usize) & (SLOTS - 1);
+            let occupied = l.occupied >> current;
+            if occupied == 0 {
+                continue;
+            }
+            let slot = current + occupied.trailing_zeros() as usize;
+            let level_mask = (1u128 << (shift + LEVEL_BITS)) - 1;
+            let level_start = (self.elapsed as u128 & !level_mask) as u64;
+            let start = level_start + ((slot as u64) << shift);
+            return Some((level, slot, start));
+        }
+        None
+    }
+
+    /// Returns the level of a timer at `deadline`.
+    fn level_for(&self, deadline: u64) -> usize {
+        let masked = (self.elapsed ^ deadline) | (SLOTS as u64 - 1);
+        let significant = 63 - masked.leading_zeros();
+        (significant / LEVEL_BITS) as usize
+    }
+
+    fn entry_mut(&mut self, id: TimerId) -> Option<&mut Entry> {
+        let slot = self.entries.get_mut(id.index)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        slot.entry.as_mut()
+    }
+
+    /// Appends an unlinked entry to the list it belongs to.
+    fn link(&mut self, index: usize) {
+        let deadline = self.entries[index].entry.as_ref().unwrap().deadline;
+        let location = if deadline < self.elapsed {
+            None
+        } else {
+            let level = self.level_for(deadline);
+            let slot = ((deadline >> (level as u32 * LEVEL_BITS)) as usize) & (SLOTS - 1);
+            self.levels[level].occupied |= 1 << slot;
+            Some((level, slot))
+        };
+        let list = match location {
+            Some((level, slot)) => &mut self.levels[level].slots[slot],
+            None => &mut self.ready,
+        };
+        let tail = list.tail.replace(index);
+        if list.head.is_none() {
+            list.head = Some(index);
+        }
+        if let Some(tail) = tail {
+            self.entries[tail].entry.as_mut().unwrap().next = Some(index);
+        }
+        let entry = self.entries[index].entry.as_mut().unwrap();
+        entry.location = location;
+        entry.prev = tail;
+        entry.next = None;
+    }
+
+    /// Removes an entry from its list.
+    fn unlink(&mut self, index: usize) {
+        let entry = self.entries[index].entry.as_mut().unwrap();
+        let (prev, next, location) = (entry.prev.take(), entry.next.take(), entry.location);
+        let list = match location {
+            Some((level, slot)) => &mut self.levels[level].slots[slot],
+            None => &mut self.ready,
+        };
+        match prev {
+            Some(prev) => self.entries[prev].entry.as_mut().unwrap().next = next,
+            None => list.head = next,
+        }
+        match next {
+            Some(next) => self.entries[next].entry.as_mut().unwrap().prev = prev,
+            None => list.tail = prev,
+        }
+        if let (Some((level, slot)), None) = (location, list.head) {
+            self.levels[level].occupied &= !(1 << slot);
+        }
+    }
+
+    /// Calls `f` on each entry of a detached list in order.
+    fn drain(&mut self, list: List, mut f: impl FnMut(&mut Self, usize)) {
+        let mut cur = list.head;
+        while let Some(index) = cur {
+            let entry = self.entries[index].entry.as_mut().unwrap();
+            cur = entry.next.take();
+            entry.prev = None;
+            f(self, index);
+        }
+    }
+
+    /// Frees an unlinked entry and returns its callback.
+    fn release(&mut self, index: usize) -> Callback {
+        let slot = &mut self.entries[index];
+        let entry = slot.entry.take().unwrap();
+        slot.generation += 1;
+        self.free.push(index);
+        self.len -= 1;
+        entry.callback
+    }
+
+    fn release_into(&mut self, index: usize, expired: &mut Vec<Callback>) {
+        let callback = self.release(index);
+        expired.push(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn record(log: &Arc<Mutex<Vec<u64>>>, value: u64) -> Callback {
+        let log = log.clone();
+        Callback::Call(Box::new(move || log.lock().unwrap().push(value)))
+    }
+
+    fn fire(timer: &mut Timer, now: u64) {
+        for callback in timer.expire(Duration::from_nanos(now)) {
+            callback.call();
+        }
+    }
+
+    #[test]
+    fn order() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut timer = Timer::default();
+        let deadlines = [5, 70, 1 << 20, 3, 70, 1 << 40, 64, 4096, 1 << 63];
+        for (i, &d) in deadlines.iter().enumerate() {
+            timer.add(Duration::from_nanos(d), record(&log, i as u64));
+        }
+        assert_eq!(timer.len(), deadlines.len());
+
+        let mut sorted: Vec<_> = (0..deadlines.len() as u64).collect();
+        sorted.sort_by_key(|&i| deadlines[i as usize]);
+        let mut fired = vec![];
+        while let Some(next) = timer.next() {
+            let next = next.as_nanos() as u64;
+            assert!(deadlines.contains(&next));
+            fire(&mut timer, next);
+            fired.extend(log.lock().unwrap().drain(..));
+        }
+        assert_eq!(fired, sorted);
+        assert_eq!(timer.len(), 0);
+    }
+
+    #[test]
+    fn cancel() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut timer = Timer::default();
+        let a = timer.add(Duration::from_nanos(100), record(&log, 0));
+        let _b = timer.add(Duration::from_nanos(100), record(&log, 1));
+        let c = timer.add(Duration::from_nanos(1 << 30), record(&log, 2));
+        assert!(timer.remove(a).is_some());
+        assert!(timer.remove(c).is_some());
+        assert!(timer.remove(c).is_none());
+        assert_eq!(timer.len(), 1);
+        assert_eq!(timer.next(), Some(Duration::from_nanos(100)));
+        fire(&mut timer, 1 << 31);
+        assert_eq!(*log.lock().unwrap(), [1]);
+        assert_eq!(timer.next(), None);
+
+        // stale ID does not cancel a new timer
+        let d = timer.add(Duration::from_nanos(1 << 32), record(&log, 3));
+        assert!(timer.remove(a).is_none());
+        assert_eq!(timer.len(), 1);
+        assert!(timer.remove(d).is_some());
+        assert_eq!(timer.len(), 0);
+    }
+
+    #[test]
+    fn past_deadline() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut timer = Timer::default();
+        fire(&mut timer, 1000);
+        timer.add(Duration::from_nanos(10), record(&log, 0));
+        assert_eq!(timer.next(), Some(Duration::from_nanos(1000)));
+        fire(&mut timer, 1000);
+        assert_eq!(*log.lock().unwrap(), [0]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Per-node wall clocks.
+//!
+//! Every node reads `SystemTime` from its own wall clock, which may be offset from the
+//! simulation time and run at a slightly different rate. `Instant` is not affected, since
+//! monotonic clocks are local to a node and never compared across nodes.
+
+use std::time::{Duration, SystemTime};
+
+/// The wall clock of a node.
+#[derive(Debug, Clone)]
+pub(super) struct WallClock {
+    /// The offset from the simulation time at `since` in nanoseconds.
+    /// Positive if the clock is ahead.
+    offset: i64,
+    /// The drift rate in parts per million. Positive if the clock runs fast.
+    drift: f64,
+    /// The simulation time when `offset` was measured.
+    since: SystemTime,
+}
+
+impl WallClock {
+    /// Creates a clock with the given offset in nanoseconds and drift rate in ppm,
+    /// starting at simulation time `now`.
+    pub fn new(now: SystemTime, offset: i64, drift: f64) -> Self {
+        WallClock {
+            offset,
+            drift,
+            since: now,
+        }
+    }
+
+    /// Returns the offset from the simulation time in nanoseconds when the simulation time is `now`.
+    fn offset(&self, now: SystemTime) -> i64 {
+        let elapsed = diff(now, self.since) as f64;
+        self.offset + (elapsed * self.drift / 1e6) as i64
+    }
+
+    /// Returns the time of this clock when the simulation time is `now`.
+    pub fn time(&self, now: SystemTime) -> SystemTime {
+        shift(now, self.offset(now))
+    }
+
+    /// Sets the clock to `time` when the simulation time is `now`.
+    pub fn set(&mut self, now: SystemTime, time: SystemTime) {
+        self.offset = diff(time, now);
+        self.since = now;
+    }
+
+    /// Synchronizes the clock to `now` with an error of `nanos` nanoseconds,
+    /// keeping the drift rate.
+    pub fn sync(&mut self, now: SystemTime, nanos: i64) {
+        self.offset = nanos;
+        self.since = now;
+    }
+
+    /// Steps the clock by `nanos` nanoseconds. Negative values step it backward.
+    pub fn jump(&mut self, nanos: i64) {
+        self.offset += nanos;
+    }
+
+    /// Sets the drift rate in ppm from simulation time `now`.
+    pub fn set_drift(&mut self, now: SystemTime, drift: f64) {
+        self.offset = self.offset(now);
+        self.since = now;
+        self.drift = drift;
+    }
+}
+
+/// Returns `time + nanos`.
+fn shift(time: SystemTime, nanos: i64) -> SystemTime {
+    if nanos >= 0 {
+        time + Duration::from_nanos(nanos as u64)
+    } else {
+        time - Duration::from_nanos(nanos.unsigned_abs())
+    }
+}
+
+/// Returns `a - b` in nanoseconds.
+fn diff(a: SystemTime, b: SystemTime) -> i64 {
+    match a.duration_since(b) {
+        Ok(d) => d.as_nanos() as i64,
+        Err(e) => -(e.duration().as_nanos() as i64),
+    }
+}
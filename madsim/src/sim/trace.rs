@@ -0,0 +1,226 @@
+//! Execution trace for determinism check.
+//!
+//! Every random number generated, task polled and message delivered is a step of the trace.
+//! The first run logs the running hash and the event of each step. The second run compares
+//! its hashes with the log, and on mismatch reports the first differing event with the
+//! surrounding events of both runs.
+
+use crate::task::{self, NodeId};
+use std::{
+    collections::VecDeque,
+    fmt,
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    panic::Location,
+    time::Duration,
+};
+
+/// The number of events shown before and after the divergent one.
+const CONTEXT: usize = 5;
+
+/// The kind of a step.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum EventKind {
+    /// A random number is generated.
+    Rand,
+    /// A task spawned at the location is polled.
+    Poll(&'static Location<'static>),
+    /// A message is delivered.
+    Deliver {
+        src: SocketAddr,
+        dst: SocketAddr,
+        len: u64,
+    },
+}
+
+/// A step of the execution trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Event {
+    time: Duration,
+    node: Option<NodeId>,
+    task: Option<task::Id>,
+    kind: EventKind,
+}
+
+impl Event {
+    /// Creates an event in the current context.
+    pub fn new(kind: EventKind, time: Option<Duration>) -> Self {
+        let task = crate::context::try_current_task();
+        Event {
+            time: time.unwrap_or_default(),
+            node: task.as_ref().map(|t| t.node.id),
+            task: task.as_ref().map(|t| t.id),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>12}", format!("{:?}", self.time))?;
+        match self.node {
+            Some(node) => write!(f, "  node {:<4}", node.to_string())?,
+            None => write!(f, "  node -   ")?,
+        }
+        match self.task {
+            Some(task) => write!(f, "  task {:<6}", task.to_string())?,
+            None => write!(f, "  task -     ")?,
+        }
+        match &self.kind {
+            EventKind::Rand => write!(f, "  rand"),
+            EventKind::Poll(location) => write!(f, "  poll     task spawned at {location}"),
+            EventKind::Deliver { src, dst, len } => {
+                write!(f, "  deliver  {src} -> {dst} ({len} bytes)")
+            }
+        }
+    }
+}
+
+/// The trace of the first run.
+#[derive(Debug, Default)]
+pub(crate) struct Log {
+    /// The lowest byte of the running hash at each step.
+    hashes: Vec<u8>,
+    events: Vec<Event>,
+}
+
+impl Log {
+    pub fn push(&mut self, hash: u64, event: Event) {
+        self.hashes.push(hash as u8);
+        self.events.push(event);
+    }
+}
+
+/// The state of the second run.
+#[derive(Debug)]
+pub(crate) struct Check {
+    log: Log,
+    /// The index of the next step.
+    step: usize,
+    /// The most recent events of this run.
+    recent: VecDeque<Event>,
+}
+
+impl Check {
+    pub fn new(log: Log) -> Self {
+        Check {
+            log,
+            step: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn into_log(self) -> Log {
+        self.log
+    }
+
+    /// Checks the next step.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a report if the step diverges from the log.
+    pub fn check(&mut self, hash: u64, event: Event) {
+        if self.recent.len() == CONTEXT + 1 {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event);
+        if self.log.hashes.get(self.step) != Some(&(hash as u8)) {
+            panic!("{}", self.report());
+        }
+        self.step += 1;
+    }
+
+    /// Panics if this run ended before the logged one.
+    pub fn finish(&self) {
+        if self.step != self.log.hashes.len() {
+            panic!(
+                "non-determinism detected at step {}: the run ended early\n\
+                 next events of the first run:\n{}",
+                self.step,
+                Lines(&self.log.events, self.step, self.step, CONTEXT),
+            );
+        }
+    }
+
+    /// Returns the report of the divergence at the current step.
+    fn report(&self) -> String {
+        // the running hash may collide in the lowest byte,
+        // so the events may diverge a few steps earlier.
+        let start = self.step + 1 - self.recent.len();
+        let first = (start..=self.step)
+            .find(|&i| self.log.events.get(i) != Some(&self.recent[i - start]))
+            .unwrap_or(self.step);
+        let second: Vec<_> = self.recent.iter().cloned().collect();
+        let event = &self.recent[first - start];
+        format!(
+            "non-determinism detected at step {first} at {:?}\n\
+             first run:\n{}\
+             second run:\n{}",
+            event.time,
+            Lines(&self.log.events, 0, first, CONTEXT),
+            Lines(&second, start, first, CONTEXT),
+        )
+    }
+}
+
+/// Formats events around the divergent step.
+///
+/// `events[0]` is the step `offset`. The divergent step is marked with `>`.
+struct Lines<'a>(&'a [Event], usize, usize, usize);
+
+impl fmt::Display for Lines<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Lines(events, offset, step, context) = *self;
+        let begin = step.saturating_sub(context).max(offset);
+        let end = (step + context + 1).min(offset + events.len());
+        if begin >= end {
+            return writeln!(f, "    <end of run>");
+        }
+        for i in begin..end {
+            let mark = if i == step { '>' } else { ' ' };
+            writeln!(f, "  {mark} {i:>8}  {}", events[i - offset])?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the hash of the event kind.
+pub(crate) fn hash(kind: &EventKind) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    kind.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time: u64, kind: EventKind) -> Event {
+        Event {
+            time: Duration::from_millis(time),
+            node: Some(NodeId::zero()),
+            task: None,
+            kind,
+        }
+    }
+
+    #[test]
+    fn report() {
+        let mut log = Log::default();
+        for i in 0..10 {
+            log.push(i, event(i, EventKind::Rand));
+        }
+        let mut check = Check::new(log);
+        for i in 0..4 {
+            check.check(i, event(i, EventKind::Rand));
+        }
+        let poll = EventKind::Poll(Location::caller());
+        let report = std::panic::catch_unwind(move || check.check(99, event(4, poll)))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert!(report.starts_with("non-determinism detected at step 4 at 4ms\n"));
+        assert!(report.contains(">        4           4ms  node 0     task -       rand"));
+        assert!(report.contains("task spawned at"));
+    }
+}
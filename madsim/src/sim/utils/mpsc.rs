@@ -1,8 +1,6 @@
 //! A multi-producer, single-consumer queue but allows
 //! consumer to randomly choose an element from the queue.
 
-use crate::rand::GlobalRng;
-use rand::Rng;
 use spin::Mutex;
 use std::{fmt, sync::Arc};
 
@@ -59,10 +57,15 @@ impl<T> Sender<T> {
         }
         Err(SendError(value))
     }
+
+    /// Returns the number of values in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.queue.lock().len()
+    }
 }
 
 /// This enumeration is the list of the possible reasons
-/// that `try_recv_random` could not return data when called.
+/// that `try_recv_by` could not return data when called.
 pub enum TryRecvError {
     Empty,
     Disconnected,
@@ -70,10 +73,12 @@ pub enum TryRecvError {
 
 impl<T> Receiver<T> {
     /// Attempts to return a pending value on this receiver without blocking.
-    pub fn try_recv_random(&self, rng: &GlobalRng) -> Result<T, TryRecvError> {
+    ///
+    /// `choose` returns the index of the value to receive among the pending ones.
+    pub fn try_recv_by(&self, choose: impl FnOnce(&[T]) -> usize) -> Result<T, TryRecvError> {
         let mut queue = self.inner.queue.lock();
         if !queue.is_empty() {
-            let idx = rng.with(|rng| rng.gen_range(0..queue.len()));
+            let idx = choose(&queue);
             Ok(queue.swap_remove(idx))
         } else if Arc::weak_count(&self.inner) == 0 {
             Err(TryRecvError::Disconnected)
@@ -0,0 +1,16 @@
+//! A bridge to run futures on a real tokio runtime.
+//!
+//! Outside the simulation, everything runs on the real runtime.
+
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+/// Runs the future on the current tokio runtime.
+#[track_caller]
+pub fn spawn_external<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future)
+}
@@ -14,6 +14,27 @@ pub fn buggify_with_prob(_probability: f64) -> bool {
     false
 }
 
+/// Marks a fault point. Always returns false when not running in simulation mode.
+#[macro_export]
+macro_rules! buggify {
+    () => {
+        false
+    };
+    ($probability:expr) => {{
+        let _ = $probability;
+        false
+    }};
+}
+
+/// Marks a fault point with the probability.
+/// Always returns false when not running in simulation mode.
+#[macro_export]
+macro_rules! buggify_with_prob {
+    ($probability:expr) => {
+        $crate::buggify!($probability)
+    };
+}
+
 /// Enable buggify.
 #[inline(always)]
 pub fn enable() {}
@@ -0,0 +1,9 @@
+//! Interesting-state hooks for guided seed exploration.
+//!
+//! They do nothing when not running in simulation mode.
+
+use std::hash::Hash;
+
+/// Records that the current run has reached the state.
+#[inline(always)]
+pub fn hit(_state: impl Hash) {}
@@ -4,10 +4,17 @@ use std::{
     fs::Metadata,
     io::{Result, SeekFrom},
     path::Path,
+    pin::Pin,
+    task::{Context, Poll},
 };
 
-pub use tokio::fs::{metadata, read};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+pub use tokio::fs::{
+    copy, create_dir, create_dir_all, metadata, read, read_dir, read_to_string, remove_dir,
+    remove_dir_all, remove_file, rename, try_exists, write, DirEntry, ReadDir,
+};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+};
 
 /// A reference to an open file on the filesystem.
 pub struct File {
@@ -31,6 +38,11 @@ impl File {
         })
     }
 
+    /// Returns a new [`OpenOptions`] object.
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
     /// Reads a number of bytes starting from a given offset.
     pub async fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<usize> {
         // TODO: make it &self
@@ -57,8 +69,123 @@ impl File {
         self.inner.sync_all().await
     }
 
+    /// This function is similar to [`sync_all`](File::sync_all), except that it may not
+    /// synchronize file metadata to the filesystem.
+    pub async fn sync_data(&self) -> Result<()> {
+        self.inner.sync_data().await
+    }
+
     /// Queries metadata about the underlying file.
     pub async fn metadata(&self) -> Result<Metadata> {
         self.inner.metadata().await
     }
 }
+
+impl AsyncRead for File {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for File {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSeek for File {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<()> {
+        Pin::new(&mut self.inner).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u64>> {
+        Pin::new(&mut self.inner).poll_complete(cx)
+    }
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    inner: tokio::fs::OpenOptions,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenOptions {
+    /// Creates a blank new set of options ready for configuration.
+    pub fn new() -> Self {
+        OpenOptions {
+            inner: tokio::fs::OpenOptions::new(),
+        }
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.inner.read(read);
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.inner.write(write);
+        self
+    }
+
+    /// Sets the option for the append mode.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.inner.append(append);
+        self
+    }
+
+    /// Sets the option for truncating a previous file.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.inner.truncate(truncate);
+        self
+    }
+
+    /// Sets the option for creating a new file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.inner.create(create);
+        self
+    }
+
+    /// Sets the option to always create a new file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.inner.create_new(create_new);
+        self
+    }
+
+    /// Sets the flags passed to `open`.
+    #[cfg(unix)]
+    pub fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.inner.custom_flags(flags);
+        self
+    }
+
+    /// Opens a file at `path` with the options specified by `self`.
+    pub async fn open(&self, path: impl AsRef<Path>) -> Result<File> {
+        Ok(File {
+            inner: self.inner.open(path).await?,
+        })
+    }
+}
@@ -0,0 +1,49 @@
+//! Deterministic hashing.
+//!
+//! When not running in simulation mode, these are the std types.
+
+pub use std::collections::hash_map::{DefaultHasher, RandomState};
+
+/// A [`HashMap`](std::collections::HashMap) using [`RandomState`].
+pub type HashMap<K, V> = std::collections::HashMap<K, V, RandomState>;
+
+/// A [`HashSet`](std::collections::HashSet) using [`RandomState`].
+pub type HashSet<T> = std::collections::HashSet<T, RandomState>;
+
+/// Constructors of [`HashMap`], the same as the inherent ones.
+pub trait HashMapExt {
+    /// Creates an empty map.
+    fn new() -> Self;
+
+    /// Creates an empty map with at least the specified capacity.
+    fn with_capacity(capacity: usize) -> Self;
+}
+
+impl<K, V> HashMapExt for HashMap<K, V> {
+    fn new() -> Self {
+        HashMap::new()
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        HashMap::with_capacity(capacity)
+    }
+}
+
+/// Constructors of [`HashSet`], the same as the inherent ones.
+pub trait HashSetExt {
+    /// Creates an empty set.
+    fn new() -> Self;
+
+    /// Creates an empty set with at least the specified capacity.
+    fn with_capacity(capacity: usize) -> Self;
+}
+
+impl<T> HashSetExt for HashSet<T> {
+    fn new() -> Self {
+        HashSet::new()
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        HashSet::with_capacity(capacity)
+    }
+}
@@ -0,0 +1,28 @@
+//! Simulated memory accounting.
+//!
+//! It does nothing when not running in simulation mode.
+
+/// Memory accounted to the current node, freed on drop.
+#[derive(Debug)]
+pub struct Allocation {
+    size: usize,
+}
+
+impl Allocation {
+    /// Returns the size of the allocation in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Accounts `size` bytes of memory to the current node until the returned value is dropped.
+#[inline(always)]
+pub fn alloc(size: usize) -> Allocation {
+    Allocation { size }
+}
+
+/// Returns the memory usage of the current node in bytes. Always 0.
+#[inline(always)]
+pub fn usage() -> usize {
+    0
+}
@@ -1,5 +1,9 @@
+pub mod bridge;
 pub mod buggify;
+pub mod coverage;
 pub mod fs;
+pub mod hash;
+pub mod memory;
 pub mod net;
 pub mod signal;
 pub mod time;
@@ -6,9 +6,39 @@
 //!
 //! - [`call`][Endpoint::call]
 //! - [`call_with_data`][Endpoint::call_with_data]
+//! - [`call_with_bytes`][Endpoint::call_with_bytes]
 //! - [`call_timeout`][Endpoint::call_timeout]
 //! - [`add_rpc_handler`][Endpoint::add_rpc_handler]
 //! - [`add_rpc_handler_with_data`][Endpoint::add_rpc_handler_with_data]
+//! - [`add_rpc_handler_with_bytes`][Endpoint::add_rpc_handler_with_bytes]
+//! - [`call_stream`][Endpoint::call_stream]
+//! - [`call_server_stream`][Endpoint::call_server_stream]
+//! - [`add_stream_handler`][Endpoint::add_stream_handler]
+//!
+//! # Deadlines
+//!
+//! A call fails with [`DeadlineExceeded`] if the response does not arrive within the timeout of
+//! [`call_timeout`][Endpoint::call_timeout], or the default deadline of the endpoint set by
+//! [`set_rpc_deadline`][Endpoint::set_rpc_deadline]. The call is then cancelled as below, so the
+//! handler stops and no late response is sent.
+//!
+//! # Payload sizes
+//!
+//! An endpoint may limit the size of requests and responses of its calls with
+//! [`set_rpc_max_request_size`][Endpoint::set_rpc_max_request_size] and
+//! [`set_rpc_max_response_size`][Endpoint::set_rpc_max_response_size]. Calls that exceed them
+//! fail with [`PayloadTooLarge`].
+//!
+//! # Interceptors
+//!
+//! Interceptors added by [`add_rpc_interceptor`][Endpoint::add_rpc_interceptor] see each
+//! incoming call before its handler, and may [reject](Verdict::Reject) or
+//! [delay](Verdict::Delay) it, or add annotations that the handler reads by [`current_call`].
+//!
+//! # Cancellation
+//!
+//! If the caller drops an in-flight call, e.g. on timeout, the future of the handler is dropped
+//! on the remote node and no response is sent.
 //!
 //! # Examples
 //!
@@ -58,15 +88,21 @@ use super::*;
 use bytes::Buf;
 #[doc(no_inline)]
 pub use bytes::Bytes;
-use futures_util::FutureExt;
+use futures_util::{future::BoxFuture, ready, select_biased, FutureExt, Stream};
 use rand::Rng;
 #[doc(no_inline)]
 pub use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     any::Any,
+    collections::{BTreeMap, HashMap},
+    fmt,
     future::Future,
     io::{self, IoSlice},
+    marker::PhantomData,
     net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
     time::Duration,
 };
 
@@ -79,6 +115,162 @@ pub trait Request: Serialize + DeserializeOwned + Any + Send + Sync {
     const ID: u64;
 }
 
+/// The tag of messages that cancel calls, which must not collide with any [`Request::ID`].
+const CANCEL_TAG: u64 = u64::MAX;
+
+/// The error of a call that did not complete before its deadline.
+///
+/// It is the inner error of an [`io::Error`] of kind [`TimedOut`](io::ErrorKind::TimedOut).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl DeadlineExceeded {
+    /// Returns whether the error is caused by an exceeded deadline.
+    pub fn matches(err: &io::Error) -> bool {
+        err.get_ref().is_some_and(|e| e.is::<DeadlineExceeded>())
+    }
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// The error of a call whose request or response exceeds the size limit of the endpoint.
+///
+/// It is the inner error of an [`io::Error`] of kind [`InvalidInput`](io::ErrorKind::InvalidInput)
+/// for requests, or [`InvalidData`](io::ErrorKind::InvalidData) for responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadTooLarge {
+    /// The size of the payload in bytes.
+    pub size: usize,
+    /// The limit in bytes.
+    pub limit: usize,
+}
+
+impl PayloadTooLarge {
+    /// Returns an error if `size` exceeds `limit`.
+    fn check(size: usize, limit: usize, kind: io::ErrorKind) -> io::Result<()> {
+        if size > limit {
+            return Err(io::Error::new(kind, PayloadTooLarge { size, limit }));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RPC payload of {} bytes exceeds the limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+/// The error of a call rejected by an interceptor of the remote endpoint.
+///
+/// It is the inner error of an [`io::Error`] of kind
+/// [`PermissionDenied`](io::ErrorKind::PermissionDenied).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejected;
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC rejected by the remote endpoint")
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// An incoming call seen by interceptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallMeta {
+    /// The address of the caller.
+    pub peer: SocketAddr,
+    /// The tag of the request, i.e. [`Request::ID`].
+    pub tag: u64,
+    /// The size of the request and its data in bytes.
+    pub size: usize,
+    /// Annotations added by interceptors.
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The decision of an interceptor on an incoming call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verdict {
+    /// Pass the call to the next interceptor, or the handler.
+    #[default]
+    Accept,
+    /// Fail the call with [`Rejected`] without calling the handler.
+    Reject,
+    /// Pass the call on after the given delay.
+    Delay(Duration),
+}
+
+type Interceptor = Arc<dyn Fn(&mut CallMeta) -> Verdict + Send + Sync>;
+
+tokio::task_local! {
+    static CURRENT_CALL: CallMeta;
+}
+
+/// Returns the incoming call handled by the current task, or `None` if not in a handler.
+///
+/// Only the future returned by the handler can see the call, not the tasks it spawns.
+pub fn current_call() -> Option<CallMeta> {
+    CURRENT_CALL.try_with(|call| call.clone()).ok()
+}
+
+/// RPC options of an endpoint.
+#[derive(Default)]
+pub(crate) struct RpcOptions {
+    /// The default deadline of calls.
+    deadline: Option<Duration>,
+    /// The maximum size of requests in bytes.
+    max_request_size: Option<usize>,
+    /// The maximum size of responses in bytes.
+    max_response_size: Option<usize>,
+    /// Interceptors of incoming calls in order.
+    interceptors: Vec<Interceptor>,
+    /// Incoming calls that the caller may cancel, by the caller and response tag.
+    cancels: HashMap<(SocketAddr, u64), tokio::sync::oneshot::Sender<()>>,
+    /// Whether the task receiving cancellations has been spawned.
+    cancel_listener: bool,
+}
+
+/// The response length that marks a rejected call.
+const REJECTED: u32 = u32::MAX;
+
+/// A streaming RPC request.
+///
+/// A call opens a stream of [`Item`](StreamRequest::Item)s from the caller to the handler, and a
+/// stream of [`Response`](StreamRequest::Response)s back. Server-streaming calls send no items.
+///
+/// The ID must not collide with any [`Request::ID`].
+pub trait StreamRequest: Serialize + DeserializeOwned + Any + Send + Sync {
+    /// A message from the caller.
+    type Item: Serialize + DeserializeOwned + Any + Send + Sync;
+
+    /// A message from the handler.
+    type Response: Serialize + DeserializeOwned + Any + Send + Sync;
+
+    /// A unique ID.
+    const ID: u64;
+}
+
+/// The acknowledgement that tells the sender of a stream that the receiver has been dropped.
+const CLOSED: u64 = u64::MAX;
+
+/// The maximum number of messages of a stream that are sent but not yet read by the receiver.
+///
+/// Once the window is full, [`StreamSender::send`] waits for the receiver to catch up.
+pub const STREAM_WINDOW: u64 = 16;
+
 #[doc(hidden)]
 pub const fn hash_str(s: &str) -> u64 {
     // simple hash33
@@ -93,16 +285,73 @@ pub const fn hash_str(s: &str) -> u64 {
 }
 
 impl Endpoint {
+    /// Sets the default deadline of calls from this endpoint, or `None` to wait forever.
+    ///
+    /// It does not apply to [`call_timeout`](Endpoint::call_timeout), which has its own.
+    pub fn set_rpc_deadline(&self, deadline: Option<Duration>) {
+        self.rpc_options.lock().unwrap().deadline = deadline;
+    }
+
+    /// Returns the default deadline of calls from this endpoint.
+    pub fn rpc_deadline(&self) -> Option<Duration> {
+        self.rpc_options.lock().unwrap().deadline
+    }
+
+    /// Add an interceptor of incoming calls to the handlers of this endpoint.
+    ///
+    /// Interceptors run in the order they are added. A call is rejected if any interceptor
+    /// rejects it, and delayed by the sum of the delays. Interceptors only see unary calls.
+    pub fn add_rpc_interceptor(
+        &self,
+        f: impl Fn(&mut CallMeta) -> Verdict + Send + Sync + 'static,
+    ) {
+        self.rpc_options
+            .lock()
+            .unwrap()
+            .interceptors
+            .push(Arc::new(f));
+    }
+
+    /// Runs the interceptors on an incoming call. Returns the delay, or `None` if rejected.
+    fn intercept(&self, call: &mut CallMeta) -> Option<Duration> {
+        let interceptors = self.rpc_options.lock().unwrap().interceptors.clone();
+        let mut delay = Duration::ZERO;
+        for interceptor in interceptors {
+            match interceptor(call) {
+                Verdict::Accept => {}
+                Verdict::Reject => return None,
+                Verdict::Delay(d) => delay += d,
+            }
+        }
+        Some(delay)
+    }
+
+    /// Sets the maximum size of requests in calls from this endpoint, or `None` if unlimited.
+    ///
+    /// The size is that of the serialized request plus the data.
+    pub fn set_rpc_max_request_size(&self, size: Option<usize>) {
+        self.rpc_options.lock().unwrap().max_request_size = size;
+    }
+
+    /// Sets the maximum size of responses in calls from this endpoint, or `None` if unlimited.
+    ///
+    /// The size is that of the serialized response plus the data.
+    pub fn set_rpc_max_response_size(&self, size: Option<usize>) {
+        self.rpc_options.lock().unwrap().max_response_size = size;
+    }
+
     /// Call function on a remote node with timeout.
+    ///
+    /// Returns [`DeadlineExceeded`] if the response does not arrive in time.
     pub async fn call_timeout<R: Request>(
         &self,
         dst: SocketAddr,
         request: R,
         timeout: Duration,
     ) -> io::Result<R::Response> {
-        crate::time::timeout(timeout, self.call(dst, request))
-            .await
-            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "RPC timeout"))?
+        let call = self.call_inner(dst, request, Bytes::new());
+        let (rsp, _data) = with_deadline(Some(timeout), call).await?;
+        Ok(rsp)
     }
 
     /// Call function on a remote node.
@@ -117,23 +366,66 @@ impl Endpoint {
         dst: SocketAddr,
         request: R,
         data: &[u8],
+    ) -> io::Result<(R::Response, Bytes)> {
+        self.call_with_bytes(dst, request, Bytes::copy_from_slice(data))
+            .await
+    }
+
+    /// Call function on a remote node, sending and receiving data without copying it.
+    pub async fn call_with_bytes<R: Request>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+        data: Bytes,
+    ) -> io::Result<(R::Response, Bytes)> {
+        let deadline = self.rpc_deadline();
+        with_deadline(deadline, self.call_inner(dst, request, data)).await
+    }
+
+    async fn call_inner<R: Request>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+        data: Bytes,
     ) -> io::Result<(R::Response, Bytes)> {
         let req_tag = R::ID;
         let rsp_tag = rand::thread_rng().gen::<u64>();
         let rsp_tag_buf = rsp_tag.to_be_bytes();
         let req = bincode::serialize(&request).unwrap();
+        let (max_request_size, max_response_size) = {
+            let options = self.rpc_options.lock().unwrap();
+            (options.max_request_size, options.max_response_size)
+        };
+        if let Some(limit) = max_request_size {
+            let size = req.len() + data.len();
+            PayloadTooLarge::check(size, limit, io::ErrorKind::InvalidInput)?;
+        }
         let req_len_buf = (req.len() as u32).to_be_bytes();
         let mut iov = [
             IoSlice::new(&rsp_tag_buf[..]),
             IoSlice::new(&req_len_buf[..]),
             IoSlice::new(&req),
-            IoSlice::new(data),
+            IoSlice::new(&data),
         ];
         self.send_to_vectored(dst, req_tag, &mut iov).await?;
 
+        let mut guard = CancelGuard {
+            net: self,
+            dst,
+            tag: rsp_tag,
+            done: false,
+        };
         let (mut data, from) = self.recv_from_raw(rsp_tag).await?;
+        guard.done = true;
         assert_eq!(from, dst);
-        let rsp_len = data.get_u32() as usize;
+        let rsp_len = data.get_u32();
+        if rsp_len == REJECTED {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, Rejected));
+        }
+        let rsp_len = rsp_len as usize;
+        if let Some(limit) = max_response_size {
+            PayloadTooLarge::check(data.len(), limit, io::ErrorKind::InvalidData)?;
+        }
         let rsp_bytes = data.split_to(rsp_len);
         let rsp = bincode::deserialize(&rsp_bytes).unwrap();
         Ok((rsp, data))
@@ -153,20 +445,56 @@ impl Endpoint {
     where
         AsyncFn: FnMut(R, Bytes) -> Fut + Send + 'static,
         Fut: Future<Output = (R::Response, Vec<u8>)> + Send + 'static,
+    {
+        self.add_rpc_handler_with_bytes(move |req, data| {
+            f(req, data).map(|(rsp, data)| (rsp, Bytes::from(data)))
+        })
+    }
+
+    /// Add a RPC handler that send and receive data without copying it.
+    pub fn add_rpc_handler_with_bytes<R: Request, AsyncFn, Fut>(&self, mut f: AsyncFn)
+    where
+        AsyncFn: FnMut(R, Bytes) -> Fut + Send + 'static,
+        Fut: Future<Output = (R::Response, Bytes)> + Send + 'static,
     {
         let req_tag = R::ID;
+        self.listen_cancels();
         let net = self.clone();
         crate::task::spawn(async move {
             loop {
                 let (mut data, from) = net.recv_from_raw(req_tag).await.unwrap();
                 let rsp_tag = data.get_u64();
+                let mut call = CallMeta {
+                    peer: from,
+                    tag: req_tag,
+                    size: data.len() - 4,
+                    annotations: BTreeMap::new(),
+                };
+                let Some(delay) = net.intercept(&mut call) else {
+                    let net = net.clone();
+                    crate::task::spawn(async move {
+                        let buf = REJECTED.to_be_bytes();
+                        net.send_to(from, rsp_tag, &buf).await.unwrap();
+                    });
+                    continue;
+                };
                 let req_len = data.get_u32() as usize;
                 let req_bytes = data.split_to(req_len);
                 let req: R = bincode::deserialize(&req_bytes).unwrap();
                 let rsp_future = f(req, data);
+                let rsp_future = async move {
+                    if !delay.is_zero() {
+                        crate::time::sleep(delay).await;
+                    }
+                    CURRENT_CALL.scope(call, rsp_future).await
+                };
                 let net = net.clone();
+                let cancelled = Cancellable::new(&net, from, rsp_tag);
                 crate::task::spawn(async move {
-                    let (rsp, data) = rsp_future.await;
+                    let (rsp, data) = select_biased! {
+                        rsp = rsp_future.fuse() => rsp,
+                        _ = cancelled.fuse() => return,
+                    };
                     let rsp = bincode::serialize(&rsp).unwrap();
                     let rsp_len_buf = (rsp.len() as u32).to_be_bytes();
                     let mut iov = [
@@ -179,4 +507,334 @@ impl Endpoint {
             }
         });
     }
+
+    /// Spawns the task that cancels incoming calls, if not yet spawned.
+    ///
+    /// The caller sends the response tag of a call with [`CANCEL_TAG`] if it drops the call.
+    /// Cancellations of calls that are unknown or finished are discarded.
+    fn listen_cancels(&self) {
+        let mut options = self.rpc_options.lock().unwrap();
+        if std::mem::replace(&mut options.cancel_listener, true) {
+            return;
+        }
+        drop(options);
+        let net = self.clone();
+        crate::task::spawn(async move {
+            loop {
+                let (mut data, from) = net.recv_from_raw(CANCEL_TAG).await.unwrap();
+                let rsp_tag = data.get_u64();
+                let tx = net
+                    .rpc_options
+                    .lock()
+                    .unwrap()
+                    .cancels
+                    .remove(&(from, rsp_tag));
+                if let Some(tx) = tx {
+                    _ = tx.send(());
+                }
+            }
+        });
+    }
+
+    /// Open a bidirectional stream to a remote node.
+    ///
+    /// Returns the sender of items to the handler and the receiver of its responses.
+    pub async fn call_stream<R: StreamRequest>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+    ) -> io::Result<(StreamSender<R::Item>, StreamReceiver<R::Response>)> {
+        // responses are sent with `tag` and items with `tag + 2`
+        let tag = rand::thread_rng().gen::<u64>();
+        let tag_buf = tag.to_be_bytes();
+        let req = bincode::serialize(&request).unwrap();
+        let mut iov = [IoSlice::new(&tag_buf[..]), IoSlice::new(&req)];
+        self.send_to_vectored(dst, R::ID, &mut iov).await?;
+        let tx = StreamSender::new(self, dst, tag.wrapping_add(2));
+        let rx = StreamReceiver::new(self, dst, tag);
+        Ok((tx, rx))
+    }
+
+    /// Open a stream of responses from a remote node.
+    pub async fn call_server_stream<R: StreamRequest>(
+        &self,
+        dst: SocketAddr,
+        request: R,
+    ) -> io::Result<StreamReceiver<R::Response>> {
+        let (_tx, rx) = self.call_stream(dst, request).await?;
+        Ok(rx)
+    }
+
+    /// Add a streaming RPC handler.
+    ///
+    /// The handler is called with the request, the receiver of items from the caller and the
+    /// sender of responses. The response stream ends when the sender is dropped.
+    pub fn add_stream_handler<R: StreamRequest, AsyncFn, Fut>(&self, mut f: AsyncFn)
+    where
+        AsyncFn:
+            FnMut(R, StreamReceiver<R::Item>, StreamSender<R::Response>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let req_tag = R::ID;
+        let net = self.clone();
+        crate::task::spawn(async move {
+            loop {
+                let (mut data, from) = net.recv_from_raw(req_tag).await.unwrap();
+                let tag = data.get_u64();
+                let req: R = bincode::deserialize(&data).unwrap();
+                let rx = StreamReceiver::new(&net, from, tag.wrapping_add(2));
+                let tx = StreamSender::new(&net, from, tag);
+                crate::task::spawn(f(req, rx, tx));
+            }
+        });
+    }
+}
+
+/// Awaits a call, failing with [`DeadlineExceeded`] if it does not complete within `deadline`.
+async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    call: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    let Some(deadline) = deadline else {
+        return call.await;
+    };
+    crate::time::timeout(deadline, call)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, DeadlineExceeded))?
+}
+
+/// Cancels the handler of a call if dropped before the response arrives.
+struct CancelGuard<'a> {
+    net: &'a Endpoint,
+    dst: SocketAddr,
+    tag: u64,
+    done: bool,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        // the handler has finished, or the runtime is shutting down
+        if self.done || tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+        let (net, dst, tag) = (self.net.clone(), self.dst, self.tag);
+        crate::task::spawn(async move {
+            _ = net.send_to(dst, CANCEL_TAG, &tag.to_be_bytes()).await;
+        });
+    }
+}
+
+/// Completes when an incoming call is cancelled by the caller.
+///
+/// The call is forgotten by the endpoint when this is dropped.
+struct Cancellable {
+    net: Endpoint,
+    key: (SocketAddr, u64),
+    rx: tokio::sync::oneshot::Receiver<()>,
+}
+
+impl Cancellable {
+    fn new(net: &Endpoint, from: SocketAddr, rsp_tag: u64) -> Self {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let key = (from, rsp_tag);
+        net.rpc_options.lock().unwrap().cancels.insert(key, tx);
+        Cancellable {
+            net: net.clone(),
+            key,
+            rx,
+        }
+    }
+}
+
+impl Future for Cancellable {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match ready!(self.rx.poll_unpin(cx)) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Cancellable {
+    fn drop(&mut self) {
+        if let Ok(mut options) = self.net.rpc_options.lock() {
+            options.cancels.remove(&self.key);
+        }
+    }
+}
+
+/// The sending half of a stream.
+///
+/// The stream ends when the sender is dropped.
+pub struct StreamSender<T: Serialize + Send + Sync> {
+    net: Endpoint,
+    dst: SocketAddr,
+    /// Messages are sent with this tag, and acknowledgements are received with the next one.
+    tag: u64,
+    /// The sequence number of the next message.
+    seq: u64,
+    /// The number of messages read by the receiver.
+    acked: u64,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize + Send + Sync> StreamSender<T> {
+    fn new(net: &Endpoint, dst: SocketAddr, tag: u64) -> Self {
+        StreamSender {
+            net: net.clone(),
+            dst,
+            tag,
+            seq: 0,
+            acked: 0,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sends a message, waiting for the receiver to catch up if the window is full.
+    ///
+    /// Fails with [`BrokenPipe`](io::ErrorKind::BrokenPipe) once the receiver is dropped.
+    pub async fn send(&mut self, msg: T) -> io::Result<()> {
+        // take the acknowledgements that have arrived, and wait for more if the window is full
+        loop {
+            let recv = self.net.recv_from_raw(self.tag.wrapping_add(1));
+            let ack = if self.seq - self.acked >= STREAM_WINDOW {
+                Some(recv.await?)
+            } else {
+                recv.now_or_never().transpose()?
+            };
+            let Some((mut ack, _)) = ack else {
+                break;
+            };
+            match ack.get_u64() {
+                CLOSED => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "stream receiver dropped",
+                    ))
+                }
+                ack => self.acked = self.acked.max(ack),
+            }
+        }
+        let seq = self.seq;
+        self.seq += 1;
+        let frame = bincode::serialize(&(seq, Some(msg))).unwrap();
+        self.net.send_to(self.dst, self.tag, &frame).await
+    }
+}
+
+impl<T: Serialize + Send + Sync> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        // no one to notify if the runtime is shutting down
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+        let (net, dst, tag) = (self.net.clone(), self.dst, self.tag);
+        let frame = bincode::serialize(&(self.seq, None::<T>)).unwrap();
+        crate::task::spawn(async move {
+            _ = net.send_to(dst, tag, &frame).await;
+        });
+    }
+}
+
+/// The receiving half of a stream.
+///
+/// Messages are yielded in the order they were sent.
+pub struct StreamReceiver<T: DeserializeOwned + Send + Sync> {
+    net: Endpoint,
+    src: SocketAddr,
+    /// Messages are received with this tag, and acknowledgements are sent with the next one.
+    tag: u64,
+    /// Messages received ahead of their turn by sequence number. `None` ends the stream.
+    pending: BTreeMap<u64, Option<T>>,
+    /// The sequence number of the next message.
+    seq: u64,
+    /// The number of messages read when the last acknowledgement was sent.
+    acked: u64,
+    finished: bool,
+    recv: Option<BoxFuture<'static, io::Result<(Bytes, SocketAddr)>>>,
+}
+
+impl<T: DeserializeOwned + Send + Sync> Unpin for StreamReceiver<T> {}
+
+impl<T: DeserializeOwned + Send + Sync> StreamReceiver<T> {
+    fn new(net: &Endpoint, src: SocketAddr, tag: u64) -> Self {
+        StreamReceiver {
+            net: net.clone(),
+            src,
+            tag,
+            pending: BTreeMap::new(),
+            seq: 0,
+            acked: 0,
+            finished: false,
+            recv: None,
+        }
+    }
+
+    /// Receives the next message, or `None` if the stream has ended.
+    pub async fn recv(&mut self) -> Option<T> {
+        futures_util::StreamExt::next(self).await
+    }
+
+    /// Tells the sender how many messages have been read.
+    fn ack(&mut self) {
+        self.acked = self.seq;
+        let (net, src, tag, acked) = (self.net.clone(), self.src, self.tag, self.acked);
+        crate::task::spawn(async move {
+            _ = net
+                .send_to(src, tag.wrapping_add(1), &acked.to_be_bytes())
+                .await;
+        });
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync> Drop for StreamReceiver<T> {
+    fn drop(&mut self) {
+        // no one to notify if the stream has ended or the runtime is shutting down
+        if self.finished || tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+        let (net, src, tag) = (self.net.clone(), self.src, self.tag);
+        crate::task::spawn(async move {
+            _ = net
+                .send_to(src, tag.wrapping_add(1), &CLOSED.to_be_bytes())
+                .await;
+        });
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync> Stream for StreamReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = &mut *self;
+        loop {
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            if let Some(msg) = this.pending.remove(&this.seq) {
+                let Some(msg) = msg else {
+                    this.finished = true;
+                    return Poll::Ready(None);
+                };
+                this.seq += 1;
+                if this.seq - this.acked >= STREAM_WINDOW / 2 {
+                    this.ack();
+                }
+                return Poll::Ready(Some(msg));
+            }
+            let (net, tag) = (this.net.clone(), this.tag);
+            let recv = (this.recv)
+                .get_or_insert_with(|| async move { net.recv_from_raw(tag).await }.boxed());
+            let res = ready!(recv.as_mut().poll(cx));
+            this.recv = None;
+            let Ok((frame, _)) = res else {
+                this.finished = true;
+                return Poll::Ready(None);
+            };
+            let (seq, msg) = bincode::deserialize(&frame).unwrap();
+            this.pending.insert(seq, msg);
+        }
+    }
 }
@@ -26,6 +26,8 @@ pub struct Endpoint {
     sender: mpsc::Sender<SendMsg>,
     /// Client receives message from here.
     recver: mpsc::Sender<RecvMsg>,
+    #[cfg(feature = "rpc")]
+    pub(super) rpc_options: Arc<std::sync::Mutex<super::rpc::RpcOptions>>,
 }
 
 struct SendMsg {
@@ -151,6 +153,8 @@ impl Endpoint {
             addr,
             sender,
             recver,
+            #[cfg(feature = "rpc")]
+            rpc_options: Default::default(),
         })
     }
 
@@ -199,6 +203,36 @@ impl Endpoint {
         Ok(())
     }
 
+    /// Sends bytes with tag on the socket to the given address, without copying them.
+    pub async fn send_to_bytes(
+        &self,
+        dst: impl ToSocketAddrs,
+        tag: u64,
+        data: Bytes,
+    ) -> io::Result<()> {
+        self.send_to(dst, tag, &data).await
+    }
+
+    /// Sends a message of multiple segments with tag on the socket to the given address,
+    /// without copying them.
+    ///
+    /// The receiver gets the concatenation of the segments.
+    pub async fn send_to_bytes_vectored(
+        &self,
+        dst: impl ToSocketAddrs,
+        tag: u64,
+        segments: Vec<Bytes>,
+    ) -> io::Result<()> {
+        let mut bufs: Vec<_> = segments.iter().map(|s| IoSlice::new(s)).collect();
+        self.send_to_vectored(dst, tag, &mut bufs).await
+    }
+
+    /// Receives a single message with given tag on the socket, without copying it.
+    /// On success, returns the message and the origin.
+    pub async fn recv_from_bytes(&self, tag: u64) -> io::Result<(Bytes, SocketAddr)> {
+        self.recv_from_raw(tag).await
+    }
+
     /// Receives a single message with given tag on the socket.
     /// On success, returns the number of bytes read and the origin.
     ///
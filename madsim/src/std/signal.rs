@@ -1,3 +1,5 @@
 //! Asynchronous signal handling.
 
 pub use tokio::signal::ctrl_c;
+#[cfg(unix)]
+pub use tokio::signal::unix;
@@ -0,0 +1,118 @@
+#![cfg(madsim)]
+
+use madsim::{
+    net::{rpc::DeadlineExceeded, Endpoint},
+    runtime::Handle,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io, net::SocketAddr, sync::Mutex, time::Duration};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum KvError {
+    NotFound(String),
+    Transport(String),
+}
+
+impl From<io::Error> for KvError {
+    fn from(e: io::Error) -> Self {
+        KvError::Transport(e.to_string())
+    }
+}
+
+#[madsim::service]
+pub trait Kv {
+    async fn get(&self, key: String) -> Option<String>;
+    async fn put(&self, key: String, value: String);
+    async fn remove(&self, key: String) -> Result<String, KvError>;
+    async fn len(&self) -> usize {
+        0
+    }
+}
+
+/// A service that is not served.
+#[madsim::service]
+pub trait Admin {
+    async fn shutdown(&self);
+    async fn reset(&self) -> Result<(), KvError>;
+}
+
+#[derive(Default)]
+struct Server {
+    kv: Mutex<HashMap<String, String>>,
+}
+
+impl Kv for Server {
+    async fn get(&self, key: String) -> Option<String> {
+        self.kv.lock().unwrap().get(&key).cloned()
+    }
+
+    async fn put(&self, key: String, value: String) {
+        self.kv.lock().unwrap().insert(key, value);
+    }
+
+    async fn remove(&self, key: String) -> Result<String, KvError> {
+        (self.kv.lock().unwrap().remove(&key)).ok_or(KvError::NotFound(key))
+    }
+}
+
+#[madsim::test]
+async fn round_trip() {
+    let handle = Handle::current();
+    let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+    let node1 = handle.create_node().ip(addr1.ip()).build();
+    let node2 = handle.create_node().ip("10.0.0.2".parse().unwrap()).build();
+    node1.spawn(async move {
+        KvServer::new(Server::default()).serve(addr1).await.unwrap();
+    });
+
+    node2
+        .spawn(async move {
+            madsim::time::sleep(Duration::from_secs(1)).await;
+            let ep = Endpoint::bind("0.0.0.0:0").await.unwrap();
+            let client = KvClient::new(ep.clone(), addr1);
+            assert_eq!(client.addr(), addr1);
+
+            client.put("k".into(), "v".into()).await.unwrap();
+            assert_eq!(client.get("k".into()).await.unwrap(), Some("v".into()));
+            assert_eq!(client.get("x".into()).await.unwrap(), None);
+            // default methods are served too
+            assert_eq!(client.len().await.unwrap(), 0);
+
+            // errors of the method are returned as they are
+            assert_eq!(client.remove("k".into()).await, Ok("v".into()));
+            assert_eq!(
+                client.remove("k".into()).await,
+                Err(KvError::NotFound("k".into()))
+            );
+
+            // requests of other services are not handled
+            ep.set_rpc_deadline(Some(Duration::from_secs(1)));
+            let admin = AdminClient::new(ep, addr1);
+            let err = admin.shutdown().await.unwrap_err();
+            assert!(DeadlineExceeded::matches(&err), "{err}");
+            // and transport errors are converted into the error type of the method
+            let err = admin.reset().await.unwrap_err();
+            assert!(matches!(err, KvError::Transport(_)), "{err:?}");
+        })
+        .await
+        .unwrap();
+}
+
+#[test]
+fn request_ids() {
+    use madsim::net::rpc::Request;
+
+    let ids = [
+        KvGetRequest::ID,
+        KvPutRequest::ID,
+        KvRemoveRequest::ID,
+        KvLenRequest::ID,
+        AdminShutdownRequest::ID,
+        AdminResetRequest::ID,
+    ];
+    for (i, a) in ids.iter().enumerate() {
+        for b in &ids[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use async_stream::try_stream;
@@ -17,6 +18,9 @@ pub mod hello_world {
     tonic::include_proto!("helloworld");
 }
 
+/// The number of replies produced by `endless_replies`.
+pub static ENDLESS_REPLIES: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Default)]
 pub struct MyGreeter {}
 
@@ -101,6 +105,25 @@ impl Greeter for MyGreeter {
         Ok(Response::new(reply))
     }
 
+    type EndlessRepliesStream = Pin<Box<dyn Stream<Item = Result<HelloReply, Status>> + Send>>;
+
+    async fn endless_replies(
+        &self,
+        request: Request<HelloRequest>,
+    ) -> Result<Response<Self::EndlessRepliesStream>, Status> {
+        println!("Got a request: {request:?}");
+        let stream = try_stream! {
+            let name = request.into_inner().name;
+            for i in 0.. {
+                ENDLESS_REPLIES.fetch_add(1, Ordering::Relaxed);
+                yield HelloReply {
+                    message: format!("{i}: Hello {name}!"),
+                };
+            }
+        };
+        Ok(Response::new(Box::pin(stream)))
+    }
+
     type BidiHelloStream = Pin<Box<dyn Stream<Item = Result<HelloReply, Status>> + Send>>;
 
     async fn bidi_hello(
@@ -10,14 +10,21 @@ use madsim::{
 };
 use std::{
     net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
-use tonic::transport::{Endpoint, Server};
+use tonic::{
+    metadata::MetadataValue,
+    transport::{Channel, Endpoint, LoadBalance, Server},
+};
 use tonic_example::hello_world::{
     another_greeter_client::AnotherGreeterClient, another_greeter_server::AnotherGreeterServer,
     greeter_client::GreeterClient, greeter_server::GreeterServer, HelloRequest,
 };
-use tonic_example::MyGreeter;
+use tonic_example::{MyGreeter, ENDLESS_REPLIES};
 
 #[madsim::test]
 async fn basic() {
@@ -230,6 +237,50 @@ async fn client_drops_response_stream() {
         .unwrap();
 }
 
+#[madsim::test]
+async fn stream_flow_control() {
+    ENDLESS_REPLIES.store(0, Ordering::Relaxed);
+    let handle = Handle::current();
+    let addr0 = "10.0.0.1:50051".parse::<SocketAddr>().unwrap();
+    let ip1 = "10.0.0.2".parse().unwrap();
+    let node0 = handle.create_node().name("server").ip(addr0.ip()).build();
+    node0.spawn(async move {
+        Server::builder()
+            .add_service(GreeterServer::new(MyGreeter::default()))
+            .serve(addr0)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let node1 = handle.create_node().name("client1").ip(ip1).build();
+    node1
+        .spawn(async move {
+            let mut client = GreeterClient::connect("http://10.0.0.1:50051")
+                .await
+                .unwrap();
+            let response = client.endless_replies(request()).await.unwrap();
+            let mut stream = response.into_inner();
+            stream.message().await.unwrap().unwrap();
+
+            // the server stops producing while the client is not reading
+            sleep(Duration::from_secs(5)).await;
+            let produced = ENDLESS_REPLIES.load(Ordering::Relaxed);
+            assert!(produced <= 20, "{produced}");
+            sleep(Duration::from_secs(5)).await;
+            assert_eq!(ENDLESS_REPLIES.load(Ordering::Relaxed), produced);
+
+            // and resumes once the client catches up
+            for i in 1..100 {
+                let reply = stream.message().await.unwrap().unwrap();
+                assert_eq!(reply.message, format!("{i}: Hello Tonic!"));
+            }
+            assert!(ENDLESS_REPLIES.load(Ordering::Relaxed) >= 100);
+        })
+        .await
+        .unwrap();
+}
+
 #[madsim::test]
 async fn server_crash() {
     let handle = Handle::current();
@@ -277,6 +328,110 @@ async fn server_crash() {
         .unwrap();
 }
 
+#[madsim::test]
+async fn reconnect_backoff() {
+    let handle = Handle::current();
+    let addr0 = "10.0.0.1:50051".parse::<SocketAddr>().unwrap();
+    let ip1 = "10.0.0.2".parse().unwrap();
+    let node0 = handle
+        .create_node()
+        .name("server")
+        .ip(addr0.ip())
+        .init(move || async move {
+            Server::builder()
+                .add_service(GreeterServer::new(MyGreeter::default()))
+                .serve(addr0)
+                .await
+                .unwrap();
+        })
+        .build();
+
+    let node1 = handle.create_node().name("client1").ip(ip1).build();
+    node1
+        .spawn(async move {
+            // connect on the first call
+            let channel = Endpoint::from_static("http://10.0.0.1:50051").connect_lazy();
+            let mut client = GreeterClient::new(channel);
+            sleep(Duration::from_secs(1)).await;
+            client.say_hello(request()).await.unwrap();
+
+            Handle::current().kill(node0.id());
+            let error = client.say_hello(request()).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::Unavailable);
+
+            // calls fail without connecting until the backoff expires
+            Handle::current().restart(node0.id());
+            sleep(Duration::from_millis(100)).await;
+            let error = client.say_hello(request()).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::Unavailable);
+
+            sleep(Duration::from_secs(2)).await;
+            client.say_hello(request()).await.unwrap();
+        })
+        .await
+        .unwrap();
+}
+
+#[madsim::test]
+async fn load_balance() {
+    let handle = Handle::current();
+    let ip2 = "10.0.0.3".parse().unwrap();
+    let mut servers = vec![];
+    let mut counts = vec![];
+    for i in 1..=2 {
+        let addr = format!("10.0.0.{i}:50051").parse::<SocketAddr>().unwrap();
+        let node = handle.create_node().ip(addr.ip()).build();
+        let count = Arc::new(AtomicU64::new(0));
+        let count1 = count.clone();
+        node.spawn(async move {
+            Server::builder()
+                .add_service(GreeterServer::with_interceptor(
+                    MyGreeter::default(),
+                    move |req| {
+                        count1.fetch_add(1, Ordering::Relaxed);
+                        Ok(req)
+                    },
+                ))
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+        servers.push(node);
+        counts.push(count);
+    }
+    sleep(Duration::from_secs(1)).await;
+
+    let node2 = handle.create_node().name("client").ip(ip2).build();
+    node2
+        .spawn(async move {
+            let count = |i: usize| counts[i].load(Ordering::Relaxed);
+            let endpoints =
+                ["http://10.0.0.1:50051", "http://10.0.0.2:50051"].map(Endpoint::from_static);
+            let channel = Channel::balance_list(endpoints.clone().into_iter());
+            let mut client = GreeterClient::new(channel);
+            for _ in 0..4 {
+                client.say_hello(request()).await.unwrap();
+            }
+            assert_eq!((count(0), count(1)), (2, 2));
+
+            // calls fail over to available endpoints
+            Handle::current().kill(servers[0].id());
+            for _ in 0..4 {
+                client.say_hello(request()).await.unwrap();
+            }
+            assert_eq!(count(1), 6);
+
+            let channel = Channel::balance_list_with(endpoints.into_iter(), LoadBalance::PickFirst);
+            let mut client = GreeterClient::new(channel);
+            for _ in 0..2 {
+                client.say_hello(request()).await.unwrap();
+            }
+            assert_eq!(count(1), 8);
+        })
+        .await
+        .unwrap();
+}
+
 #[madsim::test]
 async fn unimplemented_service() {
     let handle = Handle::current();
@@ -365,6 +520,140 @@ async fn interceptor() {
         .unwrap();
 }
 
+#[madsim::test]
+async fn deadline_propagation() {
+    let handle = Handle::current();
+    let addr0 = "10.0.0.1:50051".parse::<SocketAddr>().unwrap();
+    let addr1 = "10.0.0.2:50051".parse::<SocketAddr>().unwrap();
+    let ip2 = "10.0.0.3".parse().unwrap();
+    let node0 = handle.create_node().name("server0").ip(addr0.ip()).build();
+    let node1 = handle.create_node().name("server1").ip(addr1.ip()).build();
+    node0.spawn(async move {
+        Server::builder()
+            .add_service(AnotherGreeterServer::with_interceptor(
+                MyGreeter::default(),
+                |req: tonic::Request<()>| {
+                    // the deadline of the client is visible to the server
+                    assert_eq!(req.metadata().get("grpc-timeout").unwrap(), "1000000u");
+                    Ok(req)
+                },
+            ))
+            .serve(addr0)
+            .await
+            .unwrap();
+    });
+    node1.spawn(async move {
+        Server::builder()
+            .timeout(Duration::from_secs(2))
+            .add_service(AnotherGreeterServer::new(MyGreeter::default()))
+            .serve(addr1)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let node2 = handle.create_node().name("client").ip(ip2).build();
+    node2
+        .spawn(async move {
+            let mut client = AnotherGreeterClient::connect("http://10.0.0.1:50051")
+                .await
+                .unwrap();
+            let mut request = request();
+            request.set_timeout(Duration::from_secs(1));
+            let t0 = Instant::now();
+            let error = client.delay(request).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::DeadlineExceeded);
+            assert!(t0.elapsed() < Duration::from_secs(2));
+
+            // the server gives up on its own timeout
+            let mut client = AnotherGreeterClient::connect("http://10.0.0.2:50051")
+                .await
+                .unwrap();
+            let t0 = Instant::now();
+            let error = client.delay(request()).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::DeadlineExceeded);
+            assert!(t0.elapsed() >= Duration::from_secs(2));
+            assert!(t0.elapsed() < Duration::from_secs(3));
+        })
+        .await
+        .unwrap();
+}
+
+#[madsim::test]
+async fn metadata() {
+    let handle = Handle::current();
+    let addr0 = "10.0.0.1:50051".parse::<SocketAddr>().unwrap();
+    let ip1 = "10.0.0.2".parse().unwrap();
+    let node0 = handle.create_node().name("server").ip(addr0.ip()).build();
+
+    /// An extension that is local to the client.
+    #[derive(Clone)]
+    struct Local;
+
+    node0.spawn(async move {
+        Server::builder()
+            .add_service(GreeterServer::with_interceptor(
+                MyGreeter::default(),
+                |req: tonic::Request<()>| {
+                    let metadata = req.metadata();
+                    if metadata
+                        .get("authorization")
+                        .map_or(true, |v| v != "Bearer token")
+                    {
+                        return Err(tonic::Status::unauthenticated("invalid token"));
+                    }
+                    let trace = metadata.get_bin("trace-bin").unwrap();
+                    assert_eq!(trace.to_bytes().unwrap().as_ref(), &[0, 1, 2]);
+                    assert_eq!(metadata.get("user-agent").unwrap(), "test tonic/0.8.3");
+                    assert_eq!(metadata.get("content-type").unwrap(), "application/grpc");
+                    assert!(req.extensions().get::<Local>().is_none());
+                    Ok(req)
+                },
+            ))
+            .serve(addr0)
+            .await
+            .unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let node1 = handle.create_node().name("client1").ip(ip1).build();
+    node1
+        .spawn(async move {
+            let channel = Endpoint::from_static("http://10.0.0.1:50051")
+                .user_agent("test")
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let mut client =
+                GreeterClient::with_interceptor(channel.clone(), |mut req: tonic::Request<()>| {
+                    let metadata = req.metadata_mut();
+                    metadata.insert("authorization", "Bearer token".parse().unwrap());
+                    metadata.insert_bin("trace-bin", MetadataValue::from_bytes(&[0, 1, 2]));
+                    // reserved headers are replaced
+                    metadata.insert("content-type", "text/plain".parse().unwrap());
+                    req.extensions_mut().insert(Local);
+                    Ok(req)
+                });
+            let response = client.say_hello(request()).await.unwrap();
+            assert_eq!(
+                response.metadata().get("content-type").unwrap(),
+                "application/grpc"
+            );
+
+            let response = client.bidi_hello(hello_stream()).await.unwrap();
+            let mut stream = response.into_inner();
+            let trailers = stream.trailers().await.unwrap().unwrap();
+            assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+
+            let mut client = GreeterClient::new(channel);
+            let error = client.say_hello(request()).await.unwrap_err();
+            assert_eq!(error.code(), tonic::Code::Unauthenticated);
+        })
+        .await
+        .unwrap();
+}
+
 #[madsim::test]
 async fn request_timeout() {
     let handle = Handle::current();